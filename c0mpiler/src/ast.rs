@@ -0,0 +1,458 @@
+use crate::scanner::span::Span;
+
+// Abstract syntax tree
+//
+// One node type per syntactic category of the C0 grammar. Each node is a struct holding its
+// kind along with the span of source text it was parsed from, so that any later pass can point
+// diagnostics at the right place. Operators get their own enums rather than reusing the
+// scanner's, since by this point e.g. '-' has been resolved into negation or subtraction
+#[derive(PartialEq, Debug, Clone)]
+pub struct Program {
+    pub decls: Vec<Decl>,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Decl {
+    pub kind: DeclKind,
+    pub span: Span,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(PartialEq, Debug, Clone)]
+pub enum DeclKind {
+    Typedef {
+        ty: Type,
+        name: String,
+    },
+    // "struct s;"
+    StructDecl {
+        name: String,
+    },
+    // "struct s { ... };"
+    StructDef {
+        name: String,
+        fields: Vec<Field>,
+    },
+    // A prototype if body is None, a definition otherwise
+    Function {
+        ret: Type,
+        name: String,
+        params: Vec<Param>,
+        specs: Vec<Spec>,
+        body: Option<Vec<Stmt>>,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Field {
+    pub ty: Type,
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Param {
+    pub ty: Type,
+    pub name: String,
+    pub span: Span,
+}
+
+// A type as written in the source
+#[derive(PartialEq, Debug, Clone)]
+pub struct Type {
+    pub kind: TypeKind,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TypeKind {
+    Int,
+    Bool,
+    Char,
+    String,
+    Void,
+    Pointer(Box<Type>),
+    Array(Box<Type>),
+    Struct(String),
+    // A name introduced by typedef
+    Name(String),
+}
+
+// A type as the typechecker sees it: no spans, and no typedef names left to resolve. This is what
+// goes in the type slot of expressions and lvalues
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Ty {
+    Int,
+    Bool,
+    Char,
+    String,
+    Void,
+    Pointer(Box<Ty>),
+    Array(Box<Ty>),
+    Struct(String),
+    // The type of NULL, which goes wherever any pointer type is expected
+    Null,
+}
+
+// Contracts, from "//@" and "/*@ @*/" annotations
+#[derive(PartialEq, Debug, Clone)]
+pub struct Spec {
+    pub kind: SpecKind,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum SpecKind {
+    Requires(Expr),
+    Ensures(Expr),
+    LoopInvariant(Expr),
+    Assert(Expr),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum StmtKind {
+    Decl {
+        ty: Type,
+        name: String,
+        init: Option<Expr>,
+    },
+    // "lv = e", or "lv op= e" when op is Some
+    Assign {
+        lhs: LValue,
+        op: Option<BinaryOp>,
+        rhs: Expr,
+    },
+    // "lv++" and "lv--"
+    PostOp {
+        lhs: LValue,
+        op: PostfixOp,
+    },
+    Expr(Expr),
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        els: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        invariants: Vec<Spec>,
+        body: Box<Stmt>,
+    },
+    For {
+        init: Option<Box<Stmt>>,
+        cond: Expr,
+        step: Option<Box<Stmt>>,
+        invariants: Vec<Spec>,
+        body: Box<Stmt>,
+    },
+    Return(Option<Expr>),
+    Block(Vec<Stmt>),
+    Assert(Expr),
+    Error(Expr),
+    Break,
+    Continue,
+    // "//@assert e;" between statements
+    Annotation(Vec<Spec>),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct LValue {
+    pub kind: LValueKind,
+    pub span: Span,
+    // Filled in by the typechecker
+    pub ty: Option<Ty>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum LValueKind {
+    Var(String),
+    Field { base: Box<LValue>, field: String },
+    Arrow { base: Box<LValue>, field: String },
+    Deref(Box<LValue>),
+    Index { base: Box<LValue>, index: Expr },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    // Filled in by the typechecker
+    pub ty: Option<Ty>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ExprKind {
+    Int(i32),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Null,
+    Var(String),
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Ternary {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        els: Box<Expr>,
+    },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    // "e.f"
+    Field {
+        base: Box<Expr>,
+        field: String,
+    },
+    // "e->f"
+    Arrow {
+        base: Box<Expr>,
+        field: String,
+    },
+    Deref(Box<Expr>),
+    Index {
+        base: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Alloc(Type),
+    AllocArray {
+        ty: Type,
+        len: Box<Expr>,
+    },
+    // "\result" and "\length(e)", only valid in contracts
+    Result,
+    Length(Box<Expr>),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Eq,
+    NotEq,
+    BitAnd,
+    BitXor,
+    BitOr,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Not,    // '!'
+    BitNot, // '~'
+    Neg,    // '-'
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PostfixOp {
+    Inc,
+    Dec,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            kind,
+            span,
+            ty: None,
+        }
+    }
+}
+
+impl LValue {
+    pub fn new(kind: LValueKind, span: Span) -> LValue {
+        LValue {
+            kind,
+            span,
+            ty: None,
+        }
+    }
+}
+
+// Tests build expected trees without caring where each node came from, so they compare against
+// parsed trees with every span reset to the default
+#[cfg(test)]
+pub mod erase {
+    use super::*;
+
+    pub fn program(program: &mut Program) {
+        program.span = Span::default();
+        program.decls.iter_mut().for_each(decl);
+    }
+
+    pub fn decl(decl: &mut Decl) {
+        decl.span = Span::default();
+        match &mut decl.kind {
+            DeclKind::Typedef { ty, .. } => type_(ty),
+            DeclKind::StructDecl { .. } => {}
+            DeclKind::StructDef { fields, .. } => {
+                for field in fields {
+                    field.span = Span::default();
+                    type_(&mut field.ty);
+                }
+            }
+            DeclKind::Function {
+                ret,
+                params,
+                specs,
+                body,
+                ..
+            } => {
+                type_(ret);
+                for param in params {
+                    param.span = Span::default();
+                    type_(&mut param.ty);
+                }
+                specs.iter_mut().for_each(spec);
+                if let Some(body) = body {
+                    body.iter_mut().for_each(stmt);
+                }
+            }
+        }
+    }
+
+    pub fn type_(ty: &mut Type) {
+        ty.span = Span::default();
+        if let TypeKind::Pointer(inner) | TypeKind::Array(inner) = &mut ty.kind {
+            type_(inner);
+        }
+    }
+
+    pub fn spec(spec: &mut Spec) {
+        spec.span = Span::default();
+        match &mut spec.kind {
+            SpecKind::Requires(e)
+            | SpecKind::Ensures(e)
+            | SpecKind::LoopInvariant(e)
+            | SpecKind::Assert(e) => expr(e),
+        }
+    }
+
+    pub fn stmt(s: &mut Stmt) {
+        s.span = Span::default();
+        match &mut s.kind {
+            StmtKind::Decl { ty, init, .. } => {
+                type_(ty);
+                init.iter_mut().for_each(expr);
+            }
+            StmtKind::Assign { lhs, rhs, .. } => {
+                lvalue(lhs);
+                expr(rhs);
+            }
+            StmtKind::PostOp { lhs, .. } => lvalue(lhs),
+            StmtKind::Expr(e) | StmtKind::Assert(e) | StmtKind::Error(e) => expr(e),
+            StmtKind::If { cond, then, els } => {
+                expr(cond);
+                stmt(then);
+                if let Some(els) = els {
+                    stmt(els);
+                }
+            }
+            StmtKind::While {
+                cond,
+                invariants,
+                body,
+            } => {
+                expr(cond);
+                invariants.iter_mut().for_each(spec);
+                stmt(body);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                invariants,
+                body,
+            } => {
+                if let Some(init) = init {
+                    stmt(init);
+                }
+                expr(cond);
+                if let Some(step) = step {
+                    stmt(step);
+                }
+                invariants.iter_mut().for_each(spec);
+                stmt(body);
+            }
+            StmtKind::Return(e) => e.iter_mut().for_each(expr),
+            StmtKind::Block(stmts) => stmts.iter_mut().for_each(stmt),
+            StmtKind::Annotation(specs) => specs.iter_mut().for_each(spec),
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    pub fn lvalue(lv: &mut LValue) {
+        lv.span = Span::default();
+        match &mut lv.kind {
+            LValueKind::Var(_) => {}
+            LValueKind::Field { base, .. }
+            | LValueKind::Arrow { base, .. }
+            | LValueKind::Deref(base) => lvalue(base),
+            LValueKind::Index { base, index } => {
+                lvalue(base);
+                expr(index);
+            }
+        }
+    }
+
+    pub fn expr(e: &mut Expr) {
+        e.span = Span::default();
+        match &mut e.kind {
+            ExprKind::Int(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Char(_)
+            | ExprKind::String(_)
+            | ExprKind::Null
+            | ExprKind::Var(_)
+            | ExprKind::Result => {}
+            ExprKind::Binary { lhs, rhs, .. } => {
+                expr(lhs);
+                expr(rhs);
+            }
+            ExprKind::Unary { operand, .. } => expr(operand),
+            ExprKind::Ternary { cond, then, els } => {
+                expr(cond);
+                expr(then);
+                expr(els);
+            }
+            ExprKind::Call { args, .. } => args.iter_mut().for_each(expr),
+            ExprKind::Field { base, .. }
+            | ExprKind::Arrow { base, .. }
+            | ExprKind::Deref(base)
+            | ExprKind::Length(base) => expr(base),
+            ExprKind::Index { base, index } => {
+                expr(base);
+                expr(index);
+            }
+            ExprKind::Alloc(ty) => type_(ty),
+            ExprKind::AllocArray { ty, len } => {
+                type_(ty);
+                expr(len);
+            }
+        }
+    }
+}
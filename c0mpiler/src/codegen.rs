@@ -0,0 +1,607 @@
+use crate::ir::cfg::Cfg;
+use crate::ir::ssa::{into_ssa, out_of_ssa};
+use crate::ir::{
+    BinaryOp, CheckedOp, CmpOp, Extern, Function, Instr, Label, Operand, Program, Size, Temp, Trap,
+    UnaryOp,
+};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+// x86-64 assembly in GNU as (AT&T) syntax, from the IR, for linking against the C library
+//
+// Each function is put into SSA form and taken back out of it before it's emitted, so the code
+// has copies where its phis were
+//
+// Every temporary lives in a stack slot of its own, 8 bytes below the one before it, so an
+// instruction loads its operands into %rax and %rcx, does its work, and stores the result back.
+// Calls follow the System V convention: the first six arguments in %rdi, %rsi, %rdx, %rcx, %r8
+// and %r9, the rest pushed right to left, and the stack 16-byte aligned at the call
+//
+// Arithmetic on ints is done on the low 32 bits, which wraps, and sign-extended again. Division
+// and modulus go through `idiv`, which faults on a zero divisor and on INT_MIN / -1, so the
+// program dies of SIGFPE as C0 asks; shifts go through %cl, after checking the amount by hand
+//
+// Functions of the program are named `_c0_<name>`, so they can't clash with the C library, and
+// are called from a `main` that prints what the program's main returns. The runtime, for
+// allocating and for raising errors, is written out at the end of every file
+const ARGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+const RUNTIME: &str = "
+.Lalloc:
+    pushq %rbp
+    movq %rsp, %rbp
+    movq %rdi, %rsi
+    movl $1, %edi
+    call calloc@PLT
+    testq %rax, %rax
+    je .Lraise_memory
+    popq %rbp
+    ret
+.Lalloc_array:
+    pushq %rbp
+    movq %rsp, %rbp
+    pushq %rbx
+    subq $8, %rsp
+    testq %rsi, %rsi
+    js .Lraise_memory
+    movq %rsi, %rbx
+    imulq %rdi, %rsi
+    addq $8, %rsi
+    movl $1, %edi
+    call calloc@PLT
+    testq %rax, %rax
+    je .Lraise_memory
+    movl %ebx, (%rax)
+    movq -8(%rbp), %rbx
+    leave
+    ret
+.Lraise_memory:
+    andq $-16, %rsp
+    movl $11, %edi
+    call raise@PLT
+    call abort@PLT
+.Lraise_arithmetic:
+    andq $-16, %rsp
+    movl $8, %edi
+    call raise@PLT
+    call abort@PLT
+.Lraise_assert:
+    andq $-16, %rsp
+    call abort@PLT
+.Lerror:
+    andq $-16, %rsp
+    movq %rdi, %rbx
+    call strlen@PLT
+    movq %rax, %rdx
+    movq %rbx, %rsi
+    movl $2, %edi
+    call write@PLT
+    movl $1, %edx
+    leaq .Lnewline(%rip), %rsi
+    movl $2, %edi
+    call write@PLT
+    call abort@PLT
+";
+
+const MAIN: &str = "
+    .globl main
+main:
+    pushq %rbp
+    movq %rsp, %rbp
+    call _c0_main
+    movl %eax, %esi
+    leaq .Lformat(%rip), %rdi
+    xorl %eax, %eax
+    call printf@PLT
+    xorl %eax, %eax
+    popq %rbp
+    ret
+";
+
+struct Emitter<'a> {
+    asm: String,
+    defined: HashSet<&'a str>,
+    externs: &'a [Extern],
+    // String literals, in the order they were first used, as .LS0, .LS1 and so on
+    strings: Vec<String>,
+    // The function being emitted, for naming its labels
+    function: String,
+}
+
+pub fn emit(program: &Program) -> String {
+    let mut emitter = Emitter {
+        asm: String::new(),
+        defined: program.functions.iter().map(|f| f.name.as_str()).collect(),
+        externs: &program.externs,
+        strings: Vec::new(),
+        function: String::new(),
+    };
+    emitter.asm.push_str("    .text\n");
+    for function in &program.functions {
+        emitter.function(&out_of_ssa(into_ssa(Cfg::new(function))).to_function());
+    }
+    if emitter.defined.contains("main") {
+        emitter.asm.push_str(MAIN);
+    }
+    emitter.asm.push_str(RUNTIME);
+
+    let mut asm = emitter.asm;
+    asm.push_str("\n    .section .rodata\n");
+    for (index, s) in emitter.strings.iter().enumerate() {
+        writeln!(asm, ".LS{}:\n    .string \"{}\"", index, escape(s)).unwrap();
+    }
+    asm.push_str(".Lnewline:\n    .string \"\\n\"\n");
+    asm.push_str(".Lformat:\n    .string \"%d\\n\"\n");
+    asm.push_str("    .section .note.GNU-stack,\"\",@progbits\n");
+    asm
+}
+
+// For .string, which takes the escapes C does
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => write!(escaped, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    escaped
+}
+
+fn slot(temp: usize) -> String {
+    format!("-{}(%rbp)", 8 * (temp + 1))
+}
+
+fn fits(n: i64) -> bool {
+    i32::try_from(n).is_ok()
+}
+
+fn condition(op: CmpOp) -> &'static str {
+    match op {
+        CmpOp::Eq => "e",
+        CmpOp::Ne => "ne",
+        CmpOp::Lt => "l",
+        CmpOp::Le => "le",
+        CmpOp::Gt => "g",
+        CmpOp::Ge => "ge",
+    }
+}
+
+impl Emitter<'_> {
+    fn line(&mut self, instr: &str) {
+        writeln!(self.asm, "    {}", instr).unwrap();
+    }
+
+    fn label(&self, label: Label) -> String {
+        format!(".L{}.{}", self.function, label.0)
+    }
+
+    fn string(&mut self, s: &str) -> usize {
+        match self.strings.iter().position(|other| other == s) {
+            Some(index) => index,
+            None => {
+                self.strings.push(s.to_string());
+                self.strings.len() - 1
+            }
+        }
+    }
+
+    // Puts the whole of `operand` in `register`
+    fn load(&mut self, operand: &Operand, register: &str) {
+        let instr = match operand {
+            Operand::Temp(temp) => format!("movq {}, {}", slot(temp.0), register),
+            Operand::Int(n) if fits(*n) => format!("movq ${}, {}", n, register),
+            Operand::Int(n) => format!("movabsq ${}, {}", n, register),
+            Operand::Str(s) => format!("leaq .LS{}(%rip), {}", self.string(s), register),
+        };
+        self.line(&instr);
+    }
+
+    // Where an instruction can read `operand` from, going through %rcx if it has to
+    fn source(&mut self, operand: &Operand) -> String {
+        match operand {
+            Operand::Temp(temp) => slot(temp.0),
+            Operand::Int(n) if fits(*n) => format!("${}", n),
+            _ => {
+                self.load(operand, "%rcx");
+                "%rcx".to_string()
+            }
+        }
+    }
+
+    fn store(&mut self, temp: usize) {
+        self.line(&format!("movq %rax, {}", slot(temp)));
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.function = function.name.clone();
+        let frame = (8 * function.next_temp()).next_multiple_of(16);
+        writeln!(self.asm, "\n    .globl _c0_{0}\n_c0_{0}:", function.name).unwrap();
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        if frame > 0 {
+            self.line(&format!("subq ${}, %rsp", frame));
+        }
+        for (index, param) in function.params.iter().enumerate() {
+            match ARGS.get(index) {
+                Some(register) => self.line(&format!("movq {}, {}", register, slot(param.0))),
+                None => {
+                    self.line(&format!(
+                        "movq {}(%rbp), %rax",
+                        16 + 8 * (index - ARGS.len())
+                    ));
+                    self.store(param.0);
+                }
+            }
+        }
+        for (index, instr) in function.body.iter().enumerate() {
+            // Jumps to the label right after them fall through instead
+            let next = match function.body.get(index + 1) {
+                Some(Instr::Label(label)) => Some(*label),
+                _ => None,
+            };
+            self.instr(instr, next);
+        }
+    }
+
+    fn instr(&mut self, instr: &Instr, next: Option<Label>) {
+        match instr {
+            Instr::Label(label) => writeln!(self.asm, "{}:", self.label(*label)).unwrap(),
+            Instr::Phi { .. } => unreachable!("phis are gone after leaving SSA form"),
+            Instr::Copy { dst, src } => {
+                self.load(src, "%rax");
+                self.store(dst.0);
+            }
+            Instr::Unary { dst, op, src } => {
+                self.load(src, "%rax");
+                self.line(match op {
+                    UnaryOp::Neg => "negl %eax",
+                    UnaryOp::Not => "notl %eax",
+                });
+                self.line("movslq %eax, %rax");
+                self.store(dst.0);
+            }
+            Instr::Binary { dst, op, lhs, rhs } => {
+                self.load(lhs, "%rax");
+                let rhs = self.source(rhs);
+                match op {
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                        let name = match op {
+                            BinaryOp::Add => "addl",
+                            BinaryOp::Sub => "subl",
+                            _ => "imull",
+                        };
+                        let rhs = if rhs == "%rcx" { "%ecx" } else { &rhs };
+                        self.line(&format!("{} {}, %eax", name, rhs));
+                        self.line("movslq %eax, %rax");
+                    }
+                    BinaryOp::And => self.line(&format!("andq {}, %rax", rhs)),
+                    BinaryOp::Or => self.line(&format!("orq {}, %rax", rhs)),
+                    BinaryOp::Xor => self.line(&format!("xorq {}, %rax", rhs)),
+                    BinaryOp::Cmp(op) => {
+                        self.line(&format!("cmpq {}, %rax", rhs));
+                        self.line(&format!("set{} %al", condition(*op)));
+                        self.line("movzbq %al, %rax");
+                    }
+                }
+                self.store(dst.0);
+            }
+            Instr::Checked { dst, op, lhs, rhs } => {
+                self.load(lhs, "%rax");
+                self.load(rhs, "%rcx");
+                match op {
+                    CheckedOp::Div | CheckedOp::Mod => {
+                        self.line("cltd");
+                        self.line("idivl %ecx");
+                        if *op == CheckedOp::Mod {
+                            self.line("movl %edx, %eax");
+                        }
+                    }
+                    CheckedOp::Shl | CheckedOp::Shr => {
+                        // Unsigned, so that negative amounts are out of range too
+                        self.line("cmpl $31, %ecx");
+                        self.line("ja .Lraise_arithmetic");
+                        self.line(match op {
+                            CheckedOp::Shl => "sall %cl, %eax",
+                            _ => "sarl %cl, %eax",
+                        });
+                    }
+                }
+                self.line("movslq %eax, %rax");
+                self.store(dst.0);
+            }
+            Instr::Address {
+                dst,
+                base,
+                index,
+                offset,
+            } => {
+                self.load(base, "%rax");
+                if let Some((index, scale)) = index {
+                    self.load(index, "%rcx");
+                    self.line(&format!("imulq ${}, %rcx, %rcx", scale));
+                    self.line("addq %rcx, %rax");
+                }
+                if *offset != 0 {
+                    let offset = self.source(&Operand::Int(*offset));
+                    self.line(&format!("addq {}, %rax", offset));
+                }
+                self.store(dst.0);
+            }
+            Instr::Load { dst, size, addr } => {
+                self.load(addr, "%rax");
+                self.line(match size {
+                    Size::Byte => "movzbq (%rax), %rax",
+                    Size::Word => "movslq (%rax), %rax",
+                    Size::Quad => "movq (%rax), %rax",
+                });
+                self.store(dst.0);
+            }
+            Instr::Store { size, addr, value } => {
+                self.load(addr, "%rax");
+                self.load(value, "%rcx");
+                self.line(match size {
+                    Size::Byte => "movb %cl, (%rax)",
+                    Size::Word => "movl %ecx, (%rax)",
+                    Size::Quad => "movq %rcx, (%rax)",
+                });
+            }
+            Instr::Call { dst, name, args } => self.call(*dst, name, args),
+            Instr::Jump(label) => {
+                if next != Some(*label) {
+                    let label = self.label(*label);
+                    self.line(&format!("jmp {}", label));
+                }
+            }
+            Instr::Branch {
+                op,
+                lhs,
+                rhs,
+                then,
+                els,
+            } => {
+                self.load(lhs, "%rax");
+                let rhs = self.source(rhs);
+                self.line(&format!("cmpq {}, %rax", rhs));
+                let (then_, els_) = (self.label(*then), self.label(*els));
+                if next == Some(*then) {
+                    self.line(&format!("j{} {}", condition(op.negate()), els_));
+                } else {
+                    self.line(&format!("j{} {}", condition(*op), then_));
+                    if next != Some(*els) {
+                        self.line(&format!("jmp {}", els_));
+                    }
+                }
+            }
+            Instr::Return(value) => {
+                if let Some(value) = value {
+                    self.load(value, "%rax");
+                }
+                self.line("leave");
+                self.line("ret");
+            }
+            Instr::Raise(trap) => match trap {
+                Trap::Memory => self.line("call .Lraise_memory"),
+                Trap::Arithmetic => self.line("call .Lraise_arithmetic"),
+                Trap::Assert => self.line("call .Lraise_assert"),
+                Trap::Error(message) => {
+                    self.load(message, "%rdi");
+                    self.line("call .Lerror");
+                }
+            },
+        }
+    }
+
+    fn call(&mut self, dst: Option<Temp>, name: &str, args: &[Operand]) {
+        // Whatever doesn't fit in registers goes on the stack, which stays aligned
+        let stacked = args.len().saturating_sub(ARGS.len());
+        if stacked % 2 == 1 {
+            self.line("subq $8, %rsp");
+        }
+        for arg in args.iter().skip(ARGS.len()).rev() {
+            self.load(arg, "%rax");
+            self.line("pushq %rax");
+        }
+        for (arg, register) in args.iter().zip(ARGS) {
+            self.load(arg, register);
+        }
+        let ret = match name {
+            "alloc" | "alloc_array" => {
+                self.line(&format!("call .L{}", name));
+                None
+            }
+            _ if self.defined.contains(name) => {
+                self.line(&format!("call _c0_{}", name));
+                None
+            }
+            _ => {
+                // For the sake of variadic functions, which want the count of vector registers
+                self.line("xorl %eax, %eax");
+                self.line(&format!("call {}@PLT", name));
+                self.externs
+                    .iter()
+                    .find(|e| e.name == name)
+                    .and_then(|e| e.ret)
+            }
+        };
+        if stacked > 0 {
+            let pushed = stacked.next_multiple_of(2) * 8;
+            self.line(&format!("addq ${}, %rsp", pushed));
+        }
+        if let Some(dst) = dst {
+            match ret {
+                Some(Size::Byte) => self.line("movzbq %al, %rax"),
+                Some(Size::Word) => self.line("movslq %eax, %rax"),
+                _ => {}
+            }
+            self.store(dst.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::lower::tests::lowered;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    // Assembles and links `source` with the system's C compiler, runs it, and gives back what it
+    // printed, or the signal it died of. None if there's no compiler to do it with
+    fn run(name: &str, source: &str) -> Option<Result<String, i32>> {
+        let dir = std::env::temp_dir().join(format!("c0mpiler-codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let asm = dir.join(format!("{}.s", name));
+        let exe = dir.join(name);
+        std::fs::write(&asm, emit(&lowered(source))).unwrap();
+        let status = Command::new("cc")
+            .arg(&asm)
+            .arg("-o")
+            .arg(&exe)
+            .status()
+            .ok()?;
+        assert!(status.success(), "{} didn't assemble", asm.display());
+        let output = Command::new(&exe).output().unwrap();
+        Some(match output.status.signal() {
+            Some(signal) => Err(signal),
+            None => Ok(String::from_utf8(output.stdout).unwrap()),
+        })
+    }
+
+    #[test]
+    fn code() {
+        let asm = emit(&lowered(
+            "int f(int x, int y) { return x / y << 2; }\n\
+             int g(int a, int b, int c, int d, int e, int f, int g) { return g; }",
+        ));
+        let f = &asm[asm.find("_c0_f:").unwrap()..asm.find("    .globl _c0_g").unwrap()];
+        assert_eq!(
+            f,
+            "_c0_f:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $32, %rsp
+    movq %rdi, -8(%rbp)
+    movq %rsi, -16(%rbp)
+.Lf.0:
+    movq -8(%rbp), %rax
+    movq -16(%rbp), %rcx
+    cltd
+    idivl %ecx
+    movslq %eax, %rax
+    movq %rax, -24(%rbp)
+    movq -24(%rbp), %rax
+    movq $2, %rcx
+    cmpl $31, %ecx
+    ja .Lraise_arithmetic
+    sall %cl, %eax
+    movslq %eax, %rax
+    movq %rax, -32(%rbp)
+    movq -32(%rbp), %rax
+    leave
+    ret
+
+"
+        );
+        // the seventh argument is found above the return address
+        assert!(asm.contains("    movq 16(%rbp), %rax\n    movq %rax, -56(%rbp)\n"));
+        assert!(!asm.contains("\nmain:"));
+    }
+
+    #[test]
+    fn runs() {
+        let programs = [
+            (
+                "fact",
+                "int fact(int n) { return n <= 1 ? 1 : n * fact(n - 1); }\n\
+                      int main() { return fact(10); }",
+                "3628800\n",
+            ),
+            (
+                "wraps",
+                "int main() { int x = 2147483647; return (x + 1) / 3 + -7 % 3; }",
+                "-715827883\n",
+            ),
+            (
+                "shifts",
+                "int main() { return (-16 >> 2) + (1 << 31) + 5 / -2; }",
+                "2147483642\n",
+            ),
+            (
+                "args",
+                "int f(int a, int b, int c, int d, int e, int f, int g, int h) {\n\
+                        return a - b + c - d + e - f + g * h;\n\
+                      }\n\
+                      int main() { return f(1, 2, 3, 4, 5, 6, 7, 8) + f(0, 0, 0, 0, 0, 0, 1, 1); }",
+                "54\n",
+            ),
+            (
+                "memory",
+                "struct p { char c; int x; int[] a; };\n\
+                        int main() {\n\
+                          struct p* s = alloc(struct p);\n\
+                          s->a = alloc_array(int, 10);\n\
+                          for (int i = 0; i < 10; i++) s->a[i] = i * i;\n\
+                          s->c = 'z'; s->x = s->a[9];\n\
+                          bool[] b = alloc_array(bool, 3); b[1] = true;\n\
+                          return s->x + (s->c == 'z' ? 1000 : 0) + (b[1] && !b[2] ? 1 : 0);\n\
+                        }",
+                "1082\n",
+            ),
+            (
+                "fib",
+                "int main() {\n\
+                          int a = 0; int b = 1;\n\
+                          for (int i = 0; i < 30; i++) { int t = a; a = b; b = t + b; }\n\
+                          return a;\n\
+                        }",
+                "832040\n",
+            ),
+            (
+                "externs",
+                "int abs(int x);\nint main() { string s = \"a\\tb\"; return abs(-5); }",
+                "5\n",
+            ),
+        ];
+        for (name, source, expected) in programs {
+            match run(name, source) {
+                Some(result) => assert_eq!(result, Ok(expected.to_string()), "{}", name),
+                None => return,
+            }
+        }
+    }
+
+    #[test]
+    fn traps() {
+        let programs = [
+            ("divide", "int main() { int x = 0; return 1 / x; }", 8),
+            (
+                "overflow",
+                "int main() { int x = -2147483647 - 1; return x % -1; }",
+                8,
+            ),
+            ("shift", "int main() { int x = 32; return 1 << x; }", 8),
+            (
+                "bounds",
+                "int main() { int[] a = alloc_array(int, 2); return a[2]; }",
+                11,
+            ),
+            ("null", "int main() { int* p = NULL; return *p; }", 11),
+            (
+                "negative",
+                "int main() { int[] a = alloc_array(int, -1); return 0; }",
+                11,
+            ),
+            ("assert", "int main() { assert(1 > 2); return 0; }", 6),
+            ("error", "int main() { error(\"no\"); return 0; }", 6),
+        ];
+        for (name, source, signal) in programs {
+            match run(name, source) {
+                Some(result) => assert_eq!(result, Err(signal), "{}", name),
+                None => return,
+            }
+        }
+    }
+}
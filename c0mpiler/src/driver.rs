@@ -0,0 +1,792 @@
+use crate::ast::Program;
+use crate::codegen::emit;
+use crate::elaborate::elaborate;
+use crate::ir;
+use crate::ir::cfg::Cfg;
+use crate::ir::lower::lower;
+use crate::parser::{ParseError, Parser};
+use crate::scanner::error::LexError;
+use crate::scanner::generated;
+use crate::scanner::span::Span;
+use crate::scanner::stream::Lexer;
+use crate::scanner::token::{Directive, LibLit, SpannedToken, StrLit, Token};
+use crate::scanner::trivia::scan_trivia;
+use crate::scanner::ScannerKind;
+use crate::semantic::flow::check_flow;
+use crate::semantic::layout::Layouts;
+use crate::semantic::resolve::{check_defined, resolve};
+use crate::semantic::typecheck::typecheck;
+use crate::semantic::SemanticError;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{stdin, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::vec;
+
+// Compiles the file at `path` into assembly, written next to it with the extension ".s".
+// `dump_elab` prints the program as the checks after elaboration see it, once they've passed, and
+// `cfg_dir` is where to write the control-flow graph of each of its functions
+pub fn run_file(
+    path: String,
+    lib_paths: Vec<PathBuf>,
+    scanner: ScannerKind,
+    dump_elab: bool,
+    cfg_dir: Option<PathBuf>,
+) -> std::io::Result<()> {
+    match Driver::new(lib_paths)
+        .scanner(scanner)
+        .load(Path::new(&path))
+        .and_then(analyze_units)
+    {
+        Ok((program, layouts)) => {
+            if dump_elab {
+                print!("{}", program);
+            }
+            let ir = lower(&program, &layouts);
+            if let Some(dir) = cfg_dir {
+                write_cfg(&dir, &ir).map_err(|error| at_path(&dir, error))?;
+            }
+            let output = Path::new(&path).with_extension("s");
+            fs::write(&output, emit(&ir)).map_err(|error| at_path(&output, error))
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("failed to compile {}", path),
+            ))
+        }
+    }
+}
+
+pub fn run_prompt(
+    lib_paths: Vec<PathBuf>,
+    scanner: ScannerKind,
+    dump_elab: bool,
+    cfg_dir: Option<PathBuf>,
+) -> std::io::Result<()> {
+    println!("Please enter the file to be compiled: ");
+
+    let path = stdin().lines().next().unwrap_or_else(|| {
+        Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "no file to compile was given",
+        ))
+    })?;
+    run_file(path, lib_paths, scanner, dump_elab, cfg_dir)
+}
+
+// Prefixes an I/O error with the path it happened at, since `std::io::Error` doesn't carry it
+fn at_path(path: &Path, error: Error) -> Error {
+    Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+// Writes the automata of the scanner generated from c0.lex into `dir`, one Graphviz file per mode
+// and per kind of token
+pub fn write_dot(dir: PathBuf) -> std::io::Result<()> {
+    fs::create_dir_all(&dir)?;
+    for (name, dot) in generated::lexer().dot_files() {
+        fs::write(dir.join(name), dot)?;
+    }
+    Ok(())
+}
+
+// Prints the tokens of the file at `path` with the whitespace and comments around each of them, as
+// tools that have to give the source back see it
+pub fn dump_trivia(path: &Path) -> std::io::Result<()> {
+    let source = fs::read_to_string(path).map_err(|error| at_path(path, error))?;
+    match scan_trivia(source) {
+        Ok(lossless) => {
+            print!("{}", lossless.listing());
+            Ok(())
+        }
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}:{}", path.display(), error);
+            }
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("failed to scan {}", path.display()),
+            ))
+        }
+    }
+}
+
+// Writes the control-flow graph of each function in `program` into `dir` as a Graphviz file named
+// after it
+pub fn write_cfg(dir: &Path, program: &ir::Program) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for function in &program.functions {
+        let dot = Cfg::new(function).to_dot();
+        fs::write(dir.join(format!("{}.dot", function.name)), dot)?;
+    }
+    Ok(())
+}
+
+// A single file pulled into the compilation. Only its directives are scanned while loading; the
+// rest is scanned as it is parsed
+#[derive(Debug)]
+pub struct SourceUnit {
+    pub path: PathBuf,
+    // Library headers (found through "#use <lib>") only declare functions that are defined
+    // elsewhere
+    pub is_header: bool,
+    pub scanner: ScannerKind,
+}
+
+// A file's tokens and lexical errors, in order. The hand-written scanner streams them from the
+// file as they're asked for, while the table-driven ones scan the whole file up front
+enum Tokens {
+    Streamed(Lexer<File>),
+    Scanned(vec::IntoIter<Result<SpannedToken, LexError>>),
+}
+
+impl Tokens {
+    fn open(path: &Path, scanner: ScannerKind) -> Result<Tokens, DriverError> {
+        let io_error = |error| DriverError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+        if scanner == ScannerKind::HandWritten {
+            let file = File::open(path).map_err(io_error)?;
+            return Ok(Tokens::Streamed(Lexer::new(file)));
+        }
+        let source = fs::read_to_string(path).map_err(io_error)?;
+        let scanned: Vec<Result<SpannedToken, LexError>> = match scanner.scan(source) {
+            Ok(tokens) => tokens.into_iter().map(Ok).collect(),
+            Err(errors) => errors.into_iter().map(Err).collect(),
+        };
+        Ok(Tokens::Scanned(scanned.into_iter()))
+    }
+
+    // Scans the rest of the file, failing with every lexical error in it along with `errors`, the
+    // ones already seen, or with whatever went wrong reading it
+    fn finish(mut self, path: &Path, mut errors: Vec<LexError>) -> Result<(), DriverError> {
+        errors.extend(self.by_ref().filter_map(Result::err));
+        if let Tokens::Streamed(lexer) = &mut self {
+            if let Some(error) = lexer.io_error() {
+                return Err(DriverError::Io {
+                    path: path.to_path_buf(),
+                    error,
+                });
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(DriverError::Lex {
+                path: path.to_path_buf(),
+                errors,
+            })
+        }
+    }
+}
+
+impl Iterator for Tokens {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Tokens::Streamed(lexer) => lexer.next(),
+            Tokens::Scanned(scanned) => scanned.next(),
+        }
+    }
+}
+
+// Hands the parser a file's tokens, setting lexical errors aside for once it is done
+struct Feed(Rc<RefCell<(Tokens, Vec<LexError>)>>);
+
+impl Iterator for Feed {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<SpannedToken> {
+        let (tokens, errors) = &mut *self.0.borrow_mut();
+        for item in tokens {
+            match item {
+                Ok(spanned) => return Some(spanned),
+                Err(error) => errors.push(error),
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum DriverError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Lex {
+        path: PathBuf,
+        errors: Vec<LexError>,
+    },
+    Parse {
+        path: PathBuf,
+        error: ParseError,
+    },
+    Semantic {
+        errors: Vec<(PathBuf, SemanticError)>,
+    },
+    // The spans below point at the "#use" directive in the `from` file
+    LibraryNotFound {
+        from: PathBuf,
+        span: Span,
+        name: String,
+    },
+    FileNotFound {
+        from: PathBuf,
+        span: Span,
+        path: PathBuf,
+    },
+    Cycle {
+        from: PathBuf,
+        span: Span,
+        cycle: Vec<PathBuf>,
+    },
+}
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriverError::Io { path, error } => {
+                write!(f, "{}: error: {}", path.display(), error)
+            }
+            DriverError::Lex { path, errors } => {
+                let lines: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{}:{}", path.display(), error))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            DriverError::Parse { path, error } => write!(f, "{}:{}", path.display(), error),
+            DriverError::Semantic { errors } => {
+                let lines: Vec<String> = errors
+                    .iter()
+                    .map(|(path, error)| format!("{}:{}", path.display(), error))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            DriverError::LibraryNotFound { from, span, name } => write!(
+                f,
+                "{}:{}:{}: error: library <{}> not found in the search path",
+                from.display(),
+                span.line,
+                span.column,
+                name
+            ),
+            DriverError::FileNotFound { from, span, path } => write!(
+                f,
+                "{}:{}:{}: error: cannot find \"{}\"",
+                from.display(),
+                span.line,
+                span.column,
+                path.display()
+            ),
+            DriverError::Cycle { from, span, cycle } => {
+                let names: Vec<String> = cycle.iter().map(|p| p.display().to_string()).collect();
+                write!(
+                    f,
+                    "{}:{}:{}: error: circular #use: {}",
+                    from.display(),
+                    span.line,
+                    span.column,
+                    names.join(" -> ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DriverError {}
+
+// Parses every file in dependency order into a single program. Typedefs carry over from one file
+// to the next, so that e.g. a type declared in a library header can be used by the main file.
+// The program's span is that of the main file, which always comes last
+#[cfg(test)]
+pub fn parse_units(units: Vec<SourceUnit>) -> Result<Program, DriverError> {
+    Ok(merge(parse_each(units)?).0)
+}
+
+// Parses every file, then checks the program as a whole. Once its names are resolved, and every
+// function it uses is known to be defined somewhere, it is elaborated, and the rest of the checks,
+// which lay out its structs on the way, see the result. Semantic errors are reported against the
+// file holding the declaration they were found in
+pub fn analyze_units(units: Vec<SourceUnit>) -> Result<(Program, Layouts), DriverError> {
+    let (program, paths, headers) = merge(parse_each(units)?);
+    let semantic = |errors: Vec<SemanticError>| DriverError::Semantic {
+        errors: errors
+            .into_iter()
+            .map(|error| (paths[error.decl].clone(), error))
+            .collect(),
+    };
+    let resolution = resolve(&program).map_err(semantic)?;
+    check_defined(&program, &headers, &resolution).map_err(semantic)?;
+    let mut program = elaborate(program);
+    let layouts = typecheck(&mut program).map_err(semantic)?;
+    check_flow(&program).map_err(semantic)?;
+    Ok((program, layouts))
+}
+
+fn parse_each(units: Vec<SourceUnit>) -> Result<Vec<(SourceUnit, Program)>, DriverError> {
+    let mut programs = Vec::new();
+    let mut typedefs = HashSet::new();
+    for unit in units {
+        let tokens = Tokens::open(&unit.path, unit.scanner)?;
+        let feed = Rc::new(RefCell::new((tokens, Vec::new())));
+        let mut parser = Parser::with_typedefs(Feed(feed.clone()), typedefs);
+        let parsed = parser.parse_program();
+        typedefs = parser.typedefs();
+        // Lexical errors come first, since they're likely what upset the parser
+        let (tokens, errors) = Rc::into_inner(feed).unwrap().into_inner();
+        tokens.finish(&unit.path, errors)?;
+        match parsed {
+            Ok(program) => programs.push((unit, program)),
+            Err(error) => {
+                return Err(DriverError::Parse {
+                    path: unit.path,
+                    error,
+                })
+            }
+        }
+    }
+    Ok(programs)
+}
+
+// Joins the files' declarations into one program, along with the file each declaration came from
+// and whether that file is a library header
+fn merge(programs: Vec<(SourceUnit, Program)>) -> (Program, Vec<PathBuf>, Vec<bool>) {
+    let mut decls = Vec::new();
+    let mut paths = Vec::new();
+    let mut headers = Vec::new();
+    let mut span = Span::default();
+    for (unit, program) in programs {
+        paths.extend(std::iter::repeat_n(unit.path, program.decls.len()));
+        headers.extend(std::iter::repeat_n(unit.is_header, program.decls.len()));
+        decls.extend(program.decls);
+        span = program.span;
+    }
+    (Program { decls, span }, paths, headers)
+}
+
+// Resolves "#use" directives, starting from the file being compiled
+//
+// Every file is loaded once, no matter how many times it is used ("include-once"), and files
+// come back in dependency order, so a file's declarations always precede the files using them.
+// Since directives have to come first, only the start of each file is scanned here
+pub struct Driver {
+    // Directories searched, in order, for "<lib>.h0" headers
+    lib_paths: Vec<PathBuf>,
+    units: Vec<SourceUnit>,
+    // Canonical paths of every file already loaded
+    loaded: HashSet<PathBuf>,
+    // Canonical paths of the chain of files currently being loaded, for detecting cycles
+    loading: Vec<PathBuf>,
+    scanner: ScannerKind,
+}
+
+impl Driver {
+    pub fn new(lib_paths: Vec<PathBuf>) -> Driver {
+        Driver {
+            lib_paths,
+            units: Vec::new(),
+            loaded: HashSet::new(),
+            loading: Vec::new(),
+            scanner: ScannerKind::default(),
+        }
+    }
+
+    pub fn scanner(mut self, scanner: ScannerKind) -> Driver {
+        self.scanner = scanner;
+        self
+    }
+
+    pub fn load(mut self, path: &Path) -> Result<Vec<SourceUnit>, DriverError> {
+        let canonical = fs::canonicalize(path).map_err(|error| DriverError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        self.include(path.to_path_buf(), canonical, false)?;
+        Ok(self.units)
+    }
+
+    fn find_library(&self, name: &str) -> Option<PathBuf> {
+        self.lib_paths
+            .iter()
+            .map(|dir| dir.join(format!("{}.h0", name)))
+            .find(|header| header.is_file())
+    }
+
+    fn include(
+        &mut self,
+        path: PathBuf,
+        canonical: PathBuf,
+        is_header: bool,
+    ) -> Result<(), DriverError> {
+        let mut tokens = Tokens::open(&path, self.scanner)?;
+        let mut directives = Vec::new();
+        for item in tokens.by_ref() {
+            match item {
+                Ok(spanned) if matches!(spanned.token, Token::Directive(_)) => {
+                    directives.push(spanned)
+                }
+                Ok(_) => break,
+                Err(error) => return tokens.finish(&path, vec![error]),
+            }
+        }
+        if let Tokens::Streamed(lexer) = &mut tokens {
+            if let Some(error) = lexer.io_error() {
+                return Err(DriverError::Io { path, error });
+            }
+        }
+
+        self.loading.push(canonical.clone());
+        for spanned in &directives {
+            let (used, used_is_header) = match &spanned.token {
+                Token::Directive(Directive::UseLib(LibLit::LibraryLiteral(name))) => {
+                    match self.find_library(name) {
+                        Some(header) => (header, true),
+                        None => {
+                            return Err(DriverError::LibraryNotFound {
+                                from: path,
+                                span: spanned.span,
+                                name: name.clone(),
+                            })
+                        }
+                    }
+                }
+                // Relative to the directory of the file doing the including
+                Token::Directive(Directive::UseFile(StrLit::StringLiteral(file))) => {
+                    let dir = path.parent().unwrap_or(Path::new(""));
+                    (dir.join(file), false)
+                }
+                _ => continue,
+            };
+
+            let used_canonical = match fs::canonicalize(&used) {
+                Ok(used_canonical) => used_canonical,
+                Err(_) => {
+                    return Err(DriverError::FileNotFound {
+                        from: path,
+                        span: spanned.span,
+                        path: used,
+                    })
+                }
+            };
+            if let Some(start) = self.loading.iter().position(|p| *p == used_canonical) {
+                let mut cycle = self.loading[start..].to_vec();
+                cycle.push(used_canonical);
+                return Err(DriverError::Cycle {
+                    from: path,
+                    span: spanned.span,
+                    cycle,
+                });
+            }
+            if !self.loaded.contains(&used_canonical) {
+                self.include(used, used_canonical, used_is_header)?;
+            }
+        }
+        self.loading.pop();
+
+        self.loaded.insert(canonical);
+        self.units.push(SourceUnit {
+            path,
+            is_header,
+            scanner: self.scanner,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test gets its own scratch directory under the system temp dir
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("c0mpiler-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn file_names(units: &[SourceUnit]) -> Vec<String> {
+        units
+            .iter()
+            .map(|unit| unit.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn libraries_and_files() {
+        let dir = temp_dir("libraries_and_files");
+        write(&dir, "lib/conio.h0", "void print(string s);");
+        write(&dir, "src/util/queue.c0", "#use <conio>\nint size;");
+        let main = write(
+            &dir,
+            "src/main.c0",
+            "#use <conio>\n#use \"util/queue.c0\"\nint main() { return 0; }",
+        );
+
+        let units = Driver::new(vec![dir.join("lib")]).load(&main).unwrap();
+
+        // dependencies first, and conio only once even though it is used twice
+        assert_eq!(file_names(&units), vec!["conio.h0", "queue.c0", "main.c0"]);
+        assert_eq!(
+            units.iter().map(|unit| unit.is_header).collect::<Vec<_>>(),
+            vec![true, false, false]
+        );
+    }
+
+    #[test]
+    fn search_path_order() {
+        let dir = temp_dir("search_path_order");
+        write(&dir, "first/args.h0", "int first;");
+        write(&dir, "second/args.h0", "int second;");
+        write(&dir, "second/img.h0", "int img;");
+        let main = write(&dir, "main.c0", "#use <args>\n#use <img>\n");
+
+        let lib_paths = vec![dir.join("first"), dir.join("second")];
+        let units = Driver::new(lib_paths).load(&main).unwrap();
+
+        assert!(units[0].path.starts_with(dir.join("first")));
+        assert!(units[1].path.starts_with(dir.join("second")));
+    }
+
+    #[test]
+    fn missing_library() {
+        let dir = temp_dir("missing_library");
+        write(&dir, "conio.h0", "void print(string s);");
+        let main = write(&dir, "main.c0", "#use <conio>\n#use <nonexistent>\n");
+
+        match Driver::new(vec![dir.clone()]).load(&main) {
+            Err(DriverError::LibraryNotFound { name, span, .. }) => {
+                assert_eq!(name, "nonexistent");
+                assert_eq!((span.line, span.column), (2, 1));
+            }
+            other => panic!("expected a missing library, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_file() {
+        let dir = temp_dir("missing_file");
+        let main = write(&dir, "main.c0", "#use \"nope.c0\"\n");
+
+        match Driver::new(vec![]).load(&main) {
+            Err(DriverError::FileNotFound { path, .. }) => assert!(path.ends_with("nope.c0")),
+            other => panic!("expected a missing file, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn typedefs_across_files() {
+        let dir = temp_dir("typedefs_across_files");
+        write(
+            &dir,
+            "lib/pair.h0",
+            "typedef struct pair* pair_t; pair_t make();",
+        );
+        let main = write(
+            &dir,
+            "main.c0",
+            "#use <pair>\nint main() { pair_t * p; return 0; }",
+        );
+
+        let units = Driver::new(vec![dir.join("lib")]).load(&main).unwrap();
+        let program = parse_units(units).unwrap();
+        assert_eq!(program.decls.len(), 3);
+    }
+
+    #[test]
+    fn lex_errors() {
+        let dir = temp_dir("lex_errors");
+        let early = write(&dir, "early.c0", "#use <conio> $\nint x;");
+        let late = write(&dir, "late.c0", "int x;\n string s = \"\\q\";\n int z = $;");
+
+        // an error among the directives stops loading, one after them is found by parsing
+        assert!(matches!(
+            Driver::new(vec![]).load(&early),
+            Err(DriverError::Lex { .. })
+        ));
+        let units = Driver::new(vec![]).load(&late).unwrap();
+        match parse_units(units) {
+            Err(DriverError::Lex { errors, .. }) => {
+                let lines: Vec<usize> = errors.iter().map(|error| error.span.line).collect();
+                assert_eq!(lines, vec![2, 3]);
+            }
+            other => panic!("expected lexical errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn scanner_kinds() {
+        let dir = temp_dir("scanner_kinds");
+        write(&dir, "lib/pair.h0", "typedef struct pair* pair_t;");
+        let main = write(
+            &dir,
+            "main.c0",
+            "#use <pair>\n\n int main() {\n  pair_t p = NULL;\n  return 0;\n }\n",
+        );
+
+        let parsed: Vec<Program> = [
+            ScannerKind::HandWritten,
+            ScannerKind::Table,
+            ScannerKind::Generated,
+        ]
+        .into_iter()
+        .map(|scanner| {
+            let driver = Driver::new(vec![dir.join("lib")]).scanner(scanner);
+            parse_units(driver.load(&main).unwrap()).unwrap()
+        })
+        .collect();
+        assert_eq!(parsed[0], parsed[1]);
+        assert_eq!(parsed[0], parsed[2]);
+    }
+
+    #[test]
+    fn semantic_errors() {
+        let dir = temp_dir("semantic_errors");
+        write(&dir, "lib/io.h0", "void print(string s);");
+        let main = write(
+            &dir,
+            "main.c0",
+            "#use <io>\nint main() {\n  print(\"hi\");\n  return y;\n}",
+        );
+
+        let units = Driver::new(vec![dir.join("lib")]).load(&main).unwrap();
+        match analyze_units(units) {
+            Err(error @ DriverError::Semantic { .. }) => {
+                let message = error.to_string();
+                let lines: Vec<&str> = message.lines().collect();
+                assert_eq!(lines.len(), 1);
+                assert!(lines[0].ends_with("main.c0:4:10: error: undeclared variable `y`"));
+            }
+            other => panic!("expected semantic errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn length_outside_contracts() {
+        let dir = temp_dir("length_outside_contracts");
+        let main = write(
+            &dir,
+            "main.c0",
+            "int sum(int[] A, int n)\n//@requires \\length(A) == n;\n{\n  return \\length(A);\n}\n\
+             int main() { return 0; }",
+        );
+
+        // `\length` is only a word inside annotations, so the one in the body is a stray backslash
+        for scanner in [
+            ScannerKind::HandWritten,
+            ScannerKind::Table,
+            ScannerKind::Generated,
+        ] {
+            match Driver::new(vec![])
+                .scanner(scanner)
+                .load(&main)
+                .and_then(analyze_units)
+            {
+                Err(error @ DriverError::Lex { .. }) => {
+                    assert!(error
+                        .to_string()
+                        .ends_with("main.c0:4:10: error: unrecognizable character `\\\\`"));
+                    assert_eq!(error.to_string().lines().count(), 1);
+                }
+                other => panic!("expected lexical errors, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn undefined_functions() {
+        let dir = temp_dir("undefined_functions");
+        write(&dir, "lib/io.h0", "void print(string s);");
+        let main = write(
+            &dir,
+            "main.c0",
+            "#use <io>\nint twice(int x);\nint main() {\n  print(\"hi\");\n  return twice(3);\n}",
+        );
+
+        // print comes from the library, but nothing defines twice
+        let units = Driver::new(vec![dir.join("lib")]).load(&main).unwrap();
+        match analyze_units(units) {
+            Err(error @ DriverError::Semantic { .. }) => {
+                let message = error.to_string();
+                assert!(message
+                    .ends_with("main.c0:2:1: error: function `twice` is used but never defined"));
+                assert_eq!(message.lines().count(), 1);
+            }
+            other => panic!("expected semantic errors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cfg_files() {
+        let dir = temp_dir("cfg_files");
+        let main = write(
+            &dir,
+            "main.c0",
+            "int twice(int x) { return 2 * x; }\nint main() { return twice(3); }",
+        );
+        let units = Driver::new(vec![]).load(&main).unwrap();
+        let (program, layouts) = analyze_units(units).unwrap();
+        write_cfg(&dir.join("cfg"), &lower(&program, &layouts)).unwrap();
+        let twice = fs::read_to_string(dir.join("cfg/twice.dot")).unwrap();
+        assert!(twice.starts_with("digraph \"twice\" {"));
+        assert!(twice.contains("t1 = mul 2, t0"));
+        assert!(dir.join("cfg/main.dot").exists());
+    }
+
+    #[test]
+    fn assembly_file() {
+        let dir = temp_dir("assembly_file");
+        let main = write(&dir, "main.c0", "int main() { return 7; }");
+        run_file(
+            main.to_string_lossy().to_string(),
+            vec![],
+            ScannerKind::HandWritten,
+            false,
+            None,
+        )
+        .unwrap();
+        let asm = fs::read_to_string(dir.join("main.s")).unwrap();
+        assert!(asm.contains("_c0_main:\n"));
+        assert!(asm.contains("    movq $7, %rax\n"));
+    }
+
+    #[test]
+    fn cycles() {
+        let dir = temp_dir("cycles");
+        let a = write(&dir, "a.c0", "#use \"b.c0\"\n");
+        write(&dir, "b.c0", "#use \"c.c0\"\n");
+        write(&dir, "c.c0", "#use \"a.c0\"\n");
+        let own = write(&dir, "own.c0", "#use \"./own.c0\"\n");
+
+        match Driver::new(vec![]).load(&a) {
+            Err(DriverError::Cycle { from, cycle, .. }) => {
+                assert!(from.ends_with("c.c0"));
+                let names: Vec<String> = cycle
+                    .iter()
+                    .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+                    .collect();
+                assert_eq!(names, vec!["a.c0", "b.c0", "c.c0", "a.c0"]);
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        assert!(matches!(
+            Driver::new(vec![]).load(&own),
+            Err(DriverError::Cycle { .. })
+        ));
+    }
+}
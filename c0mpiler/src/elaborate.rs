@@ -0,0 +1,513 @@
+use crate::ast::{
+    BinaryOp, Decl, DeclKind, Expr, ExprKind, LValue, LValueKind, PostfixOp, Program, Spec,
+    SpecKind, Stmt, StmtKind, Type, TypeKind, UnaryOp,
+};
+use crate::scanner::span::Span;
+
+// Elaboration: rewrites the program into a smaller core of the language, so that the passes after
+// it have fewer constructs to deal with. It runs once names are resolved, before typechecking
+//
+// The core has no for loops, compound assignments, "++" or "--", arrows, "&&" or "||":
+//   for (init; cond; step) body      { init; while (cond) { body; step; } }
+//   lv op= e                         lv = lv op e
+//   lv++, lv--                       lv = lv + 1, lv = lv - 1
+//   e->f                             (*e).f
+//   a && b                           !a ? false : b
+//   a || b                           a ? true : b
+// A "continue" in a for loop's body runs the step before going round again. An lvalue updated in
+// place is only evaluated once: any index in it that could have side effects is stored in a
+// temporary first. Indices are always ints, so that's the type of every temporary
+//
+// New nodes take the span of the construct they came from, so that diagnostics still point at
+// what was written. The `&&` rewrite puts `b` in the else branch so that a type error in it comes
+// out the same way round as in `a || b`
+pub fn elaborate(program: Program) -> Program {
+    let mut elaborator = Elaborator::default();
+    let decls = program
+        .decls
+        .into_iter()
+        .map(|decl| elaborator.decl(decl))
+        .collect();
+    Program {
+        decls,
+        span: program.span,
+    }
+}
+
+#[derive(Default)]
+struct Elaborator {
+    temps: usize,
+}
+
+fn stmt(kind: StmtKind, span: Span) -> Stmt {
+    Stmt { kind, span }
+}
+
+// The value an lvalue holds
+fn read(lv: &LValue) -> Expr {
+    let kind = match &lv.kind {
+        LValueKind::Var(name) => ExprKind::Var(name.clone()),
+        LValueKind::Field { base, field } => ExprKind::Field {
+            base: Box::new(read(base)),
+            field: field.clone(),
+        },
+        LValueKind::Arrow { base, field } => ExprKind::Arrow {
+            base: Box::new(read(base)),
+            field: field.clone(),
+        },
+        LValueKind::Deref(base) => ExprKind::Deref(Box::new(read(base))),
+        LValueKind::Index { base, index } => ExprKind::Index {
+            base: Box::new(read(base)),
+            index: Box::new(index.clone()),
+        },
+    };
+    Expr::new(kind, lv.span)
+}
+
+// Puts `step` before every "continue" of the loop `body` belongs to, leaving inner loops alone
+fn continue_with(body: &mut Stmt, step: &Stmt) {
+    match &mut body.kind {
+        StmtKind::Continue => {
+            let kind = StmtKind::Block(vec![step.clone(), stmt(StmtKind::Continue, body.span)]);
+            body.kind = kind;
+        }
+        StmtKind::If { then, els, .. } => {
+            continue_with(then, step);
+            if let Some(els) = els {
+                continue_with(els, step);
+            }
+        }
+        StmtKind::Block(stmts) => {
+            for inner in stmts {
+                continue_with(inner, step);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl Elaborator {
+    // Temporaries are named "$t0", "$t1" and so on, which no identifier can clash with
+    fn temp(&mut self) -> String {
+        let name = format!("$t{}", self.temps);
+        self.temps += 1;
+        name
+    }
+
+    fn decl(&mut self, decl: Decl) -> Decl {
+        let kind = match decl.kind {
+            DeclKind::Function {
+                ret,
+                name,
+                params,
+                specs,
+                body,
+            } => DeclKind::Function {
+                ret,
+                name,
+                params,
+                specs: specs.into_iter().map(|spec| self.spec(spec)).collect(),
+                body: body.map(|body| self.stmts(body)),
+            },
+            kind => kind,
+        };
+        Decl {
+            kind,
+            span: decl.span,
+        }
+    }
+
+    fn spec(&mut self, spec: Spec) -> Spec {
+        let kind = match spec.kind {
+            SpecKind::Requires(e) => SpecKind::Requires(self.expr(e)),
+            SpecKind::Ensures(e) => SpecKind::Ensures(self.expr(e)),
+            SpecKind::LoopInvariant(e) => SpecKind::LoopInvariant(self.expr(e)),
+            SpecKind::Assert(e) => SpecKind::Assert(self.expr(e)),
+        };
+        Spec {
+            kind,
+            span: spec.span,
+        }
+    }
+
+    fn stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().map(|s| self.stmt(s)).collect()
+    }
+
+    fn boxed(&mut self, s: Stmt) -> Box<Stmt> {
+        Box::new(self.stmt(s))
+    }
+
+    fn stmt(&mut self, s: Stmt) -> Stmt {
+        let span = s.span;
+        let kind = match s.kind {
+            StmtKind::Decl { ty, name, init } => StmtKind::Decl {
+                ty,
+                name,
+                init: init.map(|init| self.expr(init)),
+            },
+            StmtKind::Assign { lhs, op: None, rhs } => StmtKind::Assign {
+                lhs: self.lvalue(lhs),
+                op: None,
+                rhs: self.expr(rhs),
+            },
+            StmtKind::Assign {
+                lhs,
+                op: Some(op),
+                rhs,
+            } => return self.update(lhs, op, rhs, span),
+            StmtKind::PostOp { lhs, op } => {
+                let op = match op {
+                    PostfixOp::Inc => BinaryOp::Add,
+                    PostfixOp::Dec => BinaryOp::Sub,
+                };
+                return self.update(lhs, op, Expr::new(ExprKind::Int(1), span), span);
+            }
+            StmtKind::Expr(e) => StmtKind::Expr(self.expr(e)),
+            StmtKind::If { cond, then, els } => StmtKind::If {
+                cond: self.expr(cond),
+                then: self.boxed(*then),
+                els: els.map(|els| self.boxed(*els)),
+            },
+            StmtKind::While {
+                cond,
+                invariants,
+                body,
+            } => StmtKind::While {
+                cond: self.expr(cond),
+                invariants: invariants.into_iter().map(|spec| self.spec(spec)).collect(),
+                body: self.boxed(*body),
+            },
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                invariants,
+                body,
+            } => {
+                let init = init.map(|init| self.stmt(*init));
+                let cond = self.expr(cond);
+                let step = step.map(|step| self.stmt(*step));
+                let invariants = invariants.into_iter().map(|spec| self.spec(spec)).collect();
+                let mut body = self.stmt(*body);
+                if let Some(step) = step {
+                    continue_with(&mut body, &step);
+                    body = stmt(StmtKind::Block(vec![body, step]), span);
+                }
+                let looped = StmtKind::While {
+                    cond,
+                    invariants,
+                    body: Box::new(body),
+                };
+                StmtKind::Block(init.into_iter().chain([stmt(looped, span)]).collect())
+            }
+            StmtKind::Return(e) => StmtKind::Return(e.map(|e| self.expr(e))),
+            StmtKind::Block(stmts) => StmtKind::Block(self.stmts(stmts)),
+            StmtKind::Assert(e) => StmtKind::Assert(self.expr(e)),
+            StmtKind::Error(e) => StmtKind::Error(self.expr(e)),
+            StmtKind::Annotation(specs) => {
+                StmtKind::Annotation(specs.into_iter().map(|spec| self.spec(spec)).collect())
+            }
+            kind @ (StmtKind::Break | StmtKind::Continue) => kind,
+        };
+        stmt(kind, span)
+    }
+
+    // "lhs = lhs op rhs", with `lhs` only evaluated once
+    fn update(&mut self, lhs: LValue, op: BinaryOp, rhs: Expr, span: Span) -> Stmt {
+        let mut temps = Vec::new();
+        let lhs = self.lvalue(lhs);
+        let lhs = self.hoist(lhs, &mut temps);
+        let rhs = self.expr(rhs);
+        let value = ExprKind::Binary {
+            op,
+            lhs: Box::new(read(&lhs)),
+            rhs: Box::new(rhs),
+        };
+        let assign = StmtKind::Assign {
+            lhs,
+            op: None,
+            rhs: Expr::new(value, span),
+        };
+        if temps.is_empty() {
+            return stmt(assign, span);
+        }
+        temps.push(stmt(assign, span));
+        stmt(StmtKind::Block(temps), span)
+    }
+
+    // Moves every index of `lv` that isn't a variable or a constant into a temporary, declared in
+    // `temps`, in the order they're evaluated
+    fn hoist(&mut self, lv: LValue, temps: &mut Vec<Stmt>) -> LValue {
+        let kind = match lv.kind {
+            LValueKind::Var(_) => lv.kind,
+            LValueKind::Field { base, field } => LValueKind::Field {
+                base: Box::new(self.hoist(*base, temps)),
+                field,
+            },
+            LValueKind::Arrow { base, field } => LValueKind::Arrow {
+                base: Box::new(self.hoist(*base, temps)),
+                field,
+            },
+            LValueKind::Deref(base) => LValueKind::Deref(Box::new(self.hoist(*base, temps))),
+            LValueKind::Index { base, index } => {
+                let base = Box::new(self.hoist(*base, temps));
+                let index = match index.kind {
+                    ExprKind::Var(_) | ExprKind::Int(_) => index,
+                    _ => {
+                        let name = self.temp();
+                        let span = index.span;
+                        let ty = Type {
+                            kind: TypeKind::Int,
+                            span,
+                        };
+                        let decl = StmtKind::Decl {
+                            ty,
+                            name: name.clone(),
+                            init: Some(index),
+                        };
+                        temps.push(stmt(decl, span));
+                        Expr::new(ExprKind::Var(name), span)
+                    }
+                };
+                LValueKind::Index { base, index }
+            }
+        };
+        LValue::new(kind, lv.span)
+    }
+
+    fn lvalue(&mut self, lv: LValue) -> LValue {
+        let kind = match lv.kind {
+            LValueKind::Var(name) => LValueKind::Var(name),
+            LValueKind::Field { base, field } => LValueKind::Field {
+                base: Box::new(self.lvalue(*base)),
+                field,
+            },
+            LValueKind::Arrow { base, field } => {
+                let base = LValueKind::Deref(Box::new(self.lvalue(*base)));
+                LValueKind::Field {
+                    base: Box::new(LValue::new(base, lv.span)),
+                    field,
+                }
+            }
+            LValueKind::Deref(base) => LValueKind::Deref(Box::new(self.lvalue(*base))),
+            LValueKind::Index { base, index } => LValueKind::Index {
+                base: Box::new(self.lvalue(*base)),
+                index: self.expr(index),
+            },
+        };
+        LValue::new(kind, lv.span)
+    }
+
+    fn boxed_expr(&mut self, e: Expr) -> Box<Expr> {
+        Box::new(self.expr(e))
+    }
+
+    fn expr(&mut self, e: Expr) -> Expr {
+        let span = e.span;
+        let kind = match e.kind {
+            ExprKind::Binary {
+                op: op @ (BinaryOp::LogicalAnd | BinaryOp::LogicalOr),
+                lhs,
+                rhs,
+            } => {
+                let lhs = self.expr(*lhs);
+                let rhs = self.boxed_expr(*rhs);
+                let short = Box::new(Expr::new(ExprKind::Bool(op == BinaryOp::LogicalOr), span));
+                let cond = match op {
+                    BinaryOp::LogicalAnd => {
+                        let lhs_span = lhs.span;
+                        let not = ExprKind::Unary {
+                            op: UnaryOp::Not,
+                            operand: Box::new(lhs),
+                        };
+                        Expr::new(not, lhs_span)
+                    }
+                    _ => lhs,
+                };
+                ExprKind::Ternary {
+                    cond: Box::new(cond),
+                    then: short,
+                    els: rhs,
+                }
+            }
+            ExprKind::Binary { op, lhs, rhs } => ExprKind::Binary {
+                op,
+                lhs: self.boxed_expr(*lhs),
+                rhs: self.boxed_expr(*rhs),
+            },
+            ExprKind::Unary { op, operand } => ExprKind::Unary {
+                op,
+                operand: self.boxed_expr(*operand),
+            },
+            ExprKind::Ternary { cond, then, els } => ExprKind::Ternary {
+                cond: self.boxed_expr(*cond),
+                then: self.boxed_expr(*then),
+                els: self.boxed_expr(*els),
+            },
+            ExprKind::Call { name, args } => ExprKind::Call {
+                name,
+                args: args.into_iter().map(|arg| self.expr(arg)).collect(),
+            },
+            ExprKind::Field { base, field } => ExprKind::Field {
+                base: self.boxed_expr(*base),
+                field,
+            },
+            ExprKind::Arrow { base, field } => {
+                let base = Expr::new(ExprKind::Deref(self.boxed_expr(*base)), span);
+                ExprKind::Field {
+                    base: Box::new(base),
+                    field,
+                }
+            }
+            ExprKind::Deref(base) => ExprKind::Deref(self.boxed_expr(*base)),
+            ExprKind::Index { base, index } => ExprKind::Index {
+                base: self.boxed_expr(*base),
+                index: self.boxed_expr(*index),
+            },
+            ExprKind::AllocArray { ty, len } => ExprKind::AllocArray {
+                ty,
+                len: self.boxed_expr(*len),
+            },
+            ExprKind::Length(base) => ExprKind::Length(self.boxed_expr(*base)),
+            kind @ (ExprKind::Int(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Char(_)
+            | ExprKind::String(_)
+            | ExprKind::Null
+            | ExprKind::Var(_)
+            | ExprKind::Alloc(_)
+            | ExprKind::Result) => kind,
+        };
+        Expr::new(kind, span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+    use crate::semantic::flow::check_flow;
+    use crate::semantic::typecheck::typecheck;
+    use crate::semantic::SemanticError;
+
+    fn elaborated(source: &str) -> Program {
+        elaborate(parse(scan(source.to_string()).unwrap()).unwrap())
+    }
+
+    // The body of the only function, printed
+    fn body(source: &str) -> String {
+        let printed = elaborated(source).to_string();
+        let start = printed.find('{').unwrap();
+        printed[start..].to_string()
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            body(
+                "void f(int n) {\n\
+                   for (int i = 0; i < n; i++)\n\
+                   //@loop_invariant i >= 0;\n\
+                   {\n\
+                     if (i == 2) continue;\n\
+                     while (true) { continue; }\n\
+                   }\n\
+                 }"
+            ),
+            "{\n\
+             \x20   {\n\
+             \x20       int i = 0;\n\
+             \x20       while ((i < n))\n\
+             \x20       //@loop_invariant (i >= 0);\n\
+             \x20       {\n\
+             \x20           {\n\
+             \x20               if ((i == 2)) {\n\
+             \x20                   i = (i + 1);\n\
+             \x20                   continue;\n\
+             \x20               }\n\
+             \x20               while (true) {\n\
+             \x20                   continue;\n\
+             \x20               }\n\
+             \x20           }\n\
+             \x20           i = (i + 1);\n\
+             \x20       }\n\
+             \x20   }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn updates() {
+        assert_eq!(
+            body("void f(int[] a, int* p, int i) { a[i] += 2; *p -= 1; i--; }"),
+            "{\n\
+             \x20   a[i] = (a[i] + 2);\n\
+             \x20   (*p) = ((*p) - 1);\n\
+             \x20   i = (i - 1);\n\
+             }\n"
+        );
+        // an index with side effects is only evaluated once, before the right-hand side
+        assert_eq!(
+            body("void f(int[][] a, int i) { a[g(i)][i * 2] <<= g(0); a[g(1)][0]++; }"),
+            "{\n\
+             \x20   {\n\
+             \x20       int $t0 = g(i);\n\
+             \x20       int $t1 = (i * 2);\n\
+             \x20       a[$t0][$t1] = (a[$t0][$t1] << g(0));\n\
+             \x20   }\n\
+             \x20   {\n\
+             \x20       int $t2 = g(1);\n\
+             \x20       a[$t2][0] = (a[$t2][0] + 1);\n\
+             \x20   }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn core_expressions() {
+        assert_eq!(
+            body(
+                "bool f(struct s* p, bool a, bool b) { p->next->x = 1; return a && p->ok || !b; }"
+            ),
+            "{\n\
+             \x20   (*(*p).next).x = 1;\n\
+             \x20   return (((!a) ? false : (*p).ok) ? true : (!b));\n\
+             }\n"
+        );
+    }
+
+    // The passes after elaboration report the same problems in the same places
+    #[test]
+    fn diagnostics() {
+        let errors = |source: &str| -> Vec<String> {
+            let mut program = elaborated(source);
+            let errors = match typecheck(&mut program) {
+                Ok(_) => check_flow(&program).err().unwrap_or_default(),
+                Err(errors) => errors,
+            };
+            errors.iter().map(SemanticError::to_string).collect()
+        };
+        assert_eq!(
+            errors("bool f(int x) { return x && true || false && 1; }"),
+            vec![
+                "1:24: error: expected `bool`, found `int`",
+                "1:46: error: expected `bool`, found `int`"
+            ]
+        );
+        assert_eq!(
+            errors("int f(int* p) { p += 1; return p->x; }"),
+            vec![
+                "1:17: error: expected `int`, found `int*`",
+                "1:32: error: expected a struct, found `int`"
+            ]
+        );
+        assert_eq!(
+            errors(
+                "void f(bool b) {\n int x;\n for (int i = 0; i < 3; x++) {\n if (b) continue;\n x = 1;\n }\n }"
+            ),
+            vec!["3:25: error: variable `x` may be used uninitialized, declared on line 2"]
+        );
+    }
+}
@@ -0,0 +1,513 @@
+use crate::ir::{Function, Instr, Label, Operand, Temp};
+use crate::lexgen::dfa::escape;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+// A function's control-flow graph: its instructions split into basic blocks, straight-line code
+// that is only entered at the top and only left at the bottom, with an edge wherever control can
+// go from one block to another
+//
+// Every block starts with a label and ends in a jump, branch, return or raise. Code that falls
+// into a label gets a jump to it, a block with no label gets a fresh one, and falling off the end
+// of the function returns. Blocks that can't be reached from the entry are dropped, and the entry
+// never has predecessors, getting a block of its own in front if it would, so that there's always
+// somewhere for what holds on entry to the function
+#[derive(PartialEq, Debug, Clone)]
+pub struct Block {
+    pub label: Label,
+    // Without the label, ending in the terminator
+    pub body: Vec<Instr>,
+}
+
+impl Block {
+    pub fn terminator(&self) -> &Instr {
+        self.body.last().expect("a block ends in a terminator")
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Cfg {
+    pub name: String,
+    pub params: Vec<Temp>,
+    // The entry first, then the rest in the order they were written
+    pub blocks: Vec<Block>,
+    pub succs: Vec<Vec<usize>>,
+    pub preds: Vec<Vec<usize>>,
+}
+
+// Block `a` dominates `b` if every path from the entry to `b` goes through `a`. The immediate
+// dominator of a block is the closest of the others that do, which makes them a tree under the
+// entry. A block's dominance frontier is where its dominance stops: the blocks it doesn't strictly
+// dominate but does dominate a predecessor of
+#[derive(PartialEq, Debug, Clone)]
+pub struct Dominators {
+    // None for the entry
+    pub idom: Vec<Option<usize>>,
+    pub children: Vec<Vec<usize>>,
+    pub frontiers: Vec<Vec<usize>>,
+}
+
+impl Dominators {
+    #[cfg(test)]
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom[b] {
+                Some(up) => b = up,
+                None => return false,
+            }
+        }
+    }
+}
+
+// The temporaries that may still be read, without being written first, on the way into and out
+// of each block. A phi reads its operand on the edge it comes in by, so at the end of that
+// predecessor rather than at the start of its own block
+#[derive(PartialEq, Debug, Clone)]
+pub struct Liveness {
+    pub live_in: Vec<BTreeSet<Temp>>,
+    pub live_out: Vec<BTreeSet<Temp>>,
+}
+
+// Splits a function's body into blocks, in order
+fn partition(function: &Function) -> Vec<Block> {
+    let mut next_label = function.next_label();
+    let mut fresh = || {
+        next_label += 1;
+        Label(next_label - 1)
+    };
+    let mut blocks = Vec::new();
+    let mut current: Option<Block> = None;
+    for instr in &function.body {
+        if let Instr::Label(label) = instr {
+            if let Some(mut block) = current.take() {
+                block.body.push(Instr::Jump(*label));
+                blocks.push(block);
+            }
+            current = Some(Block {
+                label: *label,
+                body: Vec::new(),
+            });
+            continue;
+        }
+        let block = current.get_or_insert_with(|| Block {
+            label: fresh(),
+            body: Vec::new(),
+        });
+        block.body.push(instr.clone());
+        if instr.is_terminator() {
+            blocks.extend(current.take());
+        }
+    }
+    if let Some(mut block) = current {
+        block.body.push(Instr::Return(None));
+        blocks.push(block);
+    }
+    if blocks.is_empty() {
+        blocks.push(Block {
+            label: fresh(),
+            body: vec![Instr::Return(None)],
+        });
+    }
+    let entry = blocks[0].label;
+    if blocks
+        .iter()
+        .any(|block| block.terminator().targets().contains(&entry))
+    {
+        let block = Block {
+            label: fresh(),
+            body: vec![Instr::Jump(entry)],
+        };
+        blocks.insert(0, block);
+    }
+    blocks
+}
+
+impl Cfg {
+    pub fn new(function: &Function) -> Cfg {
+        let mut cfg = Cfg {
+            name: function.name.clone(),
+            params: function.params.clone(),
+            blocks: partition(function),
+            succs: Vec::new(),
+            preds: Vec::new(),
+        };
+        cfg.connect();
+        let reachable: BTreeSet<usize> = cfg.reverse_postorder().into_iter().collect();
+        if reachable.len() < cfg.blocks.len() {
+            let blocks = std::mem::take(&mut cfg.blocks);
+            cfg.blocks = blocks
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| reachable.contains(index))
+                .map(|(_, block)| block)
+                .collect();
+            cfg.connect();
+        }
+        cfg
+    }
+
+    pub fn index(&self, label: Label) -> Option<usize> {
+        self.blocks.iter().position(|block| block.label == label)
+    }
+
+    // Works out the edges again from the blocks' terminators, for when they've been changed
+    pub fn connect(&mut self) {
+        let indices: HashMap<Label, usize> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.label, index))
+            .collect();
+        self.succs = vec![Vec::new(); self.blocks.len()];
+        self.preds = vec![Vec::new(); self.blocks.len()];
+        for (from, block) in self.blocks.iter().enumerate() {
+            for label in block.terminator().targets() {
+                let Some(&to) = indices.get(&label) else {
+                    panic!("jump to {}, which isn't in `{}`", label, self.name)
+                };
+                if !self.succs[from].contains(&to) {
+                    self.succs[from].push(to);
+                    self.preds[to].push(from);
+                }
+            }
+        }
+    }
+
+    // The blocks reachable from the entry, each one before all of its successors other than
+    // along a back edge
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::new();
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            match self.succs[block].get(next) {
+                Some(&succ) => {
+                    stack.push((block, next + 1));
+                    if !visited[succ] {
+                        visited[succ] = true;
+                        stack.push((succ, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    // Cooper, Harvey and Kennedy's "A Simple, Fast Dominance Algorithm": each block's immediate
+    // dominator is where the dominator tree paths of its predecessors meet, iterated in reverse
+    // postorder until nothing changes
+    pub fn dominators(&self) -> Dominators {
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (index, &block) in order.iter().enumerate() {
+            position[block] = index;
+        }
+        let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+        idom[0] = Some(0);
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while position[a] > position[b] {
+                    a = idom[a].unwrap();
+                }
+                while position[b] > position[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order[1..] {
+                let mut preds = self.preds[block].iter().filter(|&&p| idom[p].is_some());
+                let first = *preds
+                    .next()
+                    .expect("a reachable block has a processed predecessor");
+                let new = preds.fold(first, |new, &pred| intersect(&idom, pred, new));
+                if idom[block] != Some(new) {
+                    idom[block] = Some(new);
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+
+        let mut children = vec![Vec::new(); self.blocks.len()];
+        for (block, parent) in idom.iter().enumerate() {
+            if let Some(parent) = parent {
+                children[*parent].push(block);
+            }
+        }
+        let mut frontiers = vec![BTreeSet::new(); self.blocks.len()];
+        for (block, preds) in self.preds.iter().enumerate() {
+            if preds.len() < 2 {
+                continue;
+            }
+            for &pred in preds {
+                let mut runner = pred;
+                while Some(runner) != idom[block] {
+                    frontiers[runner].insert(block);
+                    runner = idom[runner].expect("the entry dominates every block");
+                }
+            }
+        }
+        Dominators {
+            idom,
+            children,
+            frontiers: frontiers
+                .into_iter()
+                .map(|frontier| frontier.into_iter().collect())
+                .collect(),
+        }
+    }
+
+    pub fn liveness(&self) -> Liveness {
+        let count = self.blocks.len();
+        let mut uses = vec![BTreeSet::new(); count];
+        let mut defs = vec![BTreeSet::new(); count];
+        // What the phis of each block's successors read coming from it
+        let mut edge_uses = vec![BTreeSet::new(); count];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for instr in &block.body {
+                if let Instr::Phi { args, .. } = instr {
+                    for (label, arg) in args {
+                        if let (Some(pred), Operand::Temp(temp)) = (self.index(*label), arg) {
+                            edge_uses[pred].insert(*temp);
+                        }
+                    }
+                } else {
+                    for temp in instr.uses() {
+                        if !defs[index].contains(&temp) {
+                            uses[index].insert(temp);
+                        }
+                    }
+                }
+                defs[index].extend(instr.def());
+            }
+        }
+        let mut live_in = vec![BTreeSet::new(); count];
+        let mut live_out = vec![BTreeSet::new(); count];
+        let order = self.reverse_postorder();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().rev() {
+                let mut out = edge_uses[block].clone();
+                for &succ in &self.succs[block] {
+                    out.extend(live_in[succ].iter().copied());
+                }
+                let mut inn = uses[block].clone();
+                inn.extend(out.difference(&defs[block]).copied());
+                if inn != live_in[block] || out != live_out[block] {
+                    changed = true;
+                    live_in[block] = inn;
+                    live_out[block] = out;
+                }
+            }
+        }
+        Liveness { live_in, live_out }
+    }
+
+    pub fn to_function(&self) -> Function {
+        let mut body = Vec::new();
+        for block in &self.blocks {
+            body.push(Instr::Label(block.label));
+            body.extend(block.body.iter().cloned());
+        }
+        Function {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body,
+        }
+    }
+
+    // One box per block, holding its code, with the edges between them solid and the dominator
+    // tree drawn over them in dashes
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape(&self.name)).unwrap();
+        writeln!(dot, "    node [shape=box, fontname=monospace];").unwrap();
+        for block in &self.blocks {
+            let mut text = format!("{}:\\l", block.label);
+            for instr in &block.body {
+                text.push_str(&escape(&instr.to_string()));
+                text.push_str("\\l");
+            }
+            writeln!(dot, "    {} [label=\"{}\"];", block.label, text).unwrap();
+        }
+        for (from, block) in self.blocks.iter().enumerate() {
+            match block.terminator() {
+                Instr::Branch { then, els, .. } => {
+                    writeln!(dot, "    {} -> {} [label=\"then\"];", block.label, then).unwrap();
+                    writeln!(dot, "    {} -> {} [label=\"else\"];", block.label, els).unwrap();
+                }
+                _ => {
+                    for &to in &self.succs[from] {
+                        let to = self.blocks[to].label;
+                        writeln!(dot, "    {} -> {};", block.label, to).unwrap();
+                    }
+                }
+            }
+        }
+        let dominators = self.dominators();
+        for (block, idom) in dominators.idom.iter().enumerate() {
+            if let Some(idom) = idom {
+                writeln!(
+                    dot,
+                    "    {} -> {} [style=dashed, color=gray, constraint=false];",
+                    self.blocks[*idom].label, self.blocks[block].label
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::parse::parse;
+
+    fn cfg(source: &str) -> Cfg {
+        Cfg::new(&parse(source).unwrap().functions[0])
+    }
+
+    #[test]
+    fn blocks() {
+        // a loop back to the first label, a label fallen into, and code that can't be reached
+        let graph = cfg("function g(t0) {\n\
+                         L0:\n\
+                             t1 = mul t0, 2\n\
+                             if lt t1, t0 goto L0 else L1\n\
+                             t2 = 5\n\
+                         L1:\n\
+                         L2:\n\
+                             return t1\n\
+                         L3:\n\
+                             jump L2\n\
+                         }");
+        assert_eq!(
+            graph.to_function().to_string(),
+            "function g(t0) {\n\
+             L5:\n    jump L0\n\
+             L0:\n    t1 = mul t0, 2\n    if lt t1, t0 goto L0 else L1\n\
+             L1:\n    jump L2\n\
+             L2:\n    return t1\n\
+             }\n"
+        );
+        assert_eq!(graph.succs, vec![vec![1], vec![1, 2], vec![3], vec![]]);
+        assert_eq!(graph.preds, vec![vec![], vec![0, 1], vec![1], vec![2]]);
+
+        let graph = cfg("function h() {\n    t0 = 1\n}");
+        assert_eq!(
+            graph.to_function().to_string(),
+            "function h() {\nL0:\n    t0 = 1\n    return\n}\n"
+        );
+    }
+
+    const LOOP: &str = "function f(t0) {\n\
+                         L0:\n\
+                             t1 = 0\n\
+                             jump L1\n\
+                         L1:\n\
+                             if lt t1, t0 goto L2 else L5\n\
+                         L2:\n\
+                             if eq t1, 3 goto L3 else L4\n\
+                         L3:\n\
+                             t1 = add t1, 2\n\
+                         L4:\n\
+                             t1 = add t1, 1\n\
+                             jump L1\n\
+                         L5:\n\
+                             return t1\n\
+                         }";
+
+    #[test]
+    fn dominators() {
+        let graph = cfg(LOOP);
+        assert_eq!(graph.reverse_postorder(), vec![0, 1, 5, 2, 3, 4]);
+        let dominators = graph.dominators();
+        assert_eq!(
+            dominators.idom,
+            vec![None, Some(0), Some(1), Some(2), Some(2), Some(1)]
+        );
+        assert_eq!(
+            dominators.children,
+            vec![vec![1], vec![2, 5], vec![3, 4], vec![], vec![], vec![]]
+        );
+        assert_eq!(
+            dominators.frontiers,
+            vec![vec![], vec![1], vec![1], vec![4], vec![1], vec![]]
+        );
+        assert!(dominators.dominates(1, 4));
+        assert!(dominators.dominates(3, 3));
+        assert!(!dominators.dominates(3, 4));
+        assert!(!dominators.dominates(5, 1));
+    }
+
+    #[test]
+    fn liveness() {
+        let liveness = cfg(LOOP).liveness();
+        let sets = |sets: &[&[usize]]| -> Vec<BTreeSet<Temp>> {
+            sets.iter()
+                .map(|set| set.iter().map(|&n| Temp(n)).collect())
+                .collect()
+        };
+        let both: &[usize] = &[0, 1];
+        assert_eq!(
+            liveness.live_in,
+            sets(&[&[0], both, both, both, both, &[1]])
+        );
+        assert_eq!(
+            liveness.live_out,
+            sets(&[both, both, both, both, both, &[]])
+        );
+
+        // a phi's operands are live out of the block they come from, not into the phi's own
+        let liveness = cfg("function f(t0) {\n\
+                            L0:\n\
+                                if lt t0, 0 goto L1 else L2\n\
+                            L1:\n\
+                                t1 = neg t0\n\
+                                jump L2\n\
+                            L2:\n\
+                                t2 = phi [L0: t0], [L1: t1]\n\
+                                return t2\n\
+                            }")
+        .liveness();
+        assert_eq!(liveness.live_in, sets(&[&[0], &[0], &[]]));
+        assert_eq!(liveness.live_out, sets(&[&[0], &[1], &[]]));
+    }
+
+    #[test]
+    fn dot() {
+        let graph = cfg("function f(t0) {\n\
+                             if eq t0, 0 goto L0 else L1\n\
+                         L0:\n\
+                             raise error \"zero\"\n\
+                         L1:\n\
+                             return t0\n\
+                         }");
+        assert_eq!(
+            graph.to_dot(),
+            "digraph \"f\" {\n    \
+             node [shape=box, fontname=monospace];\n    \
+             L2 [label=\"L2:\\l    if eq t0, 0 goto L0 else L1\\l\"];\n    \
+             L0 [label=\"L0:\\l    raise error \\\"zero\\\"\\l\"];\n    \
+             L1 [label=\"L1:\\l    return t0\\l\"];\n    \
+             L2 -> L0 [label=\"then\"];\n    \
+             L2 -> L1 [label=\"else\"];\n    \
+             L2 -> L0 [style=dashed, color=gray, constraint=false];\n    \
+             L2 -> L1 [style=dashed, color=gray, constraint=false];\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::ir::{
+    BinaryOp, CheckedOp, Function, Instr, Label, Operand, Program, Size, Temp, Trap, UnaryOp,
+};
+use std::collections::HashMap;
+
+// Runs IR directly, so that tests can check what a function computes rather than how it was
+// written: lowering against what a C0 program means, and every later pass against the function
+// it was given
+//
+// Memory is one growing array of bytes, starting past a few that stand in for NULL. Going outside
+// it, reading a temporary that was never written, or calling a function the program doesn't
+// define is a bug in whatever produced the IR, so it panics instead of raising
+const STEPS: usize = 1_000_000;
+
+struct Machine<'a> {
+    program: &'a Program,
+    memory: Vec<u8>,
+    strings: HashMap<String, i64>,
+    steps: usize,
+}
+
+// Calls `name` with `args` and gives back what it returns, or what it raised. An error's message
+// comes back as a string
+pub fn run(program: &Program, name: &str, args: &[i64]) -> Result<Option<i64>, Trap> {
+    let mut machine = Machine {
+        program,
+        memory: vec![0; 16],
+        strings: HashMap::new(),
+        steps: 0,
+    };
+    machine.call(name, args)
+}
+
+fn int(value: i64) -> i32 {
+    value as i32
+}
+
+impl Machine<'_> {
+    fn allocate(&mut self, size: usize) -> i64 {
+        let start = self.memory.len().next_multiple_of(8);
+        self.memory.resize(start + size.max(1), 0);
+        start as i64
+    }
+
+    fn bytes(&self, addr: i64, size: usize) -> &[u8] {
+        let start = usize::try_from(addr).ok().filter(|&start| start >= 16);
+        match start.and_then(|start| self.memory.get(start..start + size)) {
+            Some(bytes) => bytes,
+            None => panic!("access to {} bytes at {} is out of memory", size, addr),
+        }
+    }
+
+    fn load(&self, size: Size, addr: i64) -> i64 {
+        match size {
+            Size::Byte => self.bytes(addr, 1)[0] as i64,
+            Size::Word => i32::from_le_bytes(self.bytes(addr, 4).try_into().unwrap()) as i64,
+            Size::Quad => i64::from_le_bytes(self.bytes(addr, 8).try_into().unwrap()),
+        }
+    }
+
+    fn store(&mut self, size: Size, addr: i64, value: i64) {
+        let bytes = value.to_le_bytes();
+        let size = match size {
+            Size::Byte => 1,
+            Size::Word => 4,
+            Size::Quad => 8,
+        };
+        self.bytes(addr, size);
+        let start = addr as usize;
+        self.memory[start..start + size].copy_from_slice(&bytes[..size]);
+    }
+
+    fn string(&mut self, s: &str) -> i64 {
+        if let Some(&addr) = self.strings.get(s) {
+            return addr;
+        }
+        let addr = self.allocate(s.len() + 1);
+        let start = addr as usize;
+        self.memory[start..start + s.len()].copy_from_slice(s.as_bytes());
+        self.strings.insert(s.to_string(), addr);
+        addr
+    }
+
+    fn read_string(&self, addr: i64) -> String {
+        let bytes = self.bytes(addr, 1);
+        let end = self.memory[addr as usize..]
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(bytes.len());
+        String::from_utf8_lossy(&self.memory[addr as usize..addr as usize + end]).into_owned()
+    }
+
+    fn call(&mut self, name: &str, args: &[i64]) -> Result<Option<i64>, Trap> {
+        match (name, args) {
+            ("alloc", &[size]) => return Ok(Some(self.allocate(size as usize))),
+            ("alloc_array", &[size, len]) => {
+                if len < 0 {
+                    return Err(Trap::Memory);
+                }
+                let array = self.allocate(8 + (size * len) as usize);
+                self.store(Size::Word, array, len);
+                return Ok(Some(array));
+            }
+            _ => {}
+        }
+        let program = self.program;
+        let Some(function) = program.functions.iter().find(|f| f.name == name) else {
+            panic!("call to `{}`, which isn't defined", name)
+        };
+        assert_eq!(function.params.len(), args.len(), "arguments to `{}`", name);
+        let temps = function.params.iter().copied().zip(args.iter().copied());
+        self.execute(function, temps.collect())
+    }
+
+    fn execute(
+        &mut self,
+        function: &Function,
+        mut temps: HashMap<Temp, i64>,
+    ) -> Result<Option<i64>, Trap> {
+        let labels: HashMap<Label, usize> = function
+            .body
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instr)| match instr {
+                Instr::Label(label) => Some((*label, index)),
+                _ => None,
+            })
+            .collect();
+        let mut pc = 0;
+        // The block control is in, and the one it came from
+        let (mut block, mut from) = (None, None);
+        loop {
+            self.steps += 1;
+            assert!(self.steps < STEPS, "ran for too long");
+            let Some(instr) = function.body.get(pc) else {
+                panic!("fell off the end of `{}`", function.name)
+            };
+            pc += 1;
+            if let Instr::Phi { .. } = instr {
+                let phis: Vec<&Instr> = function.body[pc - 1..]
+                    .iter()
+                    .take_while(|instr| matches!(instr, Instr::Phi { .. }))
+                    .collect();
+                let mut values = Vec::new();
+                for phi in &phis {
+                    let Instr::Phi { dst, args } = phi else {
+                        unreachable!()
+                    };
+                    let arg = args.iter().find(|(label, _)| Some(*label) == from);
+                    let Some((_, arg)) = arg else {
+                        panic!("{} has nothing for coming from {:?}", dst, from)
+                    };
+                    let value = match arg {
+                        Operand::Temp(temp) => temps[temp],
+                        Operand::Int(n) => *n,
+                        Operand::Str(s) => self.string(s),
+                    };
+                    values.push((*dst, value));
+                }
+                temps.extend(values);
+                pc += phis.len() - 1;
+                continue;
+            }
+            let mut value = |operand: &Operand| match operand {
+                Operand::Temp(temp) => match temps.get(temp) {
+                    Some(&value) => value,
+                    None => panic!("{} read before it was written in `{}`", temp, function.name),
+                },
+                Operand::Int(n) => *n,
+                Operand::Str(s) => self.string(s),
+            };
+            let result = match instr {
+                Instr::Label(label) => {
+                    (from, block) = (block, Some(*label));
+                    None
+                }
+                Instr::Phi { .. } => unreachable!("phis are run together"),
+                Instr::Copy { dst, src } => Some((*dst, value(src))),
+                Instr::Unary { dst, op, src } => {
+                    let src = int(value(src));
+                    let result = match op {
+                        UnaryOp::Neg => src.wrapping_neg(),
+                        UnaryOp::Not => !src,
+                    };
+                    Some((*dst, result as i64))
+                }
+                Instr::Binary { dst, op, lhs, rhs } => {
+                    let (lhs, rhs) = (value(lhs), value(rhs));
+                    let result = match op {
+                        BinaryOp::Add => int(lhs).wrapping_add(int(rhs)) as i64,
+                        BinaryOp::Sub => int(lhs).wrapping_sub(int(rhs)) as i64,
+                        BinaryOp::Mul => int(lhs).wrapping_mul(int(rhs)) as i64,
+                        BinaryOp::And => lhs & rhs,
+                        BinaryOp::Or => lhs | rhs,
+                        BinaryOp::Xor => lhs ^ rhs,
+                        BinaryOp::Cmp(op) => op.holds(lhs, rhs) as i64,
+                    };
+                    Some((*dst, result))
+                }
+                Instr::Checked { dst, op, lhs, rhs } => {
+                    let (lhs, rhs) = (int(value(lhs)), int(value(rhs)));
+                    let result = match op {
+                        CheckedOp::Div => lhs.checked_div(rhs),
+                        CheckedOp::Mod => lhs.checked_rem(rhs),
+                        CheckedOp::Shl => (0..32).contains(&rhs).then(|| lhs << rhs),
+                        CheckedOp::Shr => (0..32).contains(&rhs).then(|| lhs >> rhs),
+                    };
+                    Some((*dst, result.ok_or(Trap::Arithmetic)? as i64))
+                }
+                Instr::Address {
+                    dst,
+                    base,
+                    index,
+                    offset,
+                } => {
+                    let index = index
+                        .as_ref()
+                        .map_or(0, |(index, scale)| value(index) * *scale as i64);
+                    Some((*dst, value(base) + index + offset))
+                }
+                Instr::Load { dst, size, addr } => {
+                    let addr = value(addr);
+                    Some((*dst, self.load(*size, addr)))
+                }
+                Instr::Store {
+                    size,
+                    addr,
+                    value: v,
+                } => {
+                    let (addr, v) = (value(addr), value(v));
+                    self.store(*size, addr, v);
+                    None
+                }
+                Instr::Call { dst, name, args } => {
+                    let args: Vec<i64> = args.iter().map(&mut value).collect();
+                    let result = self.call(name, &args)?;
+                    match dst {
+                        Some(dst) => {
+                            let result =
+                                result.unwrap_or_else(|| panic!("`{}` returned nothing", name));
+                            Some((*dst, result))
+                        }
+                        None => None,
+                    }
+                }
+                Instr::Jump(label) => {
+                    pc = labels[label];
+                    None
+                }
+                Instr::Branch {
+                    op,
+                    lhs,
+                    rhs,
+                    then,
+                    els,
+                } => {
+                    let holds = op.holds(value(lhs), value(rhs));
+                    pc = labels[if holds { then } else { els }];
+                    None
+                }
+                Instr::Return(result) => return Ok(result.as_ref().map(value)),
+                Instr::Raise(Trap::Error(message)) => {
+                    let message = value(message);
+                    return Err(Trap::Error(Operand::Str(self.read_string(message))));
+                }
+                Instr::Raise(trap) => return Err(trap.clone()),
+            };
+            if let Some((dst, result)) = result {
+                temps.insert(dst, result);
+            }
+        }
+    }
+}
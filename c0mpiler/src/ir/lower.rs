@@ -0,0 +1,774 @@
+use crate::ast::{self, DeclKind, Expr, ExprKind, LValue, LValueKind, Stmt, StmtKind, Ty};
+use crate::ir::{
+    BinaryOp, CheckedOp, CmpOp, Extern, Function, Instr, Label, Operand, Program, Size, Temp, Trap,
+    UnaryOp,
+};
+use crate::semantic::layout::Layouts;
+use std::collections::{HashMap, HashSet};
+
+// Lowering: turns the checked program, elaborated into the core language and with every type
+// filled in, into three-address code. Each variable becomes a temporary of its own, conditions
+// become branches, short-circuiting included, and every access to memory is spelled out with the
+// checks it needs
+//
+// An array is a pointer to its length, an int padded to 8 bytes, followed by its elements. The
+// default array, in memory that was never assigned to, is NULL and has length 0. Contracts are
+// only checked when asked for, which isn't supported yet, so they're left out
+//
+// Code that can't be reached, like whatever follows a return, isn't lowered at all, and each kind
+// of runtime error is raised from a single place at the end of the function
+pub fn lower(program: &ast::Program, layouts: &Layouts) -> Program {
+    let defined: HashSet<String> = program
+        .decls
+        .iter()
+        .filter_map(|decl| match &decl.kind {
+            DeclKind::Function {
+                name,
+                body: Some(_),
+                ..
+            } => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut lowered = Program::default();
+    for decl in &program.decls {
+        if let DeclKind::Function {
+            name,
+            params,
+            body: Some(body),
+            ..
+        } = &decl.kind
+        {
+            let (function, externs) = Lowerer::new(layouts, &defined).function(name, params, body);
+            lowered.functions.push(function);
+            for extern_ in externs {
+                if !lowered.externs.iter().any(|e| e.name == extern_.name) {
+                    lowered.externs.push(extern_);
+                }
+            }
+        }
+    }
+    lowered
+}
+
+// Where a value in memory sits: base + index * scale + offset
+struct Place {
+    base: Operand,
+    index: Option<(Operand, u32)>,
+    offset: i64,
+}
+
+struct Lowerer<'a> {
+    layouts: &'a Layouts,
+    // The functions with bodies, and those called without one
+    defined: &'a HashSet<String>,
+    externs: Vec<Extern>,
+    vars: HashMap<String, Temp>,
+    body: Vec<Instr>,
+    temps: usize,
+    labels: usize,
+    // Whether the last instruction emitted doesn't fall through
+    terminated: bool,
+    targeted: HashSet<Label>,
+    // Where "continue" and "break" go in each enclosing loop
+    loops: Vec<(Label, Label)>,
+    traps: Vec<(Trap, Label)>,
+}
+
+fn cmp_op(op: ast::BinaryOp) -> Option<CmpOp> {
+    match op {
+        ast::BinaryOp::Eq => Some(CmpOp::Eq),
+        ast::BinaryOp::NotEq => Some(CmpOp::Ne),
+        ast::BinaryOp::Less => Some(CmpOp::Lt),
+        ast::BinaryOp::LessEq => Some(CmpOp::Le),
+        ast::BinaryOp::Greater => Some(CmpOp::Gt),
+        ast::BinaryOp::GreaterEq => Some(CmpOp::Ge),
+        _ => None,
+    }
+}
+
+fn checked_op(op: ast::BinaryOp) -> Option<CheckedOp> {
+    match op {
+        ast::BinaryOp::Div => Some(CheckedOp::Div),
+        ast::BinaryOp::Mod => Some(CheckedOp::Mod),
+        ast::BinaryOp::Shl => Some(CheckedOp::Shl),
+        ast::BinaryOp::Shr => Some(CheckedOp::Shr),
+        _ => None,
+    }
+}
+
+fn binary_op(op: ast::BinaryOp) -> BinaryOp {
+    match op {
+        ast::BinaryOp::Mul => BinaryOp::Mul,
+        ast::BinaryOp::Add => BinaryOp::Add,
+        ast::BinaryOp::Sub => BinaryOp::Sub,
+        ast::BinaryOp::BitAnd => BinaryOp::And,
+        ast::BinaryOp::BitXor => BinaryOp::Xor,
+        ast::BinaryOp::BitOr => BinaryOp::Or,
+        op => match cmp_op(op) {
+            Some(op) => BinaryOp::Cmp(op),
+            None => unreachable!("not in the core language"),
+        },
+    }
+}
+
+fn ty(e: &Expr) -> &Ty {
+    e.ty.as_ref()
+        .expect("lowering an expression that wasn't typechecked")
+}
+
+fn lvalue_ty(lv: &LValue) -> &Ty {
+    lv.ty
+        .as_ref()
+        .expect("lowering an lvalue that wasn't typechecked")
+}
+
+impl<'a> Lowerer<'a> {
+    fn new(layouts: &'a Layouts, defined: &'a HashSet<String>) -> Lowerer<'a> {
+        Lowerer {
+            layouts,
+            defined,
+            externs: Vec::new(),
+            vars: HashMap::new(),
+            body: Vec::new(),
+            temps: 0,
+            labels: 0,
+            terminated: false,
+            targeted: HashSet::new(),
+            loops: Vec::new(),
+            traps: Vec::new(),
+        }
+    }
+
+    fn temp(&mut self) -> Temp {
+        self.temps += 1;
+        Temp(self.temps - 1)
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label(self.labels - 1)
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        if self.terminated {
+            return;
+        }
+        self.targeted.extend(instr.targets());
+        self.terminated = instr.is_terminator();
+        self.body.push(instr);
+    }
+
+    // A label nothing jumps to, after code that doesn't fall through, starts code that can't be
+    // reached
+    fn place(&mut self, label: Label) {
+        if !self.terminated || self.targeted.contains(&label) {
+            self.body.push(Instr::Label(label));
+            self.terminated = false;
+        }
+    }
+
+    fn trap(&mut self, trap: Trap) -> Label {
+        if let Some((_, label)) = self.traps.iter().find(|(t, _)| *t == trap) {
+            return *label;
+        }
+        let label = self.label();
+        self.traps.push((trap, label));
+        label
+    }
+
+    // Goes on only if `lhs op rhs` doesn't hold
+    fn check(&mut self, op: CmpOp, lhs: Operand, rhs: Operand, trap: Trap) {
+        let fail = self.trap(trap);
+        let ok = self.label();
+        self.emit(Instr::Branch {
+            op,
+            lhs,
+            rhs,
+            then: fail,
+            els: ok,
+        });
+        self.place(ok);
+    }
+
+    fn size(&self, ty: &Ty) -> usize {
+        self.layouts
+            .size(ty)
+            .expect("lowering a value of unknown size")
+    }
+
+    fn access_size(&self, ty: &Ty) -> Size {
+        match self.size(ty) {
+            1 => Size::Byte,
+            4 => Size::Word,
+            _ => Size::Quad,
+        }
+    }
+
+    fn function(
+        mut self,
+        name: &str,
+        params: &[ast::Param],
+        body: &[Stmt],
+    ) -> (Function, Vec<Extern>) {
+        let params = params
+            .iter()
+            .map(|param| {
+                let temp = self.temp();
+                self.vars.insert(param.name.clone(), temp);
+                temp
+            })
+            .collect();
+        for s in body {
+            self.stmt(s);
+        }
+        // Only a void function can get here, since the others were checked to return
+        self.emit(Instr::Return(None));
+        for (trap, label) in std::mem::take(&mut self.traps) {
+            self.place(label);
+            self.emit(Instr::Raise(trap));
+        }
+        let function = Function {
+            name: name.to_string(),
+            params,
+            body: self.body,
+        };
+        (function, self.externs)
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        match &s.kind {
+            StmtKind::Decl { name, init, .. } => {
+                let value = init.as_ref().map(|init| self.expr(init));
+                let temp = self.temp();
+                self.vars.insert(name.clone(), temp);
+                if let Some(src) = value {
+                    self.emit(Instr::Copy { dst: temp, src });
+                }
+            }
+            StmtKind::Assign { lhs, op: None, rhs } => {
+                if let LValueKind::Var(name) = &lhs.kind {
+                    let src = self.expr(rhs);
+                    let dst = self.vars[name];
+                    self.emit(Instr::Copy { dst, src });
+                } else {
+                    let place = self.lvalue(lhs);
+                    let addr = self.address(place);
+                    let value = self.expr(rhs);
+                    let size = self.access_size(lvalue_ty(lhs));
+                    self.emit(Instr::Store { size, addr, value });
+                }
+            }
+            StmtKind::Expr(e) => {
+                self.expr(e);
+            }
+            StmtKind::If { cond, then, els } => {
+                let (then_label, end) = (self.label(), self.label());
+                let els_label = if els.is_some() { self.label() } else { end };
+                self.cond(cond, then_label, els_label);
+                self.place(then_label);
+                self.stmt(then);
+                if let Some(els) = els {
+                    self.emit(Instr::Jump(end));
+                    self.place(els_label);
+                    self.stmt(els);
+                }
+                self.emit(Instr::Jump(end));
+                self.place(end);
+            }
+            StmtKind::While { cond, body, .. } => {
+                let (head, body_label, exit) = (self.label(), self.label(), self.label());
+                self.emit(Instr::Jump(head));
+                self.place(head);
+                self.cond(cond, body_label, exit);
+                self.place(body_label);
+                self.loops.push((head, exit));
+                self.stmt(body);
+                self.loops.pop();
+                self.emit(Instr::Jump(head));
+                self.place(exit);
+            }
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|value| self.expr(value));
+                self.emit(Instr::Return(value));
+            }
+            StmtKind::Block(stmts) => stmts.iter().for_each(|s| self.stmt(s)),
+            StmtKind::Assert(cond) => {
+                let fail = self.trap(Trap::Assert);
+                let ok = self.label();
+                self.cond(cond, ok, fail);
+                self.place(ok);
+            }
+            StmtKind::Error(message) => {
+                let message = self.expr(message);
+                self.emit(Instr::Raise(Trap::Error(message)));
+            }
+            StmtKind::Break => {
+                let (_, exit) = *self.loops.last().expect("break outside of a loop");
+                self.emit(Instr::Jump(exit));
+            }
+            StmtKind::Continue => {
+                let (head, _) = *self.loops.last().expect("continue outside of a loop");
+                self.emit(Instr::Jump(head));
+            }
+            StmtKind::Annotation(_) => {}
+            StmtKind::Assign { .. } | StmtKind::PostOp { .. } | StmtKind::For { .. } => {
+                unreachable!("not in the core language")
+            }
+        }
+    }
+
+    // Jumps to `then` if `e` holds and to `els` otherwise
+    fn cond(&mut self, e: &Expr, then: Label, els: Label) {
+        match &e.kind {
+            ExprKind::Bool(b) => self.emit(Instr::Jump(if *b { then } else { els })),
+            ExprKind::Unary {
+                op: ast::UnaryOp::Not,
+                operand,
+            } => self.cond(operand, els, then),
+            ExprKind::Binary { op, lhs, rhs } if cmp_op(*op).is_some() => {
+                let lhs = self.expr(lhs);
+                let rhs = self.expr(rhs);
+                self.emit(Instr::Branch {
+                    op: cmp_op(*op).unwrap(),
+                    lhs,
+                    rhs,
+                    then,
+                    els,
+                });
+            }
+            ExprKind::Ternary {
+                cond,
+                then: a,
+                els: b,
+            } => {
+                // A constant branch, which is what "&&" and "||" leave, goes straight to the
+                // outcome
+                let mut branch = |e: &Expr| match e.kind {
+                    ExprKind::Bool(true) => (then, false),
+                    ExprKind::Bool(false) => (els, false),
+                    _ => (self.label(), true),
+                };
+                let ((a_label, a_open), (b_label, b_open)) = (branch(a), branch(b));
+                self.cond(cond, a_label, b_label);
+                for (label, open, e) in [(a_label, a_open, a), (b_label, b_open, b)] {
+                    if open {
+                        self.place(label);
+                        self.cond(e, then, els);
+                    }
+                }
+            }
+            _ => {
+                let value = self.expr(e);
+                self.emit(Instr::Branch {
+                    op: CmpOp::Ne,
+                    lhs: value,
+                    rhs: Operand::Int(0),
+                    then,
+                    els,
+                });
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) -> Operand {
+        match &e.kind {
+            ExprKind::Int(n) => Operand::Int(*n as i64),
+            ExprKind::Bool(b) => Operand::Int(*b as i64),
+            ExprKind::Char(c) => Operand::Int(*c as i64),
+            ExprKind::String(s) => Operand::Str(s.clone()),
+            ExprKind::Null => Operand::Int(0),
+            ExprKind::Var(name) => Operand::Temp(self.vars[name]),
+            ExprKind::Binary { op, lhs, rhs } => {
+                let lhs = self.expr(lhs);
+                let rhs = self.expr(rhs);
+                let dst = self.temp();
+                let instr = match checked_op(*op) {
+                    Some(op) => Instr::Checked { dst, op, lhs, rhs },
+                    None => Instr::Binary {
+                        dst,
+                        op: binary_op(*op),
+                        lhs,
+                        rhs,
+                    },
+                };
+                self.emit(instr);
+                Operand::Temp(dst)
+            }
+            ExprKind::Unary { op, operand } => {
+                let src = self.expr(operand);
+                let dst = self.temp();
+                self.emit(match op {
+                    ast::UnaryOp::Neg => Instr::Unary {
+                        dst,
+                        op: UnaryOp::Neg,
+                        src,
+                    },
+                    ast::UnaryOp::BitNot => Instr::Unary {
+                        dst,
+                        op: UnaryOp::Not,
+                        src,
+                    },
+                    ast::UnaryOp::Not => Instr::Binary {
+                        dst,
+                        op: BinaryOp::Xor,
+                        lhs: src,
+                        rhs: Operand::Int(1),
+                    },
+                });
+                Operand::Temp(dst)
+            }
+            ExprKind::Ternary { cond, then, els } => {
+                let dst = self.temp();
+                let (then_label, els_label, end) = (self.label(), self.label(), self.label());
+                self.cond(cond, then_label, els_label);
+                for (label, e) in [(then_label, then), (els_label, els)] {
+                    self.place(label);
+                    let src = self.expr(e);
+                    self.emit(Instr::Copy { dst, src });
+                    self.emit(Instr::Jump(end));
+                }
+                self.place(end);
+                Operand::Temp(dst)
+            }
+            ExprKind::Call { name, args } => {
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                let dst = (*ty(e) != Ty::Void).then(|| self.temp());
+                if !self.defined.contains(name) {
+                    let ret = dst.map(|_| self.access_size(ty(e)));
+                    self.externs.push(Extern {
+                        name: name.clone(),
+                        ret,
+                    });
+                }
+                self.emit(Instr::Call {
+                    dst,
+                    name: name.clone(),
+                    args,
+                });
+                dst.map_or(Operand::Int(0), Operand::Temp)
+            }
+            ExprKind::Field { .. } | ExprKind::Deref(_) | ExprKind::Index { .. } => {
+                let place = self.place_of(e);
+                self.load(place, ty(e))
+            }
+            ExprKind::Alloc(_) => {
+                let Ty::Pointer(pointee) = ty(e) else {
+                    unreachable!("alloc gives a pointer")
+                };
+                let size = Operand::Int(self.size(pointee) as i64);
+                self.call("alloc", vec![size])
+            }
+            ExprKind::AllocArray { len, .. } => {
+                let Ty::Array(element) = ty(e) else {
+                    unreachable!("alloc_array gives an array")
+                };
+                let size = Operand::Int(self.size(element) as i64);
+                let len = self.expr(len);
+                self.call("alloc_array", vec![size, len])
+            }
+            ExprKind::Result | ExprKind::Length(_) => {
+                unreachable!("contracts aren't lowered")
+            }
+            ExprKind::Arrow { .. } => unreachable!("not in the core language"),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Operand>) -> Operand {
+        let dst = self.temp();
+        self.emit(Instr::Call {
+            dst: Some(dst),
+            name: name.to_string(),
+            args,
+        });
+        Operand::Temp(dst)
+    }
+
+    // The value at `place`, unless it's a struct, which is only ever used through its fields
+    fn load(&mut self, place: Place, ty: &Ty) -> Operand {
+        let addr = self.address(place);
+        let dst = self.temp();
+        let size = self.access_size(ty);
+        self.emit(Instr::Load { dst, size, addr });
+        Operand::Temp(dst)
+    }
+
+    fn address(&mut self, place: Place) -> Operand {
+        match place {
+            Place {
+                base,
+                index: None,
+                offset: 0,
+            } => base,
+            Place {
+                base,
+                index,
+                offset,
+            } => {
+                let dst = self.temp();
+                self.emit(Instr::Address {
+                    dst,
+                    base,
+                    index,
+                    offset,
+                });
+                Operand::Temp(dst)
+            }
+        }
+    }
+
+    // Where in memory a field access, dereference or array access refers to
+    fn place_of(&mut self, e: &Expr) -> Place {
+        match &e.kind {
+            ExprKind::Field { base, field } => {
+                let place = self.place_of(base);
+                self.field(place, ty(base), field)
+            }
+            ExprKind::Deref(pointer) => {
+                let pointer = self.expr(pointer);
+                self.deref(pointer)
+            }
+            ExprKind::Index { base, index } => {
+                let array = self.expr(base);
+                let index = self.expr(index);
+                self.index(array, index, ty(e))
+            }
+            _ => unreachable!("a struct can only be in memory"),
+        }
+    }
+
+    fn lvalue(&mut self, lv: &LValue) -> Place {
+        match &lv.kind {
+            LValueKind::Field { base, field } => {
+                let place = self.lvalue(base);
+                self.field(place, lvalue_ty(base), field)
+            }
+            LValueKind::Deref(pointer) => {
+                let pointer = self.lvalue_value(pointer);
+                self.deref(pointer)
+            }
+            LValueKind::Index { base, index } => {
+                let array = self.lvalue_value(base);
+                let index = self.expr(index);
+                self.index(array, index, lvalue_ty(lv))
+            }
+            LValueKind::Var(_) => unreachable!("a struct can only be in memory"),
+            LValueKind::Arrow { .. } => unreachable!("not in the core language"),
+        }
+    }
+
+    fn lvalue_value(&mut self, lv: &LValue) -> Operand {
+        match &lv.kind {
+            LValueKind::Var(name) => Operand::Temp(self.vars[name]),
+            _ => {
+                let place = self.lvalue(lv);
+                self.load(place, lvalue_ty(lv))
+            }
+        }
+    }
+
+    fn field(&mut self, place: Place, strukt: &Ty, field: &str) -> Place {
+        let Ty::Struct(name) = strukt else {
+            unreachable!("only structs have fields")
+        };
+        let layout = self.layouts.get(name).expect("struct was laid out");
+        let offset = layout.field(field).expect("field was checked").offset;
+        Place {
+            offset: place.offset + offset as i64,
+            ..place
+        }
+    }
+
+    fn deref(&mut self, pointer: Operand) -> Place {
+        self.check(CmpOp::Eq, pointer.clone(), Operand::Int(0), Trap::Memory);
+        Place {
+            base: pointer,
+            index: None,
+            offset: 0,
+        }
+    }
+
+    fn index(&mut self, array: Operand, index: Operand, element: &Ty) -> Place {
+        self.check(CmpOp::Eq, array.clone(), Operand::Int(0), Trap::Memory);
+        let len = self.temp();
+        self.emit(Instr::Load {
+            dst: len,
+            size: Size::Word,
+            addr: array.clone(),
+        });
+        self.check(CmpOp::Lt, index.clone(), Operand::Int(0), Trap::Memory);
+        self.check(CmpOp::Ge, index.clone(), Operand::Temp(len), Trap::Memory);
+        Place {
+            base: array,
+            index: Some((index, self.size(element) as u32)),
+            offset: 8,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::elaborate::elaborate;
+    use crate::ir::interp::run;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+    use crate::semantic::typecheck::typecheck;
+
+    pub fn lowered(source: &str) -> Program {
+        let program = parse(scan(source.to_string()).unwrap()).unwrap();
+        let mut program = elaborate(program);
+        let layouts = typecheck(&mut program).unwrap();
+        lower(&program, &layouts)
+    }
+
+    fn main(source: &str) -> Result<Option<i64>, Trap> {
+        run(&lowered(source), "main", &[])
+    }
+
+    #[test]
+    fn code() {
+        let program = lowered(
+            "int f(int x, int[] a) {\n\
+               int y = 0;\n\
+               while (x > 0 && y != 3) { y += a[x] / 2; x--; }\n\
+               return y;\n\
+             }\n\
+             void g(int* p) { if (!(*p < 2)) return; *p = 7; }",
+        );
+        assert_eq!(
+            program.to_string(),
+            "function f(t0, t1) {
+    t2 = 0
+    jump L0
+L0:
+    if gt t0, 0 goto L3 else L2
+L3:
+    if ne t2, 3 goto L1 else L2
+L1:
+    if eq t1, 0 goto L4 else L5
+L5:
+    t3 = load.4 t1
+    if lt t0, 0 goto L4 else L6
+L6:
+    if ge t0, t3 goto L4 else L7
+L7:
+    t4 = addr t1 + t0 * 4 + 8
+    t5 = load.4 t4
+    t6 = div t5, 2
+    t7 = add t2, t6
+    t2 = t7
+    t8 = sub t0, 1
+    t0 = t8
+    jump L0
+L2:
+    return t2
+L4:
+    raise memory
+}
+
+function g(t0) {
+    if eq t0, 0 goto L2 else L3
+L3:
+    t1 = load.4 t0
+    if lt t1, 2 goto L1 else L0
+L0:
+    return
+L1:
+    if eq t0, 0 goto L2 else L4
+L4:
+    store.4 t0, 7
+    return
+L2:
+    raise memory
+}
+"
+        );
+    }
+
+    #[test]
+    fn runs() {
+        assert_eq!(
+            main(
+                "int fact(int n) { return n <= 1 ? 1 : n * fact(n - 1); }\n\
+                 int main() { return fact(10); }"
+            ),
+            Ok(Some(3628800))
+        );
+        // ints wrap around
+        assert_eq!(
+            main("int main() { int x = 2147483647; x++; return x; }"),
+            Ok(Some(-2147483648))
+        );
+        assert_eq!(
+            main(
+                "int main() {\n\
+                   int s = 0;\n\
+                   for (int i = 0; i < 10; i++) { if (i % 2 == 0) continue; if (i > 7) break; s += i; }\n\
+                   return s;\n\
+                 }"
+            ),
+            Ok(Some(16))
+        );
+        // short-circuiting keeps the division from happening
+        assert_eq!(
+            main("int main() { int x = 0; return x != 0 && 10 / x > 1 || !(x < 0) ? 1 : 0; }"),
+            Ok(Some(1))
+        );
+    }
+
+    #[test]
+    fn memory() {
+        let source = "struct point { char tag; int x; int y; };\n\
+                      struct shape { bool closed; struct point[] points; struct point* center; };\n\
+                      int main() {\n\
+                        struct shape* s = alloc(struct shape);\n\
+                        s->points = alloc_array(struct point, 3);\n\
+                        for (int i = 0; i < 3; i++) { s->points[i].x = i; s->points[i].y = i * i; }\n\
+                        s->points[1].tag = 'a';\n\
+                        s->center = alloc(struct point);\n\
+                        (*s->center).y += s->points[2].y + s->points[1].x;\n\
+                        return s->center->y * 100 + (s->points[1].tag == 'a' ? 1 : 0);\n\
+                      }";
+        assert_eq!(main(source), Ok(Some(501)));
+
+        let program = lowered(source);
+        let body = program.functions[0].to_string();
+        // a field of an array element is found in one step
+        assert!(body.contains("addr t6 + t4 * 12 + 12\n"), "{}", body);
+
+        // and everything that can go wrong with it
+        assert_eq!(
+            main("int main() { int* p = NULL; return *p; }"),
+            Err(Trap::Memory)
+        );
+        assert_eq!(
+            main("int main() { int[] a = alloc_array(int, 2); return a[2]; }"),
+            Err(Trap::Memory)
+        );
+        assert_eq!(
+            main("struct s { int[] a; };\n int main() { struct s* p = alloc(struct s); p->a[0] = 1; return 0; }"),
+            Err(Trap::Memory)
+        );
+        assert_eq!(
+            main("int main() { int[] a = alloc_array(int, -1); return 0; }"),
+            Err(Trap::Memory)
+        );
+        assert_eq!(
+            main("int main() { int x = -2147483648; return x / -1; }"),
+            Err(Trap::Arithmetic)
+        );
+        assert_eq!(
+            main("int main() { return 1 << 32; }"),
+            Err(Trap::Arithmetic)
+        );
+        assert_eq!(
+            main("int main() { assert(1 > 2); return 0; }"),
+            Err(Trap::Assert)
+        );
+        assert_eq!(
+            main("int main() { error(\"oops\"); }"),
+            Err(Trap::Error(Operand::Str("oops".to_string())))
+        );
+    }
+}
@@ -0,0 +1,590 @@
+pub mod cfg;
+#[cfg(test)]
+pub mod interp;
+pub mod lower;
+#[cfg(test)]
+pub mod parse;
+pub mod ssa;
+
+use std::fmt;
+
+// Three-address code: the intermediate representation between the checked program and machine
+// code. A function is a list of instructions over an unbounded supply of temporaries, each
+// instruction doing at most one thing, with control flow made explicit as labels and jumps
+//
+// Every value fits in 64 bits. Pointers, arrays and strings use all of them; ints are kept
+// sign-extended from 32 bits, and bools and chars zero-extended from 8, so that any two values
+// can be compared whole. Arithmetic is on 32-bit ints and wraps around
+//
+// Division, modulus and shifts have instructions of their own, since they can fail: they raise an
+// arithmetic error for a zero divisor, for INT_MIN / -1, and for a shift by less than 0 or more
+// than 31. Memory is only read and written through addresses computed beforehand, and any check
+// they need, for NULL or for an array's bounds, is written out as branches to a `raise`
+//
+// The text format is what Display prints and `parse` reads back, with the functions called from
+// libraries declared first, along with the size of what they return:
+//
+//   extern string_length.4
+//
+//   function sum(t0, t1) {
+//       t2 = add t0, t1
+//       t3 = div t2, 2
+//       if lt t3, 0 goto L0 else L1
+//   L0:
+//       raise arithmetic
+//   L1:
+//       t4 = call clamp(t3, "limit")
+//       t5 = addr t0 + t4 * 4 + 8
+//       store.4 t5, t3
+//       return t3
+//   }
+//
+// In SSA form every temporary is written exactly once, and blocks start with phis choosing between
+// the values that come in from each predecessor, as in "t6 = phi [L0: t1], [L3: t5]"
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, PartialOrd, Ord)]
+pub struct Temp(pub usize);
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, PartialOrd, Ord)]
+pub struct Label(pub usize);
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Operand {
+    Temp(Temp),
+    Int(i64),
+    // A pointer to a string constant
+    Str(String),
+}
+
+// The number of bytes a load or store moves
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Size {
+    Byte,
+    Word, // 4 bytes, an int
+    Quad, // 8 bytes, a pointer
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not, // bitwise
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Xor,
+    // Comparisons give 1 or 0
+    Cmp(CmpOp),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// The operations that raise an arithmetic error on bad operands
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CheckedOp {
+    Div,
+    Mod,
+    Shl,
+    Shr,
+}
+
+// What makes a program stop with an error
+#[derive(PartialEq, Debug, Clone)]
+pub enum Trap {
+    Memory, // NULL dereferenced, index out of bounds, or a negative array size
+    // Only ever raised by checked operations, but can be written out too
+    #[allow(dead_code)]
+    Arithmetic,
+    Assert,
+    // error(msg), with a pointer to the message
+    Error(Operand),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Instr {
+    Label(Label),
+    // Only in SSA form, at the start of a block: the operand given for whichever predecessor
+    // control came from. All of a block's phis take their values at once, on the way in
+    Phi {
+        dst: Temp,
+        args: Vec<(Label, Operand)>,
+    },
+    Copy {
+        dst: Temp,
+        src: Operand,
+    },
+    Unary {
+        dst: Temp,
+        op: UnaryOp,
+        src: Operand,
+    },
+    Binary {
+        dst: Temp,
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    Checked {
+        dst: Temp,
+        op: CheckedOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    // dst = base + index * scale + offset, in 64 bits
+    Address {
+        dst: Temp,
+        base: Operand,
+        index: Option<(Operand, u32)>,
+        offset: i64,
+    },
+    // Loads of 4 bytes are sign-extended, loads of 1 zero-extended
+    Load {
+        dst: Temp,
+        size: Size,
+        addr: Operand,
+    },
+    Store {
+        size: Size,
+        addr: Operand,
+        value: Operand,
+    },
+    // To a function of the program, one declared in a library, or to the runtime's "alloc(size)"
+    // and "alloc_array(element size, count)", which return zeroed memory and can't clash with
+    // C0 names since they're keywords
+    Call {
+        dst: Option<Temp>,
+        name: String,
+        args: Vec<Operand>,
+    },
+    Jump(Label),
+    Branch {
+        op: CmpOp,
+        lhs: Operand,
+        rhs: Operand,
+        then: Label,
+        els: Label,
+    },
+    Return(Option<Operand>),
+    Raise(Trap),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Temp>,
+    pub body: Vec<Instr>,
+}
+
+// A function the program calls without defining, from a library. What it returns, if anything,
+// comes back like a load of that size, since code compiled elsewhere needn't have extended it
+#[derive(PartialEq, Debug, Clone)]
+pub struct Extern {
+    pub name: String,
+    pub ret: Option<Size>,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Program {
+    pub externs: Vec<Extern>,
+    pub functions: Vec<Function>,
+}
+
+impl Instr {
+    // Whether control never goes on to the next instruction
+    pub fn is_terminator(&self) -> bool {
+        matches!(
+            self,
+            Instr::Jump(_) | Instr::Branch { .. } | Instr::Return(_) | Instr::Raise(_)
+        )
+    }
+
+    // The labels control can go to from here, other than the next instruction
+    pub fn targets(&self) -> Vec<Label> {
+        match self {
+            Instr::Jump(label) => vec![*label],
+            Instr::Branch { then, els, .. } => vec![*then, *els],
+            _ => Vec::new(),
+        }
+    }
+
+    // The temporary written, if any
+    pub fn def(&self) -> Option<Temp> {
+        match self {
+            Instr::Phi { dst, .. }
+            | Instr::Copy { dst, .. }
+            | Instr::Unary { dst, .. }
+            | Instr::Binary { dst, .. }
+            | Instr::Checked { dst, .. }
+            | Instr::Address { dst, .. }
+            | Instr::Load { dst, .. } => Some(*dst),
+            Instr::Call { dst, .. } => *dst,
+            _ => None,
+        }
+    }
+
+    pub fn def_mut(&mut self) -> Option<&mut Temp> {
+        match self {
+            Instr::Phi { dst, .. }
+            | Instr::Copy { dst, .. }
+            | Instr::Unary { dst, .. }
+            | Instr::Binary { dst, .. }
+            | Instr::Checked { dst, .. }
+            | Instr::Address { dst, .. }
+            | Instr::Load { dst, .. } => Some(dst),
+            Instr::Call { dst, .. } => dst.as_mut(),
+            _ => None,
+        }
+    }
+
+    // Every operand read, in order
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Instr::Copy { src, .. } | Instr::Unary { src, .. } => vec![src],
+            Instr::Binary { lhs, rhs, .. }
+            | Instr::Checked { lhs, rhs, .. }
+            | Instr::Branch { lhs, rhs, .. } => vec![lhs, rhs],
+            Instr::Address { base, index, .. } => {
+                let mut operands = vec![base];
+                if let Some((index, _)) = index {
+                    operands.push(index);
+                }
+                operands
+            }
+            Instr::Load { addr, .. } => vec![addr],
+            Instr::Store { addr, value, .. } => vec![addr, value],
+            Instr::Call { args, .. } => args.iter_mut().collect(),
+            Instr::Phi { args, .. } => args.iter_mut().map(|(_, arg)| arg).collect(),
+            Instr::Return(value) => value.iter_mut().collect(),
+            Instr::Raise(Trap::Error(message)) => vec![message],
+            Instr::Label(_) | Instr::Jump(_) | Instr::Raise(_) => Vec::new(),
+        }
+    }
+
+    // Sends control that went to `from` to `to` instead
+    pub fn retarget(&mut self, from: Label, to: Label) {
+        match self {
+            Instr::Jump(label) if *label == from => *label = to,
+            Instr::Branch { then, els, .. } => {
+                for label in [then, els] {
+                    if *label == from {
+                        *label = to;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Every temporary read, in order
+    pub fn uses(&self) -> Vec<Temp> {
+        let mut instr = self.clone();
+        instr
+            .operands_mut()
+            .into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Temp(temp) => Some(*temp),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Function {
+    // One more than the highest temporary mentioned, so that new ones can be made from there
+    pub fn next_temp(&self) -> usize {
+        let temps = self
+            .body
+            .iter()
+            .flat_map(|instr| instr.uses().into_iter().chain(instr.def()));
+        temps
+            .chain(self.params.iter().copied())
+            .map(|Temp(n)| n + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn next_label(&self) -> usize {
+        self.body
+            .iter()
+            .flat_map(|instr| match instr {
+                Instr::Label(label) => vec![*label],
+                _ => instr.targets(),
+            })
+            .map(|Label(n)| n + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "t{}", self.0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L{}", self.0)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Temp(temp) => write!(f, "{}", temp),
+            Operand::Int(n) => write!(f, "{}", n),
+            Operand::Str(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if c.is_ascii_graphic() || c == ' ' => write!(f, "{}", c)?,
+                        c => write!(f, "\\x{:02x}", c as u32)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = match self {
+            Size::Byte => 1,
+            Size::Word => 4,
+            Size::Quad => 8,
+        };
+        write!(f, "{}", bytes)
+    }
+}
+
+impl UnaryOp {
+    #[cfg(test)]
+    pub const ALL: [UnaryOp; 2] = [UnaryOp::Neg, UnaryOp::Not];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "neg",
+            UnaryOp::Not => "not",
+        }
+    }
+}
+
+impl CmpOp {
+    #[cfg(test)]
+    pub const ALL: [CmpOp; 6] = [
+        CmpOp::Eq,
+        CmpOp::Ne,
+        CmpOp::Lt,
+        CmpOp::Le,
+        CmpOp::Gt,
+        CmpOp::Ge,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CmpOp::Eq => "eq",
+            CmpOp::Ne => "ne",
+            CmpOp::Lt => "lt",
+            CmpOp::Le => "le",
+            CmpOp::Gt => "gt",
+            CmpOp::Ge => "ge",
+        }
+    }
+
+    // The comparison that holds exactly when this one doesn't
+    pub fn negate(self) -> CmpOp {
+        match self {
+            CmpOp::Eq => CmpOp::Ne,
+            CmpOp::Ne => CmpOp::Eq,
+            CmpOp::Lt => CmpOp::Ge,
+            CmpOp::Le => CmpOp::Gt,
+            CmpOp::Gt => CmpOp::Le,
+            CmpOp::Ge => CmpOp::Lt,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn holds(self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl BinaryOp {
+    #[cfg(test)]
+    pub const ALL: [BinaryOp; 12] = [
+        BinaryOp::Add,
+        BinaryOp::Sub,
+        BinaryOp::Mul,
+        BinaryOp::And,
+        BinaryOp::Or,
+        BinaryOp::Xor,
+        BinaryOp::Cmp(CmpOp::Eq),
+        BinaryOp::Cmp(CmpOp::Ne),
+        BinaryOp::Cmp(CmpOp::Lt),
+        BinaryOp::Cmp(CmpOp::Le),
+        BinaryOp::Cmp(CmpOp::Gt),
+        BinaryOp::Cmp(CmpOp::Ge),
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::Xor => "xor",
+            BinaryOp::Cmp(op) => op.name(),
+        }
+    }
+}
+
+impl CheckedOp {
+    #[cfg(test)]
+    pub const ALL: [CheckedOp; 4] = [
+        CheckedOp::Div,
+        CheckedOp::Mod,
+        CheckedOp::Shl,
+        CheckedOp::Shr,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CheckedOp::Div => "div",
+            CheckedOp::Mod => "mod",
+            CheckedOp::Shl => "shl",
+            CheckedOp::Shr => "shr",
+        }
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instr::Label(label) => write!(f, "{}:", label),
+            Instr::Phi { dst, args } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|(label, arg)| format!("[{}: {}]", label, arg))
+                    .collect();
+                write!(f, "    {} = phi {}", dst, args.join(", "))
+            }
+            Instr::Copy { dst, src } => write!(f, "    {} = {}", dst, src),
+            Instr::Unary { dst, op, src } => write!(f, "    {} = {} {}", dst, op.name(), src),
+            Instr::Binary { dst, op, lhs, rhs } => {
+                write!(f, "    {} = {} {}, {}", dst, op.name(), lhs, rhs)
+            }
+            Instr::Checked { dst, op, lhs, rhs } => {
+                write!(f, "    {} = {} {}, {}", dst, op.name(), lhs, rhs)
+            }
+            Instr::Address {
+                dst,
+                base,
+                index,
+                offset,
+            } => {
+                write!(f, "    {} = addr {}", dst, base)?;
+                if let Some((index, scale)) = index {
+                    write!(f, " + {} * {}", index, scale)?;
+                }
+                write!(f, " + {}", offset)
+            }
+            Instr::Load { dst, size, addr } => write!(f, "    {} = load.{} {}", dst, size, addr),
+            Instr::Store { size, addr, value } => {
+                write!(f, "    store.{} {}, {}", size, addr, value)
+            }
+            Instr::Call { dst, name, args } => {
+                write!(f, "    ")?;
+                if let Some(dst) = dst {
+                    write!(f, "{} = ", dst)?;
+                }
+                let args: Vec<String> = args.iter().map(Operand::to_string).collect();
+                write!(f, "call {}({})", name, args.join(", "))
+            }
+            Instr::Jump(label) => write!(f, "    jump {}", label),
+            Instr::Branch {
+                op,
+                lhs,
+                rhs,
+                then,
+                els,
+            } => write!(
+                f,
+                "    if {} {}, {} goto {} else {}",
+                op.name(),
+                lhs,
+                rhs,
+                then,
+                els
+            ),
+            Instr::Return(Some(value)) => write!(f, "    return {}", value),
+            Instr::Return(None) => write!(f, "    return"),
+            Instr::Raise(trap) => match trap {
+                Trap::Memory => write!(f, "    raise memory"),
+                Trap::Arithmetic => write!(f, "    raise arithmetic"),
+                Trap::Assert => write!(f, "    raise assert"),
+                Trap::Error(message) => write!(f, "    raise error {}", message),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(Temp::to_string).collect();
+        writeln!(f, "function {}({}) {{", self.name, params.join(", "))?;
+        for instr in &self.body {
+            writeln!(f, "{}", instr)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Extern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "extern {}", self.name)?;
+        if let Some(size) = self.ret {
+            write!(f, ".{}", size)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for extern_ in &self.externs {
+            writeln!(f, "{}", extern_)?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 || !self.externs.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
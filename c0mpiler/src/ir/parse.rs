@@ -0,0 +1,763 @@
+use crate::ir::{
+    BinaryOp, CheckedOp, CmpOp, Extern, Function, Instr, Label, Operand, Program, Size, Temp, Trap,
+    UnaryOp,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// Reads back the text format the IR prints as, so that passes over it can be tested on functions
+// written by hand. One instruction per line, with labels on lines of their own; blank lines and
+// "//" comments are skipped
+#[derive(PartialEq, Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: error: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    // Names, mnemonics like "load.4", temporaries and labels
+    Word(String),
+    Int(i64),
+    Str(String),
+    Punct(char),
+    Newline,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Int(n) => write!(f, "`{}`", n),
+            Token::Str(s) => write!(f, "{}", Operand::Str(s.clone())),
+            Token::Punct(c) => write!(f, "`{}`", c),
+            Token::Newline => write!(f, "end of line"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer<'_> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+
+    fn tokens(mut self) -> Result<Vec<(Token, usize, usize)>, ParseError> {
+        let mut tokens = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            let (line, column) = (self.line, self.column);
+            let token = match c {
+                '\n' => {
+                    self.bump();
+                    Token::Newline
+                }
+                c if c.is_whitespace() => {
+                    self.bump();
+                    continue;
+                }
+                '/' => {
+                    self.bump();
+                    if self.bump() != Some('/') {
+                        return Err(self.error("expected `//` to start a comment".to_string()));
+                    }
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.bump();
+                    }
+                    continue;
+                }
+                '"' => {
+                    self.bump();
+                    Token::Str(self.string().map_err(|message| ParseError {
+                        line,
+                        column,
+                        message,
+                    })?)
+                }
+                c if c == '-' || c.is_ascii_digit() => {
+                    let mut text = String::new();
+                    text.extend(self.bump());
+                    while self.chars.peek().is_some_and(char::is_ascii_digit) {
+                        text.extend(self.bump());
+                    }
+                    let n = text.parse().map_err(|_| ParseError {
+                        line,
+                        column,
+                        message: format!("`{}` is not a number", text),
+                    })?;
+                    Token::Int(n)
+                }
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut word = String::new();
+                    while self
+                        .chars
+                        .peek()
+                        .is_some_and(|&c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+                    {
+                        word.extend(self.bump());
+                    }
+                    Token::Word(word)
+                }
+                '(' | ')' | ',' | '=' | '+' | '*' | ':' | '{' | '}' | '[' | ']' => {
+                    self.bump();
+                    Token::Punct(c)
+                }
+                c => return Err(self.error(format!("unexpected character `{}`", c))),
+            };
+            tokens.push((token, line, column));
+        }
+        tokens.push((Token::Newline, self.line, self.column));
+        Ok(tokens)
+    }
+
+    // The rest of a string constant, with the escapes Display writes. Errors are reported where
+    // the string starts
+    fn string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.bump() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('x') => {
+                        let digits: String =
+                            [self.bump(), self.bump()].into_iter().flatten().collect();
+                        let code = u8::from_str_radix(&digits, 16)
+                            .map_err(|_| format!("bad escape `\\x{}`", digits))?;
+                        s.push(code as char);
+                    }
+                    other => {
+                        let escape: String = other.into_iter().collect();
+                        return Err(format!("bad escape `\\{}`", escape));
+                    }
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<Program, ParseError> {
+    let lexer = Lexer {
+        chars: source.chars().peekable(),
+        line: 1,
+        column: 1,
+    };
+    let mut parser = Parser {
+        tokens: lexer.tokens()?,
+        current: 0,
+    };
+    let mut program = Program::default();
+    loop {
+        parser.newlines();
+        if parser.at_end() {
+            return Ok(program);
+        }
+        if parser.peek_word() == Some("extern") {
+            program.externs.push(parser.extern_()?);
+            parser.end_of_line()?;
+        } else {
+            program.functions.push(parser.function()?);
+        }
+    }
+}
+
+// Every label is defined once, and everything referring to one refers to a label that is defined
+fn check_labels(body: &[Instr], positions: &[(usize, usize)]) -> Result<(), ParseError> {
+    let error = |index: usize, message: String| {
+        let (line, column) = positions[index];
+        ParseError {
+            line,
+            column,
+            message,
+        }
+    };
+    let mut defined = HashMap::new();
+    for (index, instr) in body.iter().enumerate() {
+        if let Instr::Label(label) = instr {
+            if let Some(first) = defined.insert(*label, index) {
+                let first = positions[first].0;
+                return Err(error(
+                    index,
+                    format!("{} is already defined on line {}", label, first),
+                ));
+            }
+        }
+    }
+    for (index, instr) in body.iter().enumerate() {
+        let mut labels = instr.targets();
+        if let Instr::Phi { args, .. } = instr {
+            labels.extend(args.iter().map(|(label, _)| *label));
+        }
+        if let Some(label) = labels.iter().find(|label| !defined.contains_key(label)) {
+            return Err(error(
+                index,
+                format!("{} is not defined in this function", label),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// "t3" and "L3"
+fn numbered(word: &str, prefix: char) -> Option<usize> {
+    let digits = word.strip_prefix(prefix)?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn size(word: &str, mnemonic: &str) -> Option<Size> {
+    match word.strip_prefix(mnemonic)? {
+        ".1" => Some(Size::Byte),
+        ".4" => Some(Size::Word),
+        ".8" => Some(Size::Quad),
+        _ => None,
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize, usize)>,
+    current: usize,
+}
+
+impl Parser {
+    fn at_end(&self) -> bool {
+        self.current + 1 >= self.tokens.len()
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current].0
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Token::Word(word) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek().clone();
+        if !self.at_end() {
+            self.current += 1;
+        }
+        token
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        let (token, line, column) = &self.tokens[self.current];
+        let found = if self.at_end() {
+            "end of input".to_string()
+        } else {
+            token.to_string()
+        };
+        ParseError {
+            line: *line,
+            column: *column,
+            message: format!("expected {}, found {}", expected, found),
+        }
+    }
+
+    fn newlines(&mut self) {
+        while self.peek() == &Token::Newline && !self.at_end() {
+            self.advance();
+        }
+    }
+
+    fn punct(&mut self, c: char) -> Result<(), ParseError> {
+        if self.peek() != &Token::Punct(c) {
+            return Err(self.error(&format!("`{}`", c)));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.peek_word() != Some(keyword) {
+            return Err(self.error(&format!("`{}`", keyword)));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        if self.peek() != &Token::Newline {
+            return Err(self.error("end of line"));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        match self.peek_word() {
+            Some(word) if !word.contains('.') => {
+                let name = word.to_string();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error("a name")),
+        }
+    }
+
+    fn int(&mut self) -> Result<i64, ParseError> {
+        match self.peek() {
+            Token::Int(n) => {
+                let n = *n;
+                self.advance();
+                Ok(n)
+            }
+            _ => Err(self.error("a number")),
+        }
+    }
+
+    fn temp(&mut self) -> Result<Temp, ParseError> {
+        match self.peek_word().and_then(|word| numbered(word, 't')) {
+            Some(n) => {
+                self.advance();
+                Ok(Temp(n))
+            }
+            None => Err(self.error("a temporary")),
+        }
+    }
+
+    fn label(&mut self) -> Result<Label, ParseError> {
+        match self.peek_word().and_then(|word| numbered(word, 'L')) {
+            Some(n) => {
+                self.advance();
+                Ok(Label(n))
+            }
+            None => Err(self.error("a label")),
+        }
+    }
+
+    fn at_operand(&self) -> bool {
+        match self.peek() {
+            Token::Int(_) | Token::Str(_) => true,
+            Token::Word(word) => numbered(word, 't').is_some(),
+            _ => false,
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        match self.peek().clone() {
+            Token::Int(n) => {
+                self.advance();
+                Ok(Operand::Int(n))
+            }
+            Token::Str(s) => {
+                self.advance();
+                Ok(Operand::Str(s))
+            }
+            _ => self
+                .temp()
+                .map(Operand::Temp)
+                .map_err(|_| self.error("an operand")),
+        }
+    }
+
+    // "lhs, rhs"
+    fn pair(&mut self) -> Result<(Operand, Operand), ParseError> {
+        let lhs = self.operand()?;
+        self.punct(',')?;
+        Ok((lhs, self.operand()?))
+    }
+
+    // "extern name", or "extern name.N" for one returning N bytes
+    fn extern_(&mut self) -> Result<Extern, ParseError> {
+        self.keyword("extern")?;
+        let Some(word) = self.peek_word().map(str::to_string) else {
+            return Err(self.error("a name"));
+        };
+        let (name, ret) = match word.split_once('.') {
+            Some((name, _)) => match size(&word, name) {
+                Some(size) => (name.to_string(), Some(size)),
+                None => return Err(self.error("a name")),
+            },
+            None => (word, None),
+        };
+        self.advance();
+        Ok(Extern { name, ret })
+    }
+
+    fn function(&mut self) -> Result<Function, ParseError> {
+        self.keyword("function")?;
+        let name = self.name()?;
+        self.punct('(')?;
+        let mut params = Vec::new();
+        if self.peek() != &Token::Punct(')') {
+            params.push(self.temp()?);
+            while self.peek() == &Token::Punct(',') {
+                self.advance();
+                params.push(self.temp()?);
+            }
+        }
+        self.punct(')')?;
+        self.punct('{')?;
+        self.end_of_line()?;
+        let mut body = Vec::new();
+        // Where each instruction starts, for complaining about its labels once they're all known
+        let mut positions = Vec::new();
+        loop {
+            self.newlines();
+            if self.peek() == &Token::Punct('}') {
+                self.advance();
+                break;
+            }
+            if self.at_end() {
+                return Err(self.error("`}`"));
+            }
+            let (_, line, column) = self.tokens[self.current];
+            positions.push((line, column));
+            body.push(self.instr()?);
+            self.end_of_line()?;
+        }
+        check_labels(&body, &positions)?;
+        Ok(Function { name, params, body })
+    }
+
+    fn instr(&mut self) -> Result<Instr, ParseError> {
+        if let Some(word) = self.peek_word() {
+            if numbered(word, 'L').is_some() {
+                let label = self.label()?;
+                self.punct(':')?;
+                return Ok(Instr::Label(label));
+            }
+            if numbered(word, 't').is_some() {
+                let dst = self.temp()?;
+                self.punct('=')?;
+                return self.value(dst);
+            }
+        }
+        let Some(word) = self.peek_word().map(str::to_string) else {
+            return Err(self.error("an instruction"));
+        };
+        if let Some(size) = size(&word, "store") {
+            self.advance();
+            let (addr, value) = self.pair()?;
+            return Ok(Instr::Store { size, addr, value });
+        }
+        match word.as_str() {
+            "call" => self.call(None),
+            "jump" => {
+                self.advance();
+                Ok(Instr::Jump(self.label()?))
+            }
+            "if" => {
+                self.advance();
+                let op = self.cmp_op()?;
+                let (lhs, rhs) = self.pair()?;
+                self.keyword("goto")?;
+                let then = self.label()?;
+                self.keyword("else")?;
+                let els = self.label()?;
+                Ok(Instr::Branch {
+                    op,
+                    lhs,
+                    rhs,
+                    then,
+                    els,
+                })
+            }
+            "return" => {
+                self.advance();
+                if self.at_operand() {
+                    Ok(Instr::Return(Some(self.operand()?)))
+                } else {
+                    Ok(Instr::Return(None))
+                }
+            }
+            "raise" => {
+                self.advance();
+                let trap = match self.peek_word() {
+                    Some("memory") => Trap::Memory,
+                    Some("arithmetic") => Trap::Arithmetic,
+                    Some("assert") => Trap::Assert,
+                    Some("error") => {
+                        self.advance();
+                        return Ok(Instr::Raise(Trap::Error(self.operand()?)));
+                    }
+                    _ => return Err(self.error("`memory`, `arithmetic`, `assert` or `error`")),
+                };
+                self.advance();
+                Ok(Instr::Raise(trap))
+            }
+            _ => Err(self.error("an instruction")),
+        }
+    }
+
+    fn cmp_op(&mut self) -> Result<CmpOp, ParseError> {
+        let word = self.peek_word();
+        match CmpOp::ALL.into_iter().find(|op| Some(op.name()) == word) {
+            Some(op) => {
+                self.advance();
+                Ok(op)
+            }
+            None => Err(self.error("a comparison")),
+        }
+    }
+
+    // Whatever follows "t = "
+    fn value(&mut self, dst: Temp) -> Result<Instr, ParseError> {
+        if self.at_operand() {
+            return Ok(Instr::Copy {
+                dst,
+                src: self.operand()?,
+            });
+        }
+        let Some(word) = self.peek_word().map(str::to_string) else {
+            return Err(self.error("an operand or an operation"));
+        };
+        if let Some(size) = size(&word, "load") {
+            self.advance();
+            let addr = self.operand()?;
+            return Ok(Instr::Load { dst, size, addr });
+        }
+        if let Some(op) = UnaryOp::ALL.into_iter().find(|op| op.name() == word) {
+            self.advance();
+            let src = self.operand()?;
+            return Ok(Instr::Unary { dst, op, src });
+        }
+        if let Some(op) = BinaryOp::ALL.into_iter().find(|op| op.name() == word) {
+            self.advance();
+            let (lhs, rhs) = self.pair()?;
+            return Ok(Instr::Binary { dst, op, lhs, rhs });
+        }
+        if let Some(op) = CheckedOp::ALL.into_iter().find(|op| op.name() == word) {
+            self.advance();
+            let (lhs, rhs) = self.pair()?;
+            return Ok(Instr::Checked { dst, op, lhs, rhs });
+        }
+        match word.as_str() {
+            "call" => self.call(Some(dst)),
+            "phi" => {
+                self.advance();
+                let mut args = vec![self.phi_arg()?];
+                while self.peek() == &Token::Punct(',') {
+                    self.advance();
+                    args.push(self.phi_arg()?);
+                }
+                Ok(Instr::Phi { dst, args })
+            }
+            "addr" => {
+                self.advance();
+                let base = self.operand()?;
+                self.punct('+')?;
+                let mut index = None;
+                if self.at_operand() && !matches!(self.peek(), Token::Int(_)) {
+                    let operand = self.operand()?;
+                    self.punct('*')?;
+                    let scale = self.int()?;
+                    let scale = u32::try_from(scale).map_err(|_| self.error("a scale"))?;
+                    index = Some((operand, scale));
+                    self.punct('+')?;
+                }
+                let offset = self.int()?;
+                Ok(Instr::Address {
+                    dst,
+                    base,
+                    index,
+                    offset,
+                })
+            }
+            _ => Err(self.error("an operand or an operation")),
+        }
+    }
+
+    // "[L0: t1]"
+    fn phi_arg(&mut self) -> Result<(Label, Operand), ParseError> {
+        self.punct('[')?;
+        let label = self.label()?;
+        self.punct(':')?;
+        let arg = self.operand()?;
+        self.punct(']')?;
+        Ok((label, arg))
+    }
+
+    fn call(&mut self, dst: Option<Temp>) -> Result<Instr, ParseError> {
+        self.keyword("call")?;
+        let name = self.name()?;
+        self.punct('(')?;
+        let mut args = Vec::new();
+        if self.peek() != &Token::Punct(')') {
+            args.push(self.operand()?);
+            while self.peek() == &Token::Punct(',') {
+                self.advance();
+                args.push(self.operand()?);
+            }
+        }
+        self.punct(')')?;
+        Ok(Instr::Call { dst, name, args })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back() {
+        let source = "\
+extern print
+extern string_length.4
+
+function sum(t0, t1) {
+    t2 = add t0, t1
+    t3 = div t2, -2
+    t4 = shl t3, t1
+    t5 = not t4
+    t6 = lt t5, t2
+    if ge t6, 0 goto L0 else L1
+L0:
+    raise arithmetic
+L1:
+    t7 = call clamp(t3, \"say \\\"hi\\\"\\n\\x01\")
+    call flush()
+    t8 = addr t0 + t7 * 4 + 8
+    t9 = addr t8 + -16
+    store.4 t8, t3
+    t10 = load.1 t9
+    raise error \"no\"
+    jump L1
+    return t10
+}
+
+function main() {
+    return
+}
+
+function ssa(t0) {
+L0:
+    jump L1
+L1:
+    t1 = phi [L0: t0], [L1: t2]
+    t3 = phi [L0: 0], [L1: t1]
+    t2 = sub t1, 1
+    if gt t2, 0 goto L1 else L2
+L2:
+    return t3
+}
+";
+        let program = parse(source).unwrap();
+        assert_eq!(program.to_string(), source);
+        assert_eq!(
+            program.externs[1],
+            Extern {
+                name: "string_length".to_string(),
+                ret: Some(Size::Word)
+            }
+        );
+        let sum = &program.functions[0];
+        assert_eq!(sum.params, vec![Temp(0), Temp(1)]);
+        assert_eq!(
+            sum.body[1],
+            Instr::Checked {
+                dst: Temp(3),
+                op: CheckedOp::Div,
+                lhs: Operand::Temp(Temp(2)),
+                rhs: Operand::Int(-2)
+            }
+        );
+        assert_eq!(
+            sum.body[9],
+            Instr::Call {
+                dst: Some(Temp(7)),
+                name: "clamp".to_string(),
+                args: vec![
+                    Operand::Temp(Temp(3)),
+                    Operand::Str("say \"hi\"\n\x01".to_string())
+                ]
+            }
+        );
+        assert_eq!(
+            sum.body[12],
+            Instr::Address {
+                dst: Temp(9),
+                base: Operand::Temp(Temp(8)),
+                index: None,
+                offset: -16
+            }
+        );
+        assert_eq!(sum.next_temp(), 11);
+        assert_eq!(sum.next_label(), 2);
+
+        // written by hand, with comments and blank lines
+        let loose = "// counts down\nfunction f(t0) {\n\n  L0:  // top\n  if gt t0, 0 goto L1 else L2\nL1:\n t0 = sub t0, 1\n jump L0\nL2:\n return t0\n}";
+        assert_eq!(
+            parse(loose).unwrap().to_string(),
+            "function f(t0) {\nL0:\n    if gt t0, 0 goto L1 else L2\nL1:\n    t0 = sub t0, 1\n    jump L0\nL2:\n    return t0\n}\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error("function f() {\n    t1 = frob t0\n}"),
+            "2:10: error: expected an operand or an operation, found `frob`"
+        );
+        assert_eq!(
+            error("function f() {\n    store.2 t0, t1\n}"),
+            "2:5: error: expected an instruction, found `store.2`"
+        );
+        assert_eq!(
+            error("function f() {\n    if lt t0, t1 goto L1\n}"),
+            "2:25: error: expected `else`, found end of line"
+        );
+        assert_eq!(
+            error("function f() {\n    return t0 t1\n}"),
+            "2:15: error: expected end of line, found `t1`"
+        );
+        assert_eq!(
+            error("function f() {\n    t0 = \"open\n}"),
+            "2:10: error: unterminated string"
+        );
+        assert_eq!(
+            error("function f() {\n    return\n"),
+            "3:1: error: expected `}`, found end of input"
+        );
+        assert_eq!(
+            error("function f() {\nL0:\n    jump L9\n}"),
+            "3:5: error: L9 is not defined in this function"
+        );
+        assert_eq!(
+            error("function f() {\nL0:\n    jump L0\nL0:\n    return\n}"),
+            "4:1: error: L0 is already defined on line 2"
+        );
+    }
+}
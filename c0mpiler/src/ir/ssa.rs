@@ -0,0 +1,498 @@
+use crate::ir::cfg::{Block, Cfg, Dominators};
+use crate::ir::{Instr, Label, Operand, Temp};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Static single assignment form, where every temporary is written exactly once, so that passes
+// after it can tie each use to the one place its value comes from
+//
+// Going in follows Cytron et al.: a temporary needs a phi wherever the values of two of its writes
+// meet, which is the iterated dominance frontier of the blocks writing it. Of those, only the
+// blocks where it's live get one, which keeps out the phis nothing would read. Renaming walks the
+// dominator tree, numbering the temporaries again from t0 in the order they're written, the
+// parameters first. A temporary that reaches a phi without being written on the way reads as 0,
+// which a checked program never does
+//
+// Coming out, each phi becomes a copy at the end of each predecessor. A predecessor with other
+// successors gets a block of its own on the edge first; otherwise the copy would also clobber the
+// phi's temporary on the way to those, where the old value may still be needed (the lost-copy
+// problem). The copies into one block happen at once, so they're ordered so that no temporary is
+// written before every copy reading it is done, going through a fresh temporary to break any
+// cycle, like two phis swapping their values (the swap problem)
+pub fn into_ssa(mut cfg: Cfg) -> Cfg {
+    let dominators = cfg.dominators();
+    place_phis(&mut cfg, &dominators);
+    let mut renamer = Renamer {
+        stacks: HashMap::new(),
+        next: 0,
+    };
+    let params = std::mem::take(&mut cfg.params);
+    cfg.params = params
+        .into_iter()
+        .map(|param| renamer.fresh(param))
+        .collect();
+    renamer.block(&mut cfg, &dominators, 0);
+    cfg
+}
+
+fn place_phis(cfg: &mut Cfg, dominators: &Dominators) {
+    let liveness = cfg.liveness();
+    let mut sites: BTreeMap<Temp, BTreeSet<usize>> = BTreeMap::new();
+    for &param in &cfg.params {
+        sites.entry(param).or_default().insert(0);
+    }
+    for (index, block) in cfg.blocks.iter().enumerate() {
+        for instr in &block.body {
+            if let Some(temp) = instr.def() {
+                sites.entry(temp).or_default().insert(index);
+            }
+        }
+    }
+    let mut phis: Vec<Vec<Temp>> = vec![Vec::new(); cfg.blocks.len()];
+    for (temp, sites) in sites {
+        let mut work: Vec<usize> = sites.iter().copied().collect();
+        let mut placed = BTreeSet::new();
+        while let Some(block) = work.pop() {
+            for &frontier in &dominators.frontiers[block] {
+                if placed.contains(&frontier) || !liveness.live_in[frontier].contains(&temp) {
+                    continue;
+                }
+                placed.insert(frontier);
+                phis[frontier].push(temp);
+                if !sites.contains(&frontier) {
+                    work.push(frontier);
+                }
+            }
+        }
+    }
+    for (index, temps) in phis.into_iter().enumerate() {
+        let preds: Vec<Label> = cfg.preds[index]
+            .iter()
+            .map(|&pred| cfg.blocks[pred].label)
+            .collect();
+        let phis = temps.into_iter().map(|temp| Instr::Phi {
+            dst: temp,
+            args: preds
+                .iter()
+                .map(|&pred| (pred, Operand::Temp(temp)))
+                .collect(),
+        });
+        cfg.blocks[index].body.splice(0..0, phis);
+    }
+}
+
+// The current name of each temporary of the original function, innermost last
+struct Renamer {
+    stacks: HashMap<Temp, Vec<Temp>>,
+    next: usize,
+}
+
+impl Renamer {
+    fn fresh(&mut self, temp: Temp) -> Temp {
+        let name = Temp(self.next);
+        self.next += 1;
+        self.stacks.entry(temp).or_default().push(name);
+        name
+    }
+
+    fn current(&self, temp: Temp) -> Operand {
+        match self.stacks.get(&temp).and_then(|stack| stack.last()) {
+            Some(&name) => Operand::Temp(name),
+            None => Operand::Int(0),
+        }
+    }
+
+    fn rename(&self, operand: &mut Operand) {
+        if let Operand::Temp(temp) = operand {
+            *operand = self.current(*temp);
+        }
+    }
+
+    fn block(&mut self, cfg: &mut Cfg, dominators: &Dominators, index: usize) {
+        let mut written = Vec::new();
+        for instr in &mut cfg.blocks[index].body {
+            if !matches!(instr, Instr::Phi { .. }) {
+                for operand in instr.operands_mut() {
+                    self.rename(operand);
+                }
+            }
+            if let Some(dst) = instr.def_mut() {
+                written.push(*dst);
+                *dst = self.fresh(*dst);
+            }
+        }
+        let label = cfg.blocks[index].label;
+        for succ in cfg.succs[index].clone() {
+            for instr in &mut cfg.blocks[succ].body {
+                let Instr::Phi { args, .. } = instr else {
+                    break;
+                };
+                for (_, arg) in args.iter_mut().filter(|(from, _)| *from == label) {
+                    self.rename(arg);
+                }
+            }
+        }
+        for &child in &dominators.children[index] {
+            self.block(cfg, dominators, child);
+        }
+        for temp in written {
+            self.stacks.get_mut(&temp).unwrap().pop();
+        }
+    }
+}
+
+pub fn out_of_ssa(mut cfg: Cfg) -> Cfg {
+    let function = cfg.to_function();
+    let (mut next_label, mut next_temp) = (function.next_label(), function.next_temp());
+    split_edges(&mut cfg, &mut next_label);
+
+    let mut copies: Vec<Vec<(Temp, Operand)>> = vec![Vec::new(); cfg.blocks.len()];
+    for index in 0..cfg.blocks.len() {
+        let phis: Vec<Instr> = {
+            let body = &mut cfg.blocks[index].body;
+            let count = body
+                .iter()
+                .take_while(|instr| matches!(instr, Instr::Phi { .. }))
+                .count();
+            body.drain(..count).collect()
+        };
+        for phi in phis {
+            let Instr::Phi { dst, args } = phi else {
+                unreachable!()
+            };
+            for (label, arg) in args {
+                let pred = cfg
+                    .index(label)
+                    .expect("a phi's operands come from predecessors");
+                copies[pred].push((dst, arg));
+            }
+        }
+    }
+    for (block, copies) in cfg.blocks.iter_mut().zip(copies) {
+        let mut fresh = || {
+            next_temp += 1;
+            Temp(next_temp - 1)
+        };
+        let at = block.body.len() - 1;
+        block.body.splice(at..at, sequentialize(copies, &mut fresh));
+    }
+    cfg
+}
+
+// Puts a block on every edge into a block with phis from one with more than one successor
+fn split_edges(cfg: &mut Cfg, next_label: &mut usize) {
+    for to in 0..cfg.blocks.len() {
+        if !matches!(cfg.blocks[to].body.first(), Some(Instr::Phi { .. })) {
+            continue;
+        }
+        let target = cfg.blocks[to].label;
+        for from in cfg.preds[to].clone() {
+            if cfg.succs[from].len() < 2 {
+                continue;
+            }
+            let label = Label(*next_label);
+            *next_label += 1;
+            let source = cfg.blocks[from].label;
+            cfg.blocks[from]
+                .body
+                .last_mut()
+                .unwrap()
+                .retarget(target, label);
+            for instr in &mut cfg.blocks[to].body {
+                if let Instr::Phi { args, .. } = instr {
+                    for (pred, _) in args.iter_mut().filter(|(pred, _)| *pred == source) {
+                        *pred = label;
+                    }
+                }
+            }
+            cfg.blocks.push(Block {
+                label,
+                body: vec![Instr::Jump(target)],
+            });
+        }
+    }
+    cfg.connect();
+}
+
+// Orders copies that all happen at once into ones that happen one after another. A copy can go
+// once nothing left reads what it writes; when every copy left is waiting on another, they form
+// cycles, and one of them has its value saved to a fresh temporary for the others to read
+pub fn sequentialize(copies: Vec<(Temp, Operand)>, fresh: &mut impl FnMut() -> Temp) -> Vec<Instr> {
+    let mut pending: Vec<(Temp, Operand)> = copies
+        .into_iter()
+        .filter(|(dst, src)| *src != Operand::Temp(*dst))
+        .collect();
+    let mut sequence = Vec::new();
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|(dst, _)| !pending.iter().any(|(_, src)| *src == Operand::Temp(*dst)));
+        match ready {
+            Some(index) => {
+                let (dst, src) = pending.remove(index);
+                sequence.push(Instr::Copy { dst, src });
+            }
+            None => {
+                let (dst, _) = pending[0];
+                let saved = fresh();
+                sequence.push(Instr::Copy {
+                    dst: saved,
+                    src: Operand::Temp(dst),
+                });
+                for (_, src) in &mut pending {
+                    if *src == Operand::Temp(dst) {
+                        *src = Operand::Temp(saved);
+                    }
+                }
+            }
+        }
+    }
+    sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::interp::run;
+    use crate::ir::lower::tests::lowered;
+    use crate::ir::parse::parse;
+    use crate::ir::Function;
+
+    // Whether no temporary is written more than once, counting the parameters as written on entry
+    fn is_ssa(function: &Function) -> bool {
+        let mut written = BTreeSet::new();
+        let params = function.params.iter().copied();
+        params
+            .chain(function.body.iter().filter_map(Instr::def))
+            .all(|temp| written.insert(temp))
+    }
+
+    fn cfg(source: &str) -> Cfg {
+        Cfg::new(&parse(source).unwrap().functions[0])
+    }
+
+    fn function(cfg: &Cfg) -> crate::ir::Program {
+        crate::ir::Program {
+            externs: Vec::new(),
+            functions: vec![cfg.to_function()],
+        }
+    }
+
+    #[test]
+    fn construction() {
+        // t2 is only ever read right where it's written, so needs no phi
+        let ssa = into_ssa(cfg("function f(t0) {\n\
+                                L0:\n\
+                                    t1 = 0\n\
+                                    jump L1\n\
+                                L1:\n\
+                                    if lt t1, t0 goto L2 else L5\n\
+                                L2:\n\
+                                    t2 = mul t1, t1\n\
+                                    if eq t2, 9 goto L3 else L4\n\
+                                L3:\n\
+                                    t1 = add t1, 2\n\
+                                L4:\n\
+                                    t1 = add t1, 1\n\
+                                    jump L1\n\
+                                L5:\n\
+                                    return t1\n\
+                                }"));
+        assert_eq!(
+            ssa.to_function().to_string(),
+            "function f(t0) {
+L0:
+    t1 = 0
+    jump L1
+L1:
+    t2 = phi [L0: t1], [L4: t6]
+    if lt t2, t0 goto L2 else L5
+L2:
+    t3 = mul t2, t2
+    if eq t3, 9 goto L3 else L4
+L3:
+    t4 = add t2, 2
+    jump L4
+L4:
+    t5 = phi [L2: t2], [L3: t4]
+    t6 = add t5, 1
+    jump L1
+L5:
+    return t2
+}
+"
+        );
+        assert!(is_ssa(&ssa.to_function()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let sources = [
+            "int main() {\n\
+               int a = 1; int b = 2; int n = 0;\n\
+               while (n < 10) { int t = a; a = b; b = t + b; n++; }\n\
+               return a * 1000 + b;\n\
+             }",
+            "int gcd(int a, int b) { while (b != 0) { int r = a % b; a = b; b = r; } return a; }\n\
+             int main() { int x = gcd(1071, 462); bool big = x > 10 && x < 100; return big ? x : -x; }",
+            "int main() {\n\
+               int[] a = alloc_array(int, 10);\n\
+               for (int i = 0; i < 10; i++) a[i] = 10 - i;\n\
+               for (int i = 0; i < 10; i++)\n\
+                 for (int j = 0; j + 1 < 10 - i; j++)\n\
+                   if (a[j] > a[j + 1]) { int t = a[j]; a[j] = a[j + 1]; a[j + 1] = t; }\n\
+               int s = 0;\n\
+               for (int i = 0; i < 10; i++) { if (i == 7) break; s = s * 2 + a[i]; }\n\
+               return s;\n\
+             }",
+            "int main() { int x = 0; for (int i = 0; i < 5; i++) { if (i == 2) continue; x += i; } assert(x == 8); return x / (x - 8); }",
+        ];
+        for source in sources {
+            let program = lowered(source);
+            let expected = run(&program, "main", &[]);
+            let mut ssa = program.clone();
+            let mut back = program.clone();
+            for ((original, ssa), back) in program
+                .functions
+                .iter()
+                .zip(&mut ssa.functions)
+                .zip(&mut back.functions)
+            {
+                let converted = into_ssa(Cfg::new(original));
+                *ssa = converted.to_function();
+                assert!(is_ssa(ssa), "{}", ssa);
+                *back = out_of_ssa(converted).to_function();
+                assert!(!back.body.iter().any(|i| matches!(i, Instr::Phi { .. })));
+            }
+            assert_eq!(run(&ssa, "main", &[]), expected, "{}", ssa);
+            assert_eq!(run(&back, "main", &[]), expected, "{}", back);
+        }
+    }
+
+    // The phi's old value is still needed after the loop, while the back edge overwrites it
+    #[test]
+    fn lost_copy() {
+        let ssa = cfg("function f(t0) {\n\
+                       L0:\n\
+                           jump L1\n\
+                       L1:\n\
+                           t1 = phi [L0: t0], [L1: t2]\n\
+                           t2 = add t1, 1\n\
+                           if lt t2, 10 goto L1 else L2\n\
+                       L2:\n\
+                           return t1\n\
+                       }");
+        let back = out_of_ssa(ssa.clone());
+        assert_eq!(
+            back.to_function().to_string(),
+            "function f(t0) {
+L0:
+    t1 = t0
+    jump L1
+L1:
+    t2 = add t1, 1
+    if lt t2, 10 goto L3 else L2
+L2:
+    return t1
+L3:
+    t1 = t2
+    jump L1
+}
+"
+        );
+        for n in [0, 5, 20] {
+            assert_eq!(run(&function(&back), "f", &[n]), Ok(Some(n.max(9))));
+            assert_eq!(run(&function(&ssa), "f", &[n]), Ok(Some(n.max(9))));
+        }
+    }
+
+    // The phis swap their values on every trip round the loop
+    #[test]
+    fn swap() {
+        let ssa = cfg("function f(t0, t1, t2) {\n\
+                       L0:\n\
+                           jump L1\n\
+                       L1:\n\
+                           t3 = phi [L0: t0], [L1: t4]\n\
+                           t4 = phi [L0: t1], [L1: t3]\n\
+                           t5 = phi [L0: t2], [L1: t6]\n\
+                           t6 = sub t5, 1\n\
+                           if gt t6, 0 goto L1 else L2\n\
+                       L2:\n\
+                           t7 = mul t3, 10\n\
+                           t8 = add t7, t4\n\
+                           return t8\n\
+                       }");
+        let back = out_of_ssa(ssa.clone());
+        assert_eq!(
+            back.to_function().to_string(),
+            "function f(t0, t1, t2) {
+L0:
+    t3 = t0
+    t4 = t1
+    t5 = t2
+    jump L1
+L1:
+    t6 = sub t5, 1
+    if gt t6, 0 goto L3 else L2
+L2:
+    t7 = mul t3, 10
+    t8 = add t7, t4
+    return t8
+L3:
+    t5 = t6
+    t9 = t3
+    t3 = t4
+    t4 = t9
+    jump L1
+}
+"
+        );
+        for (trips, expected) in [(1, 12), (2, 21), (3, 12)] {
+            assert_eq!(
+                run(&function(&ssa), "f", &[1, 2, trips]),
+                Ok(Some(expected))
+            );
+            assert_eq!(
+                run(&function(&back), "f", &[1, 2, trips]),
+                Ok(Some(expected))
+            );
+        }
+    }
+
+    #[test]
+    fn sequences() {
+        let mut next = 100;
+        let mut fresh = || {
+            next += 1;
+            Temp(next - 1)
+        };
+        let copy = |dst: usize, src: usize| (Temp(dst), Operand::Temp(Temp(src)));
+        let printed = |instrs: Vec<Instr>| -> Vec<String> {
+            instrs
+                .iter()
+                .map(|i| i.to_string().trim().to_string())
+                .collect()
+        };
+        // a chain goes from its end, and a copy to itself is dropped
+        assert_eq!(
+            printed(sequentialize(
+                vec![
+                    copy(1, 2),
+                    copy(2, 3),
+                    copy(4, 4),
+                    (Temp(3), Operand::Int(7))
+                ],
+                &mut fresh
+            )),
+            vec!["t1 = t2", "t2 = t3", "t3 = 7"]
+        );
+        // a rotation of three, with a branch off it
+        assert_eq!(
+            printed(sequentialize(
+                vec![copy(1, 2), copy(2, 3), copy(3, 1), copy(5, 1)],
+                &mut fresh
+            )),
+            vec!["t5 = t1", "t100 = t1", "t1 = t2", "t2 = t3", "t3 = t100"]
+        );
+    }
+}
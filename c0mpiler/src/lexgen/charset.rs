@@ -0,0 +1,209 @@
+use std::fmt;
+
+// The alphabet. Each ASCII character is a class of its own, and every other character falls in
+// one of three classes according to whether identifiers and numbers may contain it
+pub const CLASSES: usize = 131;
+pub const NON_ASCII_ALPHABETIC: usize = 128;
+pub const NON_ASCII_ALPHANUMERIC: usize = 129;
+pub const NON_ASCII_OTHER: usize = 130;
+
+pub fn class(c: char) -> usize {
+    if c.is_ascii() {
+        c as usize
+    } else if c.is_alphabetic() {
+        NON_ASCII_ALPHABETIC
+    } else if c.is_alphanumeric() {
+        NON_ASCII_ALPHANUMERIC
+    } else {
+        NON_ASCII_OTHER
+    }
+}
+
+// A character in the given class, so that transitions can be labelled with ordinary predicates on
+// characters
+pub fn representative(class: usize) -> char {
+    match class {
+        NON_ASCII_ALPHABETIC => 'é',
+        NON_ASCII_ALPHANUMERIC => '²',
+        NON_ASCII_OTHER => '€',
+        _ => class as u8 as char,
+    }
+}
+
+// A set of classes, one bit each
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct CharSet([u64; 3]);
+
+impl CharSet {
+    pub fn empty() -> CharSet {
+        CharSet::default()
+    }
+
+    #[cfg(test)]
+    pub fn all() -> CharSet {
+        CharSet::empty().complement()
+    }
+
+    pub fn single(c: char) -> CharSet {
+        let mut set = CharSet::empty();
+        set.insert(class(c));
+        set
+    }
+
+    // Only ASCII ranges make sense, as every other character stands for its whole class
+    pub fn range(first: char, last: char) -> CharSet {
+        let mut set = CharSet::empty();
+        for c in first..=last {
+            set.insert(class(c));
+        }
+        set
+    }
+
+    pub fn matching(predicate: impl Fn(char) -> bool) -> CharSet {
+        let mut set = CharSet::empty();
+        for class in 0..CLASSES {
+            if predicate(representative(class)) {
+                set.insert(class);
+            }
+        }
+        set
+    }
+
+    // The classes named in bracket expressions, as in "[[:alpha:]_]"
+    pub fn named(name: &str) -> Option<CharSet> {
+        let set = match name {
+            "alpha" => CharSet::matching(char::is_alphabetic),
+            "alnum" => CharSet::matching(char::is_alphanumeric),
+            "digit" => CharSet::matching(|c| c.is_ascii_digit()),
+            "xdigit" => CharSet::matching(|c| c.is_ascii_hexdigit()),
+            "blank" => CharSet::matching(|c| c == ' ' || c == '\t'),
+            _ => return None,
+        };
+        Some(set)
+    }
+
+    pub fn insert(&mut self, class: usize) {
+        self.0[class / 64] |= 1 << (class % 64);
+    }
+
+    pub fn contains(&self, class: usize) -> bool {
+        self.0[class / 64] & (1 << (class % 64)) != 0
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0; 3]
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn union(&self, other: &CharSet) -> CharSet {
+        CharSet([
+            self.0[0] | other.0[0],
+            self.0[1] | other.0[1],
+            self.0[2] | other.0[2],
+        ])
+    }
+
+    pub fn complement(&self) -> CharSet {
+        let mut set = CharSet([!self.0[0], !self.0[1], !self.0[2]]);
+        // Clear the bits past the last class
+        set.0[2] &= (1 << (CLASSES - 128)) - 1;
+        set
+    }
+
+    pub fn classes(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..CLASSES).filter(|class| self.contains(*class))
+    }
+}
+
+// Written as a bracket expression, complemented when that is shorter, with runs of ASCII
+// characters collapsed into ranges. Used to label edges in the Graphviz output
+impl fmt::Display for CharSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.len() == 1 {
+            let class = self.classes().next().unwrap();
+            return write!(f, "{}", describe(class));
+        }
+        let (negated, set) = if self.len() > CLASSES / 2 {
+            ("^", self.complement())
+        } else {
+            ("", *self)
+        };
+        let mut text = String::new();
+        let mut classes = set.classes().peekable();
+        while let Some(first) = classes.next() {
+            let mut last = first;
+            while first < 128 && classes.peek() == Some(&(last + 1)) && last + 1 < 128 {
+                last = classes.next().unwrap();
+            }
+            text.push_str(&describe(first));
+            if last > first + 1 {
+                text.push('-');
+            }
+            if last > first {
+                text.push_str(&describe(last));
+            }
+        }
+        write!(f, "[{}{}]", negated, text)
+    }
+}
+
+impl fmt::Debug for CharSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+fn describe(class: usize) -> String {
+    match class {
+        NON_ASCII_ALPHABETIC => "[:^ascii:alpha:]".to_string(),
+        NON_ASCII_ALPHANUMERIC => "[:^ascii:digit:]".to_string(),
+        NON_ASCII_OTHER => "[:^ascii:other:]".to_string(),
+        _ => match class as u8 as char {
+            '\n' => "\\n".to_string(),
+            '\t' => "\\t".to_string(),
+            '\r' => "\\r".to_string(),
+            ' ' => "' '".to_string(),
+            c if c.is_ascii_graphic() => c.to_string(),
+            c => format!("\\x{:02x}", c as u8),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets() {
+        let alpha = CharSet::named("alpha").unwrap();
+        assert!(alpha.contains(class('a')) && alpha.contains(class('Z')));
+        assert!(alpha.contains(class('é')) && !alpha.contains(class('²')));
+        assert!(!alpha.contains(class('_')) && !alpha.contains(class('0')));
+        assert_eq!(alpha.len(), 53);
+        assert_eq!(CharSet::all().len(), CLASSES);
+        assert!(CharSet::all().complement().is_empty());
+        assert_eq!(
+            CharSet::range('a', 'c').union(&CharSet::single('x')),
+            CharSet::matching(|c| "abcx".contains(c))
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(CharSet::single('\n').to_string(), "\\n");
+        assert_eq!(CharSet::range('0', '9').to_string(), "[0-9]");
+        assert_eq!(
+            CharSet::matching(|c| "ab_".contains(c)).to_string(),
+            "[_ab]"
+        );
+        assert_eq!(CharSet::single('"').complement().to_string(), "[^\"]");
+        assert_eq!(
+            CharSet::named("alpha").unwrap().to_string(),
+            "[A-Za-z[:^ascii:alpha:]]"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use crate::lexgen::charset::{class, CharSet, CLASSES};
+use crate::lexgen::nfa::Nfa;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Deterministic automata. Subset construction turns an NFA into one, each state standing for the
+// set of NFA states the NFA could be in, and Hopcroft's algorithm then merges the states no input
+// can tell apart
+//
+// The automata are complete: every state has a transition on every class, those that can no
+// longer lead to a match going to the dead state, if there is one
+#[derive(Debug, Clone)]
+pub struct Dfa {
+    pub transitions: Vec<[usize; CLASSES]>,
+    // Which rule each state accepts for
+    pub accepts: Vec<Option<usize>>,
+    pub start: usize,
+    pub dead: Option<usize>,
+}
+
+impl Dfa {
+    // Subset construction. A state accepting for several rules accepts for the one listed first
+    pub fn from_nfa(nfa: &Nfa) -> Dfa {
+        let mut transitions = Vec::new();
+        let mut accepts = Vec::new();
+        let mut ids: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut subsets = vec![nfa.closure(&[nfa.start])];
+        ids.insert(subsets[0].clone(), 0);
+
+        let mut index = 0;
+        while index < subsets.len() {
+            let subset = subsets[index].clone();
+            accepts.push(
+                subset
+                    .iter()
+                    .filter_map(|state| nfa.states[*state].accept)
+                    .min(),
+            );
+            let mut row = [0; CLASSES];
+            for (class, next) in row.iter_mut().enumerate() {
+                let targets: Vec<usize> = subset
+                    .iter()
+                    .flat_map(|state| &nfa.states[*state].edges)
+                    .filter(|(set, _)| set.contains(class))
+                    .map(|(_, to)| *to)
+                    .collect();
+                let target = nfa.closure(&targets);
+                *next = match ids.get(&target) {
+                    Some(id) => *id,
+                    None => {
+                        ids.insert(target.clone(), subsets.len());
+                        subsets.push(target);
+                        subsets.len() - 1
+                    }
+                };
+            }
+            transitions.push(row);
+            index += 1;
+        }
+
+        let dead = ids.get(&Vec::new()).copied();
+        Dfa {
+            transitions,
+            accepts,
+            start: 0,
+            dead,
+        }
+    }
+
+    // Hopcroft's algorithm. States start out split by the rule they accept for, and a block of
+    // the partition is split whenever some class takes part of it into a block (the "splitter")
+    // and the rest elsewhere. Only the smaller half of a split needs to be used as a splitter
+    // later, which is what makes this O(n log n)
+    pub fn minimize(&self) -> Dfa {
+        let count = self.transitions.len();
+        let mut inverse = vec![vec![Vec::new(); count]; CLASSES];
+        for (state, row) in self.transitions.iter().enumerate() {
+            for (class, next) in row.iter().enumerate() {
+                inverse[class][*next].push(state);
+            }
+        }
+
+        let mut initial: HashMap<Option<usize>, Vec<usize>> = HashMap::new();
+        for state in 0..count {
+            initial.entry(self.accepts[state]).or_default().push(state);
+        }
+        let mut blocks: Vec<Vec<usize>> = initial.into_values().collect();
+        blocks.sort();
+        let mut block_of = vec![0; count];
+        for (block, states) in blocks.iter().enumerate() {
+            for state in states {
+                block_of[*state] = block;
+            }
+        }
+        let mut pending: Vec<usize> = (0..blocks.len()).collect();
+
+        let mut marked = vec![false; count];
+        while let Some(splitter) = pending.pop() {
+            let splitter_states = blocks[splitter].clone();
+            for predecessors in &inverse {
+                // The states with a transition into the splitter on this class
+                let mut into: Vec<usize> = Vec::new();
+                for state in &splitter_states {
+                    for predecessor in &predecessors[*state] {
+                        if !marked[*predecessor] {
+                            marked[*predecessor] = true;
+                            into.push(*predecessor);
+                        }
+                    }
+                }
+                let mut touched: Vec<usize> = into.iter().map(|state| block_of[*state]).collect();
+                touched.sort_unstable();
+                touched.dedup();
+                for block in touched {
+                    let (inside, outside): (Vec<usize>, Vec<usize>) =
+                        blocks[block].iter().partition(|state| marked[**state]);
+                    if outside.is_empty() {
+                        continue;
+                    }
+                    let new_block = blocks.len();
+                    let (moved, kept) = if inside.len() <= outside.len() {
+                        (inside, outside)
+                    } else {
+                        (outside, inside)
+                    };
+                    for state in &moved {
+                        block_of[*state] = new_block;
+                    }
+                    blocks[block] = kept;
+                    blocks.push(moved);
+                    // If the block is still waiting to be used as a splitter, it now only holds
+                    // one half and the other has to be added. Otherwise the smaller half is enough
+                    pending.push(new_block);
+                }
+                for state in into {
+                    marked[state] = false;
+                }
+            }
+        }
+
+        // Number the blocks in the order they're reached from the start, so the result doesn't
+        // depend on the order splits happened in
+        let mut number = vec![usize::MAX; blocks.len()];
+        let mut order = vec![block_of[self.start]];
+        number[block_of[self.start]] = 0;
+        let mut index = 0;
+        while index < order.len() {
+            let state = blocks[order[index]][0];
+            for next in self.transitions[state] {
+                let block = block_of[next];
+                if number[block] == usize::MAX {
+                    number[block] = order.len();
+                    order.push(block);
+                }
+            }
+            index += 1;
+        }
+
+        let transitions = order
+            .iter()
+            .map(|block| {
+                let mut row = [0; CLASSES];
+                for (class, next) in row.iter_mut().enumerate() {
+                    *next = number[block_of[self.transitions[blocks[*block][0]][class]]];
+                }
+                row
+            })
+            .collect();
+        let accepts = order
+            .iter()
+            .map(|block| self.accepts[blocks[*block][0]])
+            .collect();
+        let dead = self.dead.map(|dead| number[block_of[dead]]);
+        Dfa {
+            transitions,
+            accepts,
+            start: 0,
+            dead,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn state_count(&self) -> usize {
+        self.transitions.len()
+    }
+
+    // Maximal munch: the end of the longest match starting at `offset`, and the rule it matched
+    pub fn longest_match(&self, source: &str, offset: usize) -> Option<(usize, usize)> {
+        let mut state = self.start;
+        let mut longest = self.accepts[state].map(|rule| (offset, rule));
+        for (index, c) in source[offset..].char_indices() {
+            state = self.transitions[state][class(c)];
+            if Some(state) == self.dead {
+                break;
+            }
+            if let Some(rule) = self.accepts[state] {
+                longest = Some((offset + index + c.len_utf8(), rule));
+            }
+        }
+        longest
+    }
+
+    // Whether all of `text` is matched, and by which rule
+    #[cfg(test)]
+    pub fn accepts(&self, text: &str) -> Option<usize> {
+        let mut state = self.start;
+        for c in text.chars() {
+            state = self.transitions[state][class(c)];
+        }
+        self.accepts[state]
+    }
+
+    // Graphviz, leaving out the dead state and the transitions into it. Parallel transitions are
+    // merged into one labelled with the set of characters they're taken on
+    pub fn to_dot(&self, name: &str, rules: &[String]) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", name).unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    start [shape=point];").unwrap();
+        for (state, accept) in self.accepts.iter().enumerate() {
+            if Some(state) == self.dead {
+                continue;
+            }
+            match accept {
+                Some(rule) => writeln!(
+                    dot,
+                    "    {} [shape=doublecircle, xlabel=\"{}\"];",
+                    state,
+                    escape(&rules[*rule])
+                ),
+                None => writeln!(dot, "    {} [shape=circle];", state),
+            }
+            .unwrap();
+        }
+        writeln!(dot, "    start -> {};", self.start).unwrap();
+        for (state, row) in self.transitions.iter().enumerate() {
+            if Some(state) == self.dead {
+                continue;
+            }
+            let mut targets: Vec<(usize, CharSet)> = Vec::new();
+            for (class, next) in row.iter().enumerate() {
+                if Some(*next) == self.dead {
+                    continue;
+                }
+                match targets.iter_mut().find(|(target, _)| target == next) {
+                    Some((_, set)) => set.insert(class),
+                    None => {
+                        let mut set = CharSet::empty();
+                        set.insert(class);
+                        targets.push((*next, set));
+                    }
+                }
+            }
+            for (next, set) in targets {
+                let label = escape(&set.to_string());
+                writeln!(dot, "    {} -> {} [label=\"{}\"];", state, next, label).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Quotes and backslashes in Graphviz strings
+pub fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexgen::regex::parse;
+    use crate::xorshift::Xorshift;
+
+    fn dfa(rules: &[&str]) -> Dfa {
+        let mut nfa = Nfa::new();
+        for (index, rule) in rules.iter().enumerate() {
+            nfa.add_rule(&parse(rule, &HashMap::new()).unwrap(), index);
+        }
+        Dfa::from_nfa(&nfa)
+    }
+
+    #[test]
+    fn subset_construction() {
+        // The example from the dragon book: five states, plus the dead one
+        let dfa = dfa(&["(a|b)*abb"]);
+        assert_eq!(dfa.state_count(), 6);
+        assert!(dfa.dead.is_some());
+        assert_eq!(dfa.accepts("abb"), Some(0));
+        assert_eq!(dfa.accepts("babaabb"), Some(0));
+        assert_eq!(dfa.accepts("abba"), None);
+        assert_eq!(dfa.accepts("abbc"), None);
+    }
+
+    #[test]
+    fn minimization() {
+        // ... which minimizes to four
+        let minimal = dfa(&["(a|b)*abb"]).minimize();
+        assert_eq!(minimal.state_count(), 5);
+        assert_eq!(dfa(&["a*|a+|(aa)*"]).minimize().state_count(), 2);
+        // states accepting for different rules aren't merged
+        assert_eq!(dfa(&["a", "b"]).minimize().state_count(), 4);
+        assert_eq!(dfa(&["a|b"]).minimize().state_count(), 3);
+    }
+
+    // Minimizing mustn't change what the automaton accepts, checked over random strings
+    #[test]
+    fn minimization_preserves_language() {
+        let patterns: [&[&str]; 4] = [
+            &["(a|b)*abb"],
+            &["if", "[a-z]+", "[0-9]+", " "],
+            &["\\\"([^\"\\\\]|\\\\.)*\\\"", "a*b?a*"],
+            &["(ab|ba)*", "a(a|b)*a", "b+"],
+        ];
+        let mut rng = Xorshift::new(0x9e37_79b9_7f4a_7c15);
+        let alphabet: Vec<char> = "abfiz09 \"\\".chars().collect();
+        for rules in patterns {
+            let dfa = dfa(rules);
+            let minimal = dfa.minimize();
+            assert!(minimal.state_count() <= dfa.state_count());
+            for _ in 0..500 {
+                let text: String = (0..rng.below(10))
+                    .map(|_| alphabet[rng.below(alphabet.len())])
+                    .collect();
+                assert_eq!(dfa.accepts(&text), minimal.accepts(&text), "{:?}", text);
+                assert_eq!(dfa.longest_match(&text, 0), minimal.longest_match(&text, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn longest_match() {
+        let dfa = dfa(&["<", "<=", "<<", "<<=", "[a-z]+"]).minimize();
+        assert_eq!(dfa.longest_match("<<=x", 0), Some((3, 3)));
+        assert_eq!(dfa.longest_match("<<x", 0), Some((2, 2)));
+        assert_eq!(dfa.longest_match("x<=", 1), Some((3, 1)));
+        assert_eq!(dfa.longest_match("abc<", 0), Some((3, 4)));
+        assert_eq!(dfa.longest_match("=", 0), None);
+    }
+
+    #[test]
+    fn dot() {
+        let dot = dfa(&["ab?"]).minimize().to_dot("ab", &["AB".to_string()]);
+        assert_eq!(
+            dot,
+            "digraph \"ab\" {\n    rankdir=LR;\n    start [shape=point];\n    \
+             0 [shape=circle];\n    2 [shape=doublecircle, xlabel=\"AB\"];\n    \
+             3 [shape=doublecircle, xlabel=\"AB\"];\n    start -> 0;\n    \
+             0 -> 2 [label=\"a\"];\n    2 -> 3 [label=\"b\"];\n}\n"
+        );
+    }
+}
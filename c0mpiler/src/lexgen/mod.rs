@@ -0,0 +1,141 @@
+pub mod charset;
+pub mod dfa;
+pub mod nfa;
+pub mod regex;
+pub mod rules;
+
+use crate::lexgen::dfa::Dfa;
+use crate::lexgen::nfa::Nfa;
+use crate::lexgen::rules::{parse_rules, RuleError, RuleFile};
+
+// A small lexer generator
+//
+// Token rules are written as regular expressions in a rule file (see `rules`). Each expression is
+// parsed (`regex`) and turned into an NFA by Thompson's construction (`nfa`), the NFAs for every
+// rule in a mode are joined into one, and that is made deterministic by subset construction and
+// minimized by Hopcroft's algorithm (`dfa`). What comes out is one table per mode, run with
+// maximal munch, and a rule's action is just its name: what to do with the text matched is left
+// to whoever runs the tables
+pub struct Lexer {
+    pub rules: RuleFile,
+    pub actions: Vec<String>,
+    // One automaton per mode, in the order the modes were declared
+    pub dfas: Vec<Dfa>,
+}
+
+impl Lexer {
+    pub fn generate(text: &str) -> Result<Lexer, RuleError> {
+        let rules = parse_rules(text)?;
+        let actions = rules.rules.iter().map(|rule| rule.action.clone()).collect();
+        let dfas = (0..rules.modes.len())
+            .map(|mode| Lexer::automaton(&rules, |rule| rule.modes.contains(&mode)))
+            .collect();
+        Ok(Lexer {
+            rules,
+            actions,
+            dfas,
+        })
+    }
+
+    // The minimal DFA for the rules picked out by `include`, with states accepting for the rules'
+    // indices in the whole file
+    fn automaton(rules: &RuleFile, include: impl Fn(&rules::Rule) -> bool) -> Dfa {
+        let mut nfa = Nfa::new();
+        for (index, rule) in rules.rules.iter().enumerate() {
+            if include(rule) {
+                nfa.add_rule(&rule.regex, index);
+            }
+        }
+        Dfa::from_nfa(&nfa).minimize()
+    }
+
+    // The end of the longest match at `offset` in the given mode, and the index of the rule it
+    // matched
+    pub fn longest_match(
+        &self,
+        mode: usize,
+        source: &str,
+        offset: usize,
+    ) -> Option<(usize, usize)> {
+        self.dfas[mode].longest_match(source, offset)
+    }
+
+    // Graphviz for each mode's automaton, and for each group of rules on their own, as pairs of a
+    // file name and its contents. A group is every rule whose action starts the same way, up to
+    // any parenthesis: "BinOp(Plus)" and "BinOp(Minus)" both go in "binop.dot"
+    pub fn dot_files(&self) -> Vec<(String, String)> {
+        let mut files = Vec::new();
+        for (mode, dfa) in self.rules.modes.iter().zip(&self.dfas) {
+            files.push((format!("{}.dot", mode), dfa.to_dot(mode, &self.actions)));
+        }
+        let mut groups: Vec<String> = Vec::new();
+        for action in &self.actions {
+            let group = group(action);
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        for name in groups {
+            let dfa = Lexer::automaton(&self.rules, |rule| group(&rule.action) == name);
+            files.push((format!("{}.dot", name), dfa.to_dot(&name, &self.actions)));
+        }
+        files
+    }
+}
+
+fn group(action: &str) -> String {
+    action.split('(').next().unwrap_or(action).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = "modes main quoted\n\
+                         If     main  \"if\"\n\
+                         Id     main  [a-z]+\n\
+                         Op(Lt) *     \"<\"\n\
+                         Op(Le) *     \"<=\"\n\
+                         Text   quoted  [^\"]+\n";
+
+    #[test]
+    fn generate() {
+        let lexer = Lexer::generate(RULES).unwrap();
+        assert_eq!(lexer.dfas.len(), 2);
+        let matched = |mode, source: &str| {
+            lexer
+                .longest_match(mode, source, 0)
+                .map(|(end, rule)| (end, lexer.actions[rule].as_str()))
+        };
+        assert_eq!(matched(0, "if("), Some((2, "If")));
+        assert_eq!(matched(0, "iffy"), Some((4, "Id")));
+        assert_eq!(matched(0, "<=a"), Some((2, "Op(Le)")));
+        assert_eq!(matched(1, "<=\""), Some((2, "Op(Le)")));
+        assert_eq!(matched(1, "if\""), Some((2, "Text")));
+        assert_eq!(matched(0, "\""), None);
+    }
+
+    #[test]
+    fn dot_files() {
+        let lexer = Lexer::generate(RULES).unwrap();
+        let names: Vec<String> = lexer
+            .dot_files()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "main.dot",
+                "quoted.dot",
+                "if.dot",
+                "id.dot",
+                "op.dot",
+                "text.dot"
+            ]
+        );
+        let (_, op) = &lexer.dot_files()[4];
+        assert!(op.starts_with("digraph \"op\" {"));
+        assert!(op.contains("xlabel=\"Op(Le)\""));
+    }
+}
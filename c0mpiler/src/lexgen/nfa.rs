@@ -0,0 +1,169 @@
+#[cfg(test)]
+use crate::lexgen::charset::class;
+use crate::lexgen::charset::CharSet;
+use crate::lexgen::regex::Regex;
+
+// Nondeterministic automata, built from regular expressions by Thompson's construction: every
+// piece of an expression becomes a fragment with one entry and one exit, glued to the others
+// with empty (epsilon) transitions
+//
+// An NFA recognizes several rules at once. Its start state has an epsilon transition to the
+// fragment for each rule, and the exit of that fragment accepts with the rule's index
+
+#[derive(Debug, Default, Clone)]
+pub struct NfaState {
+    pub epsilon: Vec<usize>,
+    pub edges: Vec<(CharSet, usize)>,
+    // Which rule the state accepts for
+    pub accept: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Nfa {
+    pub states: Vec<NfaState>,
+    pub start: usize,
+}
+
+impl Nfa {
+    pub fn new() -> Nfa {
+        Nfa {
+            states: vec![NfaState::default()],
+            start: 0,
+        }
+    }
+
+    pub fn add_rule(&mut self, regex: &Regex, rule: usize) {
+        let (entry, exit) = self.fragment(regex);
+        self.states[self.start].epsilon.push(entry);
+        self.states[exit].accept = Some(rule);
+    }
+
+    fn state(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    // Builds the fragment for `regex`, returning its entry and exit
+    fn fragment(&mut self, regex: &Regex) -> (usize, usize) {
+        let entry = self.state();
+        let exit = match regex {
+            Regex::Empty => entry,
+            Regex::Set(set) => {
+                let exit = self.state();
+                self.states[entry].edges.push((*set, exit));
+                exit
+            }
+            Regex::Concat(parts) => {
+                let mut exit = entry;
+                for part in parts {
+                    let (part_entry, part_exit) = self.fragment(part);
+                    self.states[exit].epsilon.push(part_entry);
+                    exit = part_exit;
+                }
+                exit
+            }
+            Regex::Alt(alternatives) => {
+                let exit = self.state();
+                for alternative in alternatives {
+                    let (alternative_entry, alternative_exit) = self.fragment(alternative);
+                    self.states[entry].epsilon.push(alternative_entry);
+                    self.states[alternative_exit].epsilon.push(exit);
+                }
+                exit
+            }
+            Regex::Star(inner) | Regex::Plus(inner) | Regex::Optional(inner) => {
+                let exit = self.state();
+                let (inner_entry, inner_exit) = self.fragment(inner);
+                self.states[entry].epsilon.push(inner_entry);
+                self.states[inner_exit].epsilon.push(exit);
+                if !matches!(regex, Regex::Plus(_)) {
+                    self.states[entry].epsilon.push(exit);
+                }
+                if !matches!(regex, Regex::Optional(_)) {
+                    self.states[inner_exit].epsilon.push(inner_entry);
+                }
+                exit
+            }
+        };
+        (entry, exit)
+    }
+
+    // Every state reachable from `states` through epsilon transitions alone, sorted
+    pub fn closure(&self, states: &[usize]) -> Vec<usize> {
+        let mut seen = vec![false; self.states.len()];
+        let mut stack = states.to_vec();
+        let mut closure = Vec::new();
+        while let Some(state) = stack.pop() {
+            if seen[state] {
+                continue;
+            }
+            seen[state] = true;
+            closure.push(state);
+            stack.extend(&self.states[state].epsilon);
+        }
+        closure.sort_unstable();
+        closure
+    }
+
+    // Runs the automaton over all of `text`, returning the rule it accepts for. When several
+    // would, the lowest-numbered rule wins
+    #[cfg(test)]
+    pub fn accepts(&self, text: &str) -> Option<usize> {
+        let mut current = self.closure(&[self.start]);
+        for c in text.chars() {
+            let class = class(c);
+            let next: Vec<usize> = current
+                .iter()
+                .flat_map(|state| &self.states[*state].edges)
+                .filter(|(set, _)| set.contains(class))
+                .map(|(_, to)| *to)
+                .collect();
+            current = self.closure(&next);
+        }
+        current
+            .iter()
+            .filter_map(|state| self.states[*state].accept)
+            .min()
+    }
+}
+
+impl Default for Nfa {
+    fn default() -> Nfa {
+        Nfa::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexgen::regex::parse;
+    use std::collections::HashMap;
+
+    fn nfa(rules: &[&str]) -> Nfa {
+        let mut nfa = Nfa::new();
+        for (index, rule) in rules.iter().enumerate() {
+            nfa.add_rule(&parse(rule, &HashMap::new()).unwrap(), index);
+        }
+        nfa
+    }
+
+    #[test]
+    fn thompson() {
+        let nfa = nfa(&["a(b|c)*", "x+y?", "\"\""]);
+        assert_eq!(nfa.accepts(""), Some(2));
+        assert_eq!(nfa.accepts("a"), Some(0));
+        assert_eq!(nfa.accepts("abcb"), Some(0));
+        assert_eq!(nfa.accepts("ad"), None);
+        assert_eq!(nfa.accepts("xxx"), Some(1));
+        assert_eq!(nfa.accepts("xy"), Some(1));
+        assert_eq!(nfa.accepts("y"), None);
+        assert_eq!(nfa.accepts("xyy"), None);
+    }
+
+    #[test]
+    fn priority() {
+        let nfa = nfa(&["if", "[a-z]+"]);
+        assert_eq!(nfa.accepts("if"), Some(0));
+        assert_eq!(nfa.accepts("iff"), Some(1));
+    }
+}
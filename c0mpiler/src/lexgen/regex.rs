@@ -0,0 +1,401 @@
+use crate::lexgen::charset::CharSet;
+use std::collections::HashMap;
+use std::fmt;
+
+// Regular expressions, in roughly the syntax lex uses:
+//
+//   r|s  rs  r*  r+  r?  (r)       alternation, concatenation, repetition and grouping
+//   x  \x                          a character; escaped if it is one of the operators
+//   \n \t \r \v \f                 control characters
+//   "text"                         text taken literally
+//   .                              any character but a newline
+//   [abc] [a-z] [^abc]             bracket expressions
+//   [[:alpha:]_]                   named classes, inside a bracket expression
+//   {name}                         a regular expression defined elsewhere
+//
+// Outside of quotes and brackets blanks are ignored, so rules can be laid out in columns
+#[derive(PartialEq, Debug, Clone)]
+pub enum Regex {
+    Empty,
+    Set(CharSet),
+    Concat(Vec<Regex>),
+    Alt(Vec<Regex>),
+    Star(Box<Regex>),
+    Plus(Box<Regex>),
+    Optional(Box<Regex>),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum RegexErrorKind {
+    UnexpectedEnd,
+    Unexpected(char),
+    UnclosedGroup,
+    UnclosedBracket,
+    UnclosedQuote,
+    UnknownClass(String),
+    UnknownDefinition(String),
+    InvalidRange(char, char),
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct RegexError {
+    pub kind: RegexErrorKind,
+    // Where in the expression, counted in characters
+    pub position: usize,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match &self.kind {
+            RegexErrorKind::UnexpectedEnd => "unexpected end of expression".to_string(),
+            RegexErrorKind::Unexpected(c) => format!("unexpected '{}'", c),
+            RegexErrorKind::UnclosedGroup => "missing ')'".to_string(),
+            RegexErrorKind::UnclosedBracket => "missing ']'".to_string(),
+            RegexErrorKind::UnclosedQuote => "missing closing '\"'".to_string(),
+            RegexErrorKind::UnknownClass(name) => format!("unknown class [:{}:]", name),
+            RegexErrorKind::UnknownDefinition(name) => format!("{{{}}} is not defined", name),
+            RegexErrorKind::InvalidRange(first, last) => {
+                format!("invalid range {}-{}", first, last)
+            }
+        };
+        write!(f, "{} at position {}", message, self.position)
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+pub fn parse(text: &str, definitions: &HashMap<String, Regex>) -> Result<Regex, RegexError> {
+    let mut parser = RegexParser {
+        chars: text.chars().collect(),
+        position: 0,
+        definitions,
+    };
+    let regex = parser.alternation()?;
+    parser.skip_blanks();
+    match parser.peek() {
+        None => Ok(regex),
+        Some(c) => Err(parser.error(RegexErrorKind::Unexpected(c))),
+    }
+}
+
+struct RegexParser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    definitions: &'a HashMap<String, Regex>,
+}
+
+impl RegexParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, kind: RegexErrorKind) -> RegexError {
+        RegexError {
+            kind,
+            position: self.position,
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Regex, RegexError> {
+        let mut alternatives = vec![self.concatenation()?];
+        self.skip_blanks();
+        while self.peek() == Some('|') {
+            self.position += 1;
+            alternatives.push(self.concatenation()?);
+            self.skip_blanks();
+        }
+        Ok(match alternatives.len() {
+            1 => alternatives.pop().unwrap(),
+            _ => Regex::Alt(alternatives),
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Regex, RegexError> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                None | Some('|' | ')') => break,
+                _ => parts.push(self.repetition()?),
+            }
+        }
+        Ok(match parts.len() {
+            0 => Regex::Empty,
+            1 => parts.pop().unwrap(),
+            _ => Regex::Concat(parts),
+        })
+    }
+
+    fn repetition(&mut self) -> Result<Regex, RegexError> {
+        let mut regex = self.atom()?;
+        loop {
+            regex = match self.peek() {
+                Some('*') => Regex::Star(Box::new(regex)),
+                Some('+') => Regex::Plus(Box::new(regex)),
+                Some('?') => Regex::Optional(Box::new(regex)),
+                _ => return Ok(regex),
+            };
+            self.position += 1;
+        }
+    }
+
+    fn atom(&mut self) -> Result<Regex, RegexError> {
+        let start = self.position;
+        match self.next() {
+            None => Err(self.error(RegexErrorKind::UnexpectedEnd)),
+            Some('(') => {
+                let regex = self.alternation()?;
+                match self.next() {
+                    Some(')') => Ok(regex),
+                    _ => Err(RegexError {
+                        kind: RegexErrorKind::UnclosedGroup,
+                        position: start,
+                    }),
+                }
+            }
+            Some('[') => self.bracket(start).map(Regex::Set),
+            Some('"') => {
+                let mut parts = Vec::new();
+                loop {
+                    match self.next() {
+                        None => {
+                            return Err(RegexError {
+                                kind: RegexErrorKind::UnclosedQuote,
+                                position: start,
+                            })
+                        }
+                        Some('"') => break,
+                        Some('\\') => parts.push(Regex::Set(CharSet::single(self.escape()?))),
+                        Some(c) => parts.push(Regex::Set(CharSet::single(c))),
+                    }
+                }
+                Ok(match parts.len() {
+                    0 => Regex::Empty,
+                    1 => parts.pop().unwrap(),
+                    _ => Regex::Concat(parts),
+                })
+            }
+            Some('{') => {
+                let mut name = String::new();
+                loop {
+                    match self.next() {
+                        Some('}') => break,
+                        Some(c) if c.is_alphanumeric() || c == '_' => name.push(c),
+                        Some(c) => {
+                            self.position -= 1;
+                            return Err(self.error(RegexErrorKind::Unexpected(c)));
+                        }
+                        None => return Err(self.error(RegexErrorKind::UnexpectedEnd)),
+                    }
+                }
+                match self.definitions.get(&name) {
+                    Some(regex) => Ok(regex.clone()),
+                    None => Err(RegexError {
+                        kind: RegexErrorKind::UnknownDefinition(name),
+                        position: start,
+                    }),
+                }
+            }
+            Some('.') => Ok(Regex::Set(CharSet::single('\n').complement())),
+            Some('\\') => Ok(Regex::Set(CharSet::single(self.escape()?))),
+            Some(c @ ('*' | '+' | '?' | ']' | '}')) => {
+                self.position -= 1;
+                Err(self.error(RegexErrorKind::Unexpected(c)))
+            }
+            Some(c) => Ok(Regex::Set(CharSet::single(c))),
+        }
+    }
+
+    // The character after a backslash
+    fn escape(&mut self) -> Result<char, RegexError> {
+        match self.next() {
+            None => Err(self.error(RegexErrorKind::UnexpectedEnd)),
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('v') => Ok('\x0b'),
+            Some('f') => Ok('\x0c'),
+            Some(c) => Ok(c),
+        }
+    }
+
+    // After the opening '['. A ']' right after it (or after "[^") stands for itself
+    fn bracket(&mut self, start: usize) -> Result<CharSet, RegexError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.position += 1;
+        }
+        let mut set = CharSet::empty();
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                None => {
+                    return Err(RegexError {
+                        kind: RegexErrorKind::UnclosedBracket,
+                        position: start,
+                    })
+                }
+                Some(']') if !first => break,
+                Some('[') if self.peek() == Some(':') => {
+                    let class_start = self.position - 1;
+                    self.position += 1;
+                    let mut name = String::new();
+                    while let Some(c) = self.next() {
+                        if c == ':' {
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if self.next() != Some(']') {
+                        return Err(self.error(RegexErrorKind::UnclosedBracket));
+                    }
+                    match CharSet::named(&name) {
+                        Some(named) => set = set.union(&named),
+                        None => {
+                            return Err(RegexError {
+                                kind: RegexErrorKind::UnknownClass(name),
+                                position: class_start,
+                            })
+                        }
+                    }
+                    first = false;
+                    continue;
+                }
+                Some('\\') => self.escape()?,
+                Some(c) => c,
+            };
+            first = false;
+            // A '-' last in the brackets stands for itself
+            if self.peek() == Some('-') && self.chars.get(self.position + 1) != Some(&']') {
+                self.position += 1;
+                let last = match self.next() {
+                    None => return Err(self.error(RegexErrorKind::UnexpectedEnd)),
+                    Some('\\') => self.escape()?,
+                    Some(last) => last,
+                };
+                if last < c || !last.is_ascii() {
+                    return Err(RegexError {
+                        kind: RegexErrorKind::InvalidRange(c, last),
+                        position: self.position - 3,
+                    });
+                }
+                set = set.union(&CharSet::range(c, last));
+            } else {
+                set = set.union(&CharSet::single(c));
+            }
+        }
+        Ok(if negated { set.complement() } else { set })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(chars: &str) -> Regex {
+        Regex::Set(CharSet::matching(|c| chars.contains(c)))
+    }
+
+    fn parse_plain(text: &str) -> Result<Regex, RegexError> {
+        parse(text, &HashMap::new())
+    }
+
+    #[test]
+    fn syntax() {
+        assert_eq!(parse_plain("a"), Ok(set("a")));
+        assert_eq!(
+            parse_plain("ab|c"),
+            Ok(Regex::Alt(vec![
+                Regex::Concat(vec![set("a"), set("b")]),
+                set("c")
+            ]))
+        );
+        assert_eq!(
+            parse_plain("(a|b)*c+d?"),
+            Ok(Regex::Concat(vec![
+                Regex::Star(Box::new(Regex::Alt(vec![set("a"), set("b")]))),
+                Regex::Plus(Box::new(set("c"))),
+                Regex::Optional(Box::new(set("d"))),
+            ]))
+        );
+        assert_eq!(
+            parse_plain("\"a|*\""),
+            Ok(Regex::Concat(vec![set("a"), set("|"), set("*")]))
+        );
+        assert_eq!(parse_plain("\"\""), Ok(Regex::Empty));
+        assert_eq!(
+            parse_plain("\\* \\n"),
+            Ok(Regex::Concat(vec![set("*"), set("\n")]))
+        );
+        assert_eq!(parse_plain("  a  "), Ok(set("a")));
+        assert_eq!(
+            parse_plain("."),
+            Ok(Regex::Set(CharSet::single('\n').complement()))
+        );
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(parse_plain("[abc]"), Ok(set("abc")));
+        assert_eq!(parse_plain("[a-c_]"), Ok(set("abc_")));
+        assert_eq!(parse_plain("[]a]"), Ok(set("]a")));
+        assert_eq!(parse_plain("[a-]"), Ok(set("a-")));
+        assert_eq!(parse_plain("[\\n ]"), Ok(set("\n ")));
+        assert_eq!(
+            parse_plain("[^\"\\n]"),
+            Ok(Regex::Set(CharSet::matching(|c| c != '"' && c != '\n')))
+        );
+        assert_eq!(
+            parse_plain("[[:alpha:]_]"),
+            Ok(Regex::Set(CharSet::matching(
+                |c| c.is_alphabetic() || c == '_'
+            )))
+        );
+    }
+
+    #[test]
+    fn definitions() {
+        let mut definitions = HashMap::new();
+        definitions.insert("digit".to_string(), set("0123456789"));
+        assert_eq!(
+            parse("{digit}+", &definitions),
+            Ok(Regex::Plus(Box::new(set("0123456789"))))
+        );
+        assert_eq!(
+            parse("{letter}", &definitions).map_err(|error| error.kind),
+            Err(RegexErrorKind::UnknownDefinition("letter".to_string()))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let kind = |text: &str| parse_plain(text).map_err(|error| error.kind);
+        assert_eq!(kind("(ab"), Err(RegexErrorKind::UnclosedGroup));
+        assert_eq!(kind("ab)"), Err(RegexErrorKind::Unexpected(')')));
+        assert_eq!(kind("[ab"), Err(RegexErrorKind::UnclosedBracket));
+        assert_eq!(kind("\"ab"), Err(RegexErrorKind::UnclosedQuote));
+        assert_eq!(kind("*a"), Err(RegexErrorKind::Unexpected('*')));
+        assert_eq!(kind("a\\"), Err(RegexErrorKind::UnexpectedEnd));
+        assert_eq!(kind("[z-a]"), Err(RegexErrorKind::InvalidRange('z', 'a')));
+        assert_eq!(
+            kind("[[:word:]]"),
+            Err(RegexErrorKind::UnknownClass("word".to_string()))
+        );
+        assert_eq!(
+            parse_plain("ab)").unwrap_err().to_string(),
+            "unexpected ')' at position 2"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::lexgen::regex::{parse, Regex, RegexError};
+use std::collections::HashMap;
+use std::fmt;
+
+// Rule files declare a scanner, one line at a time:
+//
+//   # a comment
+//   modes normal annotation        the scanner's modes, the first being the one it starts in
+//   digit = [0-9]                  a definition, used in later expressions as {digit}
+//   Number  *  {digit}+            a rule: an action, the modes it applies in, and an expression
+//
+// Modes are "*" for all of them or a comma-separated list. When two rules match the same, longest
+// text, the one written first wins; that order is the only priority there is
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub action: String,
+    pub modes: Vec<usize>,
+    pub regex: Regex,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleFile {
+    pub modes: Vec<String>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum RuleErrorKind {
+    Regex(RegexError),
+    MissingRegex,
+    MissingModes,
+    UnknownMode(String),
+    DuplicateModes,
+    MatchesEmpty,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct RuleError {
+    pub kind: RuleErrorKind,
+    pub line: usize,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match &self.kind {
+            RuleErrorKind::Regex(error) => error.to_string(),
+            RuleErrorKind::MissingRegex => "rule without an expression".to_string(),
+            RuleErrorKind::MissingModes => "rule before the modes are declared".to_string(),
+            RuleErrorKind::UnknownMode(mode) => format!("unknown mode {}", mode),
+            RuleErrorKind::DuplicateModes => "modes declared twice".to_string(),
+            RuleErrorKind::MatchesEmpty => "rule matches the empty string".to_string(),
+        };
+        write!(f, "line {}: error: {}", self.line, message)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+pub fn parse_rules(text: &str) -> Result<RuleFile, RuleError> {
+    let mut modes: Vec<String> = Vec::new();
+    let mut definitions = HashMap::new();
+    let mut rules = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let error = |kind| RuleError { kind, line: number };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (first, rest) = split_word(line);
+
+        if first == "modes" {
+            if !modes.is_empty() {
+                return Err(error(RuleErrorKind::DuplicateModes));
+            }
+            modes = rest.split_whitespace().map(str::to_string).collect();
+            continue;
+        }
+        if let Some(definition) = rest.strip_prefix('=') {
+            let regex = parse(definition, &definitions)
+                .map_err(|regex_error| error(RuleErrorKind::Regex(regex_error)))?;
+            definitions.insert(first.to_string(), regex);
+            continue;
+        }
+
+        if modes.is_empty() {
+            return Err(error(RuleErrorKind::MissingModes));
+        }
+        let (rule_modes, text) = split_word(rest);
+        if text.is_empty() {
+            return Err(error(RuleErrorKind::MissingRegex));
+        }
+        let rule_modes = if rule_modes == "*" {
+            (0..modes.len()).collect()
+        } else {
+            rule_modes
+                .split(',')
+                .map(|mode| match modes.iter().position(|m| m == mode) {
+                    Some(index) => Ok(index),
+                    None => Err(error(RuleErrorKind::UnknownMode(mode.to_string()))),
+                })
+                .collect::<Result<Vec<usize>, RuleError>>()?
+        };
+        let regex = parse(text, &definitions)
+            .map_err(|regex_error| error(RuleErrorKind::Regex(regex_error)))?;
+        if nullable(&regex) {
+            return Err(error(RuleErrorKind::MatchesEmpty));
+        }
+        rules.push(Rule {
+            action: first.to_string(),
+            modes: rule_modes,
+            regex,
+            line: number,
+        });
+    }
+
+    Ok(RuleFile { modes, rules })
+}
+
+// The first word of a line, and what follows it with the blanks trimmed off
+fn split_word(line: &str) -> (&str, &str) {
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim_start()),
+        None => (line, ""),
+    }
+}
+
+// Whether the expression matches the empty string. A scanner can't make progress on such a match
+fn nullable(regex: &Regex) -> bool {
+    match regex {
+        Regex::Empty | Regex::Star(_) | Regex::Optional(_) => true,
+        Regex::Set(_) => false,
+        Regex::Concat(parts) => parts.iter().all(nullable),
+        Regex::Alt(alternatives) => alternatives.iter().any(nullable),
+        Regex::Plus(inner) => nullable(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_file() {
+        let rules = parse_rules(
+            "# numbers and words\n\
+             modes main other\n\
+             \n\
+             digit = [0-9]\n\
+             Number   *           {digit}+\n\
+             Word     main        [a-z] ([a-z] | {digit})*\n\
+             Skip     main,other  \" \"\n",
+        )
+        .unwrap();
+        assert_eq!(rules.modes, vec!["main", "other"]);
+        let summary: Vec<(&str, &Vec<usize>, usize)> = rules
+            .rules
+            .iter()
+            .map(|rule| (rule.action.as_str(), &rule.modes, rule.line))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Number", &vec![0, 1], 5),
+                ("Word", &vec![0], 6),
+                ("Skip", &vec![0, 1], 7)
+            ]
+        );
+        assert_eq!(
+            rules.rules[0].regex,
+            parse("[0-9]+", &HashMap::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let kind = |text: &str| parse_rules(text).map(|_| ()).map_err(|error| error.kind);
+        assert_eq!(kind("A * a"), Err(RuleErrorKind::MissingModes));
+        assert_eq!(kind("modes m\nA *"), Err(RuleErrorKind::MissingRegex));
+        assert_eq!(
+            kind("modes m\nA n a"),
+            Err(RuleErrorKind::UnknownMode("n".to_string()))
+        );
+        assert_eq!(kind("modes m\nmodes n"), Err(RuleErrorKind::DuplicateModes));
+        assert_eq!(kind("modes m\nA * a*"), Err(RuleErrorKind::MatchesEmpty));
+        assert!(matches!(
+            kind("modes m\nA * (a"),
+            Err(RuleErrorKind::Regex(_))
+        ));
+        assert_eq!(
+            parse_rules("modes m\n\nA * a|").unwrap_err().to_string(),
+            "line 3: error: rule matches the empty string"
+        );
+    }
+}
@@ -1,13 +1,40 @@
+mod ast;
+mod codegen;
+mod driver;
+mod elaborate;
+mod ir;
+mod lexgen;
+mod parser;
+mod pretty;
 mod scanner;
+mod semantic;
+#[cfg(test)]
+mod xorshift;
 
-use clap::{arg, Command};
+use clap::{arg, ArgAction, Command};
+use scanner::ScannerKind;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() {
+fn main() -> ExitCode {
     let matches = Command::new("C0mpiler")
         .version("0.1.0")
         .author("Nicholi Caron <nmcaron@protonmail.ch>")
         .about("A compiler for the C0 programming language")
         .arg(arg!(-f --file <FILE> "Name of the file to be compiled"))
+        .arg(
+            arg!(-L --"lib-path" <DIR> "Directory to search for #use <lib> headers")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--scanner <KIND> "Scanner to use: hand-written, table-driven, or generated from rules")
+                .value_parser(["hand", "table", "generated"])
+                .default_value("hand"),
+        )
+        .arg(arg!(--dot <DIR> "Write the generated scanner's automata to DIR as Graphviz files"))
+        .arg(arg!(--"dump-elab" "Print the program after elaboration into the core language"))
+        .arg(arg!(--"cfg-dot" <DIR> "Write the control-flow graph of each function to DIR as a Graphviz file"))
+        .arg(arg!(--"dump-trivia" <FILE> "Print the tokens of FILE with the whitespace and comments around them"))
         .get_matches();
 
     /* let matches = command!()
@@ -27,10 +54,51 @@ fn main() {
     .get_matches();
     */
 
-    if let Some(f) = matches.get_one::<String>("filename") {
-        // Impl error handling here if file is unavailable
-        let _scan = scanner::run_file(f.clone());
+    let lib_paths: Vec<PathBuf> = matches
+        .get_many::<String>("lib-path")
+        .unwrap_or_default()
+        .map(PathBuf::from)
+        .collect();
+
+    let scanner = match matches.get_one::<String>("scanner").map(String::as_str) {
+        Some("table") => ScannerKind::Table,
+        Some("generated") => ScannerKind::Generated,
+        _ => ScannerKind::HandWritten,
+    };
+
+    if let Some(dir) = matches.get_one::<String>("dot") {
+        if let Err(error) = driver::write_dot(PathBuf::from(dir)) {
+            eprintln!("{}: error: {}", dir, error);
+            return ExitCode::FAILURE;
+        }
+        if !matches.contains_id("file") {
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    if let Some(path) = matches.get_one::<String>("dump-trivia") {
+        if let Err(error) = driver::dump_trivia(Path::new(path)) {
+            eprintln!("error: {}", error);
+            return ExitCode::FAILURE;
+        }
+        if !matches.contains_id("file") {
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    let dump_elab = matches.get_flag("dump-elab");
+    let cfg_dir = matches.get_one::<String>("cfg-dot").map(PathBuf::from);
+
+    let result = if let Some(f) = matches.get_one::<String>("file") {
+        driver::run_file(f.clone(), lib_paths, scanner, dump_elab, cfg_dir)
     } else {
-        let _scan = scanner::run_prompt();
+        driver::run_prompt(lib_paths, scanner, dump_elab, cfg_dir)
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
     }
 }
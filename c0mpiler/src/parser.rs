@@ -0,0 +1,1613 @@
+use crate::ast::{
+    BinaryOp, Decl, DeclKind, Expr, ExprKind, Field, LValue, LValueKind, Param, PostfixOp, Program,
+    Spec, SpecKind, Stmt, StmtKind, Type, TypeKind, UnaryOp,
+};
+use crate::scanner::span::Span;
+use crate::scanner::token::{
+    Annot, AsnOp, BinOp, ChrLit, DecNum, HexNum, Id, Keyword, Num, PostOp, Sep, SpannedToken,
+    StrLit, Token, UnOp,
+};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+#[derive(PartialEq, Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ParseErrorKind {
+    // What the grammar called for, and the token found instead (None at the end of the input)
+    Expected {
+        expected: &'static str,
+        found: Option<String>,
+    },
+    // The left-hand side of an assignment, "++" or "--" isn't something that can be assigned to
+    InvalidLValue,
+    // "#use" after the first declaration of a file
+    MisplacedDirective,
+    // A declaration as the step of a for loop
+    DeclInForStep,
+    // The decimal literal 2147483648 anywhere but right after a unary minus
+    IntegerOutOfRange,
+    // A contract written where its kind doesn't go, e.g. @requires on a loop
+    MisplacedContract {
+        contract: &'static str,
+        place: &'static str,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: error: ", self.span.line, self.span.column)?;
+        match &self.kind {
+            ParseErrorKind::Expected {
+                expected,
+                found: Some(found),
+            } => write!(f, "expected {}, found `{}`", expected, found),
+            ParseErrorKind::Expected {
+                expected,
+                found: None,
+            } => write!(f, "expected {}, found end of file", expected),
+            ParseErrorKind::InvalidLValue => write!(f, "cannot assign to this expression"),
+            ParseErrorKind::MisplacedDirective => {
+                write!(f, "#use must come before any declarations")
+            }
+            ParseErrorKind::DeclInForStep => {
+                write!(f, "the step of a for loop cannot be a declaration")
+            }
+            ParseErrorKind::IntegerOutOfRange => {
+                write!(
+                    f,
+                    "integer literal out of range, 2147483648 is only allowed as -2147483648"
+                )
+            }
+            ParseErrorKind::MisplacedContract { contract, place } => {
+                write!(f, "@{} can only go {}", contract, place)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Where annotations are written, each place taking only some kinds of contract
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum SpecPlace {
+    Function,
+    Loop,
+    Statement,
+}
+
+// Precedence of each binary operator, higher binds tighter. All of them are left-associative; the
+// conditional operator, the only right-associative one, is handled separately
+//
+// This is only consulted once an operand has been parsed, so '-' and '*' found here are always
+// subtraction and multiplication
+fn binary_op(token: &Token) -> Option<(BinaryOp, u8)> {
+    let op = match token {
+        Token::BinOp(BinOp::LogicalOR) => (BinaryOp::LogicalOr, 1),
+        Token::BinOp(BinOp::LogicalAND) => (BinaryOp::LogicalAnd, 2),
+        Token::BinOp(BinOp::BitwiseOR) => (BinaryOp::BitOr, 3),
+        Token::BinOp(BinOp::BitwiseXOR) => (BinaryOp::BitXor, 4),
+        Token::BinOp(BinOp::BitwiseAND) => (BinaryOp::BitAnd, 5),
+        Token::BinOp(BinOp::Equality) => (BinaryOp::Eq, 6),
+        Token::BinOp(BinOp::Disequality) => (BinaryOp::NotEq, 6),
+        Token::BinOp(BinOp::Less) => (BinaryOp::Less, 7),
+        Token::BinOp(BinOp::LessEq) => (BinaryOp::LessEq, 7),
+        Token::BinOp(BinOp::Greater) => (BinaryOp::Greater, 7),
+        Token::BinOp(BinOp::GreaterEq) => (BinaryOp::GreaterEq, 7),
+        Token::BinOp(BinOp::ShiftLeft) => (BinaryOp::Shl, 8),
+        Token::BinOp(BinOp::ShiftRight) => (BinaryOp::Shr, 8),
+        Token::BinOp(BinOp::Plus) => (BinaryOp::Add, 9),
+        Token::Minus => (BinaryOp::Sub, 9),
+        Token::Star => (BinaryOp::Mul, 10),
+        Token::BinOp(BinOp::Divide) => (BinaryOp::Div, 10),
+        Token::BinOp(BinOp::Modulo) => (BinaryOp::Mod, 10),
+        _ => return None,
+    };
+    Some(op)
+}
+
+// None for plain '='
+fn assignment_op(asnop: &AsnOp) -> Option<BinaryOp> {
+    match asnop {
+        AsnOp::EqAsn => None,
+        AsnOp::IncAsn => Some(BinaryOp::Add),
+        AsnOp::DecAsn => Some(BinaryOp::Sub),
+        AsnOp::MultAsn => Some(BinaryOp::Mul),
+        AsnOp::DivAsn => Some(BinaryOp::Div),
+        AsnOp::ModAsn => Some(BinaryOp::Mod),
+        AsnOp::LShiftAsn => Some(BinaryOp::Shl),
+        AsnOp::RShiftAsn => Some(BinaryOp::Shr),
+        AsnOp::ANDAsn => Some(BinaryOp::BitAnd),
+        AsnOp::XORAsn => Some(BinaryOp::BitXor),
+        AsnOp::ORAsn => Some(BinaryOp::BitOr),
+    }
+}
+
+fn lvalue(expr: Expr) -> Option<LValue> {
+    let kind = match expr.kind {
+        ExprKind::Var(name) => LValueKind::Var(name),
+        ExprKind::Field { base, field } => LValueKind::Field {
+            base: Box::new(lvalue(*base)?),
+            field,
+        },
+        ExprKind::Arrow { base, field } => LValueKind::Arrow {
+            base: Box::new(lvalue(*base)?),
+            field,
+        },
+        ExprKind::Deref(base) => LValueKind::Deref(Box::new(lvalue(*base)?)),
+        ExprKind::Index { base, index } => LValueKind::Index {
+            base: Box::new(lvalue(*base)?),
+            index: *index,
+        },
+        _ => return None,
+    };
+    Some(LValue::new(kind, expr.span))
+}
+
+#[cfg(test)]
+pub fn parse(tokens: Vec<SpannedToken>) -> Result<Program, ParseError> {
+    Parser::new(tokens).parse_program()
+}
+
+// Recursive-descent parser over the scanner's tokens, one method per grammar rule
+//
+// Each rule notes the span of the token it starts at, and once it is done the node it builds
+// covers everything up to the end of the last token it consumed
+pub struct Parser {
+    // Tokens are pulled from `tokens` as the parser gets to them, and only the few around
+    // `position` are kept, in `window`, starting with the one at `window_start`
+    tokens: Box<dyn Iterator<Item = SpannedToken>>,
+    window: VecDeque<SpannedToken>,
+    window_start: usize,
+    position: usize,
+    // Names introduced by typedef so far. C0 needs these to tell a declaration like "t * x;" apart
+    // from a multiplication, and since typedefs can come from another file (e.g. a library header)
+    // they can be carried over from one Parser to the next
+    typedefs: HashSet<String>,
+}
+
+// How many tokens the parser looks back at (the last one consumed, and the one before when it
+// steps back over a struct name) and ahead at (peek_nth(2), for "tp id (")
+const BEHIND: usize = 2;
+const AHEAD: usize = 3;
+
+impl Parser {
+    #[cfg(test)]
+    pub fn new<I>(tokens: I) -> Parser
+    where
+        I: IntoIterator<Item = SpannedToken>,
+        I::IntoIter: 'static,
+    {
+        Parser::with_typedefs(tokens, HashSet::new())
+    }
+
+    // `tokens` can be a whole file's worth, or something like a `stream::Lexer` that scans them as
+    // they are asked for
+    pub fn with_typedefs<I>(tokens: I, typedefs: HashSet<String>) -> Parser
+    where
+        I: IntoIterator<Item = SpannedToken>,
+        I::IntoIter: 'static,
+    {
+        let mut parser = Parser {
+            tokens: Box::new(tokens.into_iter()),
+            window: VecDeque::new(),
+            window_start: 0,
+            position: 0,
+            typedefs,
+        };
+        parser.advance(0);
+        parser
+    }
+
+    pub fn typedefs(self) -> HashSet<String> {
+        self.typedefs
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.get(self.position + n).map(|spanned| &spanned.token)
+    }
+
+    fn get(&self, index: usize) -> Option<&SpannedToken> {
+        index
+            .checked_sub(self.window_start)
+            .and_then(|index| self.window.get(index))
+    }
+
+    // Moves on `n` tokens, sliding the window along. The last token is never let go of, since
+    // the span at the end of the input is worked out from it
+    fn advance(&mut self, n: usize) {
+        self.position += n;
+        while self.window_start + BEHIND < self.position && self.window.len() > 1 {
+            self.window.pop_front();
+            self.window_start += 1;
+        }
+        while self.window_start + self.window.len() < self.position + AHEAD {
+            match self.tokens.next() {
+                Some(spanned) => self.window.push_back(spanned),
+                None => break,
+            }
+        }
+    }
+
+    // Span of the next token, or an empty span just past the last one at the end of the input
+    fn span(&self) -> Span {
+        match self.get(self.position) {
+            Some(spanned) => spanned.span,
+            None => match self.window.back() {
+                Some(last) => Span {
+                    offset: last.span.end(),
+                    column: last.span.column + last.span.length,
+                    length: 0,
+                    ..last.span
+                },
+                None => Span::default(),
+            },
+        }
+    }
+
+    // From `start` to the end of the last token consumed
+    fn span_from(&self, start: Span) -> Span {
+        match self.position.checked_sub(1).and_then(|last| self.get(last)) {
+            Some(last) => start.to(last.span),
+            None => start,
+        }
+    }
+
+    fn check(&self, token: &Token) -> bool {
+        self.peek() == Some(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.check(token) {
+            self.advance(1);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, expected: &'static str) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::Expected {
+                expected,
+                found: self.peek().map(|token| token.to_string()),
+            },
+            span: self.span(),
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &'static str) -> Result<(), ParseError> {
+        if self.eat(&token) {
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    fn expect_id(&mut self, expected: &'static str) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Id(Id::Id(name))) => {
+                let name = name.clone();
+                self.advance(1);
+                Ok(name)
+            }
+            _ => Err(self.error(expected)),
+        }
+    }
+
+    // program ::= (#use ...)* gdecl*
+    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+        let start = self.span();
+        // "#use" directives are resolved by the driver, but they must all come first
+        while let Some(Token::Directive(_)) = self.peek() {
+            self.advance(1);
+        }
+
+        let mut decls = Vec::new();
+        while let Some(token) = self.peek() {
+            if let Token::Directive(_) = token {
+                return Err(ParseError {
+                    kind: ParseErrorKind::MisplacedDirective,
+                    span: self.span(),
+                });
+            }
+            decls.push(self.parse_gdecl()?);
+        }
+        Ok(Program {
+            decls,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_gdecl(&mut self) -> Result<Decl, ParseError> {
+        let start = self.span();
+        let kind = match (self.peek(), self.peek_nth(2)) {
+            // typedef tp aid ;
+            (Some(Token::Keyword(Keyword::Typedef)), _) => {
+                self.advance(1);
+                let ty = self.parse_type()?;
+                let name = self.expect_id("a type name")?;
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                self.typedefs.insert(name.clone());
+                DeclKind::Typedef { ty, name }
+            }
+            // struct sid ;
+            (Some(Token::Keyword(Keyword::Struct)), Some(Token::Sep(Sep::SemiColon))) => {
+                self.advance(1);
+                let name = self.expect_id("a struct name")?;
+                self.advance(1);
+                DeclKind::StructDecl { name }
+            }
+            // struct sid { (tp fid ;)* } ;
+            (Some(Token::Keyword(Keyword::Struct)), Some(Token::Sep(Sep::LCurly))) => {
+                self.advance(1);
+                let name = self.expect_id("a struct name")?;
+                self.advance(1);
+                let mut fields = Vec::new();
+                while !self.eat(&Token::Sep(Sep::RCurly)) {
+                    let field_start = self.span();
+                    let ty = self.parse_type()?;
+                    let name = self.expect_id("a field name")?;
+                    self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                    let span = self.span_from(field_start);
+                    fields.push(Field { ty, name, span });
+                }
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                DeclKind::StructDef { name, fields }
+            }
+            _ => self.parse_function()?,
+        };
+        Ok(Decl {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    // tp vid ( [tp vid (, tp vid)*] ) spec* (; | block)
+    fn parse_function(&mut self) -> Result<DeclKind, ParseError> {
+        let ret = self.parse_type()?;
+        let name = self.expect_id("a function name")?;
+        self.expect(Token::Sep(Sep::LParen), "`(`")?;
+        let mut params = Vec::new();
+        if !self.eat(&Token::Sep(Sep::RParen)) {
+            loop {
+                let start = self.span();
+                let ty = self.parse_type()?;
+                let name = self.expect_id("a parameter name")?;
+                let span = self.span_from(start);
+                params.push(Param { ty, name, span });
+                if self.eat(&Token::Sep(Sep::RParen)) {
+                    break;
+                }
+                self.expect(Token::Sep(Sep::Comma), "`,` or `)`")?;
+            }
+        }
+        let specs = self.parse_specs(SpecPlace::Function)?;
+        let body = if self.eat(&Token::Sep(Sep::SemiColon)) {
+            None
+        } else {
+            Some(self.parse_block()?)
+        };
+        Ok(DeclKind::Function {
+            ret,
+            name,
+            params,
+            specs,
+            body,
+        })
+    }
+
+    // Zero or more annotations, each holding one or more contracts of the kinds that go in `place`
+    fn parse_specs(&mut self, place: SpecPlace) -> Result<Vec<Spec>, ParseError> {
+        let mut specs = Vec::new();
+        while let Some(Token::Annot(Annot::LineOpen | Annot::BlockOpen)) = self.peek() {
+            self.advance(1);
+            while !self.eat(&Token::Annot(Annot::Close)) {
+                specs.push(self.parse_spec(place)?);
+            }
+        }
+        Ok(specs)
+    }
+
+    fn parse_spec(&mut self, place: SpecPlace) -> Result<Spec, ParseError> {
+        let start = self.span();
+        let (spec, contract, allowed): (fn(Expr) -> SpecKind, _, _) = match self.peek() {
+            Some(Token::Keyword(Keyword::Requires)) => {
+                (SpecKind::Requires, "requires", SpecPlace::Function)
+            }
+            Some(Token::Keyword(Keyword::Ensures)) => {
+                (SpecKind::Ensures, "ensures", SpecPlace::Function)
+            }
+            Some(Token::Keyword(Keyword::LoopInvariant)) => {
+                (SpecKind::LoopInvariant, "loop_invariant", SpecPlace::Loop)
+            }
+            Some(Token::Keyword(Keyword::AnnoAssert)) => {
+                (SpecKind::Assert, "assert", SpecPlace::Statement)
+            }
+            _ => return Err(self.error("a contract")),
+        };
+        if place != allowed {
+            let place = match allowed {
+                SpecPlace::Function => "between a function's header and its body",
+                SpecPlace::Loop => "after the header of a loop",
+                SpecPlace::Statement => "among statements",
+            };
+            return Err(ParseError {
+                kind: ParseErrorKind::MisplacedContract { contract, place },
+                span: start,
+            });
+        }
+        self.advance(1);
+        let expr = self.parse_expr()?;
+        self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+        Ok(Spec {
+            kind: spec(expr),
+            span: self.span_from(start),
+        })
+    }
+
+    fn is_type_start(&self) -> bool {
+        match self.peek() {
+            Some(Token::Keyword(
+                Keyword::Int
+                | Keyword::Bool
+                | Keyword::Char
+                | Keyword::String
+                | Keyword::Void
+                | Keyword::Struct,
+            )) => true,
+            Some(Token::Id(Id::Id(name))) => self.typedefs.contains(name),
+            _ => false,
+        }
+    }
+
+    // tp ::= int | bool | char | string | void | struct sid | aid | tp * | tp [ ]
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Some(Token::Keyword(Keyword::Int)) => TypeKind::Int,
+            Some(Token::Keyword(Keyword::Bool)) => TypeKind::Bool,
+            Some(Token::Keyword(Keyword::Char)) => TypeKind::Char,
+            Some(Token::Keyword(Keyword::String)) => TypeKind::String,
+            Some(Token::Keyword(Keyword::Void)) => TypeKind::Void,
+            Some(Token::Keyword(Keyword::Struct)) => {
+                self.advance(1);
+                let name = self.expect_id("a struct name")?;
+                // step back so the advance below lands just past the name
+                self.position -= 1;
+                TypeKind::Struct(name)
+            }
+            Some(Token::Id(Id::Id(name))) if self.typedefs.contains(name) => {
+                TypeKind::Name(name.clone())
+            }
+            _ => return Err(self.error("a type")),
+        };
+        self.advance(1);
+        let mut ty = Type {
+            kind,
+            span: self.span_from(start),
+        };
+
+        loop {
+            // in a type a '*' can only be a pointer
+            let kind = if self.eat(&Token::Star) {
+                TypeKind::Pointer(Box::new(ty))
+            } else if self.check(&Token::Sep(Sep::LBracket))
+                && self.peek_nth(1) == Some(&Token::Sep(Sep::RBracket))
+            {
+                self.advance(2);
+                TypeKind::Array(Box::new(ty))
+            } else {
+                return Ok(ty);
+            };
+            ty = Type {
+                kind,
+                span: self.span_from(start),
+            };
+        }
+    }
+
+    // block ::= { stmt* }
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect(Token::Sep(Sep::LCurly), "`{`")?;
+        let mut stmts = Vec::new();
+        while !self.eat(&Token::Sep(Sep::RCurly)) {
+            if self.peek().is_none() {
+                return Err(self.error("`}`"));
+            }
+            stmts.push(self.parse_stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Some(Token::Sep(Sep::LCurly)) => StmtKind::Block(self.parse_block()?),
+            Some(Token::Keyword(Keyword::If)) => {
+                self.advance(1);
+                let cond = self.parse_condition()?;
+                let then = Box::new(self.parse_stmt()?);
+                // a dangling else belongs to the closest if
+                let els = if self.eat(&Token::Keyword(Keyword::Else)) {
+                    Some(Box::new(self.parse_stmt()?))
+                } else {
+                    None
+                };
+                StmtKind::If { cond, then, els }
+            }
+            Some(Token::Keyword(Keyword::While)) => {
+                self.advance(1);
+                let cond = self.parse_condition()?;
+                let invariants = self.parse_specs(SpecPlace::Loop)?;
+                let body = Box::new(self.parse_stmt()?);
+                StmtKind::While {
+                    cond,
+                    invariants,
+                    body,
+                }
+            }
+            Some(Token::Keyword(Keyword::For)) => {
+                self.advance(1);
+                self.expect(Token::Sep(Sep::LParen), "`(`")?;
+                let init = if self.check(&Token::Sep(Sep::SemiColon)) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_simple()?))
+                };
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                let cond = self.parse_expr()?;
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                let step = if self.check(&Token::Sep(Sep::RParen)) {
+                    None
+                } else {
+                    let step = self.parse_simple()?;
+                    if let StmtKind::Decl { .. } = step.kind {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::DeclInForStep,
+                            span: step.span,
+                        });
+                    }
+                    Some(Box::new(step))
+                };
+                self.expect(Token::Sep(Sep::RParen), "`)`")?;
+                let invariants = self.parse_specs(SpecPlace::Loop)?;
+                let body = Box::new(self.parse_stmt()?);
+                StmtKind::For {
+                    init,
+                    cond,
+                    step,
+                    invariants,
+                    body,
+                }
+            }
+            Some(Token::Keyword(Keyword::Return)) => {
+                self.advance(1);
+                let value = if self.check(&Token::Sep(Sep::SemiColon)) {
+                    None
+                } else {
+                    Some(self.parse_expr()?)
+                };
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                StmtKind::Return(value)
+            }
+            Some(Token::Keyword(Keyword::Break)) => {
+                self.advance(1);
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                StmtKind::Break
+            }
+            Some(Token::Keyword(Keyword::Continue)) => {
+                self.advance(1);
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                StmtKind::Continue
+            }
+            Some(Token::Keyword(Keyword::Assert)) => {
+                self.advance(1);
+                let expr = self.parse_condition()?;
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                StmtKind::Assert(expr)
+            }
+            Some(Token::Keyword(Keyword::Error)) => {
+                self.advance(1);
+                let expr = self.parse_condition()?;
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                StmtKind::Error(expr)
+            }
+            Some(Token::Annot(_)) => StmtKind::Annotation(self.parse_specs(SpecPlace::Statement)?),
+            _ => {
+                let simple = self.parse_simple()?;
+                self.expect(Token::Sep(Sep::SemiColon), "`;`")?;
+                simple.kind
+            }
+        };
+        Ok(Stmt {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    // ( exp )
+    fn parse_condition(&mut self) -> Result<Expr, ParseError> {
+        self.expect(Token::Sep(Sep::LParen), "`(`")?;
+        let expr = self.parse_expr()?;
+        self.expect(Token::Sep(Sep::RParen), "`)`")?;
+        Ok(expr)
+    }
+
+    // simple ::= tp vid [= exp] | lv asnop exp | lv ++ | lv -- | exp
+    fn parse_simple(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.span();
+        if self.is_type_start() {
+            let ty = self.parse_type()?;
+            let name = self.expect_id("a variable name")?;
+            let init = if self.eat(&Token::AsnOp(AsnOp::EqAsn)) {
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
+            return Ok(Stmt {
+                kind: StmtKind::Decl { ty, name, init },
+                span: self.span_from(start),
+            });
+        }
+
+        let expr = self.parse_expr()?;
+        let invalid = ParseError {
+            kind: ParseErrorKind::InvalidLValue,
+            span: expr.span,
+        };
+        let kind = match self.peek() {
+            Some(Token::AsnOp(asnop)) => {
+                let op = assignment_op(asnop);
+                self.advance(1);
+                let lhs = lvalue(expr).ok_or(invalid)?;
+                let rhs = self.parse_expr()?;
+                StmtKind::Assign { lhs, op, rhs }
+            }
+            Some(Token::PostOp(postop)) => {
+                let op = match postop {
+                    PostOp::Inc => PostfixOp::Inc,
+                    PostOp::Dec => PostfixOp::Dec,
+                };
+                self.advance(1);
+                let lhs = lvalue(expr).ok_or(invalid)?;
+                StmtKind::PostOp { lhs, op }
+            }
+            _ => StmtKind::Expr(expr),
+        };
+        Ok(Stmt {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    // exp ::= exp ? exp : exp | exp binop exp | unop exp | postfix
+    pub fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let cond = self.parse_binary(1)?;
+        if !self.eat(&Token::BinOp(BinOp::CondEq)) {
+            return Ok(cond);
+        }
+        let then = self.parse_expr()?;
+        self.expect(Token::BinOp(BinOp::CondAsn), "`:`")?;
+        // right-associative: a ? b : c ? d : e is a ? b : (c ? d : e)
+        let els = self.parse_expr()?;
+        let kind = ExprKind::Ternary {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            els: Box::new(els),
+        };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // Precedence climbing: only operators binding at least as tightly as min_prec are taken
+    fn parse_binary(&mut self, min_prec: u8) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let mut lhs = self.parse_unary()?;
+        while let Some((op, prec)) = self.peek().and_then(binary_op) {
+            if prec < min_prec {
+                break;
+            }
+            self.advance(1);
+            let rhs = self.parse_binary(prec + 1)?;
+            let kind = ExprKind::Binary {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+            lhs = Expr::new(kind, self.span_from(start));
+        }
+        Ok(lhs)
+    }
+
+    // Where an operand is expected, '-' is negation and '*' a dereference
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let op = match self.peek() {
+            Some(Token::UnOp(UnOp::LogicalNOT)) => UnaryOp::Not,
+            Some(Token::UnOp(UnOp::BitwiseNOT)) => UnaryOp::BitNot,
+            // -2147483648 is the one place the scanner's 2^31 can go, and stands for the smallest
+            // int rather than the negation of something
+            Some(Token::Minus)
+                if self.peek_nth(1) == Some(&Token::Num(Num::DecNum(DecNum::DecNum(1 << 31)))) =>
+            {
+                self.advance(2);
+                return Ok(Expr::new(ExprKind::Int(i32::MIN), self.span_from(start)));
+            }
+            Some(Token::Minus) => UnaryOp::Neg,
+            Some(Token::Star) => {
+                self.advance(1);
+                let kind = ExprKind::Deref(Box::new(self.parse_unary()?));
+                return Ok(Expr::new(kind, self.span_from(start)));
+            }
+            _ => return self.parse_postfix(),
+        };
+        self.advance(1);
+        let operand = Box::new(self.parse_unary()?);
+        let kind = ExprKind::Unary { op, operand };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // Field access and array indexing bind tighter than any prefix operator
+    fn parse_postfix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let mut expr = self.parse_primary()?;
+        loop {
+            let kind = match self.peek() {
+                Some(Token::BinOp(BinOp::FieldSelect)) => {
+                    self.advance(1);
+                    let field = self.expect_id("a field name")?;
+                    ExprKind::Field {
+                        base: Box::new(expr),
+                        field,
+                    }
+                }
+                Some(Token::BinOp(BinOp::FieldDeref)) => {
+                    self.advance(1);
+                    let field = self.expect_id("a field name")?;
+                    ExprKind::Arrow {
+                        base: Box::new(expr),
+                        field,
+                    }
+                }
+                Some(Token::Sep(Sep::LBracket)) => {
+                    self.advance(1);
+                    let index = self.parse_expr()?;
+                    self.expect(Token::Sep(Sep::RBracket), "`]`")?;
+                    ExprKind::Index {
+                        base: Box::new(expr),
+                        index: Box::new(index),
+                    }
+                }
+                _ => return Ok(expr),
+            };
+            expr = Expr::new(kind, self.span_from(start));
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Some(Token::Num(Num::DecNum(DecNum::DecNum(n)))) => match i32::try_from(*n) {
+                Ok(n) => ExprKind::Int(n),
+                Err(_) => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::IntegerOutOfRange,
+                        span: start,
+                    })
+                }
+            },
+            // hex literals give the bit pattern, so 0xFFFFFFFF is -1
+            Some(Token::Num(Num::HexNum(HexNum::HexNum(n)))) => ExprKind::Int(*n as i32),
+            Some(Token::StrLit(StrLit::StringLiteral(s))) => ExprKind::String(s.clone()),
+            Some(Token::ChrLit(ChrLit::CharacterLiteral(c))) => ExprKind::Char(*c),
+            Some(Token::Keyword(Keyword::True)) => ExprKind::Bool(true),
+            Some(Token::Keyword(Keyword::False)) => ExprKind::Bool(false),
+            Some(Token::Keyword(Keyword::Null)) => ExprKind::Null,
+            Some(Token::Keyword(Keyword::Result)) => ExprKind::Result,
+            // the parenthesized expression keeps its own span, without the parentheses
+            Some(Token::Sep(Sep::LParen)) => return self.parse_condition(),
+            Some(Token::Id(Id::Id(name))) => {
+                let name = name.clone();
+                self.advance(1);
+                if !self.eat(&Token::Sep(Sep::LParen)) {
+                    return Ok(Expr::new(ExprKind::Var(name), start));
+                }
+                let mut args = Vec::new();
+                if !self.eat(&Token::Sep(Sep::RParen)) {
+                    loop {
+                        args.push(self.parse_expr()?);
+                        if self.eat(&Token::Sep(Sep::RParen)) {
+                            break;
+                        }
+                        self.expect(Token::Sep(Sep::Comma), "`,` or `)`")?;
+                    }
+                }
+                let kind = ExprKind::Call { name, args };
+                return Ok(Expr::new(kind, self.span_from(start)));
+            }
+            // alloc ( tp )
+            Some(Token::Keyword(Keyword::Alloc)) => {
+                self.advance(1);
+                self.expect(Token::Sep(Sep::LParen), "`(`")?;
+                let ty = self.parse_type()?;
+                self.expect(Token::Sep(Sep::RParen), "`)`")?;
+                return Ok(Expr::new(ExprKind::Alloc(ty), self.span_from(start)));
+            }
+            // alloc_array ( tp , exp )
+            Some(Token::Keyword(Keyword::AllocArray)) => {
+                self.advance(1);
+                self.expect(Token::Sep(Sep::LParen), "`(`")?;
+                let ty = self.parse_type()?;
+                self.expect(Token::Sep(Sep::Comma), "`,`")?;
+                let len = Box::new(self.parse_expr()?);
+                self.expect(Token::Sep(Sep::RParen), "`)`")?;
+                let kind = ExprKind::AllocArray { ty, len };
+                return Ok(Expr::new(kind, self.span_from(start)));
+            }
+            // \length ( exp )
+            Some(Token::Keyword(Keyword::Length)) => {
+                self.advance(1);
+                let expr = self.parse_condition()?;
+                let kind = ExprKind::Length(Box::new(expr));
+                return Ok(Expr::new(kind, self.span_from(start)));
+            }
+            _ => return Err(self.error("an expression")),
+        };
+        self.advance(1);
+        Ok(Expr::new(kind, start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::erase;
+    use crate::scanner::scan;
+    use crate::scanner::stream::Lexer;
+
+    fn parse_str(source: &str) -> Result<Program, ParseError> {
+        parse(scan(source.to_string()).unwrap())
+    }
+
+    // Parses a program and throws away its spans, for comparing against trees built by hand
+    fn parse_erased(source: &str) -> Program {
+        let mut program = parse_str(source).unwrap();
+        erase::program(&mut program);
+        program
+    }
+
+    // Parses a single expression, as the initializer of a declaration
+    fn expr(source: &str) -> Expr {
+        let mut stmts = stmts(&format!("int x = {};", source));
+        match stmts.remove(0).kind {
+            StmtKind::Decl {
+                init: Some(init), ..
+            } => init,
+            other => panic!("expected a declaration, got {:?}", other),
+        }
+    }
+
+    fn stmts(source: &str) -> Vec<Stmt> {
+        let program = parse_erased(&format!("void f() {{ {} }}", source));
+        match program.decls.into_iter().next().map(|decl| decl.kind) {
+            Some(DeclKind::Function {
+                body: Some(body), ..
+            }) => body,
+            other => panic!("expected a function, got {:?}", other),
+        }
+    }
+
+    // Nodes without spans, to build expected trees from
+    fn e(kind: ExprKind) -> Expr {
+        Expr::new(kind, Span::default())
+    }
+
+    fn s(kind: StmtKind) -> Stmt {
+        Stmt {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn t(kind: TypeKind) -> Type {
+        Type {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn d(kind: DeclKind) -> Decl {
+        Decl {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn lv(kind: LValueKind) -> LValue {
+        LValue::new(kind, Span::default())
+    }
+
+    fn spec(kind: SpecKind) -> Spec {
+        Spec {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn var(name: &str) -> Box<Expr> {
+        Box::new(e(ExprKind::Var(name.to_string())))
+    }
+
+    fn int(n: i32) -> Box<Expr> {
+        Box::new(e(ExprKind::Int(n)))
+    }
+
+    fn binary(op: BinaryOp, lhs: Box<Expr>, rhs: Box<Expr>) -> Box<Expr> {
+        Box::new(e(ExprKind::Binary { op, lhs, rhs }))
+    }
+
+    fn lvar(name: &str) -> LValue {
+        lv(LValueKind::Var(name.to_string()))
+    }
+
+    fn pointer(ty: Type) -> Type {
+        t(TypeKind::Pointer(Box::new(ty)))
+    }
+
+    fn array(ty: Type) -> Type {
+        t(TypeKind::Array(Box::new(ty)))
+    }
+
+    #[test]
+    fn precedence() {
+        // a + b * c
+        assert_eq!(
+            expr("a + b * c"),
+            *binary(
+                BinaryOp::Add,
+                var("a"),
+                binary(BinaryOp::Mul, var("b"), var("c"))
+            )
+        );
+        // a < b == c < d
+        assert_eq!(
+            expr("a < b == c < d"),
+            *binary(
+                BinaryOp::Eq,
+                binary(BinaryOp::Less, var("a"), var("b")),
+                binary(BinaryOp::Less, var("c"), var("d"))
+            )
+        );
+        // a || b && c | d ^ e & f
+        assert_eq!(
+            expr("a || b && c | d ^ e & f"),
+            *binary(
+                BinaryOp::LogicalOr,
+                var("a"),
+                binary(
+                    BinaryOp::LogicalAnd,
+                    var("b"),
+                    binary(
+                        BinaryOp::BitOr,
+                        var("c"),
+                        binary(
+                            BinaryOp::BitXor,
+                            var("d"),
+                            binary(BinaryOp::BitAnd, var("e"), var("f"))
+                        )
+                    )
+                )
+            )
+        );
+        // 1 << 2 + 3
+        assert_eq!(
+            expr("1 << 2 + 3"),
+            *binary(BinaryOp::Shl, int(1), binary(BinaryOp::Add, int(2), int(3)))
+        );
+        // parentheses override precedence
+        assert_eq!(
+            expr("(a + b) * c"),
+            *binary(
+                BinaryOp::Mul,
+                binary(BinaryOp::Add, var("a"), var("b")),
+                var("c")
+            )
+        );
+    }
+
+    #[test]
+    fn associativity() {
+        assert_eq!(
+            expr("a - b - c"),
+            *binary(
+                BinaryOp::Sub,
+                binary(BinaryOp::Sub, var("a"), var("b")),
+                var("c")
+            )
+        );
+        assert_eq!(
+            expr("a / b % c"),
+            *binary(
+                BinaryOp::Mod,
+                binary(BinaryOp::Div, var("a"), var("b")),
+                var("c")
+            )
+        );
+        assert_eq!(
+            expr("a ? b : c ? d : e"),
+            e(ExprKind::Ternary {
+                cond: var("a"),
+                then: var("b"),
+                els: Box::new(e(ExprKind::Ternary {
+                    cond: var("c"),
+                    then: var("d"),
+                    els: var("e"),
+                })),
+            })
+        );
+        // the conditional binds more loosely than any binary operator
+        assert_eq!(
+            expr("a || b ? 1 : 2"),
+            e(ExprKind::Ternary {
+                cond: binary(BinaryOp::LogicalOr, var("a"), var("b")),
+                then: int(1),
+                els: int(2),
+            })
+        );
+    }
+
+    #[test]
+    fn unary_and_postfix() {
+        assert_eq!(
+            expr("-x * !y"),
+            *binary(
+                BinaryOp::Mul,
+                Box::new(e(ExprKind::Unary {
+                    op: UnaryOp::Neg,
+                    operand: var("x"),
+                })),
+                Box::new(e(ExprKind::Unary {
+                    op: UnaryOp::Not,
+                    operand: var("y"),
+                }))
+            )
+        );
+        // *p->next[i].val is *(((p->next)[i]).val)
+        assert_eq!(
+            expr("*p->next[i].val"),
+            e(ExprKind::Deref(Box::new(e(ExprKind::Field {
+                base: Box::new(e(ExprKind::Index {
+                    base: Box::new(e(ExprKind::Arrow {
+                        base: var("p"),
+                        field: "next".to_string(),
+                    })),
+                    index: var("i"),
+                })),
+                field: "val".to_string(),
+            }))))
+        );
+        assert_eq!(
+            expr("f(1, g(), x)"),
+            e(ExprKind::Call {
+                name: "f".to_string(),
+                args: vec![
+                    *int(1),
+                    e(ExprKind::Call {
+                        name: "g".to_string(),
+                        args: vec![],
+                    }),
+                    *var("x"),
+                ],
+            })
+        );
+        assert_eq!(
+            expr("alloc_array(struct node*, n)"),
+            e(ExprKind::AllocArray {
+                ty: pointer(t(TypeKind::Struct("node".to_string()))),
+                len: var("n"),
+            })
+        );
+        assert_eq!(expr("alloc(int)"), e(ExprKind::Alloc(t(TypeKind::Int))));
+    }
+
+    #[test]
+    fn minus_and_star_by_position() {
+        let neg = |operand| {
+            Box::new(e(ExprKind::Unary {
+                op: UnaryOp::Neg,
+                operand,
+            }))
+        };
+        let deref = |base| Box::new(e(ExprKind::Deref(base)));
+        let index = |base, index| Box::new(e(ExprKind::Index { base, index }));
+        let call = |name: &str| {
+            Box::new(e(ExprKind::Call {
+                name: name.to_string(),
+                args: vec![],
+            }))
+        };
+
+        // after a closing parenthesis, bracket or call, '-' and '*' are binary
+        assert_eq!(expr("(a) - b"), *binary(BinaryOp::Sub, var("a"), var("b")));
+        assert_eq!(
+            expr("x[i] * y"),
+            *binary(BinaryOp::Mul, index(var("x"), var("i")), var("y"))
+        );
+        assert_eq!(expr("f() - 1"), *binary(BinaryOp::Sub, call("f"), int(1)));
+        assert_eq!(
+            expr("(a + b) * -c"),
+            *binary(
+                BinaryOp::Mul,
+                binary(BinaryOp::Add, var("a"), var("b")),
+                neg(var("c"))
+            )
+        );
+        // wherever an operand is expected they are prefix operators
+        assert_eq!(
+            expr("a - -b"),
+            *binary(BinaryOp::Sub, var("a"), neg(var("b")))
+        );
+        assert_eq!(
+            expr("x[i] * *p"),
+            *binary(BinaryOp::Mul, index(var("x"), var("i")), deref(var("p")))
+        );
+        assert_eq!(
+            expr("f(-1, *p)"),
+            e(ExprKind::Call {
+                name: "f".to_string(),
+                args: vec![*neg(int(1)), *deref(var("p"))],
+            })
+        );
+        assert_eq!(
+            expr("**p - - 1"),
+            *binary(BinaryOp::Sub, deref(deref(var("p"))), neg(int(1)))
+        );
+        assert_eq!(
+            stmts("*p = (*q) * 2;"),
+            vec![s(StmtKind::Assign {
+                lhs: lv(LValueKind::Deref(Box::new(lvar("p")))),
+                op: None,
+                rhs: *binary(BinaryOp::Mul, deref(var("q")), int(2)),
+            })]
+        );
+    }
+
+    #[test]
+    fn integer_literals() {
+        let neg = |operand| {
+            e(ExprKind::Unary {
+                op: UnaryOp::Neg,
+                operand,
+            })
+        };
+        assert_eq!(expr("2147483647"), *int(i32::MAX));
+        assert_eq!(expr("-2147483648"), *int(i32::MIN));
+        assert_eq!(expr("-2147483647"), neg(int(i32::MAX)));
+        assert_eq!(expr("0xFFFFFFFF"), *int(-1));
+        assert_eq!(expr("0x80000000"), *int(i32::MIN));
+        let error = parse_str("int f() { return 2147483648; }").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::IntegerOutOfRange);
+        assert_eq!((error.span.column, error.span.length), (18, 10));
+        // the minus has to be a unary one, right before it
+        for source in ["-(2147483648)", "1 - 2147483648", "~2147483648"] {
+            let error = parse_str(&format!("int f() {{ return {}; }}", source)).unwrap_err();
+            assert_eq!(error.kind, ParseErrorKind::IntegerOutOfRange);
+        }
+    }
+
+    #[test]
+    fn simple_statements() {
+        assert_eq!(
+            stmts("int[] A = alloc_array(int, 3); A[0] += 1; x++; *p = NULL; f();"),
+            vec![
+                s(StmtKind::Decl {
+                    ty: array(t(TypeKind::Int)),
+                    name: "A".to_string(),
+                    init: Some(e(ExprKind::AllocArray {
+                        ty: t(TypeKind::Int),
+                        len: int(3),
+                    })),
+                }),
+                s(StmtKind::Assign {
+                    lhs: lv(LValueKind::Index {
+                        base: Box::new(lvar("A")),
+                        index: *int(0),
+                    }),
+                    op: Some(BinaryOp::Add),
+                    rhs: *int(1),
+                }),
+                s(StmtKind::PostOp {
+                    lhs: lvar("x"),
+                    op: PostfixOp::Inc,
+                }),
+                s(StmtKind::Assign {
+                    lhs: lv(LValueKind::Deref(Box::new(lvar("p")))),
+                    op: None,
+                    rhs: e(ExprKind::Null),
+                }),
+                s(StmtKind::Expr(e(ExprKind::Call {
+                    name: "f".to_string(),
+                    args: vec![],
+                }))),
+            ]
+        );
+    }
+
+    #[test]
+    fn control_statements() {
+        let parsed = stmts(
+            "for (int i = 0; i < n; i++) { if (i == 2) continue; else break; } \
+             while (true) return; assert(x); error(\"oops\");",
+        );
+        assert_eq!(
+            parsed,
+            vec![
+                s(StmtKind::For {
+                    init: Some(Box::new(s(StmtKind::Decl {
+                        ty: t(TypeKind::Int),
+                        name: "i".to_string(),
+                        init: Some(*int(0)),
+                    }))),
+                    cond: *binary(BinaryOp::Less, var("i"), var("n")),
+                    step: Some(Box::new(s(StmtKind::PostOp {
+                        lhs: lvar("i"),
+                        op: PostfixOp::Inc,
+                    }))),
+                    invariants: vec![],
+                    body: Box::new(s(StmtKind::Block(vec![s(StmtKind::If {
+                        cond: *binary(BinaryOp::Eq, var("i"), int(2)),
+                        then: Box::new(s(StmtKind::Continue)),
+                        els: Some(Box::new(s(StmtKind::Break))),
+                    })]))),
+                }),
+                s(StmtKind::While {
+                    cond: e(ExprKind::Bool(true)),
+                    invariants: vec![],
+                    body: Box::new(s(StmtKind::Return(None))),
+                }),
+                s(StmtKind::Assert(*var("x"))),
+                s(StmtKind::Error(e(ExprKind::String("oops".to_string())))),
+            ]
+        );
+
+        // a dangling else goes with the innermost if
+        let assign = |n| {
+            Box::new(s(StmtKind::Assign {
+                lhs: lvar("x"),
+                op: None,
+                rhs: *int(n),
+            }))
+        };
+        assert_eq!(
+            stmts("if (a) if (b) x = 1; else x = 2;"),
+            vec![s(StmtKind::If {
+                cond: *var("a"),
+                then: Box::new(s(StmtKind::If {
+                    cond: *var("b"),
+                    then: assign(1),
+                    els: Some(assign(2)),
+                })),
+                els: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn declarations() {
+        let program = parse_erased(
+            "#use <conio>
+             typedef struct list_node list;
+             struct list_node;
+             struct list_node { int data; list* next; };
+             int length(list* l);
+             int length(list* l)
+             //@requires l != NULL;
+             //@ensures \\result >= 0;
+             {
+               int n = 0;
+               while (l != NULL)
+                 /*@ loop_invariant n >= 0; @*/
+               {
+                 //@assert l != NULL;
+                 n++;
+                 l = l->next;
+               }
+               return n;
+             }",
+        );
+
+        let list = || pointer(t(TypeKind::Name("list".to_string())));
+        let params = || {
+            vec![Param {
+                ty: list(),
+                name: "l".to_string(),
+                span: Span::default(),
+            }]
+        };
+        let not_null = || *binary(BinaryOp::NotEq, var("l"), Box::new(e(ExprKind::Null)));
+        assert_eq!(
+            program.decls,
+            vec![
+                d(DeclKind::Typedef {
+                    ty: t(TypeKind::Struct("list_node".to_string())),
+                    name: "list".to_string(),
+                }),
+                d(DeclKind::StructDecl {
+                    name: "list_node".to_string(),
+                }),
+                d(DeclKind::StructDef {
+                    name: "list_node".to_string(),
+                    fields: vec![
+                        Field {
+                            ty: t(TypeKind::Int),
+                            name: "data".to_string(),
+                            span: Span::default(),
+                        },
+                        Field {
+                            ty: list(),
+                            name: "next".to_string(),
+                            span: Span::default(),
+                        },
+                    ],
+                }),
+                d(DeclKind::Function {
+                    ret: t(TypeKind::Int),
+                    name: "length".to_string(),
+                    params: params(),
+                    specs: vec![],
+                    body: None,
+                }),
+                d(DeclKind::Function {
+                    ret: t(TypeKind::Int),
+                    name: "length".to_string(),
+                    params: params(),
+                    specs: vec![
+                        spec(SpecKind::Requires(not_null())),
+                        spec(SpecKind::Ensures(*binary(
+                            BinaryOp::GreaterEq,
+                            Box::new(e(ExprKind::Result)),
+                            int(0)
+                        ))),
+                    ],
+                    body: Some(vec![
+                        s(StmtKind::Decl {
+                            ty: t(TypeKind::Int),
+                            name: "n".to_string(),
+                            init: Some(*int(0)),
+                        }),
+                        s(StmtKind::While {
+                            cond: not_null(),
+                            invariants: vec![spec(SpecKind::LoopInvariant(*binary(
+                                BinaryOp::GreaterEq,
+                                var("n"),
+                                int(0)
+                            )))],
+                            body: Box::new(s(StmtKind::Block(vec![
+                                s(StmtKind::Annotation(vec![spec(SpecKind::Assert(
+                                    not_null()
+                                ))])),
+                                s(StmtKind::PostOp {
+                                    lhs: lvar("n"),
+                                    op: PostfixOp::Inc,
+                                }),
+                                s(StmtKind::Assign {
+                                    lhs: lvar("l"),
+                                    op: None,
+                                    rhs: e(ExprKind::Arrow {
+                                        base: var("l"),
+                                        field: "next".to_string(),
+                                    }),
+                                }),
+                            ]))),
+                        }),
+                        s(StmtKind::Return(Some(*var("n")))),
+                    ]),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn typedef_names() {
+        // once t names a type, "t * x" declares a pointer instead of multiplying
+        let parsed = stmts("t * x;");
+        assert!(matches!(
+            &parsed[0].kind,
+            StmtKind::Expr(Expr {
+                kind: ExprKind::Binary { .. },
+                ..
+            })
+        ));
+
+        let program = parse_erased("typedef int t; void f() { t * x; t[] y; }");
+        match &program.decls[1].kind {
+            DeclKind::Function {
+                body: Some(body), ..
+            } => {
+                assert_eq!(
+                    body[0],
+                    s(StmtKind::Decl {
+                        ty: pointer(t(TypeKind::Name("t".to_string()))),
+                        name: "x".to_string(),
+                        init: None,
+                    })
+                );
+                assert_eq!(
+                    body[1],
+                    s(StmtKind::Decl {
+                        ty: array(t(TypeKind::Name("t".to_string()))),
+                        name: "y".to_string(),
+                        init: None,
+                    })
+                );
+            }
+            other => panic!("expected a function, got {:?}", other),
+        }
+
+        // names can start with an underscore
+        let program = parse_erased("typedef int _t;\nvoid _f(_t _x) { _t * _y; }");
+        match &program.decls[1].kind {
+            DeclKind::Function {
+                name,
+                params,
+                body: Some(body),
+                ..
+            } => {
+                assert_eq!((name.as_str(), params[0].name.as_str()), ("_f", "_x"));
+                assert_eq!(
+                    body[0],
+                    s(StmtKind::Decl {
+                        ty: pointer(t(TypeKind::Name("_t".to_string()))),
+                        name: "_y".to_string(),
+                        init: None,
+                    })
+                );
+            }
+            other => panic!("expected a function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn spans() {
+        let program = parse_str("int f(int x) {\n  return x + 10;\n }").unwrap();
+        let spanned = |span: Span| (span.line, span.column, span.length);
+        assert_eq!(spanned(program.span), (1, 1, 34));
+
+        let decl = &program.decls[0];
+        assert_eq!(spanned(decl.span), (1, 1, 34));
+        let (params, body) = match &decl.kind {
+            DeclKind::Function {
+                params,
+                body: Some(body),
+                ..
+            } => (params, body),
+            other => panic!("expected a function, got {:?}", other),
+        };
+        assert_eq!(spanned(params[0].span), (1, 7, 5));
+        assert_eq!(spanned(params[0].ty.span), (1, 7, 3));
+
+        // the statement includes its ';', the expression only runs from operand to operand
+        assert_eq!(spanned(body[0].span), (2, 3, 14));
+        match &body[0].kind {
+            StmtKind::Return(Some(Expr {
+                kind: ExprKind::Binary { lhs, rhs, .. },
+                span,
+                ty,
+            })) => {
+                assert_eq!(spanned(*span), (2, 10, 6));
+                assert_eq!(spanned(lhs.span), (2, 10, 1));
+                assert_eq!(spanned(rhs.span), (2, 14, 2));
+                assert_eq!(*ty, None);
+            }
+            other => panic!("expected a return, got {:?}", other),
+        }
+
+        // postfix chains and compound types grow from where they start
+        let program = parse_str("void g() { p->next[i] = alloc(int*); }").unwrap();
+        let DeclKind::Function {
+            body: Some(body), ..
+        } = &program.decls[0].kind
+        else {
+            panic!("expected a function");
+        };
+        match &body[0].kind {
+            StmtKind::Assign { lhs, rhs, .. } => {
+                assert_eq!(spanned(lhs.span), (1, 12, 10));
+                assert_eq!(spanned(rhs.span), (1, 25, 11));
+                let ExprKind::Alloc(ty) = &rhs.kind else {
+                    panic!("expected alloc, got {:?}", rhs.kind);
+                };
+                assert_eq!(spanned(ty.span), (1, 31, 4));
+            }
+            other => panic!("expected an assignment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let error = parse_str("int main() {\n  return 0\n  }").unwrap_err();
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::Expected {
+                expected: "`;`",
+                found: Some("}".to_string()),
+            }
+        );
+        assert_eq!(error.to_string(), "3:3: error: expected `;`, found `}`");
+
+        let error = parse_str("void f() { f() = 3; }").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::InvalidLValue);
+        assert_eq!((error.span.line, error.span.column), (1, 12));
+
+        let error = parse_str("int f() { return 1;").unwrap_err();
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::Expected {
+                expected: "`}`",
+                found: None,
+            }
+        );
+
+        assert_eq!(
+            parse_str("int x(); #use <conio>").unwrap_err().kind,
+            ParseErrorKind::MisplacedDirective
+        );
+        assert_eq!(
+            parse_str("void f() { for (;true; int i = 0) {} }")
+                .unwrap_err()
+                .kind,
+            ParseErrorKind::DeclInForStep
+        );
+
+        // each kind of contract has its own place
+        let misplaced = |source: &str| parse_str(source).unwrap_err().to_string();
+        assert_eq!(
+            misplaced("int f()\n//@loop_invariant true;\n;"),
+            "2:4: error: @loop_invariant can only go after the header of a loop"
+        );
+        assert_eq!(
+            misplaced("int f()\n//@assert true;\n;"),
+            "2:4: error: @assert can only go among statements"
+        );
+        assert_eq!(
+            misplaced("void f() { while (true)\n//@requires true;\n{} }"),
+            "2:4: error: @requires can only go between a function's header and its body"
+        );
+        assert_eq!(
+            misplaced("void f() { for (;true;)\n//@ensures true;\n{} }"),
+            "2:4: error: @ensures can only go between a function's header and its body"
+        );
+        assert_eq!(
+            misplaced("void f() {\n//@loop_invariant true;\n}"),
+            "2:4: error: @loop_invariant can only go after the header of a loop"
+        );
+    }
+
+    #[test]
+    fn streamed() {
+        // a program far longer than the parser's window, pulled a token at a time from a reader
+        let function = "int f(int x)\n //@requires x > 0;\n {\n  struct s * p = alloc(struct s);\n  return x;\n }\n ";
+        let source = function.repeat(1000);
+        let lexer = Lexer::new(std::io::Cursor::new(source.clone()));
+        let streamed = Parser::new(lexer.map(Result::unwrap))
+            .parse_program()
+            .unwrap();
+        assert_eq!(streamed.decls.len(), 1000);
+        assert_eq!(streamed, parse_str(&source).unwrap());
+
+        // the end of the input is still found right after the last token
+        let error = Parser::new(Lexer::new("int f() { return 1;".as_bytes()).map(Result::unwrap))
+            .parse_program()
+            .unwrap_err();
+        assert_eq!(error.span.offset, 19);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::ast::{
+    BinaryOp, Decl, DeclKind, Expr, ExprKind, LValue, LValueKind, PostfixOp, Program, Spec,
+    SpecKind, Stmt, StmtKind, Type, TypeKind, UnaryOp,
+};
+use std::fmt;
+
+// Prints the tree back out as C0, for looking at what the passes after parsing have made of it.
+// Every compound expression is parenthesized, so the structure is plain to see without knowing
+// the precedence rules. Whatever came from the source reads back in as the same tree
+const INDENT: &str = "    ";
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, decl) in self.decls.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", decl)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Decl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            DeclKind::Typedef { ty, name } => writeln!(f, "typedef {} {};", ty, name),
+            DeclKind::StructDecl { name } => writeln!(f, "struct {};", name),
+            DeclKind::StructDef { name, fields } => {
+                writeln!(f, "struct {} {{", name)?;
+                for field in fields {
+                    writeln!(f, "{}{} {};", INDENT, field.ty, field.name)?;
+                }
+                writeln!(f, "}};")
+            }
+            DeclKind::Function {
+                ret,
+                name,
+                params,
+                specs,
+                body,
+            } => {
+                let params: Vec<String> = params
+                    .iter()
+                    .map(|param| format!("{} {}", param.ty, param.name))
+                    .collect();
+                writeln!(f, "{} {}({})", ret, name, params.join(", "))?;
+                for spec in specs {
+                    writeln!(f, "{}", spec)?;
+                }
+                match body {
+                    Some(body) => block(f, body, 0),
+                    None => writeln!(f, ";"),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            TypeKind::Int => write!(f, "int"),
+            TypeKind::Bool => write!(f, "bool"),
+            TypeKind::Char => write!(f, "char"),
+            TypeKind::String => write!(f, "string"),
+            TypeKind::Void => write!(f, "void"),
+            TypeKind::Pointer(inner) => write!(f, "{}*", inner),
+            TypeKind::Array(inner) => write!(f, "{}[]", inner),
+            TypeKind::Struct(name) => write!(f, "struct {}", name),
+            TypeKind::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// As a line annotation, which is how contracts are printed wherever they go
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (keyword, e) = match &self.kind {
+            SpecKind::Requires(e) => ("requires", e),
+            SpecKind::Ensures(e) => ("ensures", e),
+            SpecKind::LoopInvariant(e) => ("loop_invariant", e),
+            SpecKind::Assert(e) => ("assert", e),
+        };
+        write!(f, "//@{} {};", keyword, e)
+    }
+}
+
+// Writes `stmts` as a block whose braces are at `depth`, the opening one where the line already
+// written leaves off
+fn block(f: &mut fmt::Formatter, stmts: &[Stmt], depth: usize) -> fmt::Result {
+    writeln!(f, "{{")?;
+    for s in stmts {
+        stmt(f, s, depth + 1)?;
+    }
+    writeln!(f, "{}}}", INDENT.repeat(depth))
+}
+
+// The body of an if or a loop, following its header: a block on the same line, anything else
+// indented on the next. `on_new_line` is whether the header's line has already been ended
+fn body(f: &mut fmt::Formatter, s: &Stmt, depth: usize, on_new_line: bool) -> fmt::Result {
+    match (&s.kind, on_new_line) {
+        (StmtKind::Block(stmts), true) => {
+            write!(f, "{}", INDENT.repeat(depth))?;
+            block(f, stmts, depth)
+        }
+        (StmtKind::Block(stmts), false) => {
+            write!(f, " ")?;
+            block(f, stmts, depth)
+        }
+        (_, true) => stmt(f, s, depth + 1),
+        (_, false) => {
+            writeln!(f)?;
+            stmt(f, s, depth + 1)
+        }
+    }
+}
+
+// The statements that fit in a for loop's header, without their ';'
+fn simple(s: &Stmt) -> String {
+    match &s.kind {
+        StmtKind::Decl { ty, name, init } => match init {
+            Some(init) => format!("{} {} = {}", ty, name, init),
+            None => format!("{} {}", ty, name),
+        },
+        StmtKind::Assign { lhs, op, rhs } => match op {
+            Some(op) => format!("{} {}= {}", lhs, binary_op(*op), rhs),
+            None => format!("{} = {}", lhs, rhs),
+        },
+        StmtKind::PostOp { lhs, op } => match op {
+            PostfixOp::Inc => format!("{}++", lhs),
+            PostfixOp::Dec => format!("{}--", lhs),
+        },
+        StmtKind::Expr(e) => e.to_string(),
+        _ => unreachable!("{:?} is not a simple statement", s.kind),
+    }
+}
+
+// A loop's invariants, on lines of their own between its header and its body
+fn invariants(f: &mut fmt::Formatter, specs: &[Spec], depth: usize) -> Result<bool, fmt::Error> {
+    if specs.is_empty() {
+        return Ok(false);
+    }
+    writeln!(f)?;
+    for spec in specs {
+        writeln!(f, "{}{}", INDENT.repeat(depth), spec)?;
+    }
+    Ok(true)
+}
+
+fn stmt(f: &mut fmt::Formatter, s: &Stmt, depth: usize) -> fmt::Result {
+    let indent = INDENT.repeat(depth);
+    match &s.kind {
+        StmtKind::Decl { .. }
+        | StmtKind::Assign { .. }
+        | StmtKind::PostOp { .. }
+        | StmtKind::Expr(_) => writeln!(f, "{}{};", indent, simple(s)),
+        StmtKind::If { cond, then, els } => {
+            write!(f, "{}if ({})", indent, cond)?;
+            body(f, then, depth, false)?;
+            if let Some(els) = els {
+                write!(f, "{}else", indent)?;
+                body(f, els, depth, false)?;
+            }
+            Ok(())
+        }
+        StmtKind::While {
+            cond,
+            invariants: specs,
+            body: looped,
+        } => {
+            write!(f, "{}while ({})", indent, cond)?;
+            let on_new_line = invariants(f, specs, depth)?;
+            body(f, looped, depth, on_new_line)
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            invariants: specs,
+            body: looped,
+        } => {
+            let init = init.as_deref().map(simple).unwrap_or_default();
+            let step = step.as_deref().map(simple).unwrap_or_default();
+            write!(f, "{}for ({}; {}; {})", indent, init, cond, step)?;
+            let on_new_line = invariants(f, specs, depth)?;
+            body(f, looped, depth, on_new_line)
+        }
+        StmtKind::Return(Some(e)) => writeln!(f, "{}return {};", indent, e),
+        StmtKind::Return(None) => writeln!(f, "{}return;", indent),
+        StmtKind::Block(stmts) => {
+            write!(f, "{}", indent)?;
+            block(f, stmts, depth)
+        }
+        StmtKind::Assert(e) => writeln!(f, "{}assert({});", indent, e),
+        StmtKind::Error(e) => writeln!(f, "{}error({});", indent, e),
+        StmtKind::Break => writeln!(f, "{}break;", indent),
+        StmtKind::Continue => writeln!(f, "{}continue;", indent),
+        StmtKind::Annotation(specs) => {
+            for spec in specs {
+                writeln!(f, "{}{}", indent, spec)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn binary_op(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+        BinaryOp::Less => "<",
+        BinaryOp::LessEq => "<=",
+        BinaryOp::Greater => ">",
+        BinaryOp::GreaterEq => ">=",
+        BinaryOp::Eq => "==",
+        BinaryOp::NotEq => "!=",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitXor => "^",
+        BinaryOp::BitOr => "|",
+        BinaryOp::LogicalAnd => "&&",
+        BinaryOp::LogicalOr => "||",
+    }
+}
+
+// A character as it would be written between quotes. `quote` is the one that has to be escaped
+fn escape(c: char, quote: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\u{0B}' => "\\v".to_string(),
+        '\u{0C}' => "\\f".to_string(),
+        '\u{07}' => "\\a".to_string(),
+        '\u{08}' => "\\b".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        c if c == quote => format!("\\{}", c),
+        c => c.to_string(),
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            // A negative literal can only have been written in hex, as its bit pattern
+            ExprKind::Int(n) if *n < 0 => write!(f, "0x{:X}", *n as u32),
+            ExprKind::Int(n) => write!(f, "{}", n),
+            ExprKind::Bool(b) => write!(f, "{}", b),
+            ExprKind::Char(c) => write!(f, "'{}'", escape(*c, '\'')),
+            ExprKind::String(s) => {
+                let escaped: String = s.chars().map(|c| escape(c, '"')).collect();
+                write!(f, "\"{}\"", escaped)
+            }
+            ExprKind::Null => write!(f, "NULL"),
+            ExprKind::Var(name) => write!(f, "{}", name),
+            ExprKind::Binary { op, lhs, rhs } => {
+                write!(f, "({} {} {})", lhs, binary_op(*op), rhs)
+            }
+            ExprKind::Unary { op, operand } => {
+                let op = match op {
+                    UnaryOp::Not => "!",
+                    UnaryOp::BitNot => "~",
+                    UnaryOp::Neg => "-",
+                };
+                write!(f, "({}{})", op, operand)
+            }
+            ExprKind::Ternary { cond, then, els } => {
+                write!(f, "({} ? {} : {})", cond, then, els)
+            }
+            ExprKind::Call { name, args } => {
+                let args: Vec<String> = args.iter().map(Expr::to_string).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            ExprKind::Field { base, field } => write!(f, "{}.{}", base, field),
+            ExprKind::Arrow { base, field } => write!(f, "{}->{}", base, field),
+            ExprKind::Deref(base) => write!(f, "(*{})", base),
+            ExprKind::Index { base, index } => write!(f, "{}[{}]", base, index),
+            ExprKind::Alloc(ty) => write!(f, "alloc({})", ty),
+            ExprKind::AllocArray { ty, len } => write!(f, "alloc_array({}, {})", ty, len),
+            ExprKind::Result => write!(f, "\\result"),
+            ExprKind::Length(base) => write!(f, "\\length({})", base),
+        }
+    }
+}
+
+impl fmt::Display for LValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            LValueKind::Var(name) => write!(f, "{}", name),
+            LValueKind::Field { base, field } => write!(f, "{}.{}", base, field),
+            LValueKind::Arrow { base, field } => write!(f, "{}->{}", base, field),
+            LValueKind::Deref(base) => write!(f, "(*{})", base),
+            LValueKind::Index { base, index } => write!(f, "{}[{}]", base, index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{erase, Program};
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn parsed(source: &str) -> Program {
+        let mut program = parse(scan(source.to_string()).unwrap()).unwrap();
+        erase::program(&mut program);
+        program
+    }
+
+    #[test]
+    fn reads_back() {
+        let source = "typedef struct node* list;\n\
+                      struct node { int value; list next; };\n\
+                      int sum(list l, int[] extra, int n)\n\
+                      //@requires \\length(extra) == n;\n\
+                      //@ensures \\result >= 0 || n < 0;\n\
+                      {\n\
+                        int total = 0xFFFFFFFF;\n\
+                        for (int i = 0; i < n; i++)\n\
+                        //@loop_invariant 0 <= i;\n\
+                          total += extra[i];\n\
+                        while (l != NULL) { total = total + l->value * -1; l = l->next; }\n\
+                        if (total < 0) return -total; else if (!(total == 0)) { total--; }\n\
+                        //@assert total >= 0;\n\
+                        char c = '\\n'; string s = \"tab\\there \\\"quoted\\\"\";\n\
+                        assert(c != '\\'' && s != s);\n\
+                        if (false) error(\"never\");\n\
+                        { bool b = true ? ~total > 0 : false; int* p = alloc(int); *p = 3; }\n\
+                        int[] a = alloc_array(int, 2); a[0] <<= 1;\n\
+                        return total;\n\
+                      }\n\
+                      void f();";
+        let program = parsed(source);
+        let printed = program.to_string();
+        assert_eq!(parsed(&printed), program, "{}", printed);
+    }
+
+    #[test]
+    fn layout() {
+        let program =
+            parsed("int f(int x) { if (x > 0) return x; while (true) { break; } return 0; }");
+        assert_eq!(
+            program.to_string(),
+            "int f(int x)\n\
+             {\n\
+             \x20   if ((x > 0))\n\
+             \x20       return x;\n\
+             \x20   while (true) {\n\
+             \x20       break;\n\
+             \x20   }\n\
+             \x20   return 0;\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,699 @@
+use crate::lexgen::charset::{class, representative, CLASSES};
+use crate::scanner::error::{LexError, LexErrorKind};
+use crate::scanner::span::SpanTracker;
+use crate::scanner::token::{
+    Annot, AsnOp, BinOp, ChrLit, Directive, Esc, Keyword, LibLit, PostOp, Sep, SpannedToken,
+    StrLit, Token, UnOp,
+};
+use crate::scanner::{
+    finish, int_literal, keyword, lex_error, literal_char, next_index, result, skip_block_comment,
+    Annotation, SourceChars,
+};
+use std::sync::OnceLock;
+
+// Table-driven scanner
+//
+// The automata drawn in transition_diagrams/ are written out below as explicit states and
+// transitions, merged into a single DFA, and run with maximal munch: from a start state we follow
+// transitions for as long as the input allows, and the token is whatever the last accepting state
+// we passed through recognizes. Each accepting state carries an action saying what to make of the
+// text it matched
+//
+// Two parts of C0's lexical structure aren't regular. Block comments nest, so once the DFA has
+// matched "/*" the rest is left to the same helper the hand-written scanner uses. And "@", "//@"
+// or "\result" mean different things inside and outside of annotations, so there is a start state
+// for each mode, the way lexer generators handle start conditions
+
+type State = u16;
+
+// Where the automaton goes when it gets stuck
+const DEAD: State = State::MAX;
+
+// The start states, one per mode
+const NORMAL: State = 0;
+const LINE_ANNOTATION: State = 1;
+const BLOCK_ANNOTATION: State = 2;
+
+pub(super) type MakeToken = fn() -> Token;
+
+// What an accepting state makes of the text it matched
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Accept {
+    Token(MakeToken),
+    Word,          // an identifier or keyword
+    BackslashWord, // "\result" or "\length"
+    Esc,           // an escape sequence written outside of any literal
+    DecNum,
+    HexNum,
+    StrLit,
+    ChrLit,
+    UseLib,
+    UseFile,
+    Newline, // closes a line annotation, and is otherwise skipped
+    LineOpen,
+    BlockOpen,
+    Close,
+    BlockComment, // "/*", the rest of which is skipped separately since comments nest
+    Skip,         // whitespace, line comments, and characters the scanner ignores
+    Error(LexErrorKind),
+}
+
+// Operators and separators, as drawn in binop_, asnop_, postop_, unop_ and
+// sep_transition_diagram.png. Each diagram is a tree, branching on one character at a time
+pub(super) const OPERATORS: &[(&str, MakeToken)] = &[
+    ("?", || Token::BinOp(BinOp::CondEq)),
+    (".", || Token::BinOp(BinOp::FieldSelect)),
+    ("->", || Token::BinOp(BinOp::FieldDeref)),
+    ("-", || Token::Minus),
+    ("-=", || Token::AsnOp(AsnOp::DecAsn)),
+    ("--", || Token::PostOp(PostOp::Dec)),
+    ("*", || Token::Star),
+    ("*=", || Token::AsnOp(AsnOp::MultAsn)),
+    ("/", || Token::BinOp(BinOp::Divide)),
+    ("/=", || Token::AsnOp(AsnOp::DivAsn)),
+    ("%", || Token::BinOp(BinOp::Modulo)),
+    ("%=", || Token::AsnOp(AsnOp::ModAsn)),
+    ("+", || Token::BinOp(BinOp::Plus)),
+    ("+=", || Token::AsnOp(AsnOp::IncAsn)),
+    ("++", || Token::PostOp(PostOp::Inc)),
+    ("<", || Token::BinOp(BinOp::Less)),
+    ("<=", || Token::BinOp(BinOp::LessEq)),
+    ("<<", || Token::BinOp(BinOp::ShiftLeft)),
+    ("<<=", || Token::AsnOp(AsnOp::LShiftAsn)),
+    (">", || Token::BinOp(BinOp::Greater)),
+    (">=", || Token::BinOp(BinOp::GreaterEq)),
+    (">>", || Token::BinOp(BinOp::ShiftRight)),
+    (">>=", || Token::AsnOp(AsnOp::RShiftAsn)),
+    ("=", || Token::AsnOp(AsnOp::EqAsn)),
+    ("==", || Token::BinOp(BinOp::Equality)),
+    ("&", || Token::BinOp(BinOp::BitwiseAND)),
+    ("&&", || Token::BinOp(BinOp::LogicalAND)),
+    ("&=", || Token::AsnOp(AsnOp::ANDAsn)),
+    ("^", || Token::BinOp(BinOp::BitwiseXOR)),
+    ("^=", || Token::AsnOp(AsnOp::XORAsn)),
+    ("!", || Token::UnOp(UnOp::LogicalNOT)),
+    ("!=", || Token::BinOp(BinOp::Disequality)),
+    ("|", || Token::BinOp(BinOp::BitwiseOR)),
+    ("||", || Token::BinOp(BinOp::LogicalOR)),
+    ("|=", || Token::AsnOp(AsnOp::ORAsn)),
+    (":", || Token::BinOp(BinOp::CondAsn)),
+    ("~", || Token::UnOp(UnOp::BitwiseNOT)),
+    ("(", || Token::Sep(Sep::LParen)),
+    (")", || Token::Sep(Sep::RParen)),
+    ("[", || Token::Sep(Sep::LBracket)),
+    ("]", || Token::Sep(Sep::RBracket)),
+    ("{", || Token::Sep(Sep::LCurly)),
+    ("}", || Token::Sep(Sep::RCurly)),
+    (",", || Token::Sep(Sep::Comma)),
+    (";", || Token::Sep(Sep::SemiColon)),
+];
+
+pub struct Dfa {
+    // transitions[state][class], DEAD where there is none
+    transitions: Vec<[State; CLASSES]>,
+    accepts: Vec<Option<Accept>>,
+}
+
+impl Dfa {
+    fn new() -> Dfa {
+        Dfa {
+            transitions: Vec::new(),
+            accepts: Vec::new(),
+        }
+    }
+
+    // The automaton for all of C0's tokens
+    fn c0() -> Dfa {
+        let mut dfa = Dfa::new();
+        let starts = [NORMAL, LINE_ANNOTATION, BLOCK_ANNOTATION];
+        for _ in starts {
+            dfa.state(None);
+        }
+        for start in starts {
+            dfa.add_tokens(start);
+        }
+        dfa
+    }
+
+    fn state(&mut self, accept: Option<Accept>) -> State {
+        self.transitions.push([DEAD; CLASSES]);
+        self.accepts.push(accept);
+        (self.transitions.len() - 1) as State
+    }
+
+    fn accepting(&mut self, accept: Accept) -> State {
+        self.state(Some(accept))
+    }
+
+    // Adds a transition on every character in `set` that doesn't already have one out of `from`.
+    // Specific transitions therefore have to be added before catch-all ones
+    fn edge(&mut self, from: State, set: impl Fn(char) -> bool, to: State) {
+        for (class, next) in self.transitions[from as usize].iter_mut().enumerate() {
+            if *next == DEAD && set(representative(class)) {
+                *next = to;
+            }
+        }
+    }
+
+    fn on(&mut self, from: State, c: char, to: State) {
+        self.edge(from, |next| next == c, to);
+    }
+
+    // Follows the transitions spelling out `text`, adding states where there are none yet, and
+    // makes the state it ends in accept
+    fn spell(&mut self, from: State, text: &str, accept: Accept) -> State {
+        let mut state = from;
+        for c in text.chars() {
+            let next = self.transitions[state as usize][class(c)];
+            state = if next == DEAD {
+                let next = self.state(None);
+                self.on(state, c, next);
+                next
+            } else {
+                next
+            };
+        }
+        self.accepts[state as usize] = Some(accept);
+        state
+    }
+
+    fn add_tokens(&mut self, start: State) {
+        let annotation = start != NORMAL;
+        let alphabetic = |c: char| c.is_ascii_alphabetic();
+        let alphanumeric = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let not_newline = |c: char| c != '\n';
+
+        let blank = self.accepting(Accept::Skip);
+        for c in [' ', '\t', '\r', '\u{0B}', '\u{0C}'] {
+            self.on(start, c, blank);
+        }
+        let newline = self.accepting(Accept::Newline);
+        self.on(start, '\n', newline);
+
+        // Comments. Outside of annotations, "//@" and "/*@" open one instead
+        let line_comment = self.spell(start, "//", Accept::Skip);
+        if !annotation {
+            let open = self.accepting(Accept::LineOpen);
+            self.on(line_comment, '@', open);
+        }
+        let comment_text = self.accepting(Accept::Skip);
+        self.edge(line_comment, not_newline, comment_text);
+        self.edge(comment_text, not_newline, comment_text);
+        let block_comment = self.spell(start, "/*", Accept::BlockComment);
+        if !annotation {
+            let open = self.accepting(Accept::BlockOpen);
+            self.on(block_comment, '@', open);
+        }
+
+        for (text, token) in OPERATORS {
+            self.spell(start, text, Accept::Token(*token));
+        }
+
+        // Inside an annotation '@' is ignored, unless it starts the "@*/" closing a block one
+        if annotation {
+            let at = self.accepting(Accept::Skip);
+            self.on(start, '@', at);
+            if start == BLOCK_ANNOTATION {
+                self.spell(at, "*/", Accept::Close);
+            }
+        }
+
+        // esc_transition_diagram.png, for escape sequences outside of literals. A backslash that
+        // doesn't start one is an error, and inside annotations one followed by a word is
+        // "\result" or "\length"
+        let backslash = self.accepting(Accept::Error(LexErrorKind::UnrecognizedCharacter));
+        self.on(start, '\\', backslash);
+        if annotation {
+            let word = self.accepting(Accept::BackslashWord);
+            self.edge(backslash, alphabetic, word);
+            self.edge(word, alphanumeric, word);
+        }
+        let esc = self.accepting(Accept::Esc);
+        self.edge(backslash, |c| Esc::from_char(c).is_some(), esc);
+
+        // id_transition_diagram.png
+        let id = self.accepting(Accept::Word);
+        self.edge(start, |c| alphabetic(c) || c == '_', id);
+        self.edge(id, alphanumeric, id);
+
+        // num_, decnum_ and hexnum_transition_diagram.png: "0", [1-9][0-9]* or 0[xX][0-9a-fA-F]+.
+        // A number runs on through any letters, digits or underscores right after it, which make
+        // it malformed as a whole
+        let zero = self.accepting(Accept::DecNum);
+        self.on(start, '0', zero);
+        let decimal = self.accepting(Accept::DecNum);
+        self.edge(start, |c| c.is_ascii_digit(), decimal);
+        self.edge(decimal, |c| c.is_ascii_digit(), decimal);
+        let prefix = self.accepting(Accept::Error(LexErrorKind::MalformedNumber));
+        self.edge(zero, |c| c == 'x' || c == 'X', prefix);
+        let hex = self.accepting(Accept::HexNum);
+        self.edge(prefix, |c| c.is_ascii_hexdigit(), hex);
+        self.edge(hex, |c| c.is_ascii_hexdigit(), hex);
+        let leading_zero = self.accepting(Accept::Error(LexErrorKind::LeadingZero));
+        self.edge(zero, |c| c.is_ascii_digit(), leading_zero);
+        self.edge(leading_zero, |c| c.is_ascii_digit(), leading_zero);
+        let malformed = self.accepting(Accept::Error(LexErrorKind::MalformedNumber));
+        for state in [zero, decimal, prefix, hex, leading_zero, malformed] {
+            self.edge(state, alphanumeric, malformed);
+        }
+
+        // strlit_, schar_, nchar_ and esc_transition_diagram.png. The automaton only finds where a
+        // literal ends; which characters and escape sequences it may hold is checked while decoding
+        // it, so that each bad one gets an error of its own. Every state short of the closing quote
+        // accepts as unterminated, so a literal missing one runs up to the end of the line
+        let unterminated = Accept::Error(LexErrorKind::UnterminatedString);
+        let string = self.accepting(unterminated);
+        self.on(start, '"', string);
+        let string_escape = self.accepting(unterminated);
+        self.on(string, '\\', string_escape);
+        let string_closed = self.accepting(Accept::StrLit);
+        self.on(string, '"', string_closed);
+        self.edge(string, not_newline, string);
+        self.edge(string_escape, not_newline, string);
+
+        // chrlit_, cchar_ and lchar_transition_diagram.png, checked the same way
+        let unterminated = Accept::Error(LexErrorKind::UnterminatedCharLit);
+        let quote = self.accepting(unterminated);
+        self.on(start, '\'', quote);
+        let empty = self.accepting(Accept::Error(LexErrorKind::EmptyCharLit));
+        self.on(quote, '\'', empty);
+        let char_escape = self.accepting(unterminated);
+        self.on(quote, '\\', char_escape);
+        let character = self.accepting(unterminated);
+        self.edge(quote, not_newline, character);
+        self.edge(char_escape, not_newline, character);
+        let char_closed = self.accepting(Accept::ChrLit);
+        self.on(character, '\'', char_closed);
+
+        // "#use", then liblit_transition_diagram.png or a string literal naming a file. Anything
+        // short of a whole directive is an invalid one
+        let invalid = Accept::Error(LexErrorKind::InvalidDirective);
+        let hash = self.accepting(invalid);
+        self.on(start, '#', hash);
+        let u = self.accepting(invalid);
+        self.on(hash, 'u', u);
+        let us = self.accepting(invalid);
+        self.on(u, 's', us);
+        let use_ = self.accepting(invalid);
+        self.on(us, 'e', use_);
+        let other = self.accepting(invalid);
+        for state in [hash, u, us, use_, other] {
+            self.edge(state, alphabetic, other);
+        }
+        let is_blank = |c: char| c == ' ' || c == '\t';
+        let use_blank = self.accepting(invalid);
+        self.edge(use_, is_blank, use_blank);
+        self.edge(use_blank, is_blank, use_blank);
+        let other_blank = self.accepting(invalid);
+        for state in [hash, u, us, other, other_blank] {
+            self.edge(state, is_blank, other_blank);
+        }
+        for (open, close, accept) in [('<', '>', Accept::UseLib), ('"', '"', Accept::UseFile)] {
+            let opened = self.accepting(invalid);
+            self.on(use_, open, opened);
+            self.on(use_blank, open, opened);
+            let empty = self.accepting(invalid);
+            self.on(opened, close, empty);
+            let name = self.accepting(invalid);
+            self.edge(opened, |c| c != close && c != '\n', name);
+            self.edge(name, |c| c != close && c != '\n', name);
+            let closed = self.accepting(accept);
+            self.on(name, close, closed);
+        }
+
+        // Anything else can't start a token
+        let unrecognized = self.accepting(Accept::Error(LexErrorKind::UnrecognizedCharacter));
+        self.edge(start, |_| true, unrecognized);
+    }
+
+    // Maximal munch: the end of the longest match starting at `offset`, and what it matched
+    fn longest_match(&self, start: State, source: &str, offset: usize) -> Option<(usize, Accept)> {
+        let mut state = start;
+        let mut longest = None;
+        for (index, c) in source[offset..].char_indices() {
+            state = self.transitions[state as usize][class(c)];
+            if state == DEAD {
+                break;
+            }
+            if let Some(accept) = self.accepts[state as usize] {
+                longest = Some((offset + index + c.len_utf8(), accept));
+            }
+        }
+        longest
+    }
+}
+
+// Decodes the body of a string or character literal spanning start..end, quotes included. Errors
+// come back for each character or escape sequence the literal can't hold
+fn decode(
+    source: &str,
+    start: usize,
+    end: usize,
+    in_char: bool,
+) -> Result<String, Vec<(LexErrorKind, usize, usize)>> {
+    // Up to the closing quote, so that decoding stops there
+    let body = &source[..end - 1];
+    let mut chars = SourceChars::new(body, start + 1);
+    let mut value = String::new();
+    let mut invalid = Vec::new();
+    while chars.peek().is_some() {
+        match literal_char(&mut chars, body, in_char) {
+            Ok(c) => value.push(c),
+            Err(error) => invalid.push(error),
+        }
+    }
+    if invalid.is_empty() {
+        Ok(value)
+    } else {
+        Err(invalid)
+    }
+}
+
+// Scans the same way `scanner::scan` does, producing the same tokens and errors, but driven by the
+// tables above
+pub fn scan(source: String) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+    static C0: OnceLock<Dfa> = OnceLock::new();
+    let dfa = C0.get_or_init(Dfa::c0);
+    run(source, |annotation, source, offset| {
+        let start = match annotation {
+            None => NORMAL,
+            Some(Annotation::Line) => LINE_ANNOTATION,
+            Some(Annotation::Block) => BLOCK_ANNOTATION,
+        };
+        dfa.longest_match(start, source, offset)
+    })
+}
+
+// Turns the matches found by `longest_match`, given the current mode, into tokens and errors. The
+// scanner generated from rules (see `generated`) runs through here as well
+pub(super) fn run(
+    source: String,
+    longest_match: impl Fn(Option<Annotation>, &str, usize) -> Option<(usize, Accept)>,
+) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+    let mut tokens = Vec::<SpannedToken>::new();
+    let mut errors = Vec::<LexError>::new();
+    let mut tracker = SpanTracker::new();
+    let mut annotation: Option<Annotation> = None;
+    let mut annotation_open = 0;
+    let mut offset = 0;
+
+    while offset < source.len() {
+        // Every character starts some match, if only that of an unrecognized character
+        let (end, accept) = longest_match(annotation, &source, offset).unwrap();
+        let index = offset;
+        let lexeme = &source[index..end];
+        offset = end;
+
+        let token = match accept {
+            Accept::Token(token) => token(),
+            Accept::Word => keyword(lexeme.to_string(), annotation.is_some()),
+            Accept::BackslashWord => match &lexeme[1..] {
+                "result" => Token::Keyword(Keyword::Result),
+                "length" => Token::Keyword(Keyword::Length),
+                _ => {
+                    let kind = LexErrorKind::UnrecognizedCharacter;
+                    errors.push(lex_error(&source, &mut tracker, kind, index, end));
+                    continue;
+                }
+            },
+            Accept::Esc => match lexeme[1..].chars().next().and_then(Esc::from_char) {
+                Some(esc) => Token::Esc(esc),
+                None => continue,
+            },
+            Accept::DecNum | Accept::HexNum => {
+                let number = match accept {
+                    Accept::HexNum => int_literal(&lexeme[2..], true),
+                    _ => int_literal(lexeme, false),
+                };
+                match number {
+                    Ok(token) => token,
+                    Err(kind) => {
+                        errors.push(lex_error(&source, &mut tracker, kind, index, end));
+                        continue;
+                    }
+                }
+            }
+            Accept::StrLit | Accept::ChrLit => {
+                let in_char = matches!(accept, Accept::ChrLit);
+                match decode(&source, index, end, in_char) {
+                    Ok(value) if in_char => {
+                        let c = value.chars().next().unwrap();
+                        Token::ChrLit(ChrLit::CharacterLiteral(c))
+                    }
+                    Ok(value) => Token::StrLit(StrLit::StringLiteral(value)),
+                    Err(invalid) => {
+                        for (kind, start, end) in invalid {
+                            errors.push(lex_error(&source, &mut tracker, kind, start, end));
+                        }
+                        continue;
+                    }
+                }
+            }
+            Accept::UseLib => {
+                let name = &lexeme[lexeme.find('<').unwrap() + 1..lexeme.len() - 1];
+                Token::Directive(Directive::UseLib(LibLit::LibraryLiteral(name.to_string())))
+            }
+            Accept::UseFile => {
+                let file = &lexeme[lexeme.find('"').unwrap() + 1..lexeme.len() - 1];
+                Token::Directive(Directive::UseFile(StrLit::StringLiteral(file.to_string())))
+            }
+            Accept::Newline => {
+                if annotation == Some(Annotation::Line) {
+                    annotation = None;
+                    Token::Annot(Annot::Close)
+                } else {
+                    continue;
+                }
+            }
+            Accept::LineOpen => {
+                annotation = Some(Annotation::Line);
+                Token::Annot(Annot::LineOpen)
+            }
+            Accept::BlockOpen => {
+                annotation = Some(Annotation::Block);
+                annotation_open = tokens.len();
+                Token::Annot(Annot::BlockOpen)
+            }
+            Accept::Close => {
+                annotation = None;
+                Token::Annot(Annot::Close)
+            }
+            Accept::BlockComment => {
+                let mut char_indices = SourceChars::new(&source, end);
+                let closed = skip_block_comment(&mut char_indices);
+                offset = next_index(&mut char_indices, &source);
+                if !closed {
+                    let kind = LexErrorKind::UnterminatedComment;
+                    errors.push(lex_error(&source, &mut tracker, kind, index, source.len()));
+                }
+                continue;
+            }
+            Accept::Skip => continue,
+            Accept::Error(kind) => {
+                errors.push(lex_error(&source, &mut tracker, kind, index, end));
+                continue;
+            }
+        };
+        let span = tracker.span(&source, index, end);
+        tokens.push(SpannedToken { token, span });
+    }
+
+    finish(
+        &source,
+        &mut tracker,
+        annotation,
+        annotation_open,
+        &mut tokens,
+        &mut errors,
+    );
+    result(tokens, errors)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::xorshift::Xorshift;
+
+    pub const CORPUS: &[&str] = &[
+        "int main() { return 0; }",
+        "#use <conio>\n#use \"util/queue.c0\"\nint x = 1;",
+        "int f(int[] A, int n)\n  //@requires 0 <= n && n <= \\length(A);\n  //@ensures \\result >= 0;\n{\n  int sum = 0;\n  for (int i = 0; i < n; i++)\n    /*@ loop_invariant 0 <= i;\n      @ loop_invariant sum >= 0; @*/\n  {\n    sum += A[i] * 2 - -1;\n  }\n  //@assert sum >= 0;\n  return sum;\n }",
+        "a <<= b >>= c; d->e.f != g && h || !i ? ~j : k % l ^ m | n & o;",
+        "x++; y--; p += 1; q -= 2; r *= 3; s /= 4; t %= 5; u &= 6; v ^= 7; w |= 8;",
+        "struct s { int x; }; typedef struct s* s_t; s_t p = alloc(struct s);",
+        "0 42 2147483648 0xFFFFFFFF 0X2a 0x007",
+        "2147483649 0x100000000 007 00 2A 12ab 0x 0xfg 1_000",
+        "\"hello\" \"a\\\"b\" \"\\a\\b\\f\\n\\r\\t\\v\\\\\\'\\\"\" \"it's\"",
+        "'a' '\"' '\\n' '\\0' '\\'' '\\\\'",
+        "\"a\\0b\\qc\" \"tab\there\" \"héllo\" \"oops\\\"",
+        "'' '\\x' '\\n 'é' 'ab' '",
+        "\"unterminated\n x = 1;",
+        "/* a /* nested */ comment */ x // line\n y",
+        "/* unterminated /* nested */",
+        "x /*@ requires y; @*/ z /*@ ensures\n @ w; @*/",
+        "/*@ requires x; //@ not a second annotation\n @ @ ensures y; @*/",
+        "//@ requires /* a comment */ x; @*/ y",
+        "/*@ requires x;",
+        "//@ ensures \\result == \\length(A); \\bogus",
+        "\\a \\n \\\\ \\' \\\" \\q x",
+        "#foo <bar>\n #use\n #use <>\n #use <unterminated\n #use\"a.c0\" #",
+        "requires ensures loop_invariant assert error NULL alloc_array \\result",
+        "$ ` \t \r @ x_1 _x héllo ²",
+        "",
+        "\n",
+    ];
+
+    type Scan = fn(String) -> Result<Vec<SpannedToken>, Vec<LexError>>;
+
+    // Runs `scan` and the hand-written scanner over the same source and checks they agree, token
+    // for token and error for error
+    pub fn assert_same(source: &str, scan: Scan) {
+        assert_eq!(
+            super::super::scan(source.to_string()),
+            scan(source.to_string()),
+            "scanners disagree on {:?}",
+            source
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let dfa = Dfa::c0();
+        // every character starts a match in every mode
+        for start in [NORMAL, LINE_ANNOTATION, BLOCK_ANNOTATION] {
+            for class in 0..CLASSES {
+                let c = representative(class);
+                assert!(
+                    dfa.longest_match(start, &c.to_string(), 0).is_some(),
+                    "{:?} gets stuck in mode {}",
+                    c,
+                    start
+                );
+            }
+        }
+        // maximal munch
+        let munch = |source: &str| dfa.longest_match(NORMAL, source, 0).unwrap().0;
+        assert_eq!(munch("<<=1"), 3);
+        assert_eq!(munch("<<1"), 2);
+        assert_eq!(munch("-->"), 2);
+        assert_eq!(munch("0x1F;"), 4);
+        assert_eq!(munch("12ab;"), 4);
+        assert_eq!(munch("\"a\\\"b\" c"), 6);
+        assert_eq!(munch("#use <conio> x"), 12);
+        assert_eq!(munch("//@requires"), 3);
+    }
+
+    #[test]
+    fn differential() {
+        for source in CORPUS {
+            assert_same(source, scan);
+        }
+    }
+
+    #[test]
+    fn differential_random() {
+        fuzz(|source| assert_same(source, scan));
+    }
+
+    // Pieces of C0, right and wrong, for building random sources out of
+    pub const FRAGMENTS: &[&str] = &[
+        "x",
+        "int",
+        "requires",
+        "\\result",
+        "\\length",
+        "\\foo",
+        "0",
+        "0x1F",
+        "0x",
+        "007",
+        "12",
+        "2147483648",
+        "99999999999",
+        "12ab",
+        "\"s\"",
+        "\"a\\tb\"",
+        "\"\\q\"",
+        "\"open",
+        "\"",
+        "'c'",
+        "'\\n'",
+        "'\\0'",
+        "''",
+        "'",
+        "+",
+        "++",
+        "+=",
+        "-",
+        "--",
+        "->",
+        "*",
+        "/",
+        "/=",
+        "%",
+        "<",
+        "<<",
+        "<<=",
+        ">>=",
+        "==",
+        "=",
+        "!",
+        "!=",
+        "&&",
+        "&",
+        "||",
+        "|",
+        "^",
+        "?",
+        ":",
+        "~",
+        ".",
+        "(",
+        ")",
+        "[",
+        "]",
+        "{",
+        "}",
+        ",",
+        ";",
+        "//@",
+        "/*@",
+        "@*/",
+        "@",
+        "@*",
+        "//",
+        "/*",
+        "*/",
+        "// c",
+        "/* c */",
+        "#use <lib>",
+        "#use \"f.c0\"",
+        "#bad",
+        "#",
+        "\\",
+        "\\n",
+        "$",
+        "\t",
+        "\r\n",
+        "\u{0B}\u{0C}",
+        "é",
+        "_",
+    ];
+
+    // Random token soup, glued together by blanks and newlines
+    pub fn fuzz(mut check: impl FnMut(&str)) {
+        let mut rng = Xorshift::new(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            let mut source = String::new();
+            for _ in 0..rng.below(12) {
+                source.push_str(FRAGMENTS[rng.below(FRAGMENTS.len())]);
+                source.push_str(match rng.below(4) {
+                    0 => "",
+                    1 | 2 => " ",
+                    _ => "\n",
+                });
+            }
+            check(&source);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::scanner::span::Span;
+use std::fmt;
+
+// Lexical errors are collected rather than raised, so that a single run of the scanner can report
+// every problem in a file
+#[derive(PartialEq, Debug)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+    // The offending source text, exactly as written
+    pub text: String,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LexErrorKind {
+    UnterminatedString,     // '"' with no closing '"' on the same line
+    UnterminatedCharLit,    // '\'' with no closing '\''
+    EmptyCharLit,           // "''"
+    InvalidLiteralChar,     // literals may only hold printable ASCII characters
+    InvalidEscape,          // a backslash followed by anything but a C0 escape character
+    InvalidNumber,          // a number out of range: decimals go up to 2^31, hex up to 0xFFFFFFFF
+    MalformedNumber,        // e.g. "12ab" or "0x" with no digits
+    LeadingZero,            // a decimal literal other than "0" starting with '0'
+    UnrecognizedCharacter,  // a character that can't start any token
+    UnterminatedComment,    // "/*" with no matching "*/"
+    UnterminatedAnnotation, // "/*@" with no matching "@*/"
+    InvalidDirective,       // anything starting with '#' other than "#use <lib>" or "#use \"file\""
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            LexErrorKind::UnterminatedString => "unterminated string literal",
+            LexErrorKind::UnterminatedCharLit => "unterminated character literal",
+            LexErrorKind::EmptyCharLit => "empty character literal",
+            LexErrorKind::InvalidLiteralChar => "non-printable or non-ASCII character in literal",
+            LexErrorKind::InvalidEscape => "invalid escape sequence",
+            LexErrorKind::InvalidNumber => "integer literal out of range",
+            LexErrorKind::MalformedNumber => "malformed integer literal",
+            LexErrorKind::LeadingZero => "decimal literal with a leading zero",
+            LexErrorKind::UnrecognizedCharacter => "unrecognizable character",
+            LexErrorKind::UnterminatedComment => "unterminated block comment",
+            LexErrorKind::UnterminatedAnnotation => "unterminated annotation",
+            LexErrorKind::InvalidDirective => "invalid directive",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: error: {} `{}`",
+            self.span.line,
+            self.span.column,
+            self.kind,
+            self.text.escape_debug()
+        )
+    }
+}
+
+impl std::error::Error for LexError {}
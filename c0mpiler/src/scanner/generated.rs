@@ -0,0 +1,133 @@
+use crate::lexgen::Lexer;
+use crate::scanner::dfa::{run, Accept, OPERATORS};
+use crate::scanner::error::{LexError, LexErrorKind};
+use crate::scanner::token::SpannedToken;
+use crate::scanner::Annotation;
+use std::sync::OnceLock;
+
+// The scanner generated by lexgen from the rules in c0.lex. It shares everything but its tables
+// with the one in `dfa`, and is checked against the hand-written one the same way
+pub const RULES: &str = include_str!("c0.lex");
+
+const ERRORS: [LexErrorKind; 12] = [
+    LexErrorKind::UnterminatedString,
+    LexErrorKind::UnterminatedCharLit,
+    LexErrorKind::EmptyCharLit,
+    LexErrorKind::InvalidLiteralChar,
+    LexErrorKind::InvalidEscape,
+    LexErrorKind::InvalidNumber,
+    LexErrorKind::MalformedNumber,
+    LexErrorKind::LeadingZero,
+    LexErrorKind::UnrecognizedCharacter,
+    LexErrorKind::UnterminatedComment,
+    LexErrorKind::UnterminatedAnnotation,
+    LexErrorKind::InvalidDirective,
+];
+
+struct C0 {
+    lexer: Lexer,
+    // What to do for each rule
+    accepts: Vec<Accept>,
+}
+
+// The action named in a rule
+fn accept(action: &str) -> Option<Accept> {
+    let accept = match action {
+        "Word" => Accept::Word,
+        "BackslashWord" => Accept::BackslashWord,
+        "Esc" => Accept::Esc,
+        "DecNum" => Accept::DecNum,
+        "HexNum" => Accept::HexNum,
+        "StrLit" => Accept::StrLit,
+        "ChrLit" => Accept::ChrLit,
+        "UseLib" => Accept::UseLib,
+        "UseFile" => Accept::UseFile,
+        "Newline" => Accept::Newline,
+        "LineOpen" => Accept::LineOpen,
+        "BlockOpen" => Accept::BlockOpen,
+        "Close" => Accept::Close,
+        "BlockComment" => Accept::BlockComment,
+        "Skip" => Accept::Skip,
+        _ => {
+            if let Some(kind) = action
+                .strip_prefix("Error(")
+                .and_then(|rest| rest.strip_suffix(')'))
+            {
+                let kind = ERRORS.iter().find(|error| format!("{:?}", error) == kind)?;
+                return Some(Accept::Error(*kind));
+            }
+            let (_, token) = OPERATORS
+                .iter()
+                .find(|(_, token)| format!("{:?}", token()) == action)?;
+            Accept::Token(*token)
+        }
+    };
+    Some(accept)
+}
+
+fn c0() -> &'static C0 {
+    static C0: OnceLock<C0> = OnceLock::new();
+    C0.get_or_init(|| {
+        let lexer = Lexer::generate(RULES).unwrap_or_else(|error| panic!("c0.lex: {}", error));
+        let accepts = lexer
+            .rules
+            .rules
+            .iter()
+            .map(|rule| {
+                accept(&rule.action).unwrap_or_else(|| {
+                    panic!("c0.lex: line {}: unknown action {}", rule.line, rule.action)
+                })
+            })
+            .collect();
+        C0 { lexer, accepts }
+    })
+}
+
+pub fn lexer() -> &'static Lexer {
+    &c0().lexer
+}
+
+pub fn scan(source: String) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+    let c0 = c0();
+    run(source, |annotation, source, offset| {
+        // The modes, in the order c0.lex declares them
+        let mode = match annotation {
+            None => 0,
+            Some(Annotation::Line) => 1,
+            Some(Annotation::Block) => 2,
+        };
+        c0.lexer
+            .longest_match(mode, source, offset)
+            .map(|(end, rule)| (end, c0.accepts[rule]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::dfa::tests::{assert_same, fuzz, CORPUS};
+
+    #[test]
+    fn rules() {
+        let lexer = lexer();
+        assert_eq!(lexer.rules.modes, vec!["normal", "line", "block"]);
+        // every action names something
+        assert_eq!(c0().accepts.len(), lexer.rules.rules.len());
+        // minimized, each mode's table stays under a hundred states
+        for dfa in &lexer.dfas {
+            assert!(dfa.state_count() < 100, "{}", dfa.state_count());
+        }
+    }
+
+    #[test]
+    fn differential() {
+        for source in CORPUS {
+            assert_same(source, scan);
+        }
+    }
+
+    #[test]
+    fn differential_random() {
+        fuzz(|source| assert_same(source, scan));
+    }
+}
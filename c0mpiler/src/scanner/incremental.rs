@@ -0,0 +1,271 @@
+use crate::scanner::error::LexError;
+use crate::scanner::span::{Span, SpanTracker};
+use crate::scanner::token::{Annot, SpannedToken, Token};
+use crate::scanner::{result, scan_from, Annotation, Resume, Scanned};
+use std::ops::Range;
+
+// Incremental rescanning, for editors that rescan the buffer after every change
+//
+// The scanner only carries a little state from one token to the next (the annotation it's in), so
+// an edit can't change anything much before it. We back up to a token safely
+// before the edit, scan from there, and stop as soon as the scan lines up with a token from before
+// the edit again, in the same state. From there on the old tokens are still right, only moved
+
+// Replaces the bytes in `range` of the old source with `replacement`. The range has to fall on
+// character boundaries
+#[derive(PartialEq, Debug, Clone)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl Edit {
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = source.to_string();
+        edited.replace_range(self.range.clone(), &self.replacement);
+        edited
+    }
+}
+
+// Rescans `source`, the result of making `edit` to a source that scanned to `tokens`. Gives the
+// same result as `scan(source)`
+pub fn rescan(
+    tokens: Vec<SpannedToken>,
+    edit: &Edit,
+    source: &str,
+) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+    rescan_region(tokens, edit, source).0
+}
+
+// As `rescan`, along with the range of the new source that was scanned again
+fn rescan_region(
+    mut tokens: Vec<SpannedToken>,
+    edit: &Edit,
+    source: &str,
+) -> (Result<Vec<SpannedToken>, Vec<LexError>>, Range<usize>) {
+    // Scanning a token can look up to two characters past its start (deciding whether '@' is
+    // followed by "*/"), so back up to the last token that ends before the edit begins
+    let first_touched = tokens.partition_point(|spanned| spanned.span.end() < edit.range.start);
+    let restart = first_touched.saturating_sub(1);
+    let resume = match tokens.get(restart) {
+        Some(spanned) if first_touched > 0 => {
+            let (annotation, annotation_open) = annotation_at(&tokens[..restart]);
+            Resume {
+                offset: spanned.span.offset,
+                annotation,
+                annotation_open,
+                tracker: SpanTracker::at(spanned.span),
+            }
+        }
+        _ => Resume::start(),
+    };
+    let start = resume.offset;
+
+    // Offsets past the edit move by the difference in length
+    let edit_end = edit.range.start + edit.replacement.len();
+    let old_offset = |offset: usize| offset + edit.range.len() - edit.replacement.len();
+
+    // The old tokens from where scanning restarts, and the annotation the old scan was in at each
+    // of them, worked out as far as we've needed so far
+    let old = tokens.split_off(restart);
+    let mut next = 0;
+    let mut old_annotation = resume.annotation;
+    let mut resync = None;
+    let scanned = scan_from(source, resume, tokens, |offset, annotation, _| {
+        if offset < edit_end {
+            return false;
+        }
+        let old_start = old_offset(offset);
+        let Ok(found) = old.binary_search_by_key(&old_start, |spanned| spanned.span.offset) else {
+            return false;
+        };
+        // Only Annot tokens change the annotation
+        while next < found {
+            old_annotation = match old[next].token {
+                Token::Annot(Annot::LineOpen) => Some(Annotation::Line),
+                Token::Annot(Annot::BlockOpen) => Some(Annotation::Block),
+                Token::Annot(Annot::Close) => None,
+                _ => old_annotation,
+            };
+            next += 1;
+        }
+        let same = annotation == old_annotation;
+        if same {
+            resync = Some(found);
+        }
+        same
+    });
+
+    match scanned {
+        Scanned::Finished { tokens, errors } => (result(tokens, errors), start..source.len()),
+        Scanned::Stopped {
+            resume,
+            mut tokens,
+            errors,
+        } => {
+            let Resume {
+                offset,
+                mut tracker,
+                ..
+            } = resume;
+            if !errors.is_empty() {
+                return (Err(errors), start..offset);
+            }
+            let found = resync.unwrap();
+            let now = tracker.span(source, offset, offset);
+            let then = old[found].span;
+            tokens.extend(old.into_iter().skip(found).map(|spanned| SpannedToken {
+                span: moved(spanned.span, then, now),
+                ..spanned
+            }));
+            (Ok(tokens), start..offset)
+        }
+    }
+}
+
+// The annotation the scanner is in after `tokens`, and which of them opened it
+fn annotation_at(tokens: &[SpannedToken]) -> (Option<Annotation>, usize) {
+    for (index, spanned) in tokens.iter().enumerate().rev() {
+        match spanned.token {
+            Token::Annot(Annot::LineOpen) => return (Some(Annotation::Line), index),
+            Token::Annot(Annot::BlockOpen) => return (Some(Annotation::Block), index),
+            Token::Annot(Annot::Close) => return (None, 0),
+            _ => {}
+        }
+    }
+    (None, 0)
+}
+
+// `span`, after the text starting at `then` has moved to `now`. Only the line of `then` changes
+// columns
+fn moved(span: Span, then: Span, now: Span) -> Span {
+    let column = if span.line == then.line {
+        span.column + now.column - then.column
+    } else {
+        span.column
+    };
+    Span {
+        offset: span.offset + now.offset - then.offset,
+        line: span.line + now.line - then.line,
+        column,
+        length: span.length,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::dfa::tests::{fuzz, FRAGMENTS};
+    use crate::scanner::scan;
+    use crate::xorshift::Xorshift;
+
+    // Checks rescanning after `edit` against scanning the edited source from scratch
+    fn check(source: &str, edit: &Edit) -> Range<usize> {
+        let tokens = scan(source.to_string()).expect(source);
+        let edited = edit.apply(source);
+        assert_eq!(
+            rescan(tokens.clone(), edit, &edited),
+            scan(edited.clone()),
+            "{:?} edited by {:?} into {:?}",
+            source,
+            edit,
+            edited
+        );
+        rescan_region(tokens, edit, &edited).1
+    }
+
+    fn edit(range: Range<usize>, replacement: &str) -> Edit {
+        Edit {
+            range,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    #[test]
+    fn edits() {
+        let source = "int main() {\n  int x = 1;\n  return x + 2;\n}";
+        // rename a variable
+        check(source, &edit(19..20, "counter"));
+        // join two tokens into one, and split one in two
+        check(source, &edit(35..37, ""));
+        check(source, &edit(6..6, " "));
+        // add and remove lines
+        check(source, &edit(12..12, "\n  x++;"));
+        check(source, &edit(12..25, ""));
+        // at either end
+        check(source, &edit(0..0, "#use <conio>\n"));
+        check(source, &edit(source.len()..source.len(), "\n"));
+        check(source, &edit(0..source.len(), ""));
+        // introduce an error
+        check(source, &edit(19..20, "$"));
+        check(source, &edit(27..27, "\"oops"));
+    }
+
+    #[test]
+    fn annotations() {
+        let source =
+            "int f(int x)\n //@requires x > 0;\n /*@ ensures \\result > 0;\n @*/ { return x; }";
+        let at = |text: &str| source.find(text).unwrap();
+        // open and close annotations
+        check(source, &edit(at("//@")..at("//@") + 3, "// "));
+        check(source, &edit(at("/*@")..at("/*@") + 3, "/* "));
+        check(source, &edit(at("@*/")..at("@*/") + 3, "*/"));
+        check(source, &edit(at("{")..at("{"), "/*@"));
+        check(source, &edit(at(">")..at(">"), "//@"));
+        // whether '@' closes a block annotation depends on what follows it
+        check("/*@ ensures x; @*x @*/", &edit(17..18, "/"));
+        check("/*@ ensures x; @*/ y", &edit(17..18, "x"));
+        // a line annotation still open at the end of the file
+        check("int x; //@assert x", &edit(18..18, " + 1"));
+    }
+
+    #[test]
+    fn comments_and_directives() {
+        check("x /* a */ y", &edit(5..5, "/*"));
+        check("x /* a */ y", &edit(3..4, ""));
+        check("x // a\n y", &edit(6..7, ""));
+        check("#use <a>\n x", &edit(8..8, " "));
+        check("#use <a>\n x", &edit(7..8, ""));
+        check("#use <a> x", &edit(8..9, "\n"));
+    }
+
+    #[test]
+    fn only_relexes_the_edit() {
+        let line = "int x = 1 + 2;\n ";
+        let source = line.repeat(200);
+        let middle = line.len() * 100;
+        let region = check(&source, &edit(middle + 4..middle + 5, "yy"));
+        assert!(region.start >= middle - line.len(), "{:?}", region);
+        assert!(region.end <= middle + line.len(), "{:?}", region);
+        assert!(region.len() < 2 * line.len());
+    }
+
+    // Random edits to the random sources of the differential tests that scan. Edits replace a
+    // random range with a random fragment, or delete it
+    #[test]
+    fn random_edits() {
+        let mut rng = Xorshift::new(0x853c_49e6_748f_ea9b);
+        fuzz(|source| {
+            if scan(source.to_string()).is_err() {
+                return;
+            }
+            let boundaries: Vec<usize> = source
+                .char_indices()
+                .map(|(index, _)| index)
+                .chain([source.len()])
+                .collect();
+            for _ in 0..5 {
+                let mut range = [
+                    boundaries[rng.below(boundaries.len())],
+                    boundaries[rng.below(boundaries.len())],
+                ];
+                range.sort_unstable();
+                let replacement = match rng.below(3) {
+                    0 => "",
+                    _ => FRAGMENTS[rng.below(FRAGMENTS.len())],
+                };
+                check(source, &edit(range[0]..range[1], replacement));
+            }
+        });
+    }
+}
@@ -1,48 +1,330 @@
 #![allow(non_snake_case)]
-#[allow(unused_imports)]
-use std::fs;
-use std::io::*;
+use std::iter::Peekable;
+use std::str::CharIndices;
+pub mod dfa;
+pub mod error;
+pub mod generated;
+// Rescanning after an edit is for an editor front end, which the compiler doesn't have yet
+#[cfg(test)]
+pub mod incremental;
+pub mod span;
+pub mod stream;
 pub mod token;
+pub mod trivia;
+use crate::scanner::error::{LexError, LexErrorKind};
+use crate::scanner::span::{Span, SpanTracker};
 use crate::scanner::token::{
-    AsnOp, BinOp, CChar, ChrLit, DecNum, Esc, HexNum, Id, Keyword, LibLit, Num, PostOp, SChar, Sep,
-    StrLit, Token, UnOp,
+    Annot, AsnOp, BinOp, ChrLit, DecNum, Directive, Esc, HexNum, Id, Keyword, LibLit, Num, PostOp,
+    Sep, SpannedToken, StrLit, Token, UnOp,
 };
 
-pub fn run_file(path: String) -> Result<()> {
-    if let Ok(file) = fs::read_to_string(path) {
-        scan(file);
+// C0 contracts live in annotation comments, "//@ ..." and "/*@ ... @*/", whose contents are
+// scanned as tokens rather than skipped
+#[derive(PartialEq, Clone, Copy)]
+enum Annotation {
+    Line,
+    Block,
+}
+
+// CharIndices over the source from some offset on, still handing out indices into the whole of it,
+// so that scanning can start partway through
+#[derive(Clone)]
+struct SourceChars<'a> {
+    chars: CharIndices<'a>,
+    base: usize,
+}
+
+impl SourceChars<'_> {
+    fn new(source: &str, offset: usize) -> Peekable<SourceChars<'_>> {
+        SourceChars {
+            chars: source[offset..].char_indices(),
+            base: offset,
+        }
+        .peekable()
+    }
+}
+
+impl Iterator for SourceChars<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<(usize, char)> {
+        self.chars.next().map(|(index, c)| (self.base + index, c))
+    }
+}
+
+// Byte index of the next unconsumed character, i.e. one past the end of whatever was just scanned
+fn next_index(char_indices: &mut Peekable<SourceChars>, source: &str) -> usize {
+    match char_indices.peek() {
+        Some((next_index, _)) => *next_index,
+        None => source.len(),
+    }
+}
+
+// Consumes the rest of a block comment whose opening "/*" has already been consumed. C0 allows
+// block comments to nest, so we keep track of how many are open and only stop once the outermost
+// one is closed. Returns false if we run out of input first
+fn skip_block_comment(char_indices: &mut Peekable<SourceChars>) -> bool {
+    let mut depth = 1;
+    while let Some((_, character)) = char_indices.next() {
+        match (character, char_indices.peek()) {
+            ('/', Some((_, '*'))) => {
+                char_indices.next();
+                depth += 1;
+            }
+            ('*', Some((_, '/'))) => {
+                char_indices.next();
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+// Consumes one character of a string or character literal, decoding it if it starts an escape
+// sequence. Only printable ASCII characters may appear in a literal, and "\\0" only in a character
+// literal. Errors come with the byte range of the offending character or escape sequence
+fn literal_char(
+    char_indices: &mut Peekable<SourceChars>,
+    source: &str,
+    in_char: bool,
+) -> Result<char, (LexErrorKind, usize, usize)> {
+    let (start, c) = char_indices.next().unwrap();
+    if c == '\\' {
+        let decoded = match char_indices.peek() {
+            // a backslash at the end of a line escapes nothing
+            Some((_, '\n')) | None => None,
+            Some((_, escaped)) => {
+                let escaped = *escaped;
+                char_indices.next();
+                match escaped {
+                    '0' if in_char => Some('\0'),
+                    _ => Esc::from_char(escaped).map(|esc| esc.value()),
+                }
+            }
+        };
+        let end = next_index(char_indices, source);
+        decoded.ok_or((LexErrorKind::InvalidEscape, start, end))
+    } else if (' '..='~').contains(&c) {
+        Ok(c)
     } else {
-        let file_io_error = Error::from(ErrorKind::NotFound);
-        return Err(file_io_error);
+        let end = next_index(char_indices, source);
+        Err((LexErrorKind::InvalidLiteralChar, start, end))
+    }
+}
+
+// The keyword a word spells, or an identifier. Contract keywords are only reserved inside
+// annotations
+fn keyword(word: String, in_annotation: bool) -> Token {
+    match word.as_str() {
+        "int" => Token::Keyword(Keyword::Int),
+        "bool" => Token::Keyword(Keyword::Bool),
+        "string" => Token::Keyword(Keyword::String),
+        "char" => Token::Keyword(Keyword::Char),
+        "void" => Token::Keyword(Keyword::Void),
+        "struct" => Token::Keyword(Keyword::Struct),
+        "typedef" => Token::Keyword(Keyword::Typedef),
+        "if" => Token::Keyword(Keyword::If),
+        "else" => Token::Keyword(Keyword::Else),
+        "while" => Token::Keyword(Keyword::While),
+        "for" => Token::Keyword(Keyword::For),
+        "continue" => Token::Keyword(Keyword::Continue),
+        "break" => Token::Keyword(Keyword::Break),
+        "return" => Token::Keyword(Keyword::Return),
+        "assert" if in_annotation => Token::Keyword(Keyword::AnnoAssert),
+        "assert" => Token::Keyword(Keyword::Assert),
+        "error" => Token::Keyword(Keyword::Error),
+        "true" => Token::Keyword(Keyword::True),
+        "false" => Token::Keyword(Keyword::False),
+        "NULL" => Token::Keyword(Keyword::Null),
+        "alloc" => Token::Keyword(Keyword::Alloc),
+        "alloc_array" => Token::Keyword(Keyword::AllocArray),
+        // Contract keywords, only reserved inside annotations
+        "requires" if in_annotation => Token::Keyword(Keyword::Requires),
+        "ensures" if in_annotation => Token::Keyword(Keyword::Ensures),
+        "loop_invariant" if in_annotation => Token::Keyword(Keyword::LoopInvariant),
+        _ => Token::Id(Id::Id(word)),
     }
-    Ok(())
 }
 
-pub fn run_prompt() -> Result<()> {
-    println!("Please enter the file to be compiled: ");
+// Range-checks the digits of a well-formed integer literal. C0 ints are 32-bit two's complement:
+// a hex literal is the bit pattern, so anything up to 0xFFFFFFFF is fine, while a decimal literal
+// is a magnitude, where 2^31 is allowed here but the parser only accepts it after a unary minus
+fn int_literal(digits: &str, hex: bool) -> Result<Token, LexErrorKind> {
+    let (radix, max) = if hex { (16, u32::MAX) } else { (10, 1 << 31) };
+    match u32::from_str_radix(digits, radix) {
+        Ok(num) if num > max => Err(LexErrorKind::InvalidNumber),
+        Ok(num) if hex => Ok(Token::Num(Num::HexNum(HexNum::HexNum(num)))),
+        Ok(num) => Ok(Token::Num(Num::DecNum(DecNum::DecNum(num)))),
+        Err(_) => Err(LexErrorKind::InvalidNumber),
+    }
+}
 
-    match stdin().lines().next().unwrap() {
-        Ok(path) => {
-            run_file(path).unwrap();
-            Ok(())
+// Wraps up once the whole source has been scanned: a line annotation on the last line is closed
+// by the end of the file, while a block annotation left open is an error
+fn finish(
+    source: &str,
+    tracker: &mut SpanTracker,
+    annotation: Option<Annotation>,
+    annotation_open: usize,
+    tokens: &mut Vec<SpannedToken>,
+    errors: &mut Vec<LexError>,
+) {
+    match annotation {
+        Some(Annotation::Line) => {
+            let span = tracker.span(source, source.len(), source.len());
+            let token = Token::Annot(Annot::Close);
+            tokens.push(SpannedToken { token, span });
+        }
+        Some(Annotation::Block) => {
+            let open = tokens[annotation_open].span;
+            errors.push(LexError {
+                kind: LexErrorKind::UnterminatedAnnotation,
+                span: Span {
+                    length: source.len() - open.offset,
+                    ..open
+                },
+                text: source[open.offset..].to_string(),
+            });
+        }
+        None => {}
+    }
+}
+
+// The tokens, unless anything went wrong
+fn result(
+    tokens: Vec<SpannedToken>,
+    errors: Vec<LexError>,
+) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+fn lex_error(
+    source: &str,
+    tracker: &mut SpanTracker,
+    kind: LexErrorKind,
+    start: usize,
+    end: usize,
+) -> LexError {
+    LexError {
+        kind,
+        span: tracker.span(source, start, end),
+        text: source[start..end].to_string(),
+    }
+}
+
+// Which scanner to run. They produce the same tokens and errors: the hand-written one below, the
+// table-driven one in `dfa`, built from the automata in transition_diagrams/, and the one in
+// `generated`, whose tables lexgen builds from the rules in c0.lex
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum ScannerKind {
+    #[default]
+    HandWritten,
+    Table,
+    Generated,
+}
+
+impl ScannerKind {
+    pub fn scan(self, source: String) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+        match self {
+            ScannerKind::HandWritten => scan(source),
+            ScannerKind::Table => dfa::scan(source),
+            ScannerKind::Generated => generated::scan(source),
         }
-        Err(name_parsing_error) => Err(name_parsing_error),
     }
 }
 
 // TO DO:
-// Associate line numbers and column numbers with tokens
-// CharIndices stores index -- can this be used to derive line/col number, which can then be
-// stored in a Token Struct?
-// Do we tokenize or discard spaces/newlines?
-//  I would assume tokenizing them would work best with keeping track of line/col numbers
 // How do we categorize keywords?
 //
-pub fn scan(source: String) -> Vec<Token> {
-    let mut tokens = Vec::<Token>::new();
-    let mut char_indices = source.char_indices().peekable();
+// Every token is wrapped in a SpannedToken: the byte index CharIndices hands us for the first
+// character of a token is its offset, and the index of the next unconsumed character marks its end
+//
+// Whitespace and comments are discarded; spans carry the line numbers
+//
+// Lexical errors don't stop the scan; the offending text is skipped and recorded, and if anything
+// went wrong the caller gets every error instead of the tokens
+pub fn scan(source: String) -> Result<Vec<SpannedToken>, Vec<LexError>> {
+    match scan_from(&source, Resume::start(), Vec::new(), |_, _, _| false) {
+        Scanned::Finished { tokens, errors } => result(tokens, errors),
+        Scanned::Stopped { .. } => unreachable!(),
+    }
+}
+
+// Where the scanner is at the start of a token: the only things scanning the rest of the source
+// depends on, besides the tokens before it
+#[derive(Clone)]
+struct Resume {
+    offset: usize,
+    // Which kind of annotation comment we're inside of, if any, and which token opened it
+    annotation: Option<Annotation>,
+    annotation_open: usize,
+    tracker: SpanTracker,
+}
 
-    while let Some((_index, character)) = char_indices.next() {
+impl Resume {
+    fn start() -> Resume {
+        Resume {
+            offset: 0,
+            annotation: None,
+            annotation_open: 0,
+            tracker: SpanTracker::new(),
+        }
+    }
+}
+
+enum Scanned {
+    Finished {
+        tokens: Vec<SpannedToken>,
+        errors: Vec<LexError>,
+    },
+    // `stop` asked to stop, where `resume` picks up again
+    Stopped {
+        resume: Resume,
+        tokens: Vec<SpannedToken>,
+        errors: Vec<LexError>,
+    },
+}
+
+// Scans from `resume` on, after `tokens`. Before each character it starts scanning from, `stop` is
+// asked whether to go on, given where it is, the annotation it's in, and the tokens so far
+fn scan_from(
+    source: &str,
+    resume: Resume,
+    mut tokens: Vec<SpannedToken>,
+    mut stop: impl FnMut(usize, Option<Annotation>, &[SpannedToken]) -> bool,
+) -> Scanned {
+    let mut errors = Vec::<LexError>::new();
+    let Resume {
+        offset,
+        mut annotation,
+        mut annotation_open,
+        mut tracker,
+    } = resume;
+    let mut char_indices = SourceChars::new(source, offset);
+
+    while let Some(&(index, character)) = char_indices.peek() {
+        if stop(index, annotation, &tokens) {
+            let resume = Resume {
+                offset: index,
+                annotation,
+                annotation_open,
+                tracker,
+            };
+            return Scanned::Stopped {
+                resume,
+                tokens,
+                errors,
+            };
+        }
+        char_indices.next();
         //
         // Should token be wrapped in Option then unwrapped?
         //
@@ -79,12 +361,8 @@ pub fn scan(source: String) -> Vec<Token> {
                     char_indices.next();
                     Token::BinOp(BinOp::FieldDeref)
                 }
-                _ => match tokens.last() {
-                    // "<identifier or number>-"
-                    Some(Token::Num(_)) | Some(Token::Id(_)) => Token::BinOp(BinOp::Minus),
-                    // "<!identifier or !number>-<identifier or number>"
-                    _ => Token::UnOp(UnOp::UnaryMinus),
-                },
+                // negation or subtraction, left for the parser to decide
+                _ => Token::Minus,
             },
             '*' => match char_indices.peek() {
                 // "*="
@@ -92,32 +370,54 @@ pub fn scan(source: String) -> Vec<Token> {
                     char_indices.next();
                     Token::AsnOp(AsnOp::MultAsn)
                 }
-                _ => {
-                    match tokens.last() {
-                        // Should be preceded by a number OR an identifier that resolves to a number
-                        // "num *"
-                        Some(Token::Num(_)) | Some(Token::Id(_)) => {
-                            println!("{:?}", tokens.last().unwrap());
-                            Token::BinOp(BinOp::IntTimes)
-                        }
-                        // pointer initialization: "<keyword::type> *"
-                        // pointer dereference: "*<identifier>"
-                        _ => Token::UnOp(UnOp::Pointer),
-                    }
-                }
+                // multiplication, dereference or pointer type, left for the parser to decide
+                _ => Token::Star,
             },
-            // NOTE: We are not currently validating previous token for division operator, as we
-            // did for disambiguating the multiplication operator and pointers
-            //
-            // This is because there is no ambiguity for resolving the token:
-            //      I assume this syntax check will occur in the parser
-            //
             '/' => match char_indices.peek() {
                 // "/="
                 Some((_, '=')) => {
                     char_indices.next();
                     Token::AsnOp(AsnOp::DivAsn)
                 }
+                // "//" -- line comment, runs up to (but not including) the newline
+                Some((_, '/')) => {
+                    char_indices.next();
+                    // "//@" -- line annotation, closed by the end of the line
+                    if annotation.is_none() && matches!(char_indices.peek(), Some((_, '@'))) {
+                        char_indices.next();
+                        annotation = Some(Annotation::Line);
+                        let end = next_index(&mut char_indices, source);
+                        let span = tracker.span(source, index, end);
+                        let token = Token::Annot(Annot::LineOpen);
+                        tokens.push(SpannedToken { token, span });
+                        continue;
+                    }
+                    while let Some((_, next_char)) = char_indices.peek() {
+                        if *next_char == '\n' {
+                            break;
+                        }
+                        char_indices.next();
+                    }
+                    continue;
+                }
+                // "/*" -- block comment, which may contain nested block comments
+                Some((_, '*')) => {
+                    char_indices.next();
+                    // "/*@" -- block annotation, closed by "@*/"
+                    if annotation.is_none() && matches!(char_indices.peek(), Some((_, '@'))) {
+                        char_indices.next();
+                        annotation = Some(Annotation::Block);
+                        annotation_open = tokens.len();
+                        Token::Annot(Annot::BlockOpen)
+                    } else {
+                        if !skip_block_comment(&mut char_indices) {
+                            let kind = LexErrorKind::UnterminatedComment;
+                            let end = source.len();
+                            errors.push(lex_error(source, &mut tracker, kind, index, end));
+                        }
+                        continue;
+                    }
+                }
                 // '/'
                 _ => Token::BinOp(BinOp::Divide),
             },
@@ -226,6 +526,46 @@ pub fn scan(source: String) -> Vec<Token> {
                 // '|'
                 _ => Token::BinOp(BinOp::BitwiseOR),
             },
+            // Inside an annotation '@' is ignored, so that block annotations can mark each of their
+            // lines with one, unless it is the start of the closing "@*/"
+            '@' if annotation.is_some() => {
+                let mut ahead = char_indices.clone();
+                let closing = matches!(
+                    (ahead.next(), ahead.next()),
+                    (Some((_, '*')), Some((_, '/')))
+                );
+                if annotation == Some(Annotation::Block) && closing {
+                    char_indices.next();
+                    char_indices.next();
+                    annotation = None;
+                    Token::Annot(Annot::Close)
+                } else {
+                    continue;
+                }
+            }
+            // "\result" and "\length" only mean something inside a contract
+            '\\' if annotation.is_some()
+                && matches!(char_indices.peek(), Some((_, next_char)) if next_char.is_ascii_alphabetic()) =>
+            {
+                let mut s = String::new();
+                while let Some((_, next_char)) = char_indices.peek() {
+                    if !(next_char.is_ascii_alphanumeric() || *next_char == '_') {
+                        break;
+                    }
+                    s.push(*next_char);
+                    char_indices.next();
+                }
+                match s.as_str() {
+                    "result" => Token::Keyword(Keyword::Result),
+                    "length" => Token::Keyword(Keyword::Length),
+                    _ => {
+                        let end = next_index(&mut char_indices, source);
+                        let kind = LexErrorKind::UnrecognizedCharacter;
+                        errors.push(lex_error(source, &mut tracker, kind, index, end));
+                        continue;
+                    }
+                }
+            }
             '.' => Token::BinOp(BinOp::FieldSelect),
             '?' => Token::BinOp(BinOp::CondEq),
             ':' => Token::BinOp(BinOp::CondAsn),
@@ -240,107 +580,161 @@ pub fn scan(source: String) -> Vec<Token> {
             ';' => Token::Sep(Sep::SemiColon),
             // Explicit Esc Sequences (written by source author, written as two characters)
             // Note, we have to escape the backslash character so '\\' evals to '\'
-            '\\' => match char_indices.peek() {
-                Some((_, 'a')) => {
-                    char_indices.next();
-                    Token::Esc(Esc::Alert)
-                }
-                Some((_, 'b')) => {
-                    char_indices.next();
-                    Token::Esc(Esc::Backspace)
-                }
-                Some((_, 'f')) => {
+            '\\' => match char_indices.peek().and_then(|(_, c)| Esc::from_char(*c)) {
+                Some(esc) => {
                     char_indices.next();
-                    Token::Esc(Esc::FormfeedPgBrk)
+                    Token::Esc(esc)
                 }
-                Some((_, 'n')) => {
-                    char_indices.next();
-                    Token::Esc(Esc::Newline)
+                None => {
+                    let end = next_index(&mut char_indices, source);
+                    let kind = LexErrorKind::UnrecognizedCharacter;
+                    errors.push(lex_error(source, &mut tracker, kind, index, end));
+                    continue;
                 }
-                Some((_, 'r')) => {
-                    char_indices.next();
-                    Token::Esc(Esc::CarriageReturn)
-                }
-                Some((_, 't')) => {
+            },
+            // "#use <lib>" or "#use \"file.c0\"" -- the only directive C0 has. The whole line is a
+            // single token, since '<' here doesn't mean less-than
+            '#' => {
+                let mut directive = String::new();
+                while let Some((_, next_char)) = char_indices.peek() {
+                    if !next_char.is_ascii_alphabetic() {
+                        break;
+                    }
+                    directive.push(*next_char);
                     char_indices.next();
-                    Token::Esc(Esc::HorizontalTab)
                 }
-                Some((_, 'v')) => {
+                while let Some((_, ' ' | '\t')) = char_indices.peek() {
                     char_indices.next();
-                    Token::Esc(Esc::VerticalTab)
                 }
-                Some((_, '\\')) => {
+
+                let closing = match char_indices.peek() {
+                    Some((_, '<')) if directive == "use" => Some('>'),
+                    Some((_, '"')) if directive == "use" => Some('"'),
+                    _ => None,
+                };
+                let mut target = String::new();
+                let mut terminated = false;
+                if let Some(closing) = closing {
                     char_indices.next();
-                    Token::Esc(Esc::Backslash)
+                    while let Some((_, next_char)) = char_indices.peek() {
+                        let next_char = *next_char;
+                        if next_char == '\n' {
+                            break;
+                        }
+                        char_indices.next();
+                        if next_char == closing {
+                            terminated = true;
+                            break;
+                        }
+                        target.push(next_char);
+                    }
                 }
-                Some((_, '\'')) => {
-                    char_indices.next();
-                    Token::Esc(Esc::Apostrophe)
+
+                match closing {
+                    Some('>') if terminated && !target.is_empty() => {
+                        Token::Directive(Directive::UseLib(LibLit::LibraryLiteral(target)))
+                    }
+                    Some('"') if terminated && !target.is_empty() => {
+                        Token::Directive(Directive::UseFile(StrLit::StringLiteral(target)))
+                    }
+                    _ => {
+                        let end = next_index(&mut char_indices, source);
+                        let kind = LexErrorKind::InvalidDirective;
+                        errors.push(lex_error(source, &mut tracker, kind, index, end));
+                        continue;
+                    }
                 }
-                Some((_, '\"')) => {
-                    char_indices.next();
-                    Token::Esc(Esc::DoubleQuote)
+            }
+            // A newline is whitespace like any other, except that it closes a line annotation.
+            // The span tracker does the line counting
+            '\n' => {
+                if annotation == Some(Annotation::Line) {
+                    annotation = None;
+                    Token::Annot(Annot::Close)
+                } else {
+                    continue;
                 }
-                _ => continue,
-            },
-            // Implicit Esc Sequences (represented in unicode as a single character)
-            // For now, I think we only need to handle newlines, may add as necessary
-            // '\n'
-            '\u{0A}' => {
-                char_indices.next();
-                Token::Esc(Esc::Newline)
             }
             // Checking for strings
+            // String literals can't span lines, so a newline ends an unterminated one and scanning
+            // picks back up on the next line
             '\"' => {
-                let mut last_char_matched: char = '"';
-                let s: String = char_indices
-                    .by_ref()
-                    .take_while(|(_index, character)| {
-                        last_char_matched = *character;
-                        *character != '\"'
-                    })
-                    // call to map ditches the index value, keeping the character so we can append it to s
-                    .map(|(_index, character)| character)
-                    .collect();
-
-                match last_char_matched {
-                    '"' => Token::StrLit(StrLit::StringLiteral(s)),
-                    _ => panic!(),
+                let mut s = String::new();
+                let mut terminated = false;
+                let mut invalid = Vec::new();
+                while let Some((_, next_char)) = char_indices.peek() {
+                    match next_char {
+                        '\n' => break,
+                        '"' => {
+                            char_indices.next();
+                            terminated = true;
+                            break;
+                        }
+                        _ => match literal_char(&mut char_indices, source, false) {
+                            Ok(c) => s.push(c),
+                            Err(error) => invalid.push(error),
+                        },
+                    }
                 }
+
+                if !terminated {
+                    let end = next_index(&mut char_indices, source);
+                    let kind = LexErrorKind::UnterminatedString;
+                    errors.push(lex_error(source, &mut tracker, kind, index, end));
+                    continue;
+                } else if !invalid.is_empty() {
+                    for (kind, start, end) in invalid {
+                        errors.push(lex_error(source, &mut tracker, kind, start, end));
+                    }
+                    continue;
+                }
+                Token::StrLit(StrLit::StringLiteral(s))
             }
-            '\'' => match char_indices.peek() {
-                Some((outer_index, next_char)) => {
-                    // How to differentiate from NChar and LChar -- Lots of overlap
-                    if next_char.is_ascii() {
-                        // cannot access next_char after calling next() [borrowing char_indices mutably]
-                        let c = next_char.clone();
+            // Character literals hold exactly one character or escape sequence
+            '\'' => {
+                let c = match char_indices.peek() {
+                    None | Some((_, '\n')) => {
+                        let end = next_index(&mut char_indices, source);
+                        let kind = LexErrorKind::UnterminatedCharLit;
+                        errors.push(lex_error(source, &mut tracker, kind, index, end));
+                        continue;
+                    }
+                    Some((_, '\'')) => {
                         char_indices.next();
-                        // should be followed by closing single quote
-                        match char_indices.peek() {
-                                Some((_, '\'')) => {
-                                    char_indices.next();
-                                    Token::ChrLit(ChrLit::CharacterLiteral(c))
-                                },
-                                Some((inner_index, _)) => panic!("Error scanning character literal at index {} -- No closing single quote found", inner_index),
-                                None => panic!("Error scanning character literal -- End of file reached"),
-                            }
-                    } else {
-                        panic!("Error scanning character literal at index {} -- Non-ASCII character encountered", outer_index);
+                        let end = next_index(&mut char_indices, source);
+                        let kind = LexErrorKind::EmptyCharLit;
+                        errors.push(lex_error(source, &mut tracker, kind, index, end));
+                        continue;
                     }
+                    Some(_) => literal_char(&mut char_indices, source, true),
+                };
+
+                if !matches!(char_indices.peek(), Some((_, '\''))) {
+                    let end = next_index(&mut char_indices, source);
+                    let kind = LexErrorKind::UnterminatedCharLit;
+                    errors.push(lex_error(source, &mut tracker, kind, index, end));
+                    continue;
                 }
-                _ => panic!("Error scanning character literal -- End of file reached"),
-            },
-            ' ' => continue,
+                char_indices.next();
+                match c {
+                    Ok(c) => Token::ChrLit(ChrLit::CharacterLiteral(c)),
+                    Err((kind, start, end)) => {
+                        errors.push(lex_error(source, &mut tracker, kind, start, end));
+                        continue;
+                    }
+                }
+            }
+            // The rest of C0's whitespace: space, tab, carriage return, vertical tab, form feed
+            ' ' | '\t' | '\r' | '\u{0B}' | '\u{0C}' => continue,
             _ => {
-                let mut s = character.clone().to_string();
+                let mut s = character.to_string();
                 let mut stop_flag = false;
-                if character.is_alphabetic() {
+                // Identifiers are [A-Za-z_][A-Za-z0-9_]*
+                if character.is_ascii_alphabetic() || character == '_' {
                     while !stop_flag {
                         // check before consuming
                         if let Some((_index, next_char)) = char_indices.peek() {
-                            if (next_char.is_alphanumeric() || *next_char == '_')
-                                && *next_char != ' '
-                            {
+                            if next_char.is_ascii_alphanumeric() || *next_char == '_' {
                                 // condition is true, so we are good to consume next element
                                 if let Some((_index, next_char)) = char_indices.next() {
                                     // concatenate character to string
@@ -355,83 +749,80 @@ pub fn scan(source: String) -> Vec<Token> {
                             stop_flag = true;
                         }
                     }
-                    match s.as_str() {
-                        "int" => Token::Keyword(Keyword::Int),
-                        "bool" => Token::Keyword(Keyword::Bool),
-                        "string" => Token::Keyword(Keyword::String),
-                        "char" => Token::Keyword(Keyword::Char),
-                        "void" => Token::Keyword(Keyword::Void),
-                        "struct" => Token::Keyword(Keyword::Struct),
-                        "typedef" => Token::Keyword(Keyword::Typedef),
-                        "if" => Token::Keyword(Keyword::If),
-                        "else" => Token::Keyword(Keyword::Else),
-                        "while" => Token::Keyword(Keyword::While),
-                        "for" => Token::Keyword(Keyword::For),
-                        "continue" => Token::Keyword(Keyword::Continue),
-                        "break" => Token::Keyword(Keyword::Break),
-                        "return" => Token::Keyword(Keyword::Return),
-                        "assert" => Token::Keyword(Keyword::Assert),
-                        "error" => Token::Keyword(Keyword::Error),
-                        "true" => Token::Keyword(Keyword::True),
-                        "false" => Token::Keyword(Keyword::False),
-                        "NULL" => Token::Keyword(Keyword::Null),
-                        "alloc" => Token::Keyword(Keyword::Alloc),
-                        "alloc_array" => Token::Keyword(Keyword::AllocArray),
-                        _ => Token::Id(Id::Id(s)),
+                    keyword(s, annotation.is_some())
+                } else if character.is_ascii_digit() {
+                    let hex =
+                        character == '0' && matches!(char_indices.peek(), Some((_, 'x' | 'X')));
+                    if hex {
+                        char_indices.next();
+                        s.clear();
                     }
-                } else if character.is_numeric() {
-                    let mut hex_flag = false;
-                    while !stop_flag {
-                        if let Some((_index, next_digit)) = char_indices.peek() {
-                            // decimal
-                            if next_digit.is_digit(10) {
-                                if let Some((_index, next_digit)) = char_indices.next() {
-                                    s.push(next_digit);
-                                }
-                            } else if next_digit.is_digit(16) {
-                                hex_flag = true;
-                                if let Some((_index, next_digit)) = char_indices.next() {
-                                    s.push(next_digit);
-                                }
-                            } else {
-                                stop_flag = true;
-                            }
-                        } else {
-                            stop_flag = true;
+                    // Take the whole alphanumeric run, so "12ab" is one bad literal rather than a
+                    // number followed by an identifier
+                    let mut malformed = false;
+                    while let Some((_, next_char)) = char_indices.peek() {
+                        let next_char = *next_char;
+                        if !(next_char.is_ascii_alphanumeric() || next_char == '_') {
+                            break;
                         }
+                        char_indices.next();
+                        malformed |= !next_char.is_digit(if hex { 16 } else { 10 });
+                        s.push(next_char);
                     }
 
-                    let radix: u32;
-                    if hex_flag {
-                        radix = 16;
+                    let number = if malformed || s.is_empty() {
+                        Err(LexErrorKind::MalformedNumber)
+                    } else if !hex && s.len() > 1 && s.starts_with('0') {
+                        Err(LexErrorKind::LeadingZero)
                     } else {
-                        radix = 10;
-                    }
-
-                    let num = match u32::from_str_radix(&s, radix) {
-                        Ok(num) => num,
-                        Err(e) => panic!("Error parsing num as base-{:?}: {:?}", radix, e),
+                        int_literal(&s, hex)
                     };
 
-                    if hex_flag {
-                        Token::Num(Num::HexNum(HexNum::HexNum(num)))
-                    } else {
-                        Token::Num(Num::DecNum(DecNum::DecNum(num)))
+                    match number {
+                        Ok(token) => token,
+                        Err(kind) => {
+                            let end = next_index(&mut char_indices, source);
+                            errors.push(lex_error(source, &mut tracker, kind, index, end));
+                            continue;
+                        }
                     }
                 } else {
-                    panic!("Unrecognizable character: {}", character);
+                    let end = next_index(&mut char_indices, source);
+                    let kind = LexErrorKind::UnrecognizedCharacter;
+                    errors.push(lex_error(source, &mut tracker, kind, index, end));
+                    continue;
                 }
             }
         };
-        tokens.push(token);
+        let end = next_index(&mut char_indices, source);
+        let span = tracker.span(source, index, end);
+        tokens.push(SpannedToken { token, span });
     }
-    tokens
+
+    finish(
+        source,
+        &mut tracker,
+        annotation,
+        annotation_open,
+        &mut tokens,
+        &mut errors,
+    );
+    Scanned::Finished { tokens, errors }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Most tests only care about which tokens were produced, not where they were found
+    fn scan_tokens(source: String) -> Vec<Token> {
+        scan(source)
+            .unwrap()
+            .into_iter()
+            .map(|spanned| spanned.token)
+            .collect()
+    }
+
     #[test]
     fn Id() {
         let Hello = vec![Token::Id(Id::Id("hello".to_string()))];
@@ -441,9 +832,25 @@ mod tests {
             Token::Id(Id::Id("world".to_string())),
         ];
 
-        assert_eq!(scan("hello".to_string()), Hello);
-        assert_eq!(scan("world".to_string()), World);
-        assert_eq!(scan("hello world".to_string()), HelloWorld);
+        assert_eq!(scan_tokens("hello".to_string()), Hello);
+        assert_eq!(scan_tokens("world".to_string()), World);
+        assert_eq!(scan_tokens("hello world".to_string()), HelloWorld);
+
+        // [A-Za-z_][A-Za-z0-9_]*, and nothing outside ASCII
+        let ids = |names: &[&str]| -> Vec<Token> {
+            names
+                .iter()
+                .map(|name| Token::Id(Id::Id(name.to_string())))
+                .collect()
+        };
+        assert_eq!(
+            scan_tokens("_x x_1 __ A9".to_string()),
+            ids(&["_x", "x_1", "__", "A9"])
+        );
+        assert_eq!(
+            scan_errors("héllo"),
+            vec![(LexErrorKind::UnrecognizedCharacter, "é".to_string())]
+        );
     }
 
     #[test]
@@ -455,10 +862,72 @@ mod tests {
         ))];
         let HelloNum = vec![Token::StrLit(StrLit::StringLiteral("hello 42".to_string()))];
 
-        assert_eq!(scan("\"hello\"".to_string()), Hello);
-        assert_eq!(scan("\"world!\"".to_string()), World);
-        assert_eq!(scan("\"hello world!\"".to_string()), HelloWorld);
-        assert_eq!(scan("\"hello 42\"".to_string()), HelloNum);
+        assert_eq!(scan_tokens("\"hello\"".to_string()), Hello);
+        assert_eq!(scan_tokens("\"world!\"".to_string()), World);
+        assert_eq!(scan_tokens("\"hello world!\"".to_string()), HelloWorld);
+        assert_eq!(scan_tokens("\"hello 42\"".to_string()), HelloNum);
+    }
+
+    #[test]
+    fn StrLit_escapes() {
+        let string = |s: &str| vec![Token::StrLit(StrLit::StringLiteral(s.to_string()))];
+
+        assert_eq!(scan_tokens("\"a\\\"b\"".to_string()), string("a\"b"));
+        assert_eq!(
+            scan_tokens("\"\\a\\b\\f\\n\\r\\t\\v\\\\\\'\\\"\"".to_string()),
+            string("\u{07}\u{08}\u{0C}\n\r\t\u{0B}\\'\"")
+        );
+        // an apostrophe needs no escaping in a string
+        assert_eq!(scan_tokens("\"it's\"".to_string()), string("it's"));
+
+        assert_eq!(
+            scan_errors("\"a\\0b\\qc\""),
+            vec![
+                (LexErrorKind::InvalidEscape, "\\0".to_string()),
+                (LexErrorKind::InvalidEscape, "\\q".to_string()),
+            ]
+        );
+        assert_eq!(
+            scan_errors("\"tab\there\" \"héllo\""),
+            vec![
+                (LexErrorKind::InvalidLiteralChar, "\t".to_string()),
+                (LexErrorKind::InvalidLiteralChar, "é".to_string()),
+            ]
+        );
+        // an escaped quote doesn't end the string, so this one never does
+        assert_eq!(
+            scan_errors("\"oops\\\""),
+            vec![(LexErrorKind::UnterminatedString, "\"oops\\\"".to_string())]
+        );
+
+        let errors = scan("x = \"ok \\z\";".to_string()).unwrap_err();
+        assert_eq!((errors[0].span.column, errors[0].span.length), (9, 2));
+    }
+
+    #[test]
+    fn ChrLit() {
+        let chr = |c: char| vec![Token::ChrLit(ChrLit::CharacterLiteral(c))];
+
+        assert_eq!(scan_tokens("'a'".to_string()), chr('a'));
+        assert_eq!(scan_tokens("'\"'".to_string()), chr('"'));
+        assert_eq!(scan_tokens("'\\n'".to_string()), chr('\n'));
+        assert_eq!(scan_tokens("'\\0'".to_string()), chr('\0'));
+        assert_eq!(scan_tokens("'\\''".to_string()), chr('\''));
+        assert_eq!(scan_tokens("'\\\\'".to_string()), chr('\\'));
+        assert_eq!(scan_tokens("'\\v'".to_string()), chr('\u{0B}'));
+
+        assert_eq!(
+            scan_errors("'' '\\x' '\\n"),
+            vec![
+                (LexErrorKind::EmptyCharLit, "''".to_string()),
+                (LexErrorKind::InvalidEscape, "\\x".to_string()),
+                (LexErrorKind::UnterminatedCharLit, "'\\n".to_string()),
+            ]
+        );
+        assert_eq!(
+            scan_errors("'\u{7f}'"),
+            vec![(LexErrorKind::InvalidLiteralChar, "\u{7f}".to_string())]
+        );
     }
 
     #[test]
@@ -485,27 +954,27 @@ mod tests {
         let Alloc = vec![Token::Keyword(Keyword::Alloc)];
         let AllocArray = vec![Token::Keyword(Keyword::AllocArray)];
 
-        assert_eq!(scan("int".to_string()), Int);
-        assert_eq!(scan("bool".to_string()), Bool);
-        assert_eq!(scan("string".to_string()), String);
-        assert_eq!(scan("char".to_string()), Char);
-        assert_eq!(scan("void".to_string()), Void);
-        assert_eq!(scan("struct".to_string()), Struct);
-        assert_eq!(scan("typedef".to_string()), Typedef);
-        assert_eq!(scan("if".to_string()), If);
-        assert_eq!(scan("else".to_string()), Else);
-        assert_eq!(scan("while".to_string()), While);
-        assert_eq!(scan("for".to_string()), For);
-        assert_eq!(scan("continue".to_string()), Continue);
-        assert_eq!(scan("break".to_string()), Break);
-        assert_eq!(scan("return".to_string()), Return);
-        assert_eq!(scan("assert".to_string()), Assert);
-        assert_eq!(scan("error".to_string()), Error);
-        assert_eq!(scan("true".to_string()), True);
-        assert_eq!(scan("false".to_string()), False);
-        assert_eq!(scan("NULL".to_string()), Null);
-        assert_eq!(scan("alloc".to_string()), Alloc);
-        assert_eq!(scan("alloc_array".to_string()), AllocArray);
+        assert_eq!(scan_tokens("int".to_string()), Int);
+        assert_eq!(scan_tokens("bool".to_string()), Bool);
+        assert_eq!(scan_tokens("string".to_string()), String);
+        assert_eq!(scan_tokens("char".to_string()), Char);
+        assert_eq!(scan_tokens("void".to_string()), Void);
+        assert_eq!(scan_tokens("struct".to_string()), Struct);
+        assert_eq!(scan_tokens("typedef".to_string()), Typedef);
+        assert_eq!(scan_tokens("if".to_string()), If);
+        assert_eq!(scan_tokens("else".to_string()), Else);
+        assert_eq!(scan_tokens("while".to_string()), While);
+        assert_eq!(scan_tokens("for".to_string()), For);
+        assert_eq!(scan_tokens("continue".to_string()), Continue);
+        assert_eq!(scan_tokens("break".to_string()), Break);
+        assert_eq!(scan_tokens("return".to_string()), Return);
+        assert_eq!(scan_tokens("assert".to_string()), Assert);
+        assert_eq!(scan_tokens("error".to_string()), Error);
+        assert_eq!(scan_tokens("true".to_string()), True);
+        assert_eq!(scan_tokens("false".to_string()), False);
+        assert_eq!(scan_tokens("NULL".to_string()), Null);
+        assert_eq!(scan_tokens("alloc".to_string()), Alloc);
+        assert_eq!(scan_tokens("alloc_array".to_string()), AllocArray);
     }
 
     #[test]
@@ -517,14 +986,61 @@ mod tests {
             Token::Num(Num::DecNum(DecNum::DecNum(4567))),
         ];
 
-        assert_eq!(scan("42".to_string()), answer_to_universe);
-        assert_eq!(scan("1 23 4567".to_string()), multiple_numbers);
+        assert_eq!(scan_tokens("42".to_string()), answer_to_universe);
+        assert_eq!(scan_tokens("1 23 4567".to_string()), multiple_numbers);
+        assert_eq!(
+            scan_tokens("0 2147483648".to_string()),
+            vec![
+                Token::Num(Num::DecNum(DecNum::DecNum(0))),
+                Token::Num(Num::DecNum(DecNum::DecNum(1 << 31))),
+            ]
+        );
+    }
+
+    #[test]
+    fn Num_errors() {
+        assert_eq!(
+            scan_errors("2147483649 0x100000000"),
+            vec![
+                (LexErrorKind::InvalidNumber, "2147483649".to_string()),
+                (LexErrorKind::InvalidNumber, "0x100000000".to_string()),
+            ]
+        );
+        assert_eq!(
+            scan_errors("007 00"),
+            vec![
+                (LexErrorKind::LeadingZero, "007".to_string()),
+                (LexErrorKind::LeadingZero, "00".to_string()),
+            ]
+        );
+        assert_eq!(
+            scan_errors("2A 12ab 0x 0xfg"),
+            vec![
+                (LexErrorKind::MalformedNumber, "2A".to_string()),
+                (LexErrorKind::MalformedNumber, "12ab".to_string()),
+                (LexErrorKind::MalformedNumber, "0x".to_string()),
+                (LexErrorKind::MalformedNumber, "0xfg".to_string()),
+            ]
+        );
+        // leading zeros are fine in hex
+        assert_eq!(
+            scan_tokens("0x007".to_string()),
+            vec![Token::Num(Num::HexNum(HexNum::HexNum(7)))]
+        );
     }
 
     #[test]
     fn HexNum() {
         let answer_to_universe = vec![Token::Num(Num::HexNum(HexNum::HexNum(42)))];
-        assert_eq!(scan("2A".to_string()), answer_to_universe);
+        assert_eq!(scan_tokens("0x2A".to_string()), answer_to_universe);
+        assert_eq!(scan_tokens("0X2a".to_string()), answer_to_universe);
+        assert_eq!(
+            scan_tokens("0xFFFFFFFF 0x0".to_string()),
+            vec![
+                Token::Num(Num::HexNum(HexNum::HexNum(u32::MAX))),
+                Token::Num(Num::HexNum(HexNum::HexNum(0))),
+            ]
+        );
     }
 
     #[test]
@@ -540,16 +1056,44 @@ mod tests {
         let Apostrophe = vec![Token::Esc(Esc::Apostrophe)];
         let DoubleQuote = vec![Token::Esc(Esc::DoubleQuote)];
 
-        assert_eq!(scan("\\a".to_string()), Alert);
-        assert_eq!(scan("\\b".to_string()), Backspace);
-        assert_eq!(scan("\\f".to_string()), FormfeedPgBrk);
-        assert_eq!(scan("\\n".to_string()), Newline);
-        assert_eq!(scan("\\r".to_string()), CarriageReturn);
-        assert_eq!(scan("\\t".to_string()), HorizontalTab);
-        assert_eq!(scan("\\v".to_string()), VerticalTab);
-        assert_eq!(scan("\\\\".to_string()), Backslash);
-        assert_eq!(scan("\\'".to_string()), Apostrophe);
-        assert_eq!(scan("\\\" ".to_string()), DoubleQuote);
+        assert_eq!(scan_tokens("\\a".to_string()), Alert);
+        assert_eq!(scan_tokens("\\b".to_string()), Backspace);
+        assert_eq!(scan_tokens("\\f".to_string()), FormfeedPgBrk);
+        assert_eq!(scan_tokens("\\n".to_string()), Newline);
+        assert_eq!(scan_tokens("\\r".to_string()), CarriageReturn);
+        assert_eq!(scan_tokens("\\t".to_string()), HorizontalTab);
+        assert_eq!(scan_tokens("\\v".to_string()), VerticalTab);
+        assert_eq!(scan_tokens("\\\\".to_string()), Backslash);
+        assert_eq!(scan_tokens("\\'".to_string()), Apostrophe);
+        assert_eq!(scan_tokens("\\\" ".to_string()), DoubleQuote);
+    }
+
+    #[test]
+    fn Whitespace() {
+        let xy = vec![
+            Token::Id(Id::Id("x".to_string())),
+            Token::Id(Id::Id("y".to_string())),
+        ];
+        for blank in [
+            " ",
+            "\t",
+            "\r",
+            "\u{0B}",
+            "\u{0C}",
+            "\n",
+            "\r\n",
+            " \t\n\n  ",
+        ] {
+            assert_eq!(scan_tokens(format!("x{}y", blank)), xy, "{:?}", blank);
+        }
+        // nothing after a newline is lost, and lines are still counted
+        let spanned = scan("x\ny\r\n\tz".to_string()).unwrap();
+        let places: Vec<(usize, usize)> = spanned
+            .iter()
+            .map(|spanned| (spanned.span.line, spanned.span.column))
+            .collect();
+        assert_eq!(places, vec![(1, 1), (2, 1), (3, 2)]);
+        assert_eq!(scan_tokens("\n".to_string()), vec![]);
     }
 
     #[test]
@@ -563,30 +1107,52 @@ mod tests {
         let Comma = vec![Token::Sep(Sep::Comma)];
         let SemiColon = vec![Token::Sep(Sep::SemiColon)];
 
-        assert_eq!(scan("(".to_string()), LParen);
-        assert_eq!(scan(")".to_string()), RParen);
-        assert_eq!(scan("[".to_string()), LBracket);
-        assert_eq!(scan("]".to_string()), RBracket);
-        assert_eq!(scan("{".to_string()), LCurly);
-        assert_eq!(scan("}".to_string()), RCurly);
-        assert_eq!(scan(",".to_string()), Comma);
-        assert_eq!(scan(";".to_string()), SemiColon);
+        assert_eq!(scan_tokens("(".to_string()), LParen);
+        assert_eq!(scan_tokens(")".to_string()), RParen);
+        assert_eq!(scan_tokens("[".to_string()), LBracket);
+        assert_eq!(scan_tokens("]".to_string()), RBracket);
+        assert_eq!(scan_tokens("{".to_string()), LCurly);
+        assert_eq!(scan_tokens("}".to_string()), RCurly);
+        assert_eq!(scan_tokens(",".to_string()), Comma);
+        assert_eq!(scan_tokens(";".to_string()), SemiColon);
     }
 
     #[test]
     fn UnOp() {
         let LogicalNOT = vec![Token::UnOp(UnOp::LogicalNOT)];
         let BitwiseNOT = vec![Token::UnOp(UnOp::BitwiseNOT)];
-        let UnaryMinus = vec![Token::UnOp(UnOp::UnaryMinus)];
-        let Pointer = vec![
-            Token::UnOp(UnOp::Pointer),
-            Token::Id(Id::Id("variable".to_string())),
-        ];
 
-        assert_eq!(scan("!".to_string()), LogicalNOT);
-        assert_eq!(scan("~".to_string()), BitwiseNOT);
-        assert_eq!(scan("-".to_string()), UnaryMinus);
-        assert_eq!(scan("*variable".to_string()), Pointer);
+        assert_eq!(scan_tokens("!".to_string()), LogicalNOT);
+        assert_eq!(scan_tokens("~".to_string()), BitwiseNOT);
+    }
+
+    #[test]
+    fn Minus_and_Star() {
+        // the scanner no longer guesses from the previous token; "-" and "*" come out the same
+        // wherever they are
+        assert_eq!(
+            scan_tokens("-x - (a) * *p".to_string()),
+            vec![
+                Token::Minus,
+                Token::Id(Id::Id("x".to_string())),
+                Token::Minus,
+                Token::Sep(Sep::LParen),
+                Token::Id(Id::Id("a".to_string())),
+                Token::Sep(Sep::RParen),
+                Token::Star,
+                Token::Star,
+                Token::Id(Id::Id("p".to_string())),
+            ]
+        );
+        assert_eq!(
+            scan_tokens("-= *= -- ->".to_string()),
+            vec![
+                Token::AsnOp(AsnOp::DecAsn),
+                Token::AsnOp(AsnOp::MultAsn),
+                Token::PostOp(PostOp::Dec),
+                Token::BinOp(BinOp::FieldDeref),
+            ]
+        );
     }
 
     #[test]
@@ -594,17 +1160,9 @@ mod tests {
         let CondEq = vec![Token::BinOp(BinOp::CondEq)];
         let FieldSelect = vec![Token::BinOp(BinOp::FieldSelect)];
         let FieldDeref = vec![Token::BinOp(BinOp::FieldDeref)];
-        let IntTimes = vec![
-            Token::Num(Num::DecNum(DecNum::DecNum(12))),
-            Token::BinOp(BinOp::IntTimes),
-        ];
         let Divide = vec![Token::BinOp(BinOp::Divide)];
         let Modulo = vec![Token::BinOp(BinOp::Modulo)];
         let Plus = vec![Token::BinOp(BinOp::Plus)];
-        let Minus = vec![
-            Token::Num(Num::DecNum(DecNum::DecNum(1))),
-            Token::BinOp(BinOp::Minus),
-        ];
         let ShiftLeft = vec![Token::BinOp(BinOp::ShiftLeft)];
         let Less = vec![Token::BinOp(BinOp::Less)];
         let LessEq = vec![Token::BinOp(BinOp::LessEq)];
@@ -620,28 +1178,26 @@ mod tests {
         let LogicalOR = vec![Token::BinOp(BinOp::LogicalOR)];
         let CondAsn = vec![Token::BinOp(BinOp::CondAsn)];
 
-        assert_eq!(scan("?".to_string()), CondEq);
-        assert_eq!(scan(".".to_string()), FieldSelect);
-        assert_eq!(scan("->".to_string()), FieldDeref);
-        assert_eq!(scan("12 *".to_string()), IntTimes);
-        assert_eq!(scan("/".to_string()), Divide);
-        assert_eq!(scan("%".to_string()), Modulo);
-        assert_eq!(scan("+".to_string()), Plus);
-        assert_eq!(scan("1 -".to_string()), Minus);
-        assert_eq!(scan("<<".to_string()), ShiftLeft);
-        assert_eq!(scan("<".to_string()), Less);
-        assert_eq!(scan("<=".to_string()), LessEq);
-        assert_eq!(scan(">>".to_string()), ShiftRight);
-        assert_eq!(scan(">".to_string()), Greater);
-        assert_eq!(scan(">=".to_string()), GreaterEq);
-        assert_eq!(scan("==".to_string()), Equality);
-        assert_eq!(scan("&".to_string()), BitwiseAND);
-        assert_eq!(scan("&&".to_string()), LogicalAND);
-        assert_eq!(scan("^".to_string()), BitwiseXOR);
-        assert_eq!(scan("!=".to_string()), Disequality);
-        assert_eq!(scan("|".to_string()), BitwiseOR);
-        assert_eq!(scan("||".to_string()), LogicalOR);
-        assert_eq!(scan(":".to_string()), CondAsn);
+        assert_eq!(scan_tokens("?".to_string()), CondEq);
+        assert_eq!(scan_tokens(".".to_string()), FieldSelect);
+        assert_eq!(scan_tokens("->".to_string()), FieldDeref);
+        assert_eq!(scan_tokens("/".to_string()), Divide);
+        assert_eq!(scan_tokens("%".to_string()), Modulo);
+        assert_eq!(scan_tokens("+".to_string()), Plus);
+        assert_eq!(scan_tokens("<<".to_string()), ShiftLeft);
+        assert_eq!(scan_tokens("<".to_string()), Less);
+        assert_eq!(scan_tokens("<=".to_string()), LessEq);
+        assert_eq!(scan_tokens(">>".to_string()), ShiftRight);
+        assert_eq!(scan_tokens(">".to_string()), Greater);
+        assert_eq!(scan_tokens(">=".to_string()), GreaterEq);
+        assert_eq!(scan_tokens("==".to_string()), Equality);
+        assert_eq!(scan_tokens("&".to_string()), BitwiseAND);
+        assert_eq!(scan_tokens("&&".to_string()), LogicalAND);
+        assert_eq!(scan_tokens("^".to_string()), BitwiseXOR);
+        assert_eq!(scan_tokens("!=".to_string()), Disequality);
+        assert_eq!(scan_tokens("|".to_string()), BitwiseOR);
+        assert_eq!(scan_tokens("||".to_string()), LogicalOR);
+        assert_eq!(scan_tokens(":".to_string()), CondAsn);
     }
 
     #[test]
@@ -658,17 +1214,17 @@ mod tests {
         let XORAsn = vec![Token::AsnOp(AsnOp::XORAsn)];
         let ORAsn = vec![Token::AsnOp(AsnOp::ORAsn)];
 
-        assert_eq!(scan("=".to_string()), EqAsn);
-        assert_eq!(scan("+=".to_string()), IncAsn);
-        assert_eq!(scan("-=".to_string()), DecAsn);
-        assert_eq!(scan("*=".to_string()), MultAsn);
-        assert_eq!(scan("/=".to_string()), DivAsn);
-        assert_eq!(scan("%=".to_string()), ModAsn);
-        assert_eq!(scan("<<=".to_string()), LShiftAsn);
-        assert_eq!(scan(">>=".to_string()), RShiftAsn);
-        assert_eq!(scan("&=".to_string()), ANDAsn);
-        assert_eq!(scan("^=".to_string()), XORAsn);
-        assert_eq!(scan("|=".to_string()), ORAsn);
+        assert_eq!(scan_tokens("=".to_string()), EqAsn);
+        assert_eq!(scan_tokens("+=".to_string()), IncAsn);
+        assert_eq!(scan_tokens("-=".to_string()), DecAsn);
+        assert_eq!(scan_tokens("*=".to_string()), MultAsn);
+        assert_eq!(scan_tokens("/=".to_string()), DivAsn);
+        assert_eq!(scan_tokens("%=".to_string()), ModAsn);
+        assert_eq!(scan_tokens("<<=".to_string()), LShiftAsn);
+        assert_eq!(scan_tokens(">>=".to_string()), RShiftAsn);
+        assert_eq!(scan_tokens("&=".to_string()), ANDAsn);
+        assert_eq!(scan_tokens("^=".to_string()), XORAsn);
+        assert_eq!(scan_tokens("|=".to_string()), ORAsn);
     }
 
     #[test]
@@ -676,8 +1232,8 @@ mod tests {
         let Inc = vec![Token::PostOp(PostOp::Inc)];
         let Dec = vec![Token::PostOp(PostOp::Dec)];
 
-        assert_eq!(scan("++".to_string()), Inc);
-        assert_eq!(scan("--".to_string()), Dec);
+        assert_eq!(scan_tokens("++".to_string()), Inc);
+        assert_eq!(scan_tokens("--".to_string()), Dec);
     }
 
     #[test]
@@ -710,23 +1266,19 @@ mod tests {
             Token::Sep(Sep::LParen),
             Token::Sep(Sep::RParen),
             Token::Sep(Sep::LCurly),
-            Token::Esc(Esc::Newline),
             Token::Id(Id::Id("printf".to_string())),
             Token::Sep(Sep::LParen),
             Token::StrLit(StrLit::StringLiteral("Hello world!".to_string())),
             Token::Sep(Sep::RParen),
             Token::Sep(Sep::SemiColon),
-            Token::Esc(Esc::Newline),
             Token::Keyword(Keyword::Bool),
             Token::Id(Id::Id("this_works".to_string())),
             Token::AsnOp(AsnOp::EqAsn),
             Token::Keyword(Keyword::True),
             Token::Sep(Sep::SemiColon),
-            Token::Esc(Esc::Newline),
             Token::Keyword(Keyword::Return),
             Token::Num(Num::DecNum(DecNum::DecNum(0))),
             Token::Sep(Sep::SemiColon),
-            Token::Esc(Esc::Newline),
             Token::Sep(Sep::RCurly),
         ];
 
@@ -757,11 +1309,11 @@ mod tests {
         ];
 
         assert_eq!(
-            scan("? -> % << <<= > != == = || += ^= ++ } \\r [ ; ~ %=".to_string()),
+            scan_tokens("? -> % << <<= > != == = || += ^= ++ } \\r [ ; ~ %=".to_string()),
             res1
         );
         assert_eq!(
-            scan(
+            scan_tokens(
                 "int main() {
                     printf(\"Hello world!\");
                     bool this_works = true;
@@ -772,7 +1324,7 @@ mod tests {
             program
         );
         assert_eq!(
-            scan(
+            scan_tokens(
                 r#"int main() {\nprintf("Hello world!");\nbool this_works = true;\nreturn 0;\n}"#
                     .to_string()
             ),
@@ -804,6 +1356,292 @@ mod tests {
             Token::AsnOp(AsnOp::ModAsn),
         ];
 
-        assert_eq!(scan("?->%<<<<=>!====||+=^=++}\\r[;~%=".to_string()), res1);
+        assert_eq!(
+            scan_tokens("?->%<<<<=>!====||+=^=++}\\r[;~%=".to_string()),
+            res1
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let source = "int main() {\n  return 42;\n}".to_string();
+        let spans: Vec<Span> = scan(source.clone())
+            .unwrap()
+            .into_iter()
+            .map(|spanned| spanned.span)
+            .collect();
+
+        assert_eq!(
+            spans[0],
+            Span {
+                offset: 0,
+                line: 1,
+                column: 1,
+                length: 3
+            }
+        );
+        assert_eq!(
+            spans[1],
+            Span {
+                offset: 4,
+                line: 1,
+                column: 5,
+                length: 4
+            }
+        );
+        // "return" on the second line, after two spaces of indentation
+        let ret = &spans[5];
+        assert_eq!((ret.line, ret.column), (2, 3));
+        assert_eq!(&source[ret.offset..ret.end()], "return");
+        // "42"
+        let num = &spans[6];
+        assert_eq!((num.line, num.column, num.length), (2, 10, 2));
+        assert_eq!(&source[num.offset..num.end()], "42");
+        // closing brace on the last line
+        let curly = spans.last().unwrap();
+        assert_eq!((curly.line, curly.column, curly.length), (3, 1, 1));
+    }
+
+    #[test]
+    fn spans_multibyte() {
+        // offsets and lengths are in bytes, columns in characters
+        let source = "/* héllo */ x".to_string();
+        let spanned = scan(source.clone()).unwrap();
+
+        assert_eq!(spanned[0].span.offset, 13);
+        assert_eq!(spanned[0].span.column, 13);
+        assert_eq!(&source[spanned[0].span.offset..spanned[0].span.end()], "x");
+
+        let errors = scan("'é' x".to_string()).unwrap_err();
+        assert_eq!(errors[0].span.length, 2);
+        assert_eq!(errors[0].span.column, 2);
+    }
+
+    // Errors don't stop the scan, and each one points at the text that caused it
+    fn scan_errors(source: &str) -> Vec<(LexErrorKind, String)> {
+        scan(source.to_string())
+            .unwrap_err()
+            .into_iter()
+            .map(|error| (error.kind, error.text))
+            .collect()
+    }
+
+    #[test]
+    fn LexError() {
+        assert_eq!(
+            scan_errors("\"hello"),
+            vec![(LexErrorKind::UnterminatedString, "\"hello".to_string())]
+        );
+        assert_eq!(
+            scan_errors("'a"),
+            vec![(LexErrorKind::UnterminatedCharLit, "'a".to_string())]
+        );
+        assert_eq!(
+            scan_errors("'"),
+            vec![(LexErrorKind::UnterminatedCharLit, "'".to_string())]
+        );
+        assert_eq!(
+            scan_errors("'é'"),
+            vec![(LexErrorKind::InvalidLiteralChar, "é".to_string())]
+        );
+        assert_eq!(
+            scan_errors("99999999999"),
+            vec![(LexErrorKind::InvalidNumber, "99999999999".to_string())]
+        );
+        assert_eq!(
+            scan_errors("$"),
+            vec![(LexErrorKind::UnrecognizedCharacter, "$".to_string())]
+        );
+        // a backslash that doesn't start an escape sequence
+        assert_eq!(
+            scan_errors("x \\ y \\q"),
+            vec![
+                (LexErrorKind::UnrecognizedCharacter, "\\".to_string()),
+                (LexErrorKind::UnrecognizedCharacter, "\\".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn LexError_recovery() {
+        let source = "int x = $;\n  string s = \"oops;\n  int y = 99999999999 @ 1;";
+        let errors = scan(source.to_string()).unwrap_err();
+
+        let kinds: Vec<&LexErrorKind> = errors.iter().map(|error| &error.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &LexErrorKind::UnrecognizedCharacter,
+                &LexErrorKind::UnterminatedString,
+                &LexErrorKind::InvalidNumber,
+                &LexErrorKind::UnrecognizedCharacter,
+            ]
+        );
+        // the unterminated string stops at the end of its own line
+        assert_eq!(errors[1].text, "\"oops;");
+        assert_eq!((errors[1].span.line, errors[1].span.column), (2, 14));
+        assert_eq!((errors[3].span.line, errors[3].span.column), (3, 23));
+        assert_eq!(
+            errors[3].to_string(),
+            "3:23: error: unrecognizable character `@`"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let x_plus_y = vec![
+            Token::Id(Id::Id("x".to_string())),
+            Token::BinOp(BinOp::Plus),
+            Token::Id(Id::Id("y".to_string())),
+        ];
+
+        assert_eq!(scan_tokens("x + y // x / y".to_string()), x_plus_y);
+        assert_eq!(scan_tokens("x /* / */ + y".to_string()), x_plus_y);
+        assert_eq!(scan_tokens("x/**/+/*/ */y".to_string()), x_plus_y);
+        assert_eq!(
+            scan_tokens("x /* outer /* inner */ still outer */ + y".to_string()),
+            x_plus_y
+        );
+        assert_eq!(
+            scan_tokens("x /* // not a line comment */ + y".to_string()),
+            x_plus_y
+        );
+        assert_eq!(scan_tokens("// nothing but a comment".to_string()), vec![]);
+        // division still works when it isn't followed by '/' or '*'
+        assert_eq!(
+            scan_tokens("x / y".to_string()),
+            vec![
+                Token::Id(Id::Id("x".to_string())),
+                Token::BinOp(BinOp::Divide),
+                Token::Id(Id::Id("y".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_unterminated() {
+        assert_eq!(
+            scan_errors("x /* never closed"),
+            vec![(
+                LexErrorKind::UnterminatedComment,
+                "/* never closed".to_string()
+            )]
+        );
+        // closing the inner comment still leaves the outer one open
+        let errors = scan("int x;\n  /* a /* b */ c".to_string()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, LexErrorKind::UnterminatedComment);
+        assert_eq!((errors[0].span.line, errors[0].span.column), (2, 3));
+    }
+
+    #[test]
+    fn Annot() {
+        let line = vec![
+            Token::Annot(Annot::LineOpen),
+            Token::Keyword(Keyword::Requires),
+            Token::Id(Id::Id("n".to_string())),
+            Token::BinOp(BinOp::GreaterEq),
+            Token::Num(Num::DecNum(DecNum::DecNum(0))),
+            Token::Sep(Sep::SemiColon),
+            Token::Annot(Annot::Close),
+            Token::Keyword(Keyword::Int),
+        ];
+        let block = vec![
+            Token::Annot(Annot::BlockOpen),
+            Token::Keyword(Keyword::LoopInvariant),
+            Token::Id(Id::Id("i".to_string())),
+            Token::BinOp(BinOp::LessEq),
+            Token::Id(Id::Id("n".to_string())),
+            Token::Sep(Sep::SemiColon),
+            Token::Keyword(Keyword::AnnoAssert),
+            Token::Id(Id::Id("ok".to_string())),
+            Token::Sep(Sep::SemiColon),
+            Token::Annot(Annot::Close),
+        ];
+        let ensures = vec![
+            Token::Annot(Annot::LineOpen),
+            Token::Keyword(Keyword::Ensures),
+            Token::Keyword(Keyword::Result),
+            Token::BinOp(BinOp::Equality),
+            Token::Keyword(Keyword::Length),
+            Token::Sep(Sep::LParen),
+            Token::Id(Id::Id("A".to_string())),
+            Token::Sep(Sep::RParen),
+            Token::Sep(Sep::SemiColon),
+            Token::Annot(Annot::Close),
+        ];
+
+        assert_eq!(scan_tokens("//@requires n >= 0;\nint".to_string()), line);
+        assert_eq!(
+            scan_tokens("/*@ loop_invariant i <= n;\n  @ assert ok; @*/".to_string()),
+            block
+        );
+        // a line annotation on the last line is closed by the end of the file
+        assert_eq!(
+            scan_tokens("//@ensures \\result == \\length(A);".to_string()),
+            ensures
+        );
+    }
+
+    #[test]
+    fn Annot_keywords_outside_annotations() {
+        let outside = vec![
+            Token::Id(Id::Id("requires".to_string())),
+            Token::Id(Id::Id("ensures".to_string())),
+            Token::Id(Id::Id("loop_invariant".to_string())),
+            Token::Keyword(Keyword::Assert),
+        ];
+
+        assert_eq!(
+            scan_tokens("requires ensures loop_invariant assert".to_string()),
+            outside
+        );
+        // ordinary comments, even ones mentioning contracts, are still skipped
+        assert_eq!(scan_tokens("// @requires x".to_string()), vec![]);
+        assert_eq!(scan_tokens("/* @ensures x @*/".to_string()), vec![]);
+        assert_eq!(
+            scan_errors("/*@ requires x;"),
+            vec![(
+                LexErrorKind::UnterminatedAnnotation,
+                "/*@ requires x;".to_string()
+            )]
+        );
+        assert_eq!(
+            scan_errors("int @"),
+            vec![(LexErrorKind::UnrecognizedCharacter, "@".to_string())]
+        );
+    }
+
+    #[test]
+    fn Directive() {
+        let lib = vec![Token::Directive(Directive::UseLib(LibLit::LibraryLiteral(
+            "conio".to_string(),
+        )))];
+        let file = vec![Token::Directive(Directive::UseFile(StrLit::StringLiteral(
+            "../lib/queue.c0".to_string(),
+        )))];
+
+        assert_eq!(scan_tokens("#use <conio>".to_string()), lib);
+        assert_eq!(scan_tokens("#use\t<conio>".to_string()), lib);
+        assert_eq!(scan_tokens("#use \"../lib/queue.c0\"".to_string()), file);
+
+        let spanned = scan("#use <string>\n#use <conio>\nint".to_string()).unwrap();
+        assert_eq!(spanned.len(), 3);
+        assert_eq!(spanned[0].span.length, "#use <string>".len());
+        assert_eq!((spanned[1].span.line, spanned[1].span.column), (2, 1));
+        assert_eq!(spanned[2].token, Token::Keyword(Keyword::Int));
+
+        assert_eq!(
+            scan_errors("#use <conio"),
+            vec![(LexErrorKind::InvalidDirective, "#use <conio".to_string())]
+        );
+        assert_eq!(
+            scan_errors("#include <stdio.h>"),
+            vec![(LexErrorKind::InvalidDirective, "#include ".to_string())]
+        );
+        assert_eq!(
+            scan_errors("#use <>"),
+            vec![(LexErrorKind::InvalidDirective, "#use <>".to_string())]
+        );
     }
 }
@@ -0,0 +1,81 @@
+// Location of a token in the source file
+//
+// offset and length are measured in bytes so that source[offset..offset + length] gives back the
+// exact text of the token, while line and column are 1-based and count characters, which is what
+// a human reading a diagnostic expects to see
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+impl Span {
+    pub fn end(&self) -> usize {
+        self.offset + self.length
+    }
+
+    // The span running from the start of this one to the end of `end`
+    pub fn to(&self, end: Span) -> Span {
+        Span {
+            length: end.end() - self.offset,
+            ..*self
+        }
+    }
+}
+
+// Derives line/column numbers from the byte indices handed out by CharIndices
+//
+// Tokens are produced in order, so rather than precomputing a table of line starts we walk
+// forward from the previous token start, counting newlines as we go
+#[derive(Debug, Clone)]
+pub struct SpanTracker {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Default for SpanTracker {
+    fn default() -> Self {
+        SpanTracker::new()
+    }
+}
+
+impl SpanTracker {
+    pub fn new() -> SpanTracker {
+        SpanTracker {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    // Picks up from the start of a span whose line and column are already known
+    pub fn at(span: Span) -> SpanTracker {
+        SpanTracker {
+            offset: span.offset,
+            line: span.line,
+            column: span.column,
+        }
+    }
+
+    pub fn span(&mut self, source: &str, start: usize, end: usize) -> Span {
+        for character in source[self.offset..start].chars() {
+            if character == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.offset = start;
+
+        Span {
+            offset: start,
+            line: self.line,
+            column: self.column,
+            length: end - start,
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::scanner::error::LexError;
+use crate::scanner::span::{Span, SpanTracker};
+use crate::scanner::token::SpannedToken;
+use crate::scanner::{scan_from, Annotation, Resume, Scanned};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read};
+
+// Scans a source as it is read, handing out tokens and errors one at a time, in the order they
+// appear, rather than all of them at the end. Collecting everything it produces gives the same
+// tokens and errors as `scan` over the whole source
+//
+// The source is read a line at a time, and only scanned up to the start of the last line read:
+// no token but a block comment looks further ahead than the line after the one it starts on. A
+// block comment that runs off the end of what has been read so far is scanned again once more
+// lines are in. Text is dropped once it has been scanned, except inside a block annotation, where
+// an unterminated one has to be reported with all of its text
+pub struct Lexer<R> {
+    reader: BufReader<R>,
+    // The text read but not yet dropped, starting at `base` in the whole source
+    buffer: String,
+    base: usize,
+    // Where scanning picks up again in `buffer`, after `context`: the token that opened the block
+    // annotation we're in, if any, with its span relative to `buffer` too
+    resume: Resume,
+    context: Vec<SpannedToken>,
+    // How many lines past `resume` to read before scanning
+    lines: usize,
+    eof: bool,
+    done: bool,
+    pending: VecDeque<Result<SpannedToken, LexError>>,
+    io_error: Option<io::Error>,
+}
+
+impl<R: Read> Lexer<R> {
+    pub fn new(reader: R) -> Lexer<R> {
+        Lexer {
+            reader: BufReader::new(reader),
+            buffer: String::new(),
+            base: 0,
+            resume: Resume::start(),
+            context: Vec::new(),
+            lines: 2,
+            eof: false,
+            done: false,
+            pending: VecDeque::new(),
+            io_error: None,
+        }
+    }
+
+    // The error that stopped reading early, if any. The lexer ends with whatever it had scanned
+    // before it
+    pub fn io_error(&mut self) -> Option<io::Error> {
+        self.io_error.take()
+    }
+
+    // Reads until there are `self.lines` whole lines past where scanning resumes
+    fn fill(&mut self) -> io::Result<()> {
+        let mut lines = self.buffer[self.resume.offset..].matches('\n').count();
+        while !self.eof && lines < self.lines {
+            if self.reader.read_line(&mut self.buffer)? == 0 {
+                self.eof = true;
+            }
+            lines += 1;
+        }
+        Ok(())
+    }
+
+    // Scans as far as the lines read so far allow, queueing what comes out
+    fn advance(&mut self) -> io::Result<()> {
+        loop {
+            self.fill()?;
+            let eof = self.eof;
+            // The start of the last line read, which stays behind for the token before it to
+            // look at
+            let lines = self.buffer.strip_suffix('\n').unwrap_or(&self.buffer);
+            let stop_at = match lines.rfind('\n') {
+                Some(newline) => newline + 1,
+                None => 0,
+            };
+            let context = self.context.len();
+            let scanned = scan_from(
+                &self.buffer,
+                self.resume.clone(),
+                self.context.clone(),
+                |offset, _, _| !eof && offset >= stop_at,
+            );
+            match scanned {
+                // Only a block comment runs on past the last line before the end of the source, and
+                // it may well be closed further on
+                Scanned::Finished { .. } if !eof => self.lines *= 2,
+                Scanned::Finished { tokens, errors } => {
+                    self.queue(tokens, context, errors);
+                    self.done = true;
+                    return Ok(());
+                }
+                Scanned::Stopped {
+                    resume,
+                    tokens,
+                    errors,
+                } => {
+                    self.lines = 2;
+                    self.resume = resume;
+                    self.context = match self.resume.annotation {
+                        Some(Annotation::Block) => {
+                            let open = tokens[self.resume.annotation_open].clone();
+                            self.resume.annotation_open = 0;
+                            vec![open]
+                        }
+                        _ => Vec::new(),
+                    };
+                    self.queue(tokens, context, errors);
+                    self.drop_scanned();
+                    if !self.pending.is_empty() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    // Queues the tokens after the first `context` and the errors, in order of where they start
+    fn queue(&mut self, tokens: Vec<SpannedToken>, context: usize, errors: Vec<LexError>) {
+        let base = self.base;
+        let shift = |span: Span| Span {
+            offset: span.offset + base,
+            ..span
+        };
+        let mut tokens = tokens.into_iter().skip(context).peekable();
+        let mut errors = errors.into_iter().peekable();
+        loop {
+            let item = match (tokens.peek(), errors.peek()) {
+                (None, None) => break,
+                (Some(token), Some(error)) if error.span.offset > token.span.offset => {
+                    Ok(tokens.next().unwrap())
+                }
+                (Some(_), None) => Ok(tokens.next().unwrap()),
+                _ => Err(errors.next().unwrap()),
+            };
+            self.pending.push_back(match item {
+                Ok(spanned) => Ok(SpannedToken {
+                    span: shift(spanned.span),
+                    ..spanned
+                }),
+                Err(error) => Err(LexError {
+                    span: shift(error.span),
+                    ..error
+                }),
+            });
+        }
+    }
+
+    // Drops the text before where scanning resumes, or before the block annotation it's in
+    fn drop_scanned(&mut self) {
+        let keep = match self.context.first() {
+            Some(first) => first.span.offset,
+            None => self.resume.offset,
+        };
+        let offset = self.resume.offset;
+        let at = self.resume.tracker.span(&self.buffer, offset, offset);
+        self.buffer.drain(..keep);
+        self.base += keep;
+        let back = |span: Span| Span {
+            offset: span.offset - keep,
+            ..span
+        };
+        self.resume.offset -= keep;
+        self.resume.tracker = SpanTracker::at(back(at));
+        for spanned in &mut self.context {
+            spanned.span = back(spanned.span);
+        }
+    }
+}
+
+impl<R: Read> Iterator for Lexer<R> {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            if let Err(error) = self.advance() {
+                self.io_error = Some(error);
+                self.done = true;
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::dfa::tests::{fuzz, CORPUS};
+    use crate::scanner::scan;
+
+    // Hands out its bytes a few at a time, splitting characters and lines anywhere
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = self.step.min(buf.len()).min(self.bytes.len());
+            buf[..length].copy_from_slice(&self.bytes[..length]);
+            self.bytes = &self.bytes[length..];
+            Ok(length)
+        }
+    }
+
+    fn check(source: &str) {
+        for step in [1, 3, 4096] {
+            let reader = Trickle {
+                bytes: source.as_bytes(),
+                step,
+            };
+            let streamed: Vec<Result<SpannedToken, LexError>> = Lexer::new(reader).collect();
+            let expected: Vec<Result<SpannedToken, LexError>> = match scan(source.to_string()) {
+                Ok(tokens) => tokens.into_iter().map(Ok).collect(),
+                Err(errors) => errors.into_iter().map(Err).collect(),
+            };
+            let streamed: Vec<Result<SpannedToken, LexError>> =
+                if expected.iter().all(Result::is_ok) {
+                    streamed
+                } else {
+                    streamed.into_iter().filter(Result::is_err).collect()
+                };
+            assert_eq!(streamed, expected, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn same_as_scan() {
+        for source in CORPUS {
+            check(source);
+        }
+        check("");
+        check("x /* a\n b\n c\n d\n e\n f\n g */ y\n z");
+        check("x /* a\n b /* c\n d */\n e\n f\n g */ y\n z");
+        check("int x;\n /*@ requires x;\n @ ensures y;\n ");
+        check("#use <a>\n#use <b>\n#use \"c\"\nint x;");
+        check("x /* never closed\n y\n z");
+    }
+
+    #[test]
+    fn same_as_scan_random() {
+        fuzz(check);
+    }
+
+    // An endless supply of lines, only ever made on demand
+    struct Lines {
+        line: &'static [u8],
+        left: usize,
+        at: usize,
+    }
+
+    impl Read for Lines {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.left == 0 {
+                return Ok(0);
+            }
+            let length = (self.line.len() - self.at).min(buf.len());
+            buf[..length].copy_from_slice(&self.line[self.at..self.at + length]);
+            self.at += length;
+            if self.at == self.line.len() {
+                self.at = 0;
+                self.left -= 1;
+            }
+            Ok(length)
+        }
+    }
+
+    #[test]
+    fn bounded_buffer() {
+        let line = b"int x = 1 + 2; /* a comment */ //@assert x > 0;\n";
+        let mut lexer = Lexer::new(Lines {
+            line,
+            left: 100_000,
+            at: 0,
+        });
+        let mut count = 0;
+        let mut last = None;
+        while let Some(item) = lexer.next() {
+            assert!(
+                lexer.buffer.len() <= 3 * line.len(),
+                "{}",
+                lexer.buffer.len()
+            );
+            last = Some(item.unwrap());
+            count += 1;
+        }
+        assert!(lexer.io_error().is_none());
+        // seven tokens in the declaration and seven in the annotation, counting its open and
+        // close, on every line
+        assert_eq!(count, 100_000 * 14);
+        let last = last.unwrap();
+        assert_eq!(last.span.line, 100_000);
+        assert_eq!(last.span.offset, 100_000 * line.len() - 1);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut lexer = Lexer::new(&b"int x;\n int y;\n int z;\n \xff\n"[..]);
+        let tokens: Vec<SpannedToken> = lexer.by_ref().map(Result::unwrap).collect();
+        // whatever came before the bad line is still there
+        let valid = scan("int x;\n int y;\n int z;\n ".to_string()).unwrap();
+        assert!(!tokens.is_empty());
+        assert_eq!(tokens, valid[..tokens.len()]);
+        assert_eq!(
+            lexer.io_error().map(|error| error.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+    }
+}
@@ -1,25 +1,37 @@
-#![allow(dead_code)]
+use crate::scanner::span::Span;
+use std::fmt;
+
+// A token along with where it was found in the source file
+#[derive(PartialEq, Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
 // Enumerate keywords?
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Token {
     Id(Id),
     Num(Num),
     StrLit(StrLit),
     ChrLit(ChrLit),
-    LibLit(LibLit),
-    // How to differentiate between SChar::Esc and CChar::Esc?
-    SChar(SChar),
-    CChar(CChar),
     Sep(Sep),
+    // '-' and '*' mean different things depending on where they appear in an expression
+    // (negation or subtraction, dereference or multiplication, or a pointer type), which only the
+    // parser can tell
+    Minus,
+    Star,
     UnOp(UnOp),
     BinOp(BinOp),
     AsnOp(AsnOp),
     PostOp(PostOp),
     Keyword(Keyword),
     Esc(Esc),
+    Annot(Annot),
+    Directive(Directive),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Keyword {
     Int,
     Bool,
@@ -42,70 +54,55 @@ pub enum Keyword {
     Null,
     Alloc,
     AllocArray,
+    // Only recognized inside annotations
+    Requires,      // "requires"
+    Ensures,       // "ensures"
+    LoopInvariant, // "loop_invariant"
+    AnnoAssert,    // "assert" as a contract, as opposed to the assert statement
+    Result,        // "\result"
+    Length,        // "\length"
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Id {
     Id(String),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Num {
     DecNum(DecNum),
     HexNum(HexNum),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum DecNum {
+    // At most 2^31, which is only valid as the operand of unary minus
     DecNum(u32),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum HexNum {
     // implicit conversion from base-16 to decimal
     HexNum(u32),
 }
 
-#[derive(PartialEq, Debug)]
+// String and character literals hold their decoded value, with escape sequences already resolved
+#[derive(PartialEq, Debug, Clone)]
 pub enum StrLit {
     StringLiteral(String),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum ChrLit {
     CharacterLiteral(char),
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum LibLit {
     LibraryLiteral(String),
 }
 
-#[derive(PartialEq, Debug)]
-pub enum SChar {
-    Esc,
-    Nchar,
-}
-
-#[derive(PartialEq, Debug)]
-pub enum CChar {
-    Esc,
-    LChar,
-}
-
-// Any ASCII Char except "
-#[derive(PartialEq, Debug)]
-pub enum NChar {
-    NChar(char),
-}
-
-// Any ASCII Char except >
-#[derive(PartialEq, Debug)]
-pub enum LChar {
-    LChar(char),
-}
-
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Esc {
     Alert,          // " \a "
     Backspace,      // " \b "
@@ -119,7 +116,59 @@ pub enum Esc {
     DoubleQuote,    // " \" "
 }
 
-#[derive(PartialEq, Debug)]
+impl Esc {
+    // The escape sequence a backslash followed by `c` stands for, e.g. Newline for 'n'
+    pub fn from_char(c: char) -> Option<Esc> {
+        let esc = match c {
+            'a' => Esc::Alert,
+            'b' => Esc::Backspace,
+            'f' => Esc::FormfeedPgBrk,
+            'n' => Esc::Newline,
+            'r' => Esc::CarriageReturn,
+            't' => Esc::HorizontalTab,
+            'v' => Esc::VerticalTab,
+            '\\' => Esc::Backslash,
+            '\'' => Esc::Apostrophe,
+            '"' => Esc::DoubleQuote,
+            _ => return None,
+        };
+        Some(esc)
+    }
+
+    // The character the escape sequence decodes to
+    pub fn value(&self) -> char {
+        match self {
+            Esc::Alert => '\u{07}',
+            Esc::Backspace => '\u{08}',
+            Esc::FormfeedPgBrk => '\u{0C}',
+            Esc::Newline => '\n',
+            Esc::CarriageReturn => '\r',
+            Esc::HorizontalTab => '\t',
+            Esc::VerticalTab => '\u{0B}',
+            Esc::Backslash => '\\',
+            Esc::Apostrophe => '\'',
+            Esc::DoubleQuote => '"',
+        }
+    }
+}
+
+// "#use <lib>" pulls in a library header from the search path, "#use \"file.c0\"" another
+// source file, relative to the one being compiled
+#[derive(PartialEq, Debug, Clone)]
+pub enum Directive {
+    UseLib(LibLit),
+    UseFile(StrLit),
+}
+
+// Delimiters of the annotation comments that hold contracts
+#[derive(PartialEq, Debug, Clone)]
+pub enum Annot {
+    LineOpen,  // "//@"
+    BlockOpen, // "/*@"
+    Close,     // "@*/", or the newline ending a "//@" annotation
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum Sep {
     LParen,    // '('
     RParen,    // ')'
@@ -131,24 +180,20 @@ pub enum Sep {
     SemiColon, // ';'
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum UnOp {
     LogicalNOT, // '!'
     BitwiseNOT, // '~'
-    UnaryMinus, // '-'
-    Pointer,    // '*'
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum BinOp {
     CondEq,      // '?'
     FieldSelect, // '.'
     FieldDeref,  // "->"
-    IntTimes,    // '*'
     Divide,      // '/'
     Modulo,      // '%'
     Plus,        // '+'
-    Minus,       // '-'
     ShiftLeft,   // "<<"
     Less,        // '<'
     LessEq,      // "<="
@@ -165,7 +210,8 @@ pub enum BinOp {
     CondAsn,     // ":"
 }
 
-#[derive(PartialEq, Debug)]
+#[allow(clippy::enum_variant_names)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum AsnOp {
     EqAsn,     // '='
     IncAsn,    // "+="
@@ -180,8 +226,147 @@ pub enum AsnOp {
     ORAsn,     // "|="
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum PostOp {
     Inc, // "++"
     Dec, // "--"
 }
+
+// Tokens print as they would be written in C0 source, which is what diagnostics want to show
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Id(Id::Id(name)) => write!(f, "{}", name),
+            Token::Num(Num::DecNum(DecNum::DecNum(n))) => write!(f, "{}", n),
+            Token::Num(Num::HexNum(HexNum::HexNum(n))) => write!(f, "0x{:X}", n),
+            Token::StrLit(StrLit::StringLiteral(s)) => write!(f, "\"{}\"", s.escape_debug()),
+            Token::ChrLit(ChrLit::CharacterLiteral(c)) => write!(f, "'{}'", c.escape_debug()),
+            Token::Directive(Directive::UseLib(LibLit::LibraryLiteral(name))) => {
+                write!(f, "#use <{}>", name)
+            }
+            Token::Directive(Directive::UseFile(StrLit::StringLiteral(file))) => {
+                write!(f, "#use \"{}\"", file)
+            }
+            Token::Esc(esc) => {
+                let text = match esc {
+                    Esc::Alert => "\\a",
+                    Esc::Backspace => "\\b",
+                    Esc::FormfeedPgBrk => "\\f",
+                    Esc::Newline => "\\n",
+                    Esc::CarriageReturn => "\\r",
+                    Esc::HorizontalTab => "\\t",
+                    Esc::VerticalTab => "\\v",
+                    Esc::Backslash => "\\\\",
+                    Esc::Apostrophe => "\\'",
+                    Esc::DoubleQuote => "\\\"",
+                };
+                write!(f, "{}", text)
+            }
+            Token::Keyword(keyword) => {
+                let text = match keyword {
+                    Keyword::Int => "int",
+                    Keyword::Bool => "bool",
+                    Keyword::String => "string",
+                    Keyword::Char => "char",
+                    Keyword::Void => "void",
+                    Keyword::Struct => "struct",
+                    Keyword::Typedef => "typedef",
+                    Keyword::If => "if",
+                    Keyword::Else => "else",
+                    Keyword::While => "while",
+                    Keyword::For => "for",
+                    Keyword::Continue => "continue",
+                    Keyword::Break => "break",
+                    Keyword::Return => "return",
+                    Keyword::Assert => "assert",
+                    Keyword::Error => "error",
+                    Keyword::True => "true",
+                    Keyword::False => "false",
+                    Keyword::Null => "NULL",
+                    Keyword::Alloc => "alloc",
+                    Keyword::AllocArray => "alloc_array",
+                    Keyword::Requires => "requires",
+                    Keyword::Ensures => "ensures",
+                    Keyword::LoopInvariant => "loop_invariant",
+                    Keyword::AnnoAssert => "assert",
+                    Keyword::Result => "\\result",
+                    Keyword::Length => "\\length",
+                };
+                write!(f, "{}", text)
+            }
+            Token::Annot(annot) => {
+                let text = match annot {
+                    Annot::LineOpen => "//@",
+                    Annot::BlockOpen => "/*@",
+                    Annot::Close => "end of annotation",
+                };
+                write!(f, "{}", text)
+            }
+            Token::Sep(sep) => {
+                let text = match sep {
+                    Sep::LParen => "(",
+                    Sep::RParen => ")",
+                    Sep::LBracket => "[",
+                    Sep::RBracket => "]",
+                    Sep::LCurly => "{",
+                    Sep::RCurly => "}",
+                    Sep::Comma => ",",
+                    Sep::SemiColon => ";",
+                };
+                write!(f, "{}", text)
+            }
+            Token::Minus => write!(f, "-"),
+            Token::Star => write!(f, "*"),
+            Token::UnOp(unop) => {
+                let text = match unop {
+                    UnOp::LogicalNOT => "!",
+                    UnOp::BitwiseNOT => "~",
+                };
+                write!(f, "{}", text)
+            }
+            Token::BinOp(binop) => {
+                let text = match binop {
+                    BinOp::CondEq => "?",
+                    BinOp::FieldSelect => ".",
+                    BinOp::FieldDeref => "->",
+                    BinOp::Divide => "/",
+                    BinOp::Modulo => "%",
+                    BinOp::Plus => "+",
+                    BinOp::ShiftLeft => "<<",
+                    BinOp::Less => "<",
+                    BinOp::LessEq => "<=",
+                    BinOp::ShiftRight => ">>",
+                    BinOp::Greater => ">",
+                    BinOp::GreaterEq => ">=",
+                    BinOp::Equality => "==",
+                    BinOp::BitwiseAND => "&",
+                    BinOp::LogicalAND => "&&",
+                    BinOp::BitwiseXOR => "^",
+                    BinOp::Disequality => "!=",
+                    BinOp::BitwiseOR => "|",
+                    BinOp::LogicalOR => "||",
+                    BinOp::CondAsn => ":",
+                };
+                write!(f, "{}", text)
+            }
+            Token::AsnOp(asnop) => {
+                let text = match asnop {
+                    AsnOp::EqAsn => "=",
+                    AsnOp::IncAsn => "+=",
+                    AsnOp::DecAsn => "-=",
+                    AsnOp::MultAsn => "*=",
+                    AsnOp::DivAsn => "/=",
+                    AsnOp::ModAsn => "%=",
+                    AsnOp::LShiftAsn => "<<=",
+                    AsnOp::RShiftAsn => ">>=",
+                    AsnOp::ANDAsn => "&=",
+                    AsnOp::XORAsn => "^=",
+                    AsnOp::ORAsn => "|=",
+                };
+                write!(f, "{}", text)
+            }
+            Token::PostOp(PostOp::Inc) => write!(f, "++"),
+            Token::PostOp(PostOp::Dec) => write!(f, "--"),
+        }
+    }
+}
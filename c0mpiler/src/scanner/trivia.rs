@@ -0,0 +1,312 @@
+use crate::scanner::error::LexError;
+use crate::scanner::span::{Span, SpanTracker};
+use crate::scanner::token::SpannedToken;
+use crate::scanner::{next_index, scan, skip_block_comment, SourceChars};
+use std::fmt;
+
+// A lossless scan, for tools like formatters that have to give back the source they were handed.
+// Compiling throws whitespace and comments away, but here every bit of text between two tokens is
+// kept as trivia and attached to one of them, so that writing out each token's leading trivia, its
+// text and its trailing trivia, in order, rebuilds the source byte for byte
+//
+// A token's trailing trivia runs to the end of its line, newline included; everything after that
+// leads the next token. Whatever follows the last token's line is left over at the end
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace, // a run of blanks, ending at the first newline in it
+    LineComment,
+    BlockComment,
+    Skipped, // what the scanner passes over inside annotations, like the '@' starting their lines
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct TriviaToken {
+    pub token: SpannedToken,
+    // The token exactly as written
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Lossless {
+    pub tokens: Vec<TriviaToken>,
+    pub end: Vec<Trivia>,
+}
+
+// Writes the source back out
+impl fmt::Display for Lossless {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                write!(f, "{}", trivia.text)?;
+            }
+            write!(f, "{}", token.text)?;
+            for trivia in &token.trailing {
+                write!(f, "{}", trivia.text)?;
+            }
+        }
+        for trivia in &self.end {
+            write!(f, "{}", trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+impl Lossless {
+    // One line per token and per piece of trivia, in order, giving where it starts, what it is
+    // and its text
+    pub fn listing(&self) -> String {
+        let mut listing = String::new();
+        let entry = |listing: &mut String, what: &str, span: Span, text: &str| {
+            listing.push_str(&format!(
+                "{}:{} {} {:?}\n",
+                span.line, span.column, what, text
+            ));
+        };
+        let trivia = |listing: &mut String, place: &str, trivia: &[Trivia]| {
+            for piece in trivia {
+                let what = format!("{} {}", place, piece.kind.name());
+                entry(listing, &what, piece.span, &piece.text);
+            }
+        };
+        for token in &self.tokens {
+            trivia(&mut listing, "leading", &token.leading);
+            entry(&mut listing, "token", token.token.span, &token.text);
+            trivia(&mut listing, "trailing", &token.trailing);
+        }
+        trivia(&mut listing, "end", &self.end);
+        listing
+    }
+}
+
+impl TriviaKind {
+    fn name(&self) -> &'static str {
+        match self {
+            TriviaKind::Whitespace => "whitespace",
+            TriviaKind::LineComment => "line comment",
+            TriviaKind::BlockComment => "block comment",
+            TriviaKind::Skipped => "skipped",
+        }
+    }
+}
+
+// The same tokens as `scan`, or the same errors, with the trivia around them
+pub fn scan_trivia(source: String) -> Result<Lossless, Vec<LexError>> {
+    let spanned = scan(source.clone())?;
+    let mut tracker = SpanTracker::new();
+    let mut tokens: Vec<TriviaToken> = Vec::new();
+    let mut offset = 0;
+    for token in spanned {
+        let leading = attach(
+            tokens.last_mut(),
+            split(&source, &mut tracker, offset, token.span.offset),
+        );
+        offset = token.span.end();
+        tokens.push(TriviaToken {
+            text: source[token.span.offset..offset].to_string(),
+            token,
+            leading,
+            trailing: Vec::new(),
+        });
+    }
+    let end = attach(
+        tokens.last_mut(),
+        split(&source, &mut tracker, offset, source.len()),
+    );
+    Ok(Lossless { tokens, end })
+}
+
+// Gives the token before `trivia` its share of it, up to the end of its line, and returns the rest
+fn attach(before: Option<&mut TriviaToken>, trivia: Vec<Trivia>) -> Vec<Trivia> {
+    let Some(before) = before else {
+        return trivia;
+    };
+    let mut trivia = trivia.into_iter();
+    for piece in trivia.by_ref() {
+        let ends_line = piece.text.ends_with('\n');
+        before.trailing.push(piece);
+        if ends_line {
+            break;
+        }
+    }
+    trivia.collect()
+}
+
+fn whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n' | '\u{0B}' | '\u{0C}')
+}
+
+// Splits the text between two tokens into trivia. It only ever holds what the scanner skipped, so
+// any comment in it is closed
+fn split(source: &str, tracker: &mut SpanTracker, start: usize, end: usize) -> Vec<Trivia> {
+    let mut pieces = Vec::new();
+    let mut at = start;
+    while at < end {
+        let rest = &source[at..end];
+        let (kind, length) = if rest.starts_with("//") {
+            (
+                TriviaKind::LineComment,
+                rest.find('\n').unwrap_or(rest.len()),
+            )
+        } else if rest.starts_with("/*") {
+            let mut char_indices = SourceChars::new(source, at + 2);
+            skip_block_comment(&mut char_indices);
+            let length = next_index(&mut char_indices, source) - at;
+            (TriviaKind::BlockComment, length)
+        } else if rest.starts_with(whitespace) {
+            let blank = rest.find(|c| !whitespace(c)).unwrap_or(rest.len());
+            let length = match rest[..blank].find('\n') {
+                Some(newline) => newline + 1,
+                None => blank,
+            };
+            (TriviaKind::Whitespace, length)
+        } else {
+            let skipped = rest.chars().next().unwrap();
+            (TriviaKind::Skipped, skipped.len_utf8())
+        };
+        pieces.push(Trivia {
+            kind,
+            span: tracker.span(source, at, at + length),
+            text: rest[..length].to_string(),
+        });
+        at += length;
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::dfa::tests::{fuzz, CORPUS};
+    use crate::scanner::token::{Id, Sep, Token};
+
+    // Checks that the source comes back unchanged, with the same tokens as `scan`, and that every
+    // span points at its own text
+    fn round_trip(source: &str) {
+        let Ok(lossless) = scan_trivia(source.to_string()) else {
+            assert!(scan(source.to_string()).is_err());
+            return;
+        };
+        assert_eq!(lossless.to_string(), source);
+        let tokens: Vec<SpannedToken> = lossless
+            .tokens
+            .iter()
+            .map(|token| token.token.clone())
+            .collect();
+        assert_eq!(Ok(tokens), scan(source.to_string()));
+
+        let all_trivia = lossless
+            .tokens
+            .iter()
+            .flat_map(|token| token.leading.iter().chain(&token.trailing))
+            .chain(&lossless.end);
+        for trivia in all_trivia {
+            assert_eq!(&source[trivia.span.offset..trivia.span.end()], trivia.text);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for source in CORPUS {
+            round_trip(source);
+        }
+        round_trip("");
+        round_trip("  \n// only a comment\n");
+        round_trip("int main()\r\n{\r\n\treturn 0; /* done */\r\n}\r\n");
+        round_trip("/*@ requires x;\n  @ ensures y; @*/ //@ assert z; // why\n\x0b\x0c");
+        round_trip("x /* a /* nested */ comment */ y \\ z\n\n\n");
+        fuzz(round_trip);
+    }
+
+    #[test]
+    fn listing() {
+        let source = "/*@ requires x; @*/\nint y; // why\n";
+        let lossless = scan_trivia(source.to_string()).unwrap();
+        assert_eq!(
+            lossless.listing(),
+            "1:1 token \"/*@\"\n\
+             1:4 trailing whitespace \" \"\n\
+             1:5 token \"requires\"\n\
+             1:13 trailing whitespace \" \"\n\
+             1:14 token \"x\"\n\
+             1:15 token \";\"\n\
+             1:16 trailing whitespace \" \"\n\
+             1:17 token \"@*/\"\n\
+             1:20 trailing whitespace \"\\n\"\n\
+             2:1 token \"int\"\n\
+             2:4 trailing whitespace \" \"\n\
+             2:5 token \"y\"\n\
+             2:6 token \";\"\n\
+             2:7 trailing whitespace \" \"\n\
+             2:8 trailing line comment \"// why\"\n\
+             2:14 trailing whitespace \"\\n\"\n"
+        );
+    }
+
+    #[test]
+    fn attachment() {
+        let source = "int x; // count\n\n/* doc */\nint y;  ";
+        let lossless = scan_trivia(source.to_string()).unwrap();
+        let kinds = |trivia: &[Trivia]| -> Vec<(TriviaKind, String)> {
+            trivia
+                .iter()
+                .map(|trivia| (trivia.kind, trivia.text.clone()))
+                .collect()
+        };
+
+        let semicolon = &lossless.tokens[2];
+        assert_eq!(semicolon.token.token, Token::Sep(Sep::SemiColon));
+        assert_eq!(
+            kinds(&semicolon.trailing),
+            vec![
+                (TriviaKind::Whitespace, " ".to_string()),
+                (TriviaKind::LineComment, "// count".to_string()),
+                (TriviaKind::Whitespace, "\n".to_string()),
+            ]
+        );
+        let second = &lossless.tokens[3];
+        assert_eq!(
+            kinds(&second.leading),
+            vec![
+                (TriviaKind::Whitespace, "\n".to_string()),
+                (TriviaKind::BlockComment, "/* doc */".to_string()),
+                (TriviaKind::Whitespace, "\n".to_string()),
+            ]
+        );
+        assert_eq!(second.leading[1].span.line, 3);
+
+        // the last line's blanks stay with the last token, and nothing is left over
+        let last = lossless.tokens.last().unwrap();
+        assert_eq!(
+            kinds(&last.trailing),
+            vec![(TriviaKind::Whitespace, "  ".to_string())]
+        );
+        assert!(lossless.end.is_empty());
+
+        // annotations are tokens, but the marks inside them are trivia
+        let lossless = scan_trivia("/*@\n @ x; @*/\n\n".to_string()).unwrap();
+        let x = &lossless.tokens[1];
+        assert_eq!(x.token.token, Token::Id(Id::Id("x".to_string())));
+        assert_eq!(
+            kinds(&x.leading),
+            vec![
+                (TriviaKind::Whitespace, " ".to_string()),
+                (TriviaKind::Skipped, "@".to_string()),
+                (TriviaKind::Whitespace, " ".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds(&lossless.end),
+            vec![(TriviaKind::Whitespace, "\n".to_string())]
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use crate::ast::{
+    DeclKind, Expr, ExprKind, LValue, LValueKind, Program, Spec, SpecKind, Stmt, StmtKind, TypeKind,
+};
+use crate::scanner::span::Span;
+use crate::semantic::{SemanticError, SemanticErrorKind};
+use std::collections::{HashMap, HashSet};
+
+// Control-flow checks on the typechecked program: every variable has to be initialized before it
+// is used, and every function returning a value has to return one on every path
+//
+// Both come out of one forward dataflow analysis, run straight over the tree. At each point it
+// tracks the set of variables initialized on every path reaching that point, or that no path
+// reaches it at all, after a return, break, continue or error. Where paths meet, after an if or
+// at the end of a loop, the sets are intersected. A loop's condition isn't evaluated, so its body
+// may never run, and a function whose end can be reached doesn't always return
+pub fn check_flow(program: &Program) -> Result<(), Vec<SemanticError>> {
+    let mut flow = Flow::default();
+    for (index, decl) in program.decls.iter().enumerate() {
+        flow.decl = index;
+        if let DeclKind::Function {
+            ret,
+            name,
+            params,
+            specs,
+            body: Some(body),
+        } = &decl.kind
+        {
+            flow.state = Some(HashSet::new());
+            flow.declared.clear();
+            for param in params {
+                flow.declare(&param.name, param.span);
+                flow.define(&param.name);
+            }
+            for spec in specs {
+                flow.spec(spec);
+            }
+            flow.block(body);
+            if ret.kind != TypeKind::Void && flow.state.is_some() {
+                let kind = SemanticErrorKind::MissingReturn { name: name.clone() };
+                flow.error(kind, decl.span);
+            }
+        }
+    }
+    if flow.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(flow.errors)
+    }
+}
+
+// The variables initialized on every path to a point, or None if no path gets there
+type State = Option<HashSet<String>>;
+
+// Where two paths meet
+fn join(a: State, b: State) -> State {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+// The states that leave a loop other than through its condition
+#[derive(Default)]
+struct Exits {
+    breaks: State,
+    continues: State,
+}
+
+#[derive(Default)]
+struct Flow {
+    state: State,
+    // Where each variable in scope was declared. C0 doesn't allow shadowing, so names are enough
+    declared: HashMap<String, Span>,
+    loops: Vec<Exits>,
+    errors: Vec<SemanticError>,
+    decl: usize,
+}
+
+impl Flow {
+    fn error(&mut self, kind: SemanticErrorKind, span: Span) {
+        self.errors.push(SemanticError {
+            kind,
+            span,
+            decl: self.decl,
+        });
+    }
+
+    fn declare(&mut self, name: &str, span: Span) {
+        self.declared.insert(name.to_string(), span);
+    }
+
+    fn define(&mut self, name: &str) {
+        if let Some(state) = &mut self.state {
+            state.insert(name.to_string());
+        }
+    }
+
+    // Checks a use of a variable. Nothing is checked where nothing can be reached
+    fn use_(&mut self, name: &str, span: Span) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        if state.contains(name) {
+            return;
+        }
+        // Left to name resolution if it isn't declared
+        if let Some(&declared) = self.declared.get(name) {
+            let kind = SemanticErrorKind::Uninitialized {
+                name: name.to_string(),
+                declared,
+            };
+            self.error(kind, span);
+            // Once is enough
+            self.define(name);
+        }
+    }
+
+    // Runs `body` in a scope of its own, forgetting the variables declared in it once it's done
+    fn scoped(&mut self, body: impl FnOnce(&mut Flow)) {
+        let outer: HashSet<String> = self.declared.keys().cloned().collect();
+        body(self);
+        self.declared.retain(|name, _| outer.contains(name));
+        if let Some(state) = &mut self.state {
+            state.retain(|name| outer.contains(name));
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.scoped(|flow| {
+            for stmt in stmts {
+                flow.stmt(stmt);
+            }
+        });
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Decl { name, init, .. } => {
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+                self.declare(name, stmt.span);
+                if init.is_some() {
+                    self.define(name);
+                }
+            }
+            StmtKind::Assign { lhs, op, rhs } => {
+                self.expr(rhs);
+                match (&lhs.kind, op) {
+                    (LValueKind::Var(name), None) => self.define(name),
+                    _ => self.lvalue(lhs),
+                }
+            }
+            StmtKind::PostOp { lhs, .. } => self.lvalue(lhs),
+            StmtKind::Expr(e) | StmtKind::Assert(e) => self.expr(e),
+            StmtKind::Error(e) => {
+                self.expr(e);
+                self.state = None;
+            }
+            StmtKind::If { cond, then, els } => {
+                self.expr(cond);
+                let before = self.state.clone();
+                self.scoped(|flow| flow.stmt(then));
+                let after_then = std::mem::replace(&mut self.state, before);
+                if let Some(els) = els {
+                    self.scoped(|flow| flow.stmt(els));
+                }
+                self.state = join(after_then, self.state.take());
+            }
+            StmtKind::While {
+                cond,
+                invariants,
+                body,
+            } => {
+                for spec in invariants {
+                    self.spec(spec);
+                }
+                self.expr(cond);
+                self.loop_(|flow| flow.scoped(|flow| flow.stmt(body)), |_| {});
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                invariants,
+                body,
+            } => self.scoped(|flow| {
+                if let Some(init) = init {
+                    flow.stmt(init);
+                }
+                for spec in invariants {
+                    flow.spec(spec);
+                }
+                flow.expr(cond);
+                flow.loop_(
+                    |flow| flow.scoped(|flow| flow.stmt(body)),
+                    |flow| {
+                        if let Some(step) = step {
+                            flow.stmt(step);
+                        }
+                    },
+                );
+            }),
+            StmtKind::Return(e) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+                self.state = None;
+            }
+            StmtKind::Block(stmts) => self.block(stmts),
+            StmtKind::Break | StmtKind::Continue => {
+                let state = self.state.take();
+                let is_break = matches!(stmt.kind, StmtKind::Break);
+                match self.loops.last_mut() {
+                    Some(exits) if is_break => exits.breaks = join(exits.breaks.take(), state),
+                    Some(exits) => exits.continues = join(exits.continues.take(), state),
+                    None => {
+                        let keyword = if is_break { "break" } else { "continue" };
+                        self.error(SemanticErrorKind::OutsideLoop { keyword }, stmt.span);
+                    }
+                }
+            }
+            StmtKind::Annotation(specs) => {
+                for spec in specs {
+                    self.spec(spec);
+                }
+            }
+        }
+    }
+
+    // A loop whose condition has just been evaluated. The body runs from there, then the step
+    // from wherever it finished or continued. Every variable initialized in the body is declared
+    // in it, or was initialized before it on some path, so going round again doesn't initialize
+    // anything new: the state at the condition is the one it was entered with
+    fn loop_(&mut self, body: impl FnOnce(&mut Flow), step: impl FnOnce(&mut Flow)) {
+        let entry = self.state.clone();
+        self.loops.push(Exits::default());
+        body(self);
+        let exits = self.loops.pop().unwrap();
+        self.state = join(self.state.take(), exits.continues);
+        step(self);
+        self.state = join(entry, exits.breaks);
+    }
+
+    fn spec(&mut self, spec: &Spec) {
+        match &spec.kind {
+            SpecKind::Requires(e)
+            | SpecKind::Ensures(e)
+            | SpecKind::LoopInvariant(e)
+            | SpecKind::Assert(e) => self.expr(e),
+        }
+    }
+
+    fn lvalue(&mut self, lv: &LValue) {
+        match &lv.kind {
+            LValueKind::Var(name) => self.use_(name, lv.span),
+            LValueKind::Field { base, .. }
+            | LValueKind::Arrow { base, .. }
+            | LValueKind::Deref(base) => self.lvalue(base),
+            LValueKind::Index { base, index } => {
+                self.lvalue(base);
+                self.expr(index);
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Int(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Char(_)
+            | ExprKind::String(_)
+            | ExprKind::Null
+            | ExprKind::Result
+            | ExprKind::Alloc(_) => {}
+            ExprKind::Var(name) => self.use_(name, e.span),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::Ternary { cond, then, els } => {
+                self.expr(cond);
+                self.expr(then);
+                self.expr(els);
+            }
+            ExprKind::Call { args, .. } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Field { base, .. }
+            | ExprKind::Arrow { base, .. }
+            | ExprKind::Deref(base)
+            | ExprKind::Length(base) => self.expr(base),
+            ExprKind::Index { base, index } => {
+                self.expr(base);
+                self.expr(index);
+            }
+            ExprKind::AllocArray { len, .. } => self.expr(len),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn errors(source: &str) -> Vec<String> {
+        let program = parse(scan(source.to_string()).unwrap()).unwrap();
+        match check_flow(&program) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(SemanticError::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn initialization() {
+        assert_eq!(
+            errors("int f() {\n int x;\n int y = x + 1;\n x = y;\n return x + y; }"),
+            vec!["3:10: error: variable `x` may be used uninitialized, declared on line 2"]
+        );
+        // assigning initializes, but compound assignment and increments use the old value
+        assert_eq!(
+            errors("void f() {\n int x;\n x += 1;\n int y;\n y++;\n int z;\n z = 2;\n z++; }"),
+            vec![
+                "3:2: error: variable `x` may be used uninitialized, declared on line 2",
+                "5:2: error: variable `y` may be used uninitialized, declared on line 4"
+            ]
+        );
+        // storing through a variable uses it
+        assert_eq!(
+            errors("void f() {\n int[] a;\n a[0] = 1; }"),
+            vec!["3:2: error: variable `a` may be used uninitialized, declared on line 2"]
+        );
+    }
+
+    #[test]
+    fn branches() {
+        assert!(errors("int f(bool b) { int x; if (b) x = 1; else x = 2; return x; }").is_empty());
+        assert_eq!(
+            errors("int f(bool b) {\n int x;\n if (b) x = 1;\n return x; }"),
+            vec!["4:9: error: variable `x` may be used uninitialized, declared on line 2"]
+        );
+        // a branch that doesn't come back doesn't count
+        assert!(
+            errors("int f(bool b) { int x; if (b) x = 1; else error(\"no\"); return x; }")
+                .is_empty()
+        );
+        assert!(
+            errors("int f(bool b) { int x; if (b) return 0; else x = 1; return x; }").is_empty()
+        );
+        // nothing is reported where nothing can be reached
+        assert!(errors("int f() { int x; return 0; x++; }").is_empty());
+    }
+
+    #[test]
+    fn loops() {
+        // the body may never run
+        assert_eq!(
+            errors("int f(int n) {\n int x;\n while (n > 0) { x = n; n--; }\n return x; }"),
+            vec!["4:9: error: variable `x` may be used uninitialized, declared on line 2"]
+        );
+        assert_eq!(
+            errors("int f(int n) {\n int x;\n for (int i = 0; i < n; i++) x = i;\n return x; }"),
+            vec!["4:9: error: variable `x` may be used uninitialized, declared on line 2"]
+        );
+        // the step runs after a continue, so it sees what's initialized on every way round
+        assert_eq!(
+            errors(
+                "void f(bool b) {\n int x;\n for (int i = 0; i < 3; x++) {\n if (b) continue;\n x = 1;\n }\n }"
+            ),
+            vec!["3:25: error: variable `x` may be used uninitialized, declared on line 2"]
+        );
+        assert!(errors(
+            "void f(bool b) { int x; for (int i = 0; i < 3; x++) { x = 1; if (b) continue; } }"
+        )
+        .is_empty());
+        // a variable declared in the loop is initialized from scratch every time round
+        assert_eq!(
+            errors("void f() {\n while (true) {\n int y;\n if (true) break;\n y = 1;\n }\n }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            errors("void f() {\n break;\n while (true) { continue; }\n }"),
+            vec!["2:2: error: `break` outside of a loop"]
+        );
+    }
+
+    #[test]
+    fn returns() {
+        assert!(errors("int f(bool b) { if (b) return 1; else return 2; }").is_empty());
+        assert!(errors("int f() { error(\"unreachable\"); }").is_empty());
+        assert!(errors("void f(bool b) { if (b) return; }").is_empty());
+        assert_eq!(
+            errors("int f(bool b) {\n if (b) return 1;\n }"),
+            vec!["1:1: error: function `f` does not return a value on every path"]
+        );
+        // a loop may always be left through its condition
+        assert_eq!(
+            errors("int f() {\n while (true) { return 1; }\n }"),
+            vec!["1:1: error: function `f` does not return a value on every path"]
+        );
+        assert_eq!(
+            errors("int f(int n) {\n for (int i = 0; i < n; i++) { if (i == 3) break; }\n }"),
+            vec!["1:1: error: function `f` does not return a value on every path"]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::ast::Ty;
+use crate::scanner::span::Span;
+use crate::semantic::SemanticErrorKind;
+use std::collections::HashMap;
+
+// Memory layout of structs, for code generation: the size and alignment of every type, and where
+// each field of a struct sits in it
+//
+// Scalars follow the x86-64 System V ABI: ints take 4 bytes, bools and chars 1, and pointers,
+// arrays and strings, which are all pointers underneath, 8. A struct's fields are laid out in
+// order, each at the next offset aligned for it, and the struct is aligned like its most aligned
+// field and padded to a multiple of that
+//
+// Structs are laid out as they're defined, which is when the typechecker comes across them, and a
+// struct has to be defined before its size or its fields are needed
+#[derive(PartialEq, Debug, Clone)]
+pub struct FieldLayout {
+    pub name: String,
+    pub ty: Ty,
+    pub offset: usize,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct StructLayout {
+    pub size: usize,
+    pub align: usize,
+    pub fields: Vec<FieldLayout>,
+}
+
+impl StructLayout {
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+#[derive(PartialEq, Debug, Default)]
+pub struct Layouts {
+    structs: HashMap<String, StructLayout>,
+}
+
+fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+impl Layouts {
+    pub fn get(&self, name: &str) -> Option<&StructLayout> {
+        self.structs.get(name)
+    }
+
+    // The size of a value of type `ty`, or None for void and for structs not yet defined
+    pub fn size(&self, ty: &Ty) -> Option<usize> {
+        match ty {
+            Ty::Int => Some(4),
+            Ty::Bool | Ty::Char => Some(1),
+            Ty::String | Ty::Pointer(_) | Ty::Array(_) | Ty::Null => Some(8),
+            Ty::Struct(name) => self.get(name).map(|layout| layout.size),
+            Ty::Void => None,
+        }
+    }
+
+    pub fn align(&self, ty: &Ty) -> Option<usize> {
+        match ty {
+            Ty::Struct(name) => self.get(name).map(|layout| layout.align),
+            _ => self.size(ty),
+        }
+    }
+
+    // Lays out a struct from its fields' types, in the order they're written. A field whose size
+    // isn't known yet is an error, and is left out. In C0 a struct can only hold another by value
+    // once that one is defined, so a struct containing itself through others always shows up as
+    // one of them not being defined yet; only a struct holding itself directly is called out
+    pub fn define(
+        &mut self,
+        name: &str,
+        fields: &[(String, Ty, Span)],
+    ) -> Vec<(SemanticErrorKind, Span)> {
+        let mut errors = Vec::new();
+        let mut laid_out = Vec::new();
+        let mut offset = 0;
+        let mut struct_align = 1;
+        for (field, ty, span) in fields {
+            let (Some(size), Some(align)) = (self.size(ty), self.align(ty)) else {
+                let kind = match ty {
+                    Ty::Struct(inner) if inner == name => SemanticErrorKind::RecursiveStruct {
+                        name: name.to_string(),
+                    },
+                    Ty::Struct(inner) => SemanticErrorKind::UndefinedStruct {
+                        name: inner.clone(),
+                    },
+                    _ => SemanticErrorKind::VoidType,
+                };
+                errors.push((kind, *span));
+                continue;
+            };
+            offset = align_to(offset, align);
+            laid_out.push(FieldLayout {
+                name: field.clone(),
+                ty: ty.clone(),
+                offset,
+            });
+            offset += size;
+            struct_align = struct_align.max(align);
+        }
+        let layout = StructLayout {
+            size: align_to(offset, struct_align),
+            align: struct_align,
+            fields: laid_out,
+        };
+        self.structs.insert(name.to_string(), layout);
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+    use crate::semantic::typecheck::typecheck;
+    use crate::semantic::SemanticError;
+
+    fn layouts(source: &str) -> Result<Layouts, Vec<SemanticError>> {
+        let mut program = parse(scan(source.to_string()).unwrap()).unwrap();
+        typecheck(&mut program)
+    }
+
+    // Each field's name and offset, then the struct's size and alignment
+    fn shape(layouts: &Layouts, name: &str) -> (Vec<(String, usize)>, usize, usize) {
+        let layout = layouts.get(name).unwrap();
+        let fields = layout
+            .fields
+            .iter()
+            .map(|field| (field.name.clone(), field.offset))
+            .collect();
+        (fields, layout.size, layout.align)
+    }
+
+    fn fields(fields: &[(&str, usize)]) -> Vec<(String, usize)> {
+        fields
+            .iter()
+            .map(|(name, offset)| (name.to_string(), *offset))
+            .collect()
+    }
+
+    #[test]
+    fn offsets() {
+        let source = "struct small { char c; bool b; };\n\
+                      struct mixed { char c; int x; bool b; int* p; string s; int[] a; };\n\
+                      struct outer { bool flag; struct small s; int n; struct mixed m; char end; };\n\
+                      struct empty {};";
+        let layouts = layouts(source).unwrap();
+        assert_eq!(
+            shape(&layouts, "small"),
+            (fields(&[("c", 0), ("b", 1)]), 2, 1)
+        );
+        assert_eq!(
+            shape(&layouts, "mixed"),
+            (
+                fields(&[
+                    ("c", 0),
+                    ("x", 4),
+                    ("b", 8),
+                    ("p", 16),
+                    ("s", 24),
+                    ("a", 32)
+                ]),
+                40,
+                8
+            )
+        );
+        // a struct held by value is aligned like its most aligned field, and the whole thing
+        // padded to a multiple of its alignment
+        assert_eq!(
+            shape(&layouts, "outer"),
+            (
+                fields(&[("flag", 0), ("s", 1), ("n", 4), ("m", 8), ("end", 48)]),
+                56,
+                8
+            )
+        );
+        assert_eq!(shape(&layouts, "empty"), (Vec::new(), 0, 1));
+
+        let ty = Ty::Struct("outer".to_string());
+        assert_eq!(layouts.size(&ty), Some(56));
+        assert_eq!(layouts.size(&Ty::Array(Box::new(ty))), Some(8));
+        assert_eq!(layouts.size(&Ty::Struct("missing".to_string())), None);
+    }
+
+    #[test]
+    fn recursion() {
+        // fine behind a pointer
+        let layouts_ok = layouts("struct list { int head; struct list* tail; };").unwrap();
+        assert_eq!(
+            shape(&layouts_ok, "list"),
+            (fields(&[("head", 0), ("tail", 8)]), 16, 8)
+        );
+
+        let errors = |source: &str| -> Vec<String> {
+            layouts(source)
+                .unwrap_err()
+                .iter()
+                .map(SemanticError::to_string)
+                .collect()
+        };
+        assert_eq!(
+            errors("struct s {\n int x;\n struct s inner;\n };"),
+            vec!["3:2: error: struct `s` contains itself, and would have no end; use a pointer"]
+        );
+        assert_eq!(
+            errors("struct a;\n struct b { struct a x; };\n struct a { struct b y; };"),
+            vec!["2:13: error: struct `a` is not defined"]
+        );
+        // and its size is needed by alloc and field accesses
+        assert_eq!(
+            errors("struct s;\n int f(struct s* p) { return p->x; }"),
+            vec!["2:30: error: struct `s` is not defined"]
+        );
+    }
+}
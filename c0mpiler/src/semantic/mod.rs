@@ -0,0 +1,262 @@
+pub mod flow;
+pub mod layout;
+pub mod resolve;
+pub mod typecheck;
+
+use crate::ast::Ty;
+use crate::scanner::span::Span;
+use std::fmt;
+
+// Semantic analysis, the passes between parsing and code generation. Each one walks the whole
+// program and collects every problem it finds, rather than stopping at the first
+//
+// Diagnostics point at source positions, but a program can be made of several files, so each also
+// records which top-level declaration it was found in. The driver knows which file that came from
+
+// The kinds of names C0 keeps apart: a struct, a field, a function and a variable can all be
+// called the same
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Namespace {
+    Variable,
+    Function,
+    Type, // names introduced by typedef
+    Struct,
+    Field,
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Namespace::Variable => "variable",
+            Namespace::Function => "function",
+            Namespace::Type => "type",
+            Namespace::Struct => "struct",
+            Namespace::Field => "field",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SemanticError {
+    pub kind: SemanticErrorKind,
+    pub span: Span,
+    // Index into the program's declarations
+    pub decl: usize,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum SemanticErrorKind {
+    Undeclared {
+        namespace: Namespace,
+        name: String,
+    },
+    // A function called above its first declaration
+    UsedBeforeDeclaration {
+        name: String,
+        declared: Span,
+    },
+    // Declared twice in the same scope, or a local variable shadowing another one, which C0 doesn't
+    // allow. Functions and structs may be declared any number of times, but only defined once
+    Redeclared {
+        namespace: Namespace,
+        name: String,
+        previous: Span,
+    },
+    // A variable named like a type: "t * x;" would be ambiguous
+    TypeNameAsVariable {
+        name: String,
+    },
+    Mismatch {
+        expected: Ty,
+        found: Ty,
+    },
+    // Where only some kind of type will do, e.g. "a pointer" for a dereference
+    WrongKind {
+        expected: &'static str,
+        found: Ty,
+    },
+    // Only small types, which fit in a register, can be held in variables, passed around and
+    // returned. Structs are large and have to live on the heap
+    LargeType {
+        ty: Ty,
+    },
+    // void anywhere but as a function's return type
+    VoidType,
+    // The fields of a struct, and its size, are only known once it is defined
+    UndefinedStruct {
+        name: String,
+    },
+    // A struct holding itself by value
+    RecursiveStruct {
+        name: String,
+    },
+    NoField {
+        strukt: String,
+        field: String,
+    },
+    // Equality on strings or structs
+    NotComparable {
+        ty: Ty,
+    },
+    NullDereference,
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    // A function the program declares and calls, but that neither it nor a library defines
+    UndefinedFunction {
+        name: String,
+    },
+    // A function declared twice with different types
+    ConflictingTypes {
+        name: String,
+        previous: Span,
+    },
+    // main is called with nothing, and what it returns is printed as an int
+    MainSignature,
+    ResultOutsideEnsures,
+    // \result in a function returning void, where there is nothing for it to stand for
+    ResultOfVoid,
+    // A use of a variable that isn't initialized on every path leading to it
+    Uninitialized {
+        name: String,
+        declared: Span,
+    },
+    // A function returning a value whose end can be reached
+    MissingReturn {
+        name: String,
+    },
+    OutsideLoop {
+        keyword: &'static str,
+    },
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: error: ", self.span.line, self.span.column)?;
+        match &self.kind {
+            SemanticErrorKind::Undeclared { namespace, name } => {
+                write!(f, "undeclared {} `{}`", namespace, name)
+            }
+            SemanticErrorKind::UsedBeforeDeclaration { name, declared } => write!(
+                f,
+                "function `{}` is used before its declaration on line {}",
+                name, declared.line
+            ),
+            SemanticErrorKind::Redeclared {
+                namespace,
+                name,
+                previous,
+            } => {
+                let already = match namespace {
+                    Namespace::Function | Namespace::Struct => "defined",
+                    _ => "declared",
+                };
+                write!(
+                    f,
+                    "{} `{}` is already {} on line {}",
+                    namespace, name, already, previous.line
+                )
+            }
+            SemanticErrorKind::TypeNameAsVariable { name } => {
+                write!(f, "`{}` is a type and cannot name a variable", name)
+            }
+            SemanticErrorKind::Mismatch { expected, found } => {
+                write!(f, "expected `{}`, found `{}`", expected, found)
+            }
+            SemanticErrorKind::WrongKind { expected, found } => {
+                write!(f, "expected {}, found `{}`", expected, found)
+            }
+            SemanticErrorKind::LargeType { ty } => {
+                write!(f, "`{}` is a large type and cannot be used as a value", ty)
+            }
+            SemanticErrorKind::VoidType => {
+                write!(f, "`void` can only be the return type of a function")
+            }
+            SemanticErrorKind::UndefinedStruct { name } => {
+                write!(f, "struct `{}` is not defined", name)
+            }
+            SemanticErrorKind::RecursiveStruct { name } => write!(
+                f,
+                "struct `{}` contains itself, and would have no end; use a pointer",
+                name
+            ),
+            SemanticErrorKind::NoField { strukt, field } => {
+                write!(f, "struct `{}` has no field `{}`", strukt, field)
+            }
+            SemanticErrorKind::NotComparable { ty } => {
+                write!(f, "values of type `{}` cannot be compared", ty)
+            }
+            SemanticErrorKind::NullDereference => write!(f, "cannot dereference NULL"),
+            SemanticErrorKind::ArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function `{}` takes {} argument{} but {} {} given",
+                name,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found,
+                if *found == 1 { "was" } else { "were" }
+            ),
+            SemanticErrorKind::UndefinedFunction { name } => {
+                write!(f, "function `{}` is used but never defined", name)
+            }
+            SemanticErrorKind::ConflictingTypes { name, previous } => write!(
+                f,
+                "function `{}` does not match its declaration on line {}",
+                name, previous.line
+            ),
+            SemanticErrorKind::MainSignature => {
+                write!(f, "`main` must be declared as `int main()`")
+            }
+            SemanticErrorKind::ResultOutsideEnsures => {
+                write!(f, "`\\result` can only be used in @ensures")
+            }
+            SemanticErrorKind::ResultOfVoid => {
+                write!(
+                    f,
+                    "`\\result` cannot be used in a function returning `void`"
+                )
+            }
+            SemanticErrorKind::Uninitialized { name, declared } => write!(
+                f,
+                "variable `{}` may be used uninitialized, declared on line {}",
+                name, declared.line
+            ),
+            SemanticErrorKind::MissingReturn { name } => {
+                write!(
+                    f,
+                    "function `{}` does not return a value on every path",
+                    name
+                )
+            }
+            SemanticErrorKind::OutsideLoop { keyword } => {
+                write!(f, "`{}` outside of a loop", keyword)
+            }
+        }
+    }
+}
+
+// Types as they're written in C0, with NULL's as "void*"
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ty::Int => write!(f, "int"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Char => write!(f, "char"),
+            Ty::String => write!(f, "string"),
+            Ty::Void => write!(f, "void"),
+            Ty::Pointer(inner) => write!(f, "{}*", inner),
+            Ty::Array(inner) => write!(f, "{}[]", inner),
+            Ty::Struct(name) => write!(f, "struct {}", name),
+            Ty::Null => write!(f, "void*"),
+        }
+    }
+}
+
+impl std::error::Error for SemanticError {}
@@ -0,0 +1,655 @@
+use crate::ast::{
+    Decl, DeclKind, Expr, ExprKind, LValue, LValueKind, Program, Spec, SpecKind, Stmt, StmtKind,
+    Type, TypeKind,
+};
+use crate::scanner::span::Span;
+use crate::semantic::{Namespace, SemanticError, SemanticErrorKind};
+use std::collections::{HashMap, HashSet};
+
+// Name resolution: ties every name used in the program to the declaration it refers to
+//
+// Typedefs, structs and functions are global, and have to be declared before they're used.
+// Variables are scoped to the block they're declared in, from their declaration on, and C0 doesn't
+// let one shadow another: a name can only be reused once the variable it named is out of scope.
+// Which struct a field belongs to depends on the type of the expression it's taken from, so only
+// duplicate fields are caught here, and field accesses are left to the typechecker
+
+// A use of a name, and where the thing it names was declared. For a struct that is its definition
+// if it has one
+#[derive(PartialEq, Debug, Clone)]
+pub struct Binding {
+    pub namespace: Namespace,
+    pub name: String,
+    pub used: Span,
+    pub declared: Span,
+}
+
+// Every binding in the program, in the order the uses appear
+#[derive(PartialEq, Debug, Default)]
+pub struct Resolution {
+    pub bindings: Vec<Binding>,
+}
+
+pub fn resolve(program: &Program) -> Result<Resolution, Vec<SemanticError>> {
+    let mut resolver = Resolver::default();
+    // Every function declaration, so that a call above the first one can say where it is
+    for decl in &program.decls {
+        if let DeclKind::Function { name, .. } = &decl.kind {
+            resolver.later.entry(name.clone()).or_insert(decl.span);
+        }
+    }
+    for (index, decl) in program.decls.iter().enumerate() {
+        resolver.decl = index;
+        resolver.decl(decl);
+    }
+    if resolver.errors.is_empty() {
+        Ok(Resolution {
+            bindings: resolver.bindings,
+        })
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+// Functions declared in a library's header are defined by the library, but any other function
+// that's used has to be defined in the program. `headers` says which declarations came from one,
+// and an undefined function is reported at its first declaration
+pub fn check_defined(
+    program: &Program,
+    headers: &[bool],
+    resolution: &Resolution,
+) -> Result<(), Vec<SemanticError>> {
+    let mut checked = HashSet::new();
+    let mut errors = Vec::new();
+    for binding in &resolution.bindings {
+        if binding.namespace != Namespace::Function || !checked.insert(binding.name.as_str()) {
+            continue;
+        }
+        let mut declarations = program.decls.iter().enumerate().filter(|(_, decl)| {
+            matches!(&decl.kind, DeclKind::Function { name, .. } if *name == binding.name)
+        });
+        let provided = declarations.clone().any(|(index, decl)| {
+            headers[index] || matches!(&decl.kind, DeclKind::Function { body: Some(_), .. })
+        });
+        if let (false, Some((index, decl))) = (provided, declarations.next()) {
+            errors.push(SemanticError {
+                kind: SemanticErrorKind::UndefinedFunction {
+                    name: binding.name.clone(),
+                },
+                span: decl.span,
+                decl: index,
+            });
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[derive(Default)]
+struct Resolver {
+    typedefs: HashMap<String, Span>,
+    // Structs and functions, from their first declaration, and once they're defined, from that
+    structs: HashMap<String, Span>,
+    defined_structs: HashMap<String, Span>,
+    functions: HashMap<String, Span>,
+    defined_functions: HashMap<String, Span>,
+    later: HashMap<String, Span>,
+    // The variables of the function being resolved, innermost block last
+    scopes: Vec<HashMap<String, Span>>,
+    bindings: Vec<Binding>,
+    errors: Vec<SemanticError>,
+    decl: usize,
+}
+
+impl Resolver {
+    fn error(&mut self, kind: SemanticErrorKind, span: Span) {
+        self.errors.push(SemanticError {
+            kind,
+            span,
+            decl: self.decl,
+        });
+    }
+
+    fn bind(&mut self, namespace: Namespace, name: &str, used: Span, declared: Span) {
+        self.bindings.push(Binding {
+            namespace,
+            name: name.to_string(),
+            used,
+            declared,
+        });
+    }
+
+    fn redeclared(&mut self, namespace: Namespace, name: &str, previous: Span, span: Span) {
+        let kind = SemanticErrorKind::Redeclared {
+            namespace,
+            name: name.to_string(),
+            previous,
+        };
+        self.error(kind, span);
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match &decl.kind {
+            DeclKind::Typedef { ty, name } => {
+                self.type_(ty);
+                match self.typedefs.get(name) {
+                    Some(&previous) => self.redeclared(Namespace::Type, name, previous, decl.span),
+                    None => {
+                        self.typedefs.insert(name.clone(), decl.span);
+                    }
+                }
+            }
+            DeclKind::StructDecl { name } => {
+                self.structs.entry(name.clone()).or_insert(decl.span);
+            }
+            DeclKind::StructDef { name, fields } => {
+                if let Some(&previous) = self.defined_structs.get(name) {
+                    self.redeclared(Namespace::Struct, name, previous, decl.span);
+                }
+                self.defined_structs.insert(name.clone(), decl.span);
+                self.structs.insert(name.clone(), decl.span);
+                let mut seen: HashMap<&str, Span> = HashMap::new();
+                for field in fields {
+                    self.type_(&field.ty);
+                    match seen.get(field.name.as_str()) {
+                        Some(&previous) => {
+                            self.redeclared(Namespace::Field, &field.name, previous, field.span)
+                        }
+                        None => {
+                            seen.insert(&field.name, field.span);
+                        }
+                    }
+                }
+            }
+            DeclKind::Function {
+                ret,
+                name,
+                params,
+                specs,
+                body,
+            } => {
+                self.type_(ret);
+                for param in params {
+                    self.type_(&param.ty);
+                }
+                // Declared from its own header on, so that it can call itself
+                if body.is_some() {
+                    if let Some(&previous) = self.defined_functions.get(name) {
+                        self.redeclared(Namespace::Function, name, previous, decl.span);
+                    }
+                    self.defined_functions.insert(name.clone(), decl.span);
+                    self.functions.insert(name.clone(), decl.span);
+                } else {
+                    self.functions.entry(name.clone()).or_insert(decl.span);
+                }
+
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    self.declare(&param.name, param.span);
+                }
+                for spec in specs {
+                    self.spec(spec);
+                }
+                if let Some(body) = body {
+                    self.block(body);
+                }
+                self.scopes.pop();
+            }
+        }
+    }
+
+    fn type_(&mut self, ty: &Type) {
+        match &ty.kind {
+            TypeKind::Pointer(inner) | TypeKind::Array(inner) => self.type_(inner),
+            // The parser only takes a name for a type once it has seen the typedef
+            TypeKind::Name(name) => {
+                if let Some(&declared) = self.typedefs.get(name) {
+                    self.bind(Namespace::Type, name, ty.span, declared);
+                }
+            }
+            // C0 lets a pointer to a struct be used before the struct is declared at all
+            TypeKind::Struct(name) => {
+                if let Some(&declared) = self.structs.get(name) {
+                    self.bind(Namespace::Struct, name, ty.span, declared);
+                }
+            }
+            TypeKind::Int | TypeKind::Bool | TypeKind::Char | TypeKind::String | TypeKind::Void => {
+            }
+        }
+    }
+
+    // Adds a variable to the innermost scope
+    fn declare(&mut self, name: &str, span: Span) {
+        if self.typedefs.contains_key(name) {
+            let kind = SemanticErrorKind::TypeNameAsVariable {
+                name: name.to_string(),
+            };
+            self.error(kind, span);
+        }
+        match self.lookup(name) {
+            Some(previous) => self.redeclared(Namespace::Variable, name, previous, span),
+            None => {
+                let scope = self.scopes.last_mut().unwrap();
+                scope.insert(name.to_string(), span);
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Span> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    // A statement that isn't a block still gets a scope of its own, so that e.g. a declaration as
+    // the body of an if doesn't outlive it
+    fn scoped(&mut self, stmt: &Stmt) {
+        self.block(std::slice::from_ref(stmt));
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Decl { ty, name, init } => {
+                self.type_(ty);
+                // In scope from the end of the declaration, so not in its own initializer
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+                self.declare(name, stmt.span);
+            }
+            StmtKind::Assign { lhs, rhs, .. } => {
+                self.lvalue(lhs);
+                self.expr(rhs);
+            }
+            StmtKind::PostOp { lhs, .. } => self.lvalue(lhs),
+            StmtKind::Expr(e) | StmtKind::Assert(e) | StmtKind::Error(e) => self.expr(e),
+            StmtKind::If { cond, then, els } => {
+                self.expr(cond);
+                self.scoped(then);
+                if let Some(els) = els {
+                    self.scoped(els);
+                }
+            }
+            StmtKind::While {
+                cond,
+                invariants,
+                body,
+            } => {
+                self.expr(cond);
+                for spec in invariants {
+                    self.spec(spec);
+                }
+                self.scoped(body);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                invariants,
+                body,
+            } => {
+                // A variable declared in the header is in scope for the rest of the loop
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                self.expr(cond);
+                if let Some(step) = step {
+                    self.stmt(step);
+                }
+                for spec in invariants {
+                    self.spec(spec);
+                }
+                self.scoped(body);
+                self.scopes.pop();
+            }
+            StmtKind::Return(e) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+            }
+            StmtKind::Block(stmts) => self.block(stmts),
+            StmtKind::Annotation(specs) => {
+                for spec in specs {
+                    self.spec(spec);
+                }
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    fn spec(&mut self, spec: &Spec) {
+        match &spec.kind {
+            SpecKind::Requires(e)
+            | SpecKind::Ensures(e)
+            | SpecKind::LoopInvariant(e)
+            | SpecKind::Assert(e) => self.expr(e),
+        }
+    }
+
+    fn variable(&mut self, name: &str, span: Span) {
+        match self.lookup(name) {
+            Some(declared) => self.bind(Namespace::Variable, name, span, declared),
+            None => {
+                let kind = SemanticErrorKind::Undeclared {
+                    namespace: Namespace::Variable,
+                    name: name.to_string(),
+                };
+                self.error(kind, span);
+            }
+        }
+    }
+
+    fn lvalue(&mut self, lv: &LValue) {
+        match &lv.kind {
+            LValueKind::Var(name) => self.variable(name, lv.span),
+            LValueKind::Field { base, .. }
+            | LValueKind::Arrow { base, .. }
+            | LValueKind::Deref(base) => self.lvalue(base),
+            LValueKind::Index { base, index } => {
+                self.lvalue(base);
+                self.expr(index);
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Int(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Char(_)
+            | ExprKind::String(_)
+            | ExprKind::Null
+            | ExprKind::Result => {}
+            ExprKind::Var(name) => self.variable(name, e.span),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::Ternary { cond, then, els } => {
+                self.expr(cond);
+                self.expr(then);
+                self.expr(els);
+            }
+            ExprKind::Call { name, args } => {
+                match (self.functions.get(name), self.later.get(name)) {
+                    (Some(&declared), _) => self.bind(Namespace::Function, name, e.span, declared),
+                    (None, Some(&declared)) => {
+                        let kind = SemanticErrorKind::UsedBeforeDeclaration {
+                            name: name.clone(),
+                            declared,
+                        };
+                        self.error(kind, e.span);
+                    }
+                    (None, None) => {
+                        let kind = SemanticErrorKind::Undeclared {
+                            namespace: Namespace::Function,
+                            name: name.clone(),
+                        };
+                        self.error(kind, e.span);
+                    }
+                }
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Field { base, .. }
+            | ExprKind::Arrow { base, .. }
+            | ExprKind::Deref(base)
+            | ExprKind::Length(base) => self.expr(base),
+            ExprKind::Index { base, index } => {
+                self.expr(base);
+                self.expr(index);
+            }
+            ExprKind::Alloc(ty) => self.type_(ty),
+            ExprKind::AllocArray { ty, len } => {
+                self.type_(ty);
+                self.expr(len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn resolve_str(source: &str) -> Result<Resolution, Vec<SemanticError>> {
+        resolve(&parse(scan(source.to_string()).unwrap()).unwrap())
+    }
+
+    // Each error's kind, with the line and column it points at
+    fn errors(source: &str) -> Vec<(SemanticErrorKind, (usize, usize))> {
+        resolve_str(source)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| (error.kind, (error.span.line, error.span.column)))
+            .collect()
+    }
+
+    fn undeclared(namespace: Namespace, name: &str) -> SemanticErrorKind {
+        SemanticErrorKind::Undeclared {
+            namespace,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn bindings() {
+        let source = "typedef int num;\n\
+                      struct point { num x; num y; };\n\
+                      int twice(int n);\n\
+                      int twice(int n) { return 2 * n; }\n\
+                      int main() {\n\
+                        num total = 0;\n\
+                        for (int i = 0; i < 3; i++) total += twice(i);\n\
+                        struct point* p = alloc(struct point);\n\
+                        return total;\n\
+                      }";
+        let resolution = resolve_str(source).unwrap();
+        let lines: Vec<(Namespace, &str, usize, usize)> = resolution
+            .bindings
+            .iter()
+            .map(|binding| {
+                (
+                    binding.namespace,
+                    binding.name.as_str(),
+                    binding.used.line,
+                    binding.declared.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Namespace::Type, "num", 2, 1),
+                (Namespace::Type, "num", 2, 1),
+                (Namespace::Variable, "n", 4, 4),
+                (Namespace::Type, "num", 6, 1),
+                (Namespace::Variable, "i", 7, 7),
+                (Namespace::Variable, "i", 7, 7),
+                (Namespace::Variable, "total", 7, 6),
+                // the definition, not the prototype before it
+                (Namespace::Function, "twice", 7, 4),
+                (Namespace::Variable, "i", 7, 7),
+                (Namespace::Struct, "point", 8, 2),
+                (Namespace::Struct, "point", 8, 2),
+                (Namespace::Variable, "total", 9, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn scopes() {
+        // a name can be reused once the variable it named is out of scope
+        assert!(resolve_str(
+            "void f() { { int x = 1; } { int x = 2; } for (int i = 0; i < 1; i++) {} int i = 0; }"
+        )
+        .is_ok());
+        // but not while it's in scope, shadowing included
+        let redeclared = |name: &str, errors: Vec<(SemanticErrorKind, (usize, usize))>| {
+            assert!(
+                matches!(&errors[..], [(SemanticErrorKind::Redeclared { namespace: Namespace::Variable, name: n, .. }, _)] if n == name),
+                "{:?}",
+                errors
+            );
+        };
+        redeclared("x", errors("void f(int x) { int x = 1; }"));
+        redeclared("x", errors("void f() { int x; if (true) { int x; } }"));
+        redeclared(
+            "i",
+            errors("void f() { for (int i = 0; i < 1; i++) { int i; } }"),
+        );
+        redeclared("x", errors("void f(int x, int x) {}"));
+
+        // out of scope after its block, and not in scope in its own initializer
+        assert_eq!(
+            errors("int f() { if (true) { int y = 1; } return y; }"),
+            vec![(undeclared(Namespace::Variable, "y"), (1, 43))]
+        );
+        assert_eq!(
+            errors("void f() {\n int x = x + 1;\n }"),
+            vec![(undeclared(Namespace::Variable, "x"), (2, 10))]
+        );
+        // a declaration as the body of an if is only in scope there
+        assert_eq!(
+            errors("int f() { if (true) int z = 1; return z; }"),
+            vec![(undeclared(Namespace::Variable, "z"), (1, 39))]
+        );
+        // contracts see the parameters
+        assert!(resolve_str(
+            "int f(int n)\n //@requires n >= 0;\n //@ensures \\result == n;\n { return n; }"
+        )
+        .is_ok());
+        assert_eq!(
+            errors("int f(int n)\n //@requires m >= 0;\n ;"),
+            vec![(undeclared(Namespace::Variable, "m"), (2, 14))]
+        );
+    }
+
+    #[test]
+    fn namespaces() {
+        // a struct, a field, a function, a type and a variable can share names as long as they're
+        // different kinds of name
+        assert!(resolve_str(
+            "typedef int t;\n\
+             struct s { int s; int f; };\n\
+             int f(int s) { return s; }\n\
+             int main() { int f = 1; struct s* s = NULL; return f(f); }"
+        )
+        .is_ok());
+        // but a variable can't be named like a type
+        assert_eq!(
+            errors("typedef int t;\n int f(int t) { return 0; }"),
+            vec![(
+                SemanticErrorKind::TypeNameAsVariable {
+                    name: "t".to_string()
+                },
+                (2, 8)
+            )]
+        );
+    }
+
+    #[test]
+    fn global_declarations() {
+        let redeclared = |namespace, name: &str, previous: usize, at| {
+            (
+                SemanticErrorKind::Redeclared {
+                    namespace,
+                    name: name.to_string(),
+                    previous: Span {
+                        line: previous,
+                        ..Span::default()
+                    },
+                },
+                at,
+            )
+        };
+        let with_previous_line = |errors: Vec<(SemanticErrorKind, (usize, usize))>| {
+            errors
+                .into_iter()
+                .map(|(kind, at)| match kind {
+                    SemanticErrorKind::Redeclared {
+                        namespace,
+                        name,
+                        previous,
+                    } => redeclared(namespace, &name, previous.line, at),
+                    kind => (kind, at),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // prototypes and forward struct declarations may repeat, definitions may not
+        assert!(resolve_str(
+            "struct s; struct s; struct s {}; int f(); int f(); int f() { return 0; }"
+        )
+        .is_ok());
+        assert_eq!(
+            with_previous_line(errors("int f() { return 0; }\n int f() { return 1; }")),
+            vec![redeclared(Namespace::Function, "f", 1, (2, 2))]
+        );
+        assert_eq!(
+            with_previous_line(errors("struct s {};\n struct s { int x; };")),
+            vec![redeclared(Namespace::Struct, "s", 1, (2, 2))]
+        );
+        assert_eq!(
+            with_previous_line(errors("typedef int t;\n typedef bool t;")),
+            vec![redeclared(Namespace::Type, "t", 1, (2, 2))]
+        );
+        assert_eq!(
+            with_previous_line(errors("struct s {\n int x;\n bool x;\n };")),
+            vec![redeclared(Namespace::Field, "x", 2, (3, 2))]
+        );
+    }
+
+    #[test]
+    fn functions() {
+        // recursion only needs the function's own header
+        assert!(resolve_str("int f(int n) { return n == 0 ? 0 : f(n - 1); }").is_ok());
+        let errors = resolve_str("int main() { return g(1) + h(); }\n int g(int x);").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].to_string(),
+            "1:21: error: function `g` is used before its declaration on line 2"
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "1:28: error: undeclared function `h`"
+        );
+        assert_eq!(errors[1].decl, 0);
+    }
+
+    #[test]
+    fn defined() {
+        let source = "int f(int x);\n int g();\n int h();\n int main() { return f(1) + g() + h(); }\n int g() { return 0; }";
+        let program = parse(scan(source.to_string()).unwrap()).unwrap();
+        let resolution = resolve(&program).unwrap();
+        // h comes from a library's header, and g is defined further down
+        let errors =
+            check_defined(&program, &[false, false, true, false, false], &resolution).unwrap_err();
+        let errors: Vec<String> = errors.iter().map(SemanticError::to_string).collect();
+        assert_eq!(
+            errors,
+            vec!["1:1: error: function `f` is used but never defined"]
+        );
+        // only functions that are used need defining
+        let program = parse(scan("int f(int x);".to_string()).unwrap()).unwrap();
+        let resolution = resolve(&program).unwrap();
+        assert!(check_defined(&program, &[false], &resolution).is_ok());
+    }
+}
@@ -0,0 +1,904 @@
+use crate::ast::{
+    BinaryOp, DeclKind, Expr, ExprKind, LValue, LValueKind, Program, Spec, SpecKind, Stmt,
+    StmtKind, Ty, Type, TypeKind, UnaryOp,
+};
+use crate::scanner::span::Span;
+use crate::semantic::layout::Layouts;
+use crate::semantic::{SemanticError, SemanticErrorKind};
+use std::collections::HashMap;
+
+// Typechecking: works out the type of every expression and lvalue, filling in their type slots,
+// and checks that each is used where its type is allowed
+//
+// This runs after name resolution, so every name is known to be declared. Expressions whose type
+// can't be worked out are left untyped, and nothing using them is checked any further: one mistake
+// gives one error, rather than one for every expression around it
+pub fn typecheck(program: &mut Program) -> Result<Layouts, Vec<SemanticError>> {
+    let mut checker = Checker::default();
+    for (index, decl) in program.decls.iter_mut().enumerate() {
+        checker.decl = index;
+        checker.decl_kind(&mut decl.kind, decl.span);
+    }
+    if checker.errors.is_empty() {
+        Ok(checker.layouts)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+struct Signature {
+    ret: Ty,
+    // None for a parameter whose type didn't check, so anything can be passed for it
+    params: Vec<Option<Ty>>,
+    span: Span,
+}
+
+#[derive(Default)]
+struct Checker {
+    typedefs: HashMap<String, Ty>,
+    // Every struct defined so far
+    layouts: Layouts,
+    functions: HashMap<String, Signature>,
+    scopes: Vec<HashMap<String, Ty>>,
+    // The return type of the function being checked
+    ret: Option<Ty>,
+    in_ensures: bool,
+    errors: Vec<SemanticError>,
+    decl: usize,
+}
+
+// Whether a value of type `b` can go where `a` is expected or the other way round, and the type
+// they have in common. NULL goes with any pointer
+fn join(a: &Ty, b: &Ty) -> Option<Ty> {
+    match (a, b) {
+        (Ty::Null, Ty::Pointer(_)) => Some(b.clone()),
+        (Ty::Pointer(_), Ty::Null) => Some(a.clone()),
+        _ if a == b => Some(a.clone()),
+        _ => None,
+    }
+}
+
+impl Checker {
+    fn error(&mut self, kind: SemanticErrorKind, span: Span) {
+        self.errors.push(SemanticError {
+            kind,
+            span,
+            decl: self.decl,
+        });
+    }
+
+    fn mismatch(&mut self, expected: &Ty, found: &Ty, span: Span) {
+        let kind = SemanticErrorKind::Mismatch {
+            expected: expected.clone(),
+            found: found.clone(),
+        };
+        self.error(kind, span);
+    }
+
+    fn wrong_kind(&mut self, expected: &'static str, found: Ty, span: Span) {
+        self.error(SemanticErrorKind::WrongKind { expected, found }, span);
+    }
+
+    // Checks that a value of type `found` can go where `expected` is, and says whether it can: not
+    // if its type is unknown
+    fn expect(&mut self, expected: &Ty, found: Option<Ty>, span: Span) -> bool {
+        let Some(found) = found else {
+            return false;
+        };
+        let fits = join(expected, &found).is_some();
+        if !fits {
+            self.mismatch(expected, &found, span);
+        }
+        fits
+    }
+
+    // Checks that a value can be held in a variable, or passed or returned
+    fn small(&mut self, ty: &Ty, span: Span) -> bool {
+        match ty {
+            Ty::Void => self.error(SemanticErrorKind::VoidType, span),
+            Ty::Struct(_) => {
+                let kind = SemanticErrorKind::LargeType { ty: ty.clone() };
+                self.error(kind, span);
+            }
+            _ => return true,
+        }
+        false
+    }
+
+    fn defined(&mut self, name: &str, span: Span) -> bool {
+        let defined = self.layouts.get(name).is_some();
+        if !defined {
+            let kind = SemanticErrorKind::UndefinedStruct {
+                name: name.to_string(),
+            };
+            self.error(kind, span);
+        }
+        defined
+    }
+
+    // The type a written type stands for. void is only allowed at the top, where the caller
+    // decides whether it's fine
+    fn ty(&mut self, ty: &Type) -> Option<Ty> {
+        let inner = |checker: &mut Checker, inner: &Type| match checker.ty(inner)? {
+            Ty::Void => {
+                checker.error(SemanticErrorKind::VoidType, inner.span);
+                None
+            }
+            inner => Some(Box::new(inner)),
+        };
+        match &ty.kind {
+            TypeKind::Int => Some(Ty::Int),
+            TypeKind::Bool => Some(Ty::Bool),
+            TypeKind::Char => Some(Ty::Char),
+            TypeKind::String => Some(Ty::String),
+            TypeKind::Void => Some(Ty::Void),
+            TypeKind::Pointer(pointee) => inner(self, pointee).map(Ty::Pointer),
+            TypeKind::Array(element) => inner(self, element).map(Ty::Array),
+            TypeKind::Struct(name) => Some(Ty::Struct(name.clone())),
+            TypeKind::Name(name) => self.typedefs.get(name).cloned(),
+        }
+    }
+
+    // A type that values can be made of: anything but void, and structs only once they're defined
+    fn sized(&mut self, ty: &Type) -> Option<Ty> {
+        match self.ty(ty)? {
+            Ty::Void => {
+                self.error(SemanticErrorKind::VoidType, ty.span);
+                None
+            }
+            Ty::Struct(name) if !self.defined(&name, ty.span) => None,
+            sized => Some(sized),
+        }
+    }
+
+    fn decl_kind(&mut self, kind: &mut DeclKind, span: Span) {
+        match kind {
+            DeclKind::Typedef { ty, name } => {
+                if let Some(ty) = self.ty(ty) {
+                    if ty == Ty::Void {
+                        self.error(SemanticErrorKind::VoidType, span);
+                    }
+                    self.typedefs.insert(name.clone(), ty);
+                }
+            }
+            DeclKind::StructDecl { .. } => {}
+            DeclKind::StructDef { name, fields } => {
+                let fields: Vec<(String, Ty, Span)> = fields
+                    .iter()
+                    .filter_map(|field| {
+                        Some((field.name.clone(), self.ty(&field.ty)?, field.ty.span))
+                    })
+                    .collect();
+                for (kind, span) in self.layouts.define(name, &fields) {
+                    self.error(kind, span);
+                }
+            }
+            DeclKind::Function {
+                ret,
+                name,
+                params,
+                specs,
+                body,
+            } => {
+                let ret_span = ret.span;
+                let ret = self.ty(ret).unwrap_or(Ty::Void);
+                if ret != Ty::Void {
+                    self.small(&ret, ret_span);
+                }
+                self.scopes.push(HashMap::new());
+                let mut types = Vec::new();
+                for param in params.iter() {
+                    let ty = self.ty(&param.ty);
+                    if let Some(ty) = &ty {
+                        self.small(ty, param.ty.span);
+                    }
+                    types.push(ty.clone());
+                    if let Some(ty) = ty {
+                        self.declare(&param.name, ty);
+                    }
+                }
+                if name == "main" && (ret != Ty::Int || !params.is_empty()) {
+                    self.error(SemanticErrorKind::MainSignature, span);
+                }
+
+                match self.functions.get(name) {
+                    Some(previous) if previous.ret != ret || previous.params != types => {
+                        let kind = SemanticErrorKind::ConflictingTypes {
+                            name: name.clone(),
+                            previous: previous.span,
+                        };
+                        self.error(kind, span);
+                    }
+                    Some(_) => {}
+                    None => {
+                        let signature = Signature {
+                            ret: ret.clone(),
+                            params: types,
+                            span,
+                        };
+                        self.functions.insert(name.clone(), signature);
+                    }
+                }
+
+                self.ret = Some(ret);
+                for spec in specs.iter_mut() {
+                    self.spec(spec);
+                }
+                if let Some(body) = body {
+                    self.block(body);
+                }
+                self.ret = None;
+                self.scopes.pop();
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str, ty: Ty) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.insert(name.to_string(), ty);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Ty> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+    }
+
+    fn block(&mut self, stmts: &mut [Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn scoped(&mut self, stmt: &mut Stmt) {
+        self.block(std::slice::from_mut(stmt));
+    }
+
+    fn condition(&mut self, cond: &mut Expr) {
+        let ty = self.expr(cond);
+        self.expect(&Ty::Bool, ty, cond.span);
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        let span = stmt.span;
+        match &mut stmt.kind {
+            StmtKind::Decl { ty, name, init } => {
+                let declared = self.ty(ty);
+                if let Some(declared) = &declared {
+                    self.small(declared, ty.span);
+                }
+                let found = init.as_mut().map(|init| (self.expr(init), init.span));
+                if let (Some(declared), Some((found, span))) = (&declared, found) {
+                    self.expect(declared, found, span);
+                }
+                // Still declared if its type is wrong, so that its uses don't look undeclared
+                if let Some(declared) = declared {
+                    self.declare(name, declared);
+                }
+            }
+            StmtKind::Assign { lhs, op, rhs } => {
+                let target = self.lvalue(lhs);
+                let value = self.expr(rhs);
+                match (op, target) {
+                    (Some(_), target) => {
+                        self.expect(&Ty::Int, target, lhs.span);
+                        self.expect(&Ty::Int, value, rhs.span);
+                    }
+                    (None, Some(target)) => {
+                        if self.small(&target, lhs.span) {
+                            self.expect(&target, value, rhs.span);
+                        }
+                    }
+                    (None, None) => {}
+                }
+            }
+            StmtKind::PostOp { lhs, .. } => {
+                let ty = self.lvalue(lhs);
+                self.expect(&Ty::Int, ty, lhs.span);
+            }
+            StmtKind::Expr(e) => {
+                if let Some(ty @ Ty::Struct(_)) = self.expr(e) {
+                    self.error(SemanticErrorKind::LargeType { ty }, e.span);
+                }
+            }
+            StmtKind::If { cond, then, els } => {
+                self.condition(cond);
+                self.scoped(then);
+                if let Some(els) = els {
+                    self.scoped(els);
+                }
+            }
+            StmtKind::While {
+                cond,
+                invariants,
+                body,
+            } => {
+                self.condition(cond);
+                for spec in invariants {
+                    self.spec(spec);
+                }
+                self.scoped(body);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                invariants,
+                body,
+            } => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                self.condition(cond);
+                if let Some(step) = step {
+                    self.stmt(step);
+                }
+                for spec in invariants {
+                    self.spec(spec);
+                }
+                self.scoped(body);
+                self.scopes.pop();
+            }
+            StmtKind::Return(value) => {
+                let ret = self.ret.clone().unwrap_or(Ty::Void);
+                match value {
+                    Some(e) => {
+                        let found = self.expr(e);
+                        if ret == Ty::Void {
+                            if let Some(found) = found {
+                                self.mismatch(&ret, &found, e.span);
+                            }
+                        } else {
+                            self.expect(&ret, found, e.span);
+                        }
+                    }
+                    None if ret != Ty::Void => self.mismatch(&ret, &Ty::Void, span),
+                    None => {}
+                }
+            }
+            StmtKind::Block(stmts) => self.block(stmts),
+            StmtKind::Assert(e) => self.condition(e),
+            StmtKind::Error(e) => {
+                let ty = self.expr(e);
+                self.expect(&Ty::String, ty, e.span);
+            }
+            StmtKind::Annotation(specs) => {
+                for spec in specs {
+                    self.spec(spec);
+                }
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    fn spec(&mut self, spec: &mut Spec) {
+        self.in_ensures = matches!(spec.kind, SpecKind::Ensures(_));
+        match &mut spec.kind {
+            SpecKind::Requires(e)
+            | SpecKind::Ensures(e)
+            | SpecKind::LoopInvariant(e)
+            | SpecKind::Assert(e) => self.condition(e),
+        }
+        self.in_ensures = false;
+    }
+
+    // The type of a field of a struct, which has to be defined by now
+    fn field(&mut self, strukt: &str, field: &str, span: Span) -> Option<Ty> {
+        if !self.defined(strukt, span) {
+            return None;
+        }
+        let found = self
+            .layouts
+            .get(strukt)
+            .unwrap()
+            .field(field)
+            .map(|field| field.ty.clone());
+        if found.is_none() {
+            let kind = SemanticErrorKind::NoField {
+                strukt: strukt.to_string(),
+                field: field.to_string(),
+            };
+            self.error(kind, span);
+        }
+        found
+    }
+
+    // The type "e.f" would have, for a base of type `base`
+    fn dot(&mut self, base: Option<Ty>, field: &str, span: Span) -> Option<Ty> {
+        match base? {
+            Ty::Struct(name) => self.field(&name, field, span),
+            other => {
+                self.wrong_kind("a struct", other, span);
+                None
+            }
+        }
+    }
+
+    // The type "e->f" would have
+    fn arrow(&mut self, base: Option<Ty>, field: &str, span: Span) -> Option<Ty> {
+        match base? {
+            Ty::Pointer(pointee) => match *pointee {
+                Ty::Struct(name) => self.field(&name, field, span),
+                other => {
+                    self.wrong_kind("a pointer to a struct", Ty::Pointer(Box::new(other)), span);
+                    None
+                }
+            },
+            Ty::Null => {
+                self.error(SemanticErrorKind::NullDereference, span);
+                None
+            }
+            other => {
+                self.wrong_kind("a pointer to a struct", other, span);
+                None
+            }
+        }
+    }
+
+    // The type "*e" would have
+    fn deref(&mut self, base: Option<Ty>, span: Span) -> Option<Ty> {
+        match base? {
+            Ty::Pointer(pointee) => Some(*pointee),
+            Ty::Null => {
+                self.error(SemanticErrorKind::NullDereference, span);
+                None
+            }
+            other => {
+                self.wrong_kind("a pointer", other, span);
+                None
+            }
+        }
+    }
+
+    // The type "e[i]" would have
+    fn index(&mut self, base: Option<Ty>, index: &mut Expr, span: Span) -> Option<Ty> {
+        let index_ty = self.expr(index);
+        self.expect(&Ty::Int, index_ty, index.span);
+        match base? {
+            Ty::Array(element) => Some(*element),
+            other => {
+                self.wrong_kind("an array", other, span);
+                None
+            }
+        }
+    }
+
+    fn lvalue(&mut self, lv: &mut LValue) -> Option<Ty> {
+        let span = lv.span;
+        let ty = match &mut lv.kind {
+            LValueKind::Var(name) => self.lookup(name),
+            LValueKind::Field { base, field } => {
+                let base = self.lvalue(base);
+                self.dot(base, field, span)
+            }
+            LValueKind::Arrow { base, field } => {
+                let base = self.lvalue(base);
+                self.arrow(base, field, span)
+            }
+            LValueKind::Deref(base) => {
+                let base = self.lvalue(base);
+                self.deref(base, span)
+            }
+            LValueKind::Index { base, index } => {
+                let base = self.lvalue(base);
+                self.index(base, index, span)
+            }
+        };
+        lv.ty = ty.clone();
+        ty
+    }
+
+    fn expr(&mut self, e: &mut Expr) -> Option<Ty> {
+        let span = e.span;
+        let ty = match &mut e.kind {
+            ExprKind::Int(_) => Some(Ty::Int),
+            ExprKind::Bool(_) => Some(Ty::Bool),
+            ExprKind::Char(_) => Some(Ty::Char),
+            ExprKind::String(_) => Some(Ty::String),
+            ExprKind::Null => Some(Ty::Null),
+            ExprKind::Var(name) => self.lookup(name),
+            ExprKind::Binary { op, lhs, rhs } => self.binary(*op, lhs, rhs),
+            ExprKind::Unary { op, operand } => {
+                let operand_ty = self.expr(operand);
+                let ty = match op {
+                    UnaryOp::Not => Ty::Bool,
+                    UnaryOp::BitNot | UnaryOp::Neg => Ty::Int,
+                };
+                let fits = self.expect(&ty, operand_ty, operand.span);
+                fits.then_some(ty)
+            }
+            ExprKind::Ternary { cond, then, els } => {
+                self.condition(cond);
+                let then_ty = self.expr(then);
+                let els_ty = self.expr(els);
+                match (then_ty, els_ty) {
+                    (Some(then_ty), Some(els_ty)) => match join(&then_ty, &els_ty) {
+                        Some(ty) if self.small(&ty, span) => Some(ty),
+                        Some(_) => None,
+                        None => {
+                            self.mismatch(&then_ty, &els_ty, els.span);
+                            None
+                        }
+                    },
+                    _ => None,
+                }
+            }
+            ExprKind::Call { name, args } => {
+                let found: Vec<(Option<Ty>, Span)> = args
+                    .iter_mut()
+                    .map(|arg| (self.expr(arg), arg.span))
+                    .collect();
+                match self.functions.get(name.as_str()) {
+                    Some(signature) => {
+                        let ret = signature.ret.clone();
+                        let params = signature.params.clone();
+                        if params.len() != found.len() {
+                            let kind = SemanticErrorKind::ArgumentCount {
+                                name: name.clone(),
+                                expected: params.len(),
+                                found: found.len(),
+                            };
+                            self.error(kind, span);
+                        } else {
+                            for (param, (arg, span)) in params.iter().zip(found) {
+                                if let Some(param) = param {
+                                    self.expect(param, arg, span);
+                                }
+                            }
+                        }
+                        Some(ret)
+                    }
+                    None => None,
+                }
+            }
+            ExprKind::Field { base, field } => {
+                let base = self.expr(base);
+                self.dot(base, field, span)
+            }
+            ExprKind::Arrow { base, field } => {
+                let base = self.expr(base);
+                self.arrow(base, field, span)
+            }
+            ExprKind::Deref(base) => {
+                let base = self.expr(base);
+                self.deref(base, span)
+            }
+            ExprKind::Index { base, index } => {
+                let base = self.expr(base);
+                self.index(base, index, span)
+            }
+            ExprKind::Alloc(ty) => self.sized(ty).map(|ty| Ty::Pointer(Box::new(ty))),
+            ExprKind::AllocArray { ty, len } => {
+                let element = self.sized(ty);
+                let len_ty = self.expr(len);
+                self.expect(&Ty::Int, len_ty, len.span);
+                element.map(|ty| Ty::Array(Box::new(ty)))
+            }
+            ExprKind::Result => match &self.ret {
+                _ if !self.in_ensures => {
+                    self.error(SemanticErrorKind::ResultOutsideEnsures, span);
+                    None
+                }
+                Some(Ty::Void) => {
+                    self.error(SemanticErrorKind::ResultOfVoid, span);
+                    None
+                }
+                ret => ret.clone(),
+            },
+            // `\length` is only lexed inside annotations, so it can't turn up outside a contract
+            ExprKind::Length(base) => {
+                let base_ty = self.expr(base);
+                match base_ty {
+                    Some(Ty::Array(_)) | None => {}
+                    Some(other) => self.wrong_kind("an array", other, base.span),
+                }
+                Some(Ty::Int)
+            }
+        };
+        e.ty = ty.clone();
+        ty
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: &mut Expr, rhs: &mut Expr) -> Option<Ty> {
+        let lhs_ty = self.expr(lhs);
+        let rhs_ty = self.expr(rhs);
+        match op {
+            BinaryOp::Mul
+            | BinaryOp::Div
+            | BinaryOp::Mod
+            | BinaryOp::Add
+            | BinaryOp::Sub
+            | BinaryOp::Shl
+            | BinaryOp::Shr
+            | BinaryOp::BitAnd
+            | BinaryOp::BitXor
+            | BinaryOp::BitOr => {
+                let lhs_fits = self.expect(&Ty::Int, lhs_ty, lhs.span);
+                let rhs_fits = self.expect(&Ty::Int, rhs_ty, rhs.span);
+                (lhs_fits && rhs_fits).then_some(Ty::Int)
+            }
+            // Ordered on ints and chars, as long as both sides are the same
+            BinaryOp::Less | BinaryOp::LessEq | BinaryOp::Greater | BinaryOp::GreaterEq => {
+                match lhs_ty {
+                    Some(ty @ (Ty::Char | Ty::Int)) => {
+                        self.expect(&ty, rhs_ty, rhs.span);
+                    }
+                    Some(other) => self.mismatch(&Ty::Int, &other, lhs.span),
+                    None => {}
+                }
+                Some(Ty::Bool)
+            }
+            BinaryOp::Eq | BinaryOp::NotEq => {
+                if let (Some(lhs_ty), Some(rhs_ty)) = (lhs_ty, rhs_ty) {
+                    match join(&lhs_ty, &rhs_ty) {
+                        Some(ty @ (Ty::String | Ty::Struct(_) | Ty::Void)) => {
+                            self.error(
+                                SemanticErrorKind::NotComparable { ty },
+                                lhs.span.to(rhs.span),
+                            );
+                        }
+                        Some(_) => {}
+                        None => self.mismatch(&lhs_ty, &rhs_ty, rhs.span),
+                    }
+                }
+                Some(Ty::Bool)
+            }
+            BinaryOp::LogicalAnd | BinaryOp::LogicalOr => {
+                self.expect(&Ty::Bool, lhs_ty, lhs.span);
+                self.expect(&Ty::Bool, rhs_ty, rhs.span);
+                Some(Ty::Bool)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn check(source: &str) -> Result<Program, Vec<SemanticError>> {
+        let mut program = parse(scan(source.to_string()).unwrap()).unwrap();
+        typecheck(&mut program)?;
+        Ok(program)
+    }
+
+    // Each error's message, with the line and column it points at
+    fn errors(source: &str) -> Vec<String> {
+        check(source)
+            .unwrap_err()
+            .iter()
+            .map(SemanticError::to_string)
+            .collect()
+    }
+
+    // The type of the expression returned by the last function
+    fn returned(source: &str) -> Ty {
+        let program = check(source).unwrap();
+        let Some(DeclKind::Function {
+            body: Some(body), ..
+        }) = program.decls.last().map(|decl| &decl.kind)
+        else {
+            panic!("no function body in {:?}", source);
+        };
+        match &body.last().unwrap().kind {
+            StmtKind::Return(Some(e)) => e.ty.clone().unwrap(),
+            other => panic!("expected a return, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fills_in_types() {
+        let source = "typedef int[] ints;\n\
+                      struct node { int value; struct node* next; };\n\
+                      int main() {\n\
+                        ints a = alloc_array(int, 3);\n\
+                        struct node* n = alloc(struct node);\n\
+                        n->next = NULL;\n\
+                        a[0] = n->value + 1;\n\
+                        return a[0];\n\
+                      }";
+        let program = check(source).unwrap();
+        let DeclKind::Function {
+            body: Some(body), ..
+        } = &program.decls[2].kind
+        else {
+            panic!();
+        };
+        let types = |stmt: &Stmt| match &stmt.kind {
+            StmtKind::Decl { init: Some(e), .. } => (None, e.ty.clone()),
+            StmtKind::Assign { lhs, rhs, .. } => (lhs.ty.clone(), rhs.ty.clone()),
+            StmtKind::Return(Some(e)) => (None, e.ty.clone()),
+            other => panic!("{:?}", other),
+        };
+        let node = || Ty::Pointer(Box::new(Ty::Struct("node".to_string())));
+        assert_eq!(types(&body[0]), (None, Some(Ty::Array(Box::new(Ty::Int)))));
+        assert_eq!(types(&body[1]), (None, Some(node())));
+        assert_eq!(types(&body[2]), (Some(node()), Some(Ty::Null)));
+        assert_eq!(types(&body[3]), (Some(Ty::Int), Some(Ty::Int)));
+        assert_eq!(types(&body[4]), (None, Some(Ty::Int)));
+
+        // typedef names are replaced by what they stand for
+        assert_eq!(
+            returned("typedef struct s* t;\n t f(t x) { return x; }"),
+            Ty::Pointer(Box::new(Ty::Struct("s".to_string())))
+        );
+        assert_eq!(
+            returned("bool f(char c) { return c < 'z' && c >= 'a'; }"),
+            Ty::Bool
+        );
+        assert_eq!(returned("int f(bool b) { return b ? 1 : -1; }"), Ty::Int);
+        // NULL joins whichever pointer type is on the other side
+        assert_eq!(
+            returned("int* f(bool b) { return b ? NULL : alloc(int); }"),
+            Ty::Pointer(Box::new(Ty::Int))
+        );
+    }
+
+    #[test]
+    fn mismatches() {
+        assert_eq!(
+            errors("int f() { return true; }"),
+            vec!["1:18: error: expected `int`, found `bool`"]
+        );
+        assert_eq!(
+            errors("void f() { int x = NULL; string s = 'c'; }"),
+            vec![
+                "1:20: error: expected `int`, found `void*`",
+                "1:37: error: expected `string`, found `char`"
+            ]
+        );
+        assert_eq!(
+            errors("int f(int x) { return f(x, 2) + f(true); }"),
+            vec![
+                "1:23: error: function `f` takes 1 argument but 2 were given",
+                "1:35: error: expected `int`, found `bool`"
+            ]
+        );
+        assert_eq!(
+            errors("void f(int* p, bool[] a) { if (*p) {} a[true] = 1; }"),
+            vec![
+                "1:32: error: expected `bool`, found `int`",
+                "1:41: error: expected `int`, found `bool`",
+                "1:49: error: expected `bool`, found `int`"
+            ]
+        );
+        assert_eq!(
+            errors("void f(int x) { return x; }\n int g() { return; }"),
+            vec![
+                "1:24: error: expected `void`, found `int`",
+                "2:12: error: expected `int`, found `void`"
+            ]
+        );
+        assert_eq!(
+            errors("int f(int x) { return *x + x[0]; }"),
+            vec![
+                "1:23: error: expected a pointer, found `int`",
+                "1:28: error: expected an array, found `int`"
+            ]
+        );
+        assert_eq!(
+            errors("int f(int x);\n bool f(int y);"),
+            vec!["2:2: error: function `f` does not match its declaration on line 1"]
+        );
+        // main is called with no arguments, and its result printed
+        assert_eq!(
+            errors("void main() {}"),
+            vec!["1:1: error: `main` must be declared as `int main()`"]
+        );
+        assert_eq!(
+            errors("int main(int x) { return x; }"),
+            vec!["1:1: error: `main` must be declared as `int main()`"]
+        );
+        // an untyped expression isn't complained about again further up
+        assert_eq!(errors("int f(int* x) { return *x->y + 1; }").len(), 1);
+    }
+
+    #[test]
+    fn null_and_alloc() {
+        assert!(check(
+            "struct s { int x; };\n\
+             bool f(struct s* p) { struct s* q = NULL; q = alloc(struct s); return p == NULL || NULL != q; }"
+        )
+        .is_ok());
+        assert_eq!(
+            errors("int f() { return *NULL; }"),
+            vec!["1:18: error: cannot dereference NULL"]
+        );
+        assert_eq!(
+            errors("struct s;\n struct s* f() { return alloc(struct s); }"),
+            vec!["2:31: error: struct `s` is not defined"]
+        );
+        assert_eq!(
+            errors("void f() { int* p = alloc(void); }"),
+            vec!["1:27: error: `void` can only be the return type of a function"]
+        );
+        assert_eq!(
+            errors("void f() { bool[] a = alloc_array(int, 'c'); }"),
+            vec![
+                "1:40: error: expected `int`, found `char`",
+                "1:23: error: expected `bool[]`, found `int[]`"
+            ]
+        );
+    }
+
+    #[test]
+    fn small_types() {
+        let source = "struct s { int x; struct s* next; };\n\
+                      struct t { struct s inner; };\n\
+                      int f(struct s x) {\n\
+                        struct t y;\n\
+                        struct t* p = alloc(struct t);\n\
+                        *p;\n\
+                        p->inner = p->inner;\n\
+                        return p->inner.x;\n\
+                      }\n\
+                      void* g();";
+        assert_eq!(
+            errors(source),
+            vec![
+                "3:7: error: `struct s` is a large type and cannot be used as a value",
+                "4:1: error: `struct t` is a large type and cannot be used as a value",
+                "6:1: error: `struct t` is a large type and cannot be used as a value",
+                "7:1: error: `struct s` is a large type and cannot be used as a value",
+                "10:1: error: `void` can only be the return type of a function"
+            ]
+        );
+        // a struct can only hold another by value once that one is defined
+        assert_eq!(
+            errors("struct s;\n struct t { struct s inner; };"),
+            vec!["2:13: error: struct `s` is not defined"]
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(check(
+            "bool f(int* p, int* q, char c, int[] a) { return p == q && c != 'x' && a == a; }"
+        )
+        .is_ok());
+        assert_eq!(
+            errors("bool f(string s, int x, bool b) { return s == \"a\" || x == b || b < b; }"),
+            vec![
+                "1:42: error: values of type `string` cannot be compared",
+                "1:59: error: expected `int`, found `bool`",
+                "1:64: error: expected `int`, found `bool`"
+            ]
+        );
+        assert_eq!(
+            errors("bool f(int x, char c) { return x < c; }"),
+            vec!["1:36: error: expected `int`, found `char`"]
+        );
+    }
+
+    #[test]
+    fn contracts() {
+        assert!(check(
+            "int f(int[] a, int n)\n\
+             //@requires \\length(a) == n;\n\
+             //@ensures \\result >= 0;\n\
+             { return n; }"
+        )
+        .is_ok());
+        assert_eq!(
+            errors("int f(int n)\n //@requires \\result > 0;\n //@ensures \\length(n) == 0;\n ;"),
+            vec![
+                "2:14: error: `\\result` can only be used in @ensures",
+                "3:21: error: expected an array, found `int`"
+            ]
+        );
+        assert_eq!(
+            errors("void f()\n //@ensures 1;\n { }"),
+            vec!["2:13: error: expected `bool`, found `int`"]
+        );
+        assert_eq!(
+            errors("void f()\n //@ensures \\result == 0;\n { }"),
+            vec!["2:13: error: `\\result` cannot be used in a function returning `void`"]
+        );
+    }
+}
@@ -0,0 +1,20 @@
+// The pseudo-random numbers behind the randomized tests, which keeps them deterministic without
+// pulling in a crate for it
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    // The seed can be anything but 0, which xorshift never leaves
+    pub fn new(seed: u64) -> Xorshift {
+        Xorshift { state: seed }
+    }
+
+    // A number in 0..bound
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % bound as u64) as usize
+    }
+}